@@ -6,6 +6,12 @@
 # Jito 配置
 export JITO_URL="https://amsterdam.mainnet.block-engine.jito.wtf"
 export JITO_CONCURRENCY=10
+# 运行时长 (秒)，不设置时一直运行
+# export JITO_DURATION_SEC=60
+# SLO 断言 (需要 JITO_DURATION_SEC)，未通过时退出码为 2
+# export ASSERT="jito.429_rate<1%;jito.success_per_sec>=8"
+# 运行结果保存到 SQLite 历史库 (需要 JITO_DURATION_SEC，设为空字符串禁用)
+# export HISTORY_DB="benchmark-history.db"
 
 # 运行 Jito 基准测试
 echo "开始 Jito 区块引擎基准测试..."
@@ -12,10 +12,93 @@ export GRPC_URL_2="http://127.0.0.1:10900"
 export GRPC_NAME_2="Self_Node"
 export GRPC_TOKEN_2=""
 
+# gRPC 响应压缩 (none/gzip/zstd)，按端点设置
+# export GRPC_COMPRESSION_1=zstd
+# 压缩对比: 为每个无压缩的 gRPC 端点额外建立指定压缩方式的连接，报告延迟差和带宽节省
+# export COMPARE_COMPRESSION="gzip,zstd"
+
+# 多连接: 对同一端点建立 N 个独立订阅 (NAME#1..NAME#N)，额外报告 best-of-N 延迟及各连接落后最优连接的程度
+# export GRPC_CONNECTIONS_1=3
+
+# RPC PubSub websocket 端点 (可选)，与 gRPC 端点在同一张表中排名
+# export WS_URL_1="wss://api.mainnet-beta.solana.com"
+# export WS_NAME_1="RPC_WebSocket"
+
+# HTTP JSON-RPC 轮询端点 (可选)，轮询 getSlot 作为基准，仅用于 slot 比较
+# export RPC_URL_1="https://api.mainnet-beta.solana.com"
+# export RPC_NAME_1="RPC_Polling"
+# export RPC_POLL_INTERVAL_MS=400
+
 # 测试配置
 export GRPC_COMPARISON_DURATION_SEC=30
+# 比较类型: slot、transaction 或 protocol；transaction 需要设置 MENTIONS 或 SIGNATURES
+export RACE=slot
+# protocol 比较的协议列表，默认全部
+# export PROTOCOLS="PumpFun,PumpSwap,Bonk,RaydiumCpmm,RaydiumClmm,RaydiumAmmV4"
+# export MENTIONS="6EF8rrecthR5Dkzon8Nwu78hRvfCKubJ14M5uBEwF6P"
+# export SIGNATURES=""
 export CONCURRENCY=10
+# 在传输层记录 HTTP/2 帧拉取时间，解码耗时单独统计
+export FRAME_TIMESTAMPS=false
+# 每个端点使用独立线程和运行时；CORE_IDS 可绑定 CPU 核心，例如 "2,3"
+export DEDICATED_RUNTIME=false
+# export CORE_IDS="2,3"
+# 每个 key 首次到达后等待其他端点的时间 (毫秒)，超时后按已收到的端点计分，未收到的端点记为错过 (报告中的超时未收到比例)
+# export RESOLVE_TIMEOUT_MS=500
+# 与最早到达相差不到 TIE_TOLERANCE_US 微秒的端点记为同时接收，不计入首先接收或落后接收
+# export TIE_TOLERANCE_US=10
+# 预热: 开始后 WARMUP_SECS 秒内 (且至少 WARMUP_KEYS 个) 的数据不计入统计，单独报告首条消息耗时和初始积压;
+# 冷却: 结束前 COOLDOWN_SECS 秒内首次出现的数据不计入统计
+# export WARMUP_SECS=10
+# export WARMUP_KEYS=20
+# export COOLDOWN_SECS=2
+# slot 对齐: 端点落后最新slot超过 MAX_SLOT_DIFFERENCE 个区块时的处理方式 (仅 slot 比较)，
+# exclude 不再参与比较、readmit 追上后重新参与、flag 仍参与比较只做标记; 状态需持续 SLOT_WINDOW_SECS 秒才生效 (0 为立即)
+# 各端点落后的区块数在运行中持续输出，也可用于断言/告警，例如 "endpoint=Self_Node max_slot_lag<5"
+# export MAX_SLOT_DIFFERENCE=10
+# export SLOT_POLICY=readmit
+# export SLOT_WINDOW_SECS=5
+# 峰值吞吐 (条/秒、字节/秒) 的统计窗口，单位毫秒
+# export THROUGHPUT_WINDOWS_MS="1000,10000"
+# 记录每次到达的二进制轨迹，之后无需重新测试即可重新分析:
+#   cargo run --bin replay -- arrivals.trace --warmup-secs 60 --exclude RPC_Polling
+# export TRACE_FILE="arrivals.trace"
+# 运行结束后生成报告: .md 为汇总表格, .html 为带图表的独立页面 (CDF、每分钟首先接收占比、直方图)
+# 轨迹同样可以生成报告: cargo run --bin replay -- arrivals.trace --report report.html
+# export REPORT_FILE="report.md,report.html"
+# SLO 断言，多条用分号分隔; 任何一条未通过时退出码为 2，可用于部署流水线
+# export ASSERT="endpoint=GRPC_1 p99<20ms;first_share>=40%"
+# 全屏实时面板代替逐 slot 日志 (按 q 提前结束并输出结果)
+# export TUI=true
+# 守护模式: 一直运行到 Ctrl-C/SIGTERM，每个窗口 (按整点对齐，例如每 5 分钟) 输出一次统计，
+# 用于观察一天中不同时段的差异; 不保存历史记录，也不支持 TUI/TRACE_FILE/REPORT_FILE/ASSERT
+# export DAEMON=true
+# export WINDOW_SECS=300
+# 保留最近的窗口数 (通过 /windows 查看)，288 个 5 分钟窗口即一天
+# export WINDOW_RETENTION=288
+# 每个窗口结束时追加一行 JSON
+# export WINDOW_JSON="windows.jsonl"
+# Prometheus 指标 (/metrics，最近一个窗口) 和 JSON (/windows) 的监听地址
+# export METRICS_ADDR="0.0.0.0:9100"
+# 守护模式告警，多条用分号分隔: disconnected 规则每秒检查，其余 (与 ASSERT 语法相同) 在每个窗口结束时检查;
+# 同一告警只在开始违反和恢复时各通知一次
+# export ALERT="disconnected<30s;endpoint=Self_Node p99<20ms;endpoint=Self_Node first_share>=40%"
+# 告警通知方式，多个用逗号分隔: webhook (POST JSON)、exec:命令 (告警内容在 ALERT_* 环境变量中)、file:文件 (每行一条 JSON)
+# export ALERT_SINK="https://hooks.example.com/bench,exec:./notify.sh,file:alerts.jsonl"
+# 每次运行的结果都会保存到 SQLite 历史库 (设为空字符串禁用):
+#   cargo run --bin grpc-comparison -- history list
+#   cargo run --bin grpc-comparison -- history baseline prod
+#   cargo run --bin grpc-comparison -- history diff latest --baseline prod
+# export HISTORY_DB="benchmark-history.db"
+# 记录在历史中的区域标签，便于区分不同机房的结果
+# export REGION_LABEL="fra"
 
 # 运行 gRPC 比较测试
 echo "开始 gRPC 端点比较测试..."
 RUST_LOG=info cargo run --bin grpc-comparison
+
+# HTTP/2 参数扫描: 对第一个 gRPC 端点逐一测试各参数组合 (每种组合运行 GRPC_COMPARISON_DURATION_SEC 秒)，
+# 与默认配置的连接并行比较并按到达时间差排名
+# RUST_LOG=info cargo run --bin grpc-comparison -- sweep \
+#     --stream-windows default,1MiB,4MiB --connection-windows default,16MiB \
+#     --adaptive-window default,on --buffer-sizes default,1MiB --keepalive-secs 10,30
@@ -6,4 +6,42 @@ export AUTH_TOKEN=demo_token_12345
 export GRPC_URL=https://solana-yellowstone-grpc.publicnode.com:443
 export GRPC_TOKEN=
 
+# 设置后按 DEX 事件 (签名 + 事件类型) 比较，否则比较 BlockMeta，例如 "PumpFunBuy,PumpFunSell"
+# export EVENT_TYPES="PumpFunBuy,PumpFunSell,PumpSwapCreatePool"
+# 设置后按 DEX 协议 (签名 + 协议) 比较并分协议统计，"all" 表示全部协议
+# export PROTOCOLS="PumpFun,PumpSwap,Bonk,RaydiumCpmm,RaydiumClmm,RaydiumAmmV4"
+# FzStream 压缩方式: none / lz4 / zstd；多个编码或 "compare" 时并行对比各编码的
+# 延迟、线上字节数和 CPU 开销 (仅 FzStream 连接，自动使用独立运行时)
+# export FZSTREAM_COMPRESSION=compare
+
+# gRPC 端在传输层记录 HTTP/2 帧拉取时间
+export FRAME_TIMESTAMPS=false
+# 每个端点使用独立线程和运行时；CORE_IDS 可绑定 CPU 核心，例如 "2,3"
+export DEDICATED_RUNTIME=false
+# export CORE_IDS="2,3"
+# 每个 key 首次到达后等待其他端点的时间 (毫秒)，超时后按已收到的端点计分，未收到的端点记为错过 (报告中的超时未收到比例)
+# export RESOLVE_TIMEOUT_MS=500
+# 与最早到达相差不到 TIE_TOLERANCE_US 微秒的端点记为同时接收，不计入首先接收或落后接收
+# export TIE_TOLERANCE_US=10
+# 预热: 开始后 WARMUP_SECS 秒内 (且至少 WARMUP_KEYS 个) 的数据不计入统计，单独报告首条消息耗时和初始积压;
+# 冷却: 结束前 COOLDOWN_SECS 秒内首次出现的数据不计入统计
+# export WARMUP_SECS=10
+# export WARMUP_KEYS=20
+# export COOLDOWN_SECS=2
+# slot 对齐: 端点落后最新slot超过 MAX_SLOT_DIFFERENCE 个区块时的处理方式 (仅 slot 比较)，
+# exclude 不再参与比较、readmit 追上后重新参与、flag 仍参与比较只做标记; 状态需持续 SLOT_WINDOW_SECS 秒才生效 (0 为立即)
+# 各端点落后的区块数在运行中持续输出，也可用于断言/告警，例如 "endpoint=Self_Node max_slot_lag<5"
+# export MAX_SLOT_DIFFERENCE=10
+# export SLOT_POLICY=readmit
+# export SLOT_WINDOW_SECS=5
+# 峰值吞吐 (条/秒、字节/秒) 的统计窗口，单位毫秒
+# export THROUGHPUT_WINDOWS_MS="1000,10000"
+# 记录每次到达的二进制轨迹，之后用 replay 重新分析: cargo run --bin replay -- arrivals.trace
+# export TRACE_FILE="arrivals.trace"
+# SLO 断言，多条用分号分隔; 任何一条未通过时退出码为 2
+# export ASSERT="endpoint=FzStream p99<5ms;first_share>=40%"
+# 运行结果保存到 SQLite 历史库，用 grpc-comparison history 子命令查看和对比 (设为空字符串禁用)
+# export HISTORY_DB="benchmark-history.db"
+# export REGION_LABEL="fra"
+
 RUST_LOG=info cargo run --bin grpc-vs-fzstream
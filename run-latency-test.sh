@@ -11,6 +11,8 @@ export GRPC_TOKEN=""
 export TOTAL_ROUNDS=10
 export PING_INTERVAL_MS=1000
 export TEST_TIMEOUT=120
+# 运行结果保存到 SQLite 历史库 (设为空字符串禁用)
+# export HISTORY_DB="benchmark-history.db"
 
 # 运行延迟测试
 echo "开始延迟测试..."
@@ -0,0 +1,1136 @@
+use crate::stats::{EndpointStats, EndpointStatsMap, Placement};
+use colored::*;
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tokio::time::interval;
+
+/// Arrivals closer than this to the earliest one count as ties rather than as delayed.
+pub const DEFAULT_TIE_TOLERANCE: Duration = Duration::from_micros(10);
+
+/// Identifies the same piece of chain data as seen by different endpoints.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum ArrivalKey {
+    Slot(u64),
+    /// Base58 transaction signature.
+    Signature(String),
+    /// One event inside a transaction, e.g. a PumpFun buy, or a transaction as seen by one protocol.
+    Event { signature: String, kind: String },
+}
+
+impl ArrivalKey {
+    pub fn slot(&self) -> Option<u64> {
+        match self {
+            ArrivalKey::Slot(slot) => Some(*slot),
+            ArrivalKey::Signature(_) | ArrivalKey::Event { .. } => None,
+        }
+    }
+
+    /// Group used for per-category breakdowns.
+    pub fn category(&self) -> Option<&str> {
+        match self {
+            ArrivalKey::Event { kind, .. } => Some(kind),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for ArrivalKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ArrivalKey::Slot(slot) => write!(f, "slot {}", slot),
+            ArrivalKey::Signature(signature) => write!(f, "tx {}", signature),
+            ArrivalKey::Event { signature, kind } => write!(f, "{} {}", kind, signature),
+        }
+    }
+}
+
+/// A single keyed message as timestamped by a stream task.
+#[derive(Debug, Clone)]
+pub struct Arrival {
+    pub endpoint: String,
+    pub key: ArrivalKey,
+    pub timestamp: Instant,
+    /// Time between frame poll and the decoded message, when stamped at the transport layer.
+    pub decode_time: Option<Duration>,
+}
+
+/// Messages accepted by the aggregator task.
+#[derive(Debug)]
+pub enum AggregatorMessage {
+    Arrival(Arrival),
+    EndpointFailed { endpoint: String, reason: String },
+    Shutdown,
+}
+
+/// A key that has been scored, with arrivals sorted earliest first.
+#[derive(Debug, Clone)]
+pub struct Resolution {
+    pub key: ArrivalKey,
+    pub arrivals: Vec<Arrival>,
+    pub timed_out: bool,
+    /// Active endpoints that had not delivered the key when it timed out.
+    pub missed: Vec<String>,
+    pub tie_tolerance: Duration,
+}
+
+impl Resolution {
+    pub fn first(&self) -> &Arrival {
+        &self.arrivals[0]
+    }
+
+    /// Latency of every arrival relative to the first one, in milliseconds.
+    pub fn relative_latencies(&self) -> impl Iterator<Item = (&Arrival, f64)> {
+        let first = self.arrivals[0].timestamp;
+        self.arrivals
+            .iter()
+            .map(move |a| (a, a.timestamp.duration_since(first).as_nanos() as f64 / 1_000_000.0))
+    }
+
+    /// Placement and latency in milliseconds of every arrival, earliest first.
+    pub fn placements(&self) -> impl Iterator<Item = (&Arrival, Placement, f64)> {
+        let first = self.arrivals[0].timestamp;
+        let tolerance = self.tie_tolerance;
+        // 第二个到达与第一个相差不到容差时，第一个也算同时到达
+        let contested = self.arrivals.get(1).is_some_and(|a| a.timestamp.duration_since(first) < tolerance);
+        self.relative_latencies().enumerate().map(move |(index, (arrival, latency))| {
+            let placement = if index == 0 && !contested {
+                Placement::First
+            } else if arrival.timestamp.duration_since(first) < tolerance {
+                Placement::Tied
+            } else {
+                Placement::Delayed
+            };
+            (arrival, placement, latency)
+        })
+    }
+
+    /// One log line per endpoint: its placement, or that it timed out.
+    pub fn log_lines(&self, width: usize) -> Vec<String> {
+        let first = self.first();
+        let mut lines: Vec<String> = self
+            .placements()
+            .map(|(arrival, placement, latency)| {
+                let outcome = match placement {
+                    Placement::First => "首次接收".to_string(),
+                    Placement::Tied => "同时接收".to_string(),
+                    Placement::Delayed => format!("延迟 {:>6.2}ms (相对于 {})", latency, first.endpoint),
+                };
+                format!("{:width$} 接收 {}: {}", arrival.endpoint, self.key, outcome, width = width)
+            })
+            .collect();
+        for endpoint in &self.missed {
+            lines.push(format!("{:width$} 接收 {}: 超时未收到", endpoint, self.key, width = width));
+        }
+        lines
+    }
+
+    /// Adds this resolution to `stats`, creating entries for endpoints not seen yet.
+    pub fn score(&self, stats: &mut EndpointStatsMap) {
+        for endpoint in &self.missed {
+            stats.entry(endpoint.clone()).or_default().increment_missed();
+        }
+        for (arrival, placement, latency) in self.placements() {
+            stats.entry(arrival.endpoint.clone()).or_default().record(placement, latency);
+        }
+    }
+}
+
+/// Everything the aggregator reports back to the binary driving it.
+#[derive(Debug, Clone)]
+pub enum AggregatorEvent {
+    /// The endpoint delivered its first message.
+    FirstArrival { endpoint: String, key: ArrivalKey },
+    /// The endpoint's latest slot stayed too far behind the tip and it is no longer scored.
+    Excluded { endpoint: String, slot: u64, tip: u64 },
+    /// Like `Excluded`, but under `SlotPolicy::Flag` the endpoint is still scored.
+    Flagged { endpoint: String, slot: u64, tip: u64 },
+    /// A lagging endpoint is back within the allowed slot difference. `readmitted` when it is
+    /// scored again under `SlotPolicy::Readmit`.
+    CaughtUp { endpoint: String, readmitted: bool },
+    /// Slots each endpoint was behind the previous tip when a new tip arrived.
+    SlotLag { tip: u64, behind: Vec<(String, u64)> },
+    /// Every active endpoint delivered data and scoring (or warm-up, if configured) has started.
+    Started { active: usize },
+    /// Warm-up is over; only sent when a warm-up is configured. `keys` were resolved during it.
+    Measuring { keys: u64 },
+    /// The endpoint stream failed and it was removed from the active set.
+    EndpointLost { endpoint: String, reason: String, remaining: usize },
+    Resolved(Resolution),
+}
+
+/// Turns `AggregatorEvent::SlotLag` samples into log lines for endpoints whose lag changed,
+/// so endpoints that stay in sync do not flood the log.
+#[derive(Debug, Default)]
+pub struct SlotLagLog {
+    previous: HashMap<String, u64>,
+}
+
+impl SlotLagLog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn changes(&mut self, tip: u64, behind: &[(String, u64)]) -> Vec<String> {
+        let mut lines = Vec::new();
+        for (endpoint, lag) in behind {
+            if self.previous.insert(endpoint.clone(), *lag).unwrap_or(0) == *lag {
+                continue;
+            }
+            if *lag == 0 {
+                lines.push(format!("{} 与最新slot ({}) 同步", endpoint, tip));
+            } else {
+                lines.push(format!("{} 落后最新slot ({}) {}个区块", endpoint, tip, lag));
+            }
+        }
+        lines
+    }
+}
+
+/// What happens to an endpoint whose latest slot falls too far behind the tip.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SlotPolicy {
+    /// Stop scoring it for the rest of the run.
+    #[default]
+    Exclude,
+    /// Stop scoring it until it catches up.
+    Readmit,
+    /// Keep scoring it and only report that it is lagging.
+    Flag,
+}
+
+impl SlotPolicy {
+    pub fn parse(s: &str) -> Result<Self, String> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "exclude" => Ok(SlotPolicy::Exclude),
+            "readmit" => Ok(SlotPolicy::Readmit),
+            "flag" => Ok(SlotPolicy::Flag),
+            other => Err(format!("unknown slot policy '{}', expected exclude, readmit or flag", other)),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            SlotPolicy::Exclude => "exclude",
+            SlotPolicy::Readmit => "readmit",
+            SlotPolicy::Flag => "flag",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct AggregatorConfig {
+    /// How long to wait for the remaining active endpoints after the first arrival of a key.
+    pub resolve_timeout: Duration,
+    /// Arrivals closer than this to the earliest one are ties.
+    pub tie_tolerance: Duration,
+    /// Largest allowed gap between an endpoint's latest slot and the tip.
+    pub max_slot_difference: u64,
+    pub slot_policy: SlotPolicy,
+    /// How long an endpoint has to stay beyond (or back within) `max_slot_difference` before the
+    /// policy acts. Zero acts at once, including on the first slots when scoring starts.
+    pub slot_window: Duration,
+    /// Score every resolution (and decode time) into the run-wide statistics. Callers that run
+    /// indefinitely and score the `Resolved` events themselves turn this off to keep memory flat.
+    pub keep_totals: bool,
+    /// Keys resolved this soon after the first resolved key belong to the warm-up: they are
+    /// scored into `WarmupReport::stats` and not sent as `Resolved` events.
+    pub warmup: Duration,
+    /// Warm-up also lasts until this many keys were resolved.
+    pub warmup_keys: u64,
+    /// Keys first seen this close to the deadline are neither scored nor sent.
+    pub cooldown: Duration,
+}
+
+impl Default for AggregatorConfig {
+    fn default() -> Self {
+        Self {
+            resolve_timeout: Duration::from_millis(500),
+            tie_tolerance: DEFAULT_TIE_TOLERANCE,
+            max_slot_difference: 10,
+            slot_policy: SlotPolicy::Exclude,
+            slot_window: Duration::ZERO,
+            keep_totals: true,
+            warmup: Duration::ZERO,
+            warmup_keys: 0,
+            cooldown: Duration::ZERO,
+        }
+    }
+}
+
+impl AggregatorConfig {
+    pub fn has_warmup(&self) -> bool {
+        !self.warmup.is_zero() || self.warmup_keys > 0
+    }
+}
+
+/// How one endpoint started up.
+#[derive(Debug, Clone, Default)]
+pub struct EndpointWarmup {
+    /// From the start of the run to the endpoint's first message.
+    pub time_to_first: Option<Duration>,
+    /// How many slots the first slot was behind the newest first slot of all endpoints.
+    pub first_slot_lag: Option<u64>,
+    /// Messages received before measurement started, including any initial backlog.
+    pub messages: u64,
+}
+
+/// Everything kept out of the run-wide statistics at the start and end of a run.
+#[derive(Debug, Clone, Default)]
+pub struct WarmupReport {
+    pub endpoints: HashMap<String, EndpointWarmup>,
+    /// Keys resolved during warm-up.
+    pub keys: u64,
+    /// Warm-up keys scored like the run-wide statistics.
+    pub stats: EndpointStatsMap,
+    /// From the start of the run to the first measured key; `None` if measurement never started.
+    pub measuring_after: Option<Duration>,
+    /// Keys first seen during the cool-down.
+    pub cooldown_keys: u64,
+    /// Keys first seen before scoring started, i.e. the connection-startup backlog.
+    pub backlog_keys: u64,
+}
+
+impl WarmupReport {
+    /// Prints time to first message, initial backlog and what warm-up and cool-down left out,
+    /// with `noun` naming the keys raced.
+    pub fn print_summary(&self, config: &AggregatorConfig, names: &[String], noun: &str, width: usize) {
+        println!("{}", "🔥 预热阶段 (不计入上面的统计)".yellow().bold());
+        println!("{}", "-".repeat(28).yellow());
+
+        match self.measuring_after {
+            Some(after) if config.has_warmup() => {
+                println!("预热 {} 个{}, 从开始到计入统计共 {:.1}秒", self.keys, noun, after.as_secs_f64())
+            }
+            Some(after) => println!("未设置预热, 从开始到计入统计共 {:.1}秒", after.as_secs_f64()),
+            None => println!("预热没有结束, 没有计入统计的{}", noun),
+        }
+        if self.backlog_keys > 0 {
+            println!("连接积压: 开始统计前首次出现的 {} 个{}不计入统计", self.backlog_keys, noun);
+        }
+        for name in names {
+            let Some(endpoint) = self.endpoints.get(name) else {
+                continue;
+            };
+            let first = match endpoint.time_to_first {
+                Some(time) => format!("{:.2}秒", time.as_secs_f64()),
+                None => "无".to_string(),
+            };
+            let mut line = format!(
+                "{:width$} : 首条消息 {}, 计入统计前 {} 条消息",
+                name,
+                first,
+                endpoint.messages,
+                width = width
+            );
+            if let Some(lag) = endpoint.first_slot_lag {
+                line.push_str(&format!(", 首个slot落后 {} 个区块", lag));
+            }
+            if let Some(stat) = self.stats.get(name).filter(|stat| stat.scored > 0) {
+                line.push_str(&format!(
+                    ", 预热期间首先接收 {:.2}%, 落后时平均延迟 {:.2}ms",
+                    stat.get_first_percentage(),
+                    stat.get_average_latency()
+                ));
+            }
+            println!("{}", line);
+        }
+        if !config.cooldown.is_zero() {
+            println!("冷却: 忽略最后 {:.1}秒 内首次出现的 {} 个{}", config.cooldown.as_secs_f64(), self.cooldown_keys, noun);
+        }
+    }
+}
+
+/// Sending side used by stream tasks. Cloning is cheap.
+#[derive(Debug, Clone)]
+pub struct AggregatorHandle {
+    tx: mpsc::UnboundedSender<AggregatorMessage>,
+}
+
+impl AggregatorHandle {
+    pub fn submit(&self, arrival: Arrival) {
+        let _ = self.tx.send(AggregatorMessage::Arrival(arrival));
+    }
+
+    pub fn arrival(&self, endpoint: &str, key: ArrivalKey, timestamp: Instant) {
+        let _ = self.tx.send(AggregatorMessage::Arrival(Arrival {
+            endpoint: endpoint.to_string(),
+            key,
+            timestamp,
+            decode_time: None,
+        }));
+    }
+
+    /// Reports a message stamped at frame poll time; the race uses `frame_at`.
+    pub fn stamped_arrival(&self, endpoint: &str, key: ArrivalKey, frame_at: Instant, decoded_at: Instant) {
+        let _ = self.tx.send(AggregatorMessage::Arrival(Arrival {
+            endpoint: endpoint.to_string(),
+            key,
+            timestamp: frame_at,
+            decode_time: Some(decoded_at.saturating_duration_since(frame_at)),
+        }));
+    }
+
+    pub fn endpoint_failed(&self, endpoint: &str, reason: impl Into<String>) {
+        let _ = self.tx.send(AggregatorMessage::EndpointFailed {
+            endpoint: endpoint.to_string(),
+            reason: reason.into(),
+        });
+    }
+
+    pub fn shutdown(&self) {
+        let _ = self.tx.send(AggregatorMessage::Shutdown);
+    }
+}
+
+#[derive(Debug, Default)]
+struct LagState {
+    lagging: bool,
+    /// Removed from the active set by the slot policy rather than by a failure.
+    excluded: bool,
+    /// Since when the samples disagree with `lagging`.
+    since: Option<Instant>,
+}
+
+struct PendingKey {
+    first_seen: Instant,
+    arrivals: Vec<Arrival>,
+}
+
+/// Single owner of all comparison state.
+///
+/// Stream tasks only timestamp messages and send them here, so bookkeeping for one
+/// endpoint can never delay the measurement of another.
+pub struct Aggregator {
+    config: AggregatorConfig,
+    origin: Instant,
+    deadline: Option<Instant>,
+    endpoints: Vec<String>,
+    stats: EndpointStatsMap,
+    active: HashSet<String>,
+    first_slots: HashMap<String, u64>,
+    latest_slots: HashMap<String, u64>,
+    tip: Option<u64>,
+    lag_states: HashMap<String, LagState>,
+    last_lag_sample: Option<Instant>,
+    /// When each endpoint delivered its first message.
+    first_arrivals: HashMap<String, Instant>,
+    started: bool,
+    started_at: Option<Instant>,
+    pending: HashMap<ArrivalKey, PendingKey>,
+    resolved: HashMap<ArrivalKey, Instant>,
+    /// Newest slot among resolved keys that `sweep` has forgotten; no slot up to it is raced again.
+    forgotten_slot: Option<u64>,
+    events: mpsc::UnboundedSender<AggregatorEvent>,
+    warmup: WarmupReport,
+    warmup_until: Option<Instant>,
+    measuring: bool,
+}
+
+impl Aggregator {
+    pub fn new(
+        endpoints: Vec<String>,
+        config: AggregatorConfig,
+        events: mpsc::UnboundedSender<AggregatorEvent>,
+    ) -> Self {
+        let stats = endpoints
+            .iter()
+            .map(|name| (name.clone(), EndpointStats::new()))
+            .collect();
+        let active = endpoints.iter().cloned().collect();
+        let warmup = WarmupReport {
+            endpoints: endpoints.iter().map(|name| (name.clone(), EndpointWarmup::default())).collect(),
+            ..Default::default()
+        };
+
+        Self {
+            config,
+            origin: Instant::now(),
+            deadline: None,
+            endpoints,
+            stats,
+            active,
+            first_slots: HashMap::new(),
+            latest_slots: HashMap::new(),
+            tip: None,
+            lag_states: HashMap::new(),
+            last_lag_sample: None,
+            first_arrivals: HashMap::new(),
+            started: false,
+            started_at: None,
+            pending: HashMap::new(),
+            resolved: HashMap::new(),
+            forgotten_slot: None,
+            events,
+            warmup,
+            warmup_until: None,
+            measuring: false,
+        }
+    }
+
+    /// Start of the run, for `EndpointWarmup::time_to_first`. Defaults to when the aggregator was created.
+    pub fn with_origin(mut self, origin: Instant) -> Self {
+        self.origin = origin;
+        self
+    }
+
+    /// End of the run, for the cool-down.
+    pub fn with_deadline(mut self, deadline: Instant) -> Self {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    /// Spawns the aggregator task for a run from `origin` to `deadline`. The task returns the
+    /// final statistics after `AggregatorHandle::shutdown` or once every handle has been dropped.
+    pub fn spawn(
+        endpoints: Vec<String>,
+        config: AggregatorConfig,
+        origin: Instant,
+        deadline: Instant,
+    ) -> (AggregatorHandle, mpsc::UnboundedReceiver<AggregatorEvent>, JoinHandle<(EndpointStatsMap, WarmupReport)>) {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let (events_tx, events_rx) = mpsc::unbounded_channel();
+        let aggregator = Self::new(endpoints, config, events_tx).with_origin(origin).with_deadline(deadline);
+        let task = tokio::spawn(aggregator.run(rx));
+        (AggregatorHandle { tx }, events_rx, task)
+    }
+
+    pub async fn run(mut self, mut rx: mpsc::UnboundedReceiver<AggregatorMessage>) -> (EndpointStatsMap, WarmupReport) {
+        let sweep_period = (self.config.resolve_timeout / 4).max(Duration::from_millis(1));
+        let mut sweep = interval(sweep_period);
+
+        loop {
+            tokio::select! {
+                message = rx.recv() => match message {
+                    Some(AggregatorMessage::Arrival(arrival)) => self.on_arrival(arrival),
+                    Some(AggregatorMessage::EndpointFailed { endpoint, reason }) => {
+                        self.on_endpoint_failed(&endpoint, reason)
+                    }
+                    Some(AggregatorMessage::Shutdown) | None => break,
+                },
+                _ = sweep.tick() => self.sweep(Instant::now()),
+            }
+        }
+
+        (self.stats, self.warmup)
+    }
+
+    pub fn stats(&self) -> &EndpointStatsMap {
+        &self.stats
+    }
+
+    pub fn warmup(&self) -> &WarmupReport {
+        &self.warmup
+    }
+
+    pub fn on_arrival(&mut self, arrival: Arrival) {
+        let Some(stat) = self.stats.get_mut(&arrival.endpoint) else {
+            return;
+        };
+        stat.increment_received();
+        // 已解决的 key 同样推进端点的最新 slot，落后的端点正是在解决之后才收到
+        if let Some(slot) = arrival.key.slot() {
+            self.on_slot(&arrival.endpoint, slot, arrival.timestamp);
+        }
+        if self.resolved.contains_key(&arrival.key) {
+            return;
+        }
+        // 落后的端点在 key 被清理之后才收到时不能再作为新 key，否则它会被记为首先收到、其余端点错过
+        if arrival.key.slot().is_some_and(|slot| self.forgotten_slot.is_some_and(|forgotten| slot <= forgotten)) {
+            return;
+        }
+
+        if !self.measuring {
+            if let Some(warmup) = self.warmup.endpoints.get_mut(&arrival.endpoint) {
+                warmup.messages += 1;
+            }
+        }
+        if !self.stats[&arrival.endpoint].has_received_data {
+            self.on_first_arrival(&arrival);
+        }
+        if let (Some(decode_time), Some(stat)) = (arrival.decode_time, self.stats.get_mut(&arrival.endpoint)) {
+            if self.config.keep_totals && self.measuring {
+                stat.add_decode_time(decode_time.as_secs_f64() * 1000.0);
+            }
+        }
+
+        if !self.active.contains(&arrival.endpoint) {
+            return;
+        }
+
+        let key = arrival.key.clone();
+        let pending = self.pending.entry(key.clone()).or_insert_with(|| PendingKey {
+            first_seen: arrival.timestamp,
+            arrivals: Vec::new(),
+        });
+        // 同一端点对同一个 key 只记录第一次到达
+        if pending.arrivals.iter().any(|a| a.endpoint == arrival.endpoint) {
+            return;
+        }
+        pending.arrivals.push(arrival);
+
+        if self.started && self.is_complete(&key) {
+            self.resolve(key, false);
+        }
+    }
+
+    pub fn on_endpoint_failed(&mut self, endpoint: &str, reason: String) {
+        if let Some(stat) = self.stats.get_mut(endpoint) {
+            stat.is_available = false;
+        }
+        self.lag_states.remove(endpoint);
+        if !self.active.remove(endpoint) {
+            return;
+        }
+
+        let _ = self.events.send(AggregatorEvent::EndpointLost {
+            endpoint: endpoint.to_string(),
+            reason,
+            remaining: self.active.len(),
+        });
+
+        if !self.started {
+            self.try_start(Instant::now());
+            return;
+        }
+
+        self.resolve_complete();
+    }
+
+    // 活跃端点减少后，部分 key 可能已经收齐
+    fn resolve_complete(&mut self) {
+        let complete: Vec<_> = self
+            .pending
+            .keys()
+            .filter(|key| self.is_complete(key))
+            .cloned()
+            .collect();
+        for key in complete {
+            self.resolve(key, false);
+        }
+    }
+
+    /// Resolves keys whose timeout expired and forgets old resolved keys.
+    pub fn sweep(&mut self, now: Instant) {
+        if self.started {
+            let expired: Vec<_> = self
+                .pending
+                .iter()
+                .filter(|(_, p)| now.saturating_duration_since(p.first_seen) >= self.config.resolve_timeout)
+                .map(|(key, _)| key.clone())
+                .collect();
+            for key in expired {
+                self.resolve(key, true);
+            }
+        }
+
+        let retention = self.config.resolve_timeout * 20;
+        let mut forgotten = self.forgotten_slot;
+        self.resolved.retain(|key, resolved_at| {
+            let keep = now.saturating_duration_since(*resolved_at) < retention;
+            if !keep {
+                forgotten = forgotten.max(key.slot());
+            }
+            keep
+        });
+        self.forgotten_slot = forgotten;
+    }
+
+    fn on_first_arrival(&mut self, arrival: &Arrival) {
+        if let Some(stat) = self.stats.get_mut(&arrival.endpoint) {
+            stat.has_received_data = true;
+            stat.first_slot = arrival.key.slot();
+        }
+        self.first_arrivals.insert(arrival.endpoint.clone(), arrival.timestamp);
+        if let Some(slot) = arrival.key.slot() {
+            self.first_slots.insert(arrival.endpoint.clone(), slot);
+        }
+        if let Some(warmup) = self.warmup.endpoints.get_mut(&arrival.endpoint) {
+            warmup.time_to_first = Some(arrival.timestamp.saturating_duration_since(self.origin));
+        }
+
+        let _ = self.events.send(AggregatorEvent::FirstArrival {
+            endpoint: arrival.endpoint.clone(),
+            key: arrival.key.clone(),
+        });
+
+        if !self.started {
+            self.try_start(arrival.timestamp);
+        }
+    }
+
+    fn try_start(&mut self, now: Instant) {
+        let received = self
+            .active
+            .iter()
+            .filter(|name| self.stats[*name].has_received_data)
+            .count();
+        if received < self.active.len() || received < 2 {
+            return;
+        }
+
+        if self.check_slots_alignment(now) {
+            self.started = true;
+            self.started_at = Some(now);
+            let _ = self.events.send(AggregatorEvent::Started { active: self.active.len() });
+        }
+    }
+
+    fn check_slots_alignment(&mut self, now: Instant) -> bool {
+        let Some(max_first) = self.first_slots.values().max().copied() else {
+            return true;
+        };
+        for (endpoint, slot) in &self.first_slots {
+            if let Some(warmup) = self.warmup.endpoints.get_mut(endpoint) {
+                warmup.first_slot_lag.get_or_insert(max_first.saturating_sub(*slot));
+            }
+        }
+
+        let tip = self.tip.unwrap_or(max_first);
+        let mut active: Vec<_> = self.active.iter().cloned().collect();
+        active.sort();
+        for endpoint in active {
+            if let Some(slot) = self.latest_slots.get(&endpoint).copied() {
+                self.apply_slot_policy(&endpoint, slot, tip, now);
+            }
+        }
+
+        self.active.len() >= 2
+    }
+
+    // 记录每个端点最新的 slot; 最新 slot (tip) 前进时，按前一个 tip 采样各端点落后的区块数
+    fn on_slot(&mut self, endpoint: &str, slot: u64, at: Instant) {
+        let latest = self.latest_slots.entry(endpoint.to_string()).or_insert(slot);
+        *latest = (*latest).max(slot);
+        let previous = match self.tip {
+            Some(tip) if slot <= tip => return,
+            previous => previous,
+        };
+        self.tip = Some(slot);
+        if let Some(tip) = previous {
+            self.sample_slot_lags(tip, at);
+        }
+    }
+
+    fn sample_slot_lags(&mut self, tip: u64, at: Instant) {
+        let mut behind: Vec<(String, u64)> = self
+            .latest_slots
+            .iter()
+            .filter(|(name, _)| self.active.contains(*name) || self.lag_states.get(*name).is_some_and(|s| s.excluded))
+            .map(|(name, slot)| (name.clone(), tip.saturating_sub(*slot)))
+            .collect();
+        behind.sort();
+        let elapsed = self.last_lag_sample.map(|last| at.saturating_duration_since(last)).unwrap_or_default();
+        self.last_lag_sample = Some(at);
+
+        for (endpoint, lag) in &behind {
+            let lagging = self.lag_states.get(endpoint).is_some_and(|s| s.lagging);
+            if let Some(stat) = self.stats.get_mut(endpoint) {
+                stat.slot_lag.add(*lag);
+                if lagging {
+                    stat.slot_lag.lagging_for += elapsed;
+                }
+            }
+            // 开始统计之前由 check_slots_alignment 统一判断
+            if self.started {
+                self.apply_slot_policy(endpoint, tip.saturating_sub(*lag), tip, at);
+            }
+        }
+        let _ = self.events.send(AggregatorEvent::SlotLag { tip, behind });
+    }
+
+    // 落后 (或追上) 的状态持续 slot_window 之后才按策略排除、标记或重新纳入统计
+    fn apply_slot_policy(&mut self, endpoint: &str, slot: u64, tip: u64, now: Instant) {
+        let lagging = tip.saturating_sub(slot) > self.config.max_slot_difference;
+        let state = self.lag_states.entry(endpoint.to_string()).or_default();
+        if lagging == state.lagging || (state.excluded && self.config.slot_policy == SlotPolicy::Exclude) {
+            state.since = None;
+            return;
+        }
+        let since = *state.since.get_or_insert(now);
+        if now.saturating_duration_since(since) < self.config.slot_window {
+            return;
+        }
+        state.lagging = lagging;
+        state.since = None;
+
+        let endpoint = endpoint.to_string();
+        if !lagging {
+            let readmitted = std::mem::take(&mut state.excluded);
+            if readmitted {
+                self.active.insert(endpoint.clone());
+                if let Some(stat) = self.stats.get_mut(&endpoint) {
+                    stat.is_available = true;
+                }
+            }
+            let _ = self.events.send(AggregatorEvent::CaughtUp { endpoint, readmitted });
+            if readmitted && !self.started {
+                self.try_start(now);
+            }
+        } else if self.config.slot_policy == SlotPolicy::Flag {
+            let _ = self.events.send(AggregatorEvent::Flagged { endpoint, slot, tip });
+        } else {
+            state.excluded = true;
+            self.active.remove(&endpoint);
+            if let Some(stat) = self.stats.get_mut(&endpoint) {
+                stat.is_available = false;
+            }
+            let _ = self.events.send(AggregatorEvent::Excluded { endpoint, slot, tip });
+            if self.started {
+                self.resolve_complete();
+            }
+        }
+    }
+
+    fn is_complete(&self, key: &ArrivalKey) -> bool {
+        self.pending.get(key).is_some_and(|p| {
+            self.active
+                .iter()
+                .all(|name| p.arrivals.iter().any(|a| &a.endpoint == name))
+        })
+    }
+
+    fn resolve(&mut self, key: ArrivalKey, timed_out: bool) {
+        let Some(pending) = self.pending.remove(&key) else {
+            return;
+        };
+        // 以最后一次到达作为解决时间而不是当前时间，回放轨迹时的保留窗口与实时运行一致
+        let resolved_at = pending.arrivals.iter().map(|a| a.timestamp).max().unwrap_or(pending.first_seen);
+        self.resolved.insert(key.clone(), resolved_at);
+        // 开始统计前首次出现的 key 属于连接建立时的积压，先连上的端点总是"首先"收到
+        if self.started_at.is_none_or(|started_at| pending.first_seen < started_at) {
+            self.warmup.backlog_keys += 1;
+            return;
+        }
+
+        let mut arrivals: Vec<_> = pending
+            .arrivals
+            .into_iter()
+            .filter(|a| self.active.contains(&a.endpoint))
+            .collect();
+        // 超时后按收到的端点计分，key 首次出现时已经在收数据的其余活跃端点记为错过;
+        // 只剩一个活跃端点时无从比较
+        let mut missed: Vec<_> = self
+            .active
+            .iter()
+            .filter(|name| !arrivals.iter().any(|a| &a.endpoint == *name))
+            .filter(|name| self.first_arrivals.get(*name).is_some_and(|at| *at <= pending.first_seen))
+            .cloned()
+            .collect();
+        missed.sort();
+        if arrivals.is_empty() || arrivals.len() + missed.len() < 2 {
+            return;
+        }
+
+        // 按时间戳排序，确保稳定的排序结果
+        arrivals.sort_by(|a, b| {
+            a.timestamp.cmp(&b.timestamp).then_with(|| a.endpoint.cmp(&b.endpoint))
+        });
+
+        let resolution = Resolution { key, arrivals, timed_out, missed, tie_tolerance: self.config.tie_tolerance };
+        let first_seen = resolution.first().timestamp;
+        if !self.measuring {
+            let warmup_until = *self.warmup_until.get_or_insert(first_seen + self.config.warmup);
+            if first_seen < warmup_until || self.warmup.keys < self.config.warmup_keys {
+                self.warmup.keys += 1;
+                if self.config.keep_totals {
+                    resolution.score(&mut self.warmup.stats);
+                }
+                return;
+            }
+            self.measuring = true;
+            self.warmup.measuring_after = Some(first_seen.saturating_duration_since(self.origin));
+            if self.config.has_warmup() {
+                let _ = self.events.send(AggregatorEvent::Measuring { keys: self.warmup.keys });
+            }
+        }
+        if let Some(deadline) = self.deadline {
+            if !self.config.cooldown.is_zero() && first_seen + self.config.cooldown >= deadline {
+                self.warmup.cooldown_keys += 1;
+                return;
+            }
+        }
+
+        if self.config.keep_totals {
+            resolution.score(&mut self.stats);
+        }
+
+        let _ = self.events.send(AggregatorEvent::Resolved(resolution));
+    }
+
+    pub fn endpoints(&self) -> &[String] {
+        &self.endpoints
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn aggregator(names: &[&str]) -> (Aggregator, mpsc::UnboundedReceiver<AggregatorEvent>) {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let endpoints = names.iter().map(|s| s.to_string()).collect();
+        (Aggregator::new(endpoints, AggregatorConfig::default(), tx), rx)
+    }
+
+    fn arrival(endpoint: &str, slot: u64, at: Instant) -> Arrival {
+        Arrival { endpoint: endpoint.to_string(), key: ArrivalKey::Slot(slot), timestamp: at, decode_time: None }
+    }
+
+    #[test]
+    fn test_resolves_when_all_endpoints_reported() {
+        let (mut agg, _rx) = aggregator(&["a", "b"]);
+        let t0 = Instant::now();
+
+        agg.on_arrival(arrival("a", 100, t0));
+        agg.on_arrival(arrival("b", 100, t0 + Duration::from_millis(3)));
+        assert!(agg.started);
+
+        agg.on_arrival(arrival("b", 101, t0 + Duration::from_millis(10)));
+        agg.on_arrival(arrival("a", 101, t0 + Duration::from_millis(12)));
+        agg.on_arrival(arrival("a", 102, t0 + Duration::from_millis(20)));
+        agg.on_arrival(arrival("b", 102, t0 + Duration::from_millis(23)));
+
+        let a = &agg.stats()["a"];
+        let b = &agg.stats()["b"];
+        // 100 在两个端点都连上之前首次出现，不计分
+        assert_eq!(agg.warmup().backlog_keys, 1);
+        assert_eq!(a.scored, 2);
+        assert_eq!(a.first, 1);
+        assert_eq!(b.first, 1);
+        assert_eq!(a.latencies, vec![2.0]);
+        assert_eq!(b.latencies, vec![3.0]);
+    }
+
+    #[test]
+    fn test_excludes_lagging_endpoint() {
+        let (mut agg, _rx) = aggregator(&["a", "b", "c"]);
+        let t0 = Instant::now();
+
+        agg.on_arrival(arrival("a", 100, t0));
+        agg.on_arrival(arrival("b", 100, t0));
+        agg.on_arrival(arrival("c", 50, t0));
+
+        assert!(agg.started);
+        assert!(!agg.stats()["c"].is_available);
+        assert!(!agg.active.contains("c"));
+    }
+
+    #[test]
+    fn test_lagging_endpoint_is_readmitted_after_catching_up() {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let t0 = Instant::now();
+        let ms = |n: u64| t0 + Duration::from_millis(n);
+        let config = AggregatorConfig {
+            max_slot_difference: 2,
+            slot_policy: SlotPolicy::Readmit,
+            slot_window: Duration::from_millis(100),
+            ..Default::default()
+        };
+        let mut agg = Aggregator::new(vec!["a".to_string(), "b".to_string()], config, tx);
+
+        agg.on_arrival(arrival("a", 100, ms(0)));
+        agg.on_arrival(arrival("b", 100, ms(1)));
+        // b 停在 100, 在 115 时一次性补齐，之后与 a 同步
+        for slot in 101..=130 {
+            let at = (slot - 100) * 10;
+            agg.on_arrival(arrival("a", slot, ms(at)));
+            if slot == 115 {
+                for backlog in 101..=115 {
+                    agg.on_arrival(arrival("b", backlog, ms(at + 1)));
+                }
+            } else if slot > 115 {
+                agg.on_arrival(arrival("b", slot, ms(at + 1)));
+            }
+        }
+
+        let events: Vec<_> = std::iter::from_fn(|| rx.try_recv().ok()).collect();
+        assert!(events.iter().any(|e| matches!(e, AggregatorEvent::Excluded { slot: 100, tip: 113, .. })));
+        assert!(events.iter().any(|e| matches!(e, AggregatorEvent::CaughtUp { readmitted: true, .. })));
+        let b = &agg.stats()["b"];
+        assert!(b.is_available && agg.active.contains("b"));
+        assert_eq!((b.slot_lag.max, b.slot_lag.lagging_for), (14, Duration::from_millis(120)));
+        assert_eq!(b.scored, 5);
+    }
+
+    #[test]
+    fn test_failed_endpoint_completes_pending_keys() {
+        let (mut agg, _rx) = aggregator(&["a", "b", "c"]);
+        let t0 = Instant::now();
+
+        for name in ["a", "b", "c"] {
+            agg.on_arrival(arrival(name, 1, t0));
+        }
+        agg.on_arrival(arrival("a", 2, t0));
+        agg.on_arrival(arrival("b", 2, t0 + Duration::from_millis(1)));
+        assert!(agg.pending.contains_key(&ArrivalKey::Slot(2)));
+
+        agg.on_endpoint_failed("c", "stream closed".to_string());
+        assert!(!agg.pending.contains_key(&ArrivalKey::Slot(2)));
+        assert_eq!(agg.stats()["b"].latencies, vec![1.0]);
+    }
+
+    #[test]
+    fn test_timed_out_key_scores_receivers_and_counts_misses() {
+        let (mut agg, mut rx) = aggregator(&["a", "b", "c"]);
+        let t0 = Instant::now();
+
+        for name in ["a", "b", "c"] {
+            agg.on_arrival(arrival(name, 1, t0));
+        }
+        agg.on_arrival(arrival("b", 2, t0 + Duration::from_millis(10)));
+        agg.on_arrival(arrival("a", 2, t0 + Duration::from_millis(12)));
+        agg.on_arrival(arrival("c", 3, t0 + Duration::from_millis(20)));
+        agg.sweep(t0 + Duration::from_secs(1));
+        // 超时之后才到达的不再计分
+        agg.on_arrival(arrival("c", 2, t0 + Duration::from_secs(2)));
+
+        let (a, b, c) = (&agg.stats()["a"], &agg.stats()["b"], &agg.stats()["c"]);
+        assert_eq!((a.scored, a.missed), (2, 1));
+        assert_eq!((b.first, b.missed), (1, 1));
+        assert_eq!((c.scored, c.first, c.missed), (2, 1, 1));
+        assert_eq!(a.latencies, vec![2.0]);
+        assert!((c.get_miss_rate() - 100.0 / 3.0).abs() < 1e-9);
+
+        let missed: Vec<_> = std::iter::from_fn(|| rx.try_recv().ok())
+            .filter_map(|e| match e {
+                AggregatorEvent::Resolved(r) if r.timed_out => Some(r.missed),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(missed.len(), 2);
+        assert!(missed.contains(&vec!["c".to_string()]) && missed.contains(&vec!["a".to_string(), "b".to_string()]));
+    }
+
+    #[test]
+    fn test_arrival_after_retention_is_not_raced_again() {
+        let (tx, _rx) = mpsc::unbounded_channel();
+        let t0 = Instant::now();
+        let ms = |n| t0 + Duration::from_millis(n);
+        let config = AggregatorConfig { slot_policy: SlotPolicy::Flag, ..Default::default() };
+        let mut agg = Aggregator::new(vec!["a".to_string(), "b".to_string()], config, tx);
+
+        agg.on_arrival(arrival("a", 1, ms(0)));
+        agg.on_arrival(arrival("b", 1, ms(1)));
+        agg.on_arrival(arrival("a", 2, ms(10)));
+        agg.sweep(ms(1000));
+        // 2 的记录已被清理，b 此时才收到也不能单独成为新 key
+        agg.sweep(ms(20_000));
+        agg.on_arrival(arrival("b", 2, ms(20_001)));
+        agg.sweep(ms(21_000));
+
+        let (a, b) = (&agg.stats()["a"], &agg.stats()["b"]);
+        assert!(agg.pending.is_empty());
+        assert_eq!((a.scored, a.first, a.missed), (1, 1, 0));
+        assert_eq!((b.scored, b.first, b.missed), (0, 0, 1));
+        assert_eq!(b.received, 2);
+    }
+
+    #[test]
+    fn test_log_lines_cover_every_endpoint() {
+        let t0 = Instant::now();
+        let resolution = Resolution {
+            key: ArrivalKey::Slot(7),
+            arrivals: vec![arrival("a", 7, t0), arrival("b", 7, t0 + Duration::from_millis(2))],
+            timed_out: true,
+            missed: vec!["c".to_string()],
+            tie_tolerance: DEFAULT_TIE_TOLERANCE,
+        };
+        let lines = resolution.log_lines(1);
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].ends_with(": 首次接收"));
+        assert!(lines[1].starts_with("b 接收") && lines[1].contains("延迟   2.00ms (相对于 a)"));
+        assert!(lines[2].starts_with("c 接收") && lines[2].ends_with(": 超时未收到"));
+    }
+
+    #[test]
+    fn test_startup_backlog_is_not_scored() {
+        let (mut agg, _rx) = aggregator(&["a", "b", "c"]);
+        let t0 = Instant::now();
+        let ms = |n| t0 + Duration::from_millis(n);
+
+        // a 先连上，1 和 2 在 c 连上 (开始统计) 之前首次出现
+        agg.on_arrival(arrival("a", 1, ms(0)));
+        agg.on_arrival(arrival("b", 2, ms(5)));
+        agg.on_arrival(arrival("c", 2, ms(6)));
+        assert!(agg.started);
+        agg.on_arrival(arrival("b", 3, ms(10)));
+        agg.on_arrival(arrival("a", 3, ms(11)));
+        agg.sweep(ms(1000));
+
+        let (a, b, c) = (&agg.stats()["a"], &agg.stats()["b"], &agg.stats()["c"]);
+        assert_eq!(agg.warmup().backlog_keys, 2);
+        assert_eq!((a.scored, a.first, a.missed), (1, 0, 0));
+        assert_eq!((b.scored, b.first, b.missed), (1, 1, 0));
+        assert_eq!((c.scored, c.missed), (0, 1));
+    }
+
+    #[test]
+    fn test_arrivals_within_tolerance_are_tied() {
+        let (mut agg, _rx) = aggregator(&["a", "b", "c"]);
+        let t0 = Instant::now();
+        let us = |n| t0 + Duration::from_micros(n);
+
+        for name in ["a", "b", "c"] {
+            agg.on_arrival(arrival(name, 0, t0));
+        }
+        // slot 1: a 与 b 相差 5µs 同时到达; slot 2: a 领先 20µs; slot 3: c 领先, b 与 c 相差 8µs
+        for (slot, a_at, b_at, c_at) in [(1, 0, 5, 500), (2, 1000, 1020, 1030), (3, 2100, 2008, 2000)] {
+            agg.on_arrival(arrival("a", slot, us(a_at)));
+            agg.on_arrival(arrival("b", slot, us(b_at)));
+            agg.on_arrival(arrival("c", slot, us(c_at)));
+        }
+        // 已经计分的 key 再次到达只算接收
+        agg.on_arrival(arrival("a", 1, us(3000)));
+
+        let (a, b, c) = (&agg.stats()["a"], &agg.stats()["b"], &agg.stats()["c"]);
+        assert_eq!((a.received, a.scored), (5, 4));
+        assert_eq!((a.first, a.tied, a.delayed), (1, 2, 1));
+        assert_eq!((b.first, b.tied, b.delayed), (0, 3, 1));
+        assert_eq!((c.first, c.tied, c.delayed), (0, 2, 2));
+        for stat in [a, b, c] {
+            assert_eq!(stat.first + stat.tied + stat.delayed, stat.scored);
+        }
+        assert_eq!(b.latencies, vec![0.02]);
+        assert!((a.get_first_percentage() - 25.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_warmup_and_cooldown_are_not_scored() {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let t0 = Instant::now();
+        let ms = |n| t0 + Duration::from_millis(n);
+        let config = AggregatorConfig {
+            warmup: Duration::from_millis(100),
+            warmup_keys: 2,
+            cooldown: Duration::from_millis(50),
+            ..Default::default()
+        };
+        let mut agg = Aggregator::new(vec!["a".to_string(), "b".to_string()], config, tx)
+            .with_origin(t0)
+            .with_deadline(ms(1000));
+
+        agg.on_arrival(arrival("b", 99, ms(4)));
+        agg.on_arrival(arrival("a", 100, ms(5)));
+        // 100 在预热时间内, 101 超过预热时间但预热 key 数未满, 102 开始正式统计, 103 在冷却期内
+        for (slot, a_at, b_at) in [(100, 5, 8), (101, 150, 152), (102, 200, 201), (103, 961, 960)] {
+            if slot != 100 {
+                agg.on_arrival(arrival("a", slot, ms(a_at)));
+            }
+            agg.on_arrival(arrival("b", slot, ms(b_at)));
+        }
+
+        let warmup = agg.warmup();
+        assert_eq!((warmup.keys, warmup.cooldown_keys), (2, 1));
+        assert_eq!(warmup.stats["b"].latencies, vec![3.0, 2.0]);
+        assert_eq!(warmup.measuring_after, Some(Duration::from_millis(200)));
+        assert_eq!(warmup.endpoints["a"].time_to_first, Some(Duration::from_millis(5)));
+        assert_eq!(warmup.endpoints["b"].first_slot_lag, Some(1));
+        assert_eq!((warmup.endpoints["a"].messages, warmup.endpoints["b"].messages), (3, 4));
+        assert_eq!(agg.stats()["a"].scored, 1);
+
+        let measuring = std::iter::from_fn(|| rx.try_recv().ok()).find(|e| matches!(e, AggregatorEvent::Measuring { .. }));
+        assert!(matches!(measuring, Some(AggregatorEvent::Measuring { keys: 2 })));
+    }
+}
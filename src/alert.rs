@@ -0,0 +1,406 @@
+use crate::error::{BenchmarkError, Result};
+use crate::slo::{evaluate, Assertion, Metrics};
+use crate::source::HealthSnapshot;
+use crate::window::WindowReport;
+use chrono::{Local, SecondsFormat};
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tracing::warn;
+
+/// How long a webhook or exec sink may take before the delivery counts as failed.
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A condition that should hold while monitoring. An alert fires when it stops holding and
+/// resolves once it holds again.
+#[derive(Debug, Clone, PartialEq)]
+pub enum AlertRule {
+    /// `[endpoint=NAME] disconnected<30s`: no endpoint may stay disconnected this long.
+    Disconnected { text: String, endpoint: Option<String>, after: Duration },
+    /// Any SLO assertion, e.g. `p99<20ms` or `first_share>=40%`, checked on every closed window.
+    Window(Assertion),
+}
+
+impl AlertRule {
+    /// Usable directly as a clap `value_parser`.
+    pub fn parse(text: &str) -> std::result::Result<Self, String> {
+        let mut endpoint = None;
+        let mut condition = String::new();
+        for token in text.split_whitespace() {
+            match token.strip_prefix("endpoint=") {
+                Some(name) if !name.is_empty() => endpoint = Some(name.to_string()),
+                Some(_) => return Err(format!("empty endpoint name in '{}'", text)),
+                None => condition.push_str(token),
+            }
+        }
+
+        let Some(rest) = condition.strip_prefix("disconnected") else {
+            return Assertion::parse(text).map(AlertRule::Window);
+        };
+        let value = rest
+            .strip_prefix('<')
+            .ok_or_else(|| format!("expected disconnected<DURATION in '{}'", text))?;
+        let split = value.find(|c: char| !(c.is_ascii_digit() || c == '.')).unwrap_or(value.len());
+        let (number, suffix) = value.split_at(split);
+        let number: f64 = number.parse().map_err(|_| format!("invalid number '{}' in '{}'", number, text))?;
+        let seconds = match suffix {
+            "ms" => number / 1000.0,
+            "" | "s" => number,
+            "m" => number * 60.0,
+            _ => return Err(format!("unit '{}' is not a duration in '{}'", suffix, text)),
+        };
+        Ok(AlertRule::Disconnected { text: text.trim().to_string(), endpoint, after: Duration::from_secs_f64(seconds) })
+    }
+
+    /// The endpoint the rule is limited to, if any.
+    pub fn endpoint(&self) -> Option<&str> {
+        match self {
+            AlertRule::Disconnected { endpoint, .. } => endpoint.as_deref(),
+            AlertRule::Window(assertion) => assertion.endpoint.as_deref(),
+        }
+    }
+}
+
+impl fmt::Display for AlertRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AlertRule::Disconnected { text, .. } => f.write_str(text),
+            AlertRule::Window(assertion) => assertion.fmt(f),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AlertStatus {
+    Firing,
+    Resolved,
+}
+
+impl AlertStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            AlertStatus::Firing => "firing",
+            AlertStatus::Resolved => "resolved",
+        }
+    }
+}
+
+/// Payload delivered to every sink.
+#[derive(Debug, Clone, Serialize)]
+pub struct Alert {
+    pub status: AlertStatus,
+    pub rule: String,
+    pub endpoint: Option<String>,
+    /// Human-readable, for chat webhooks and logs.
+    pub message: String,
+    /// RFC 3339, local time.
+    pub at: String,
+}
+
+/// Tracks which rules are violated and reports only the changes: one `Firing` alert when a
+/// rule starts failing for an endpoint and one `Resolved` alert when it recovers.
+#[derive(Debug)]
+pub struct AlertMonitor {
+    rules: Vec<AlertRule>,
+    /// Rule index and endpoint of every firing alert.
+    firing: HashSet<(usize, Option<String>)>,
+    disconnected_since: HashMap<String, Instant>,
+}
+
+impl AlertMonitor {
+    pub fn new(rules: Vec<AlertRule>) -> Self {
+        Self { rules, firing: HashSet::new(), disconnected_since: HashMap::new() }
+    }
+
+    pub fn rules(&self) -> &[AlertRule] {
+        &self.rules
+    }
+
+    /// Checks the disconnection rules; call about once a second. Endpoints that never
+    /// connected count as disconnected since the first call.
+    pub fn check_health(&mut self, now: Instant, health: &[(String, HealthSnapshot)]) -> Vec<Alert> {
+        for (name, snapshot) in health {
+            if snapshot.connected {
+                self.disconnected_since.remove(name);
+            } else {
+                self.disconnected_since.entry(name.clone()).or_insert(now);
+            }
+        }
+
+        let mut changes = Vec::new();
+        for (index, rule) in self.rules.iter().enumerate() {
+            let AlertRule::Disconnected { after, .. } = rule else {
+                continue;
+            };
+            for (name, _) in health.iter().filter(|(name, _)| rule.endpoint().is_none_or(|e| e == name)) {
+                let down_for = self.disconnected_since.get(name).map(|since| now.duration_since(*since));
+                let violated = down_for.is_some_and(|down_for| down_for >= *after);
+                let message = match down_for {
+                    Some(down_for) if violated => format!("{}: {} 已断开 {:.0}秒", rule, name, down_for.as_secs_f64()),
+                    _ => format!("{}: {} 已恢复连接", rule, name),
+                };
+                changes.push((index, Some(name.clone()), violated, message));
+            }
+        }
+        self.apply(changes)
+    }
+
+    /// Checks the window rules against a closed window. Endpoints without data for a metric
+    /// keep their alert state.
+    pub fn check_window(&mut self, window: &WindowReport) -> Vec<Alert> {
+        let metrics = Metrics::from_window(window);
+        let mut changes = Vec::new();
+        for (index, rule) in self.rules.iter().enumerate() {
+            let AlertRule::Window(assertion) = rule else {
+                continue;
+            };
+            for outcome in evaluate(std::slice::from_ref(assertion), &metrics) {
+                if outcome.actual.is_none() {
+                    continue;
+                }
+                changes.push((index, outcome.endpoint.clone(), !outcome.passed, outcome.describe()));
+            }
+        }
+        self.apply(changes)
+    }
+
+    fn apply(&mut self, changes: Vec<(usize, Option<String>, bool, String)>) -> Vec<Alert> {
+        let at = Local::now().to_rfc3339_opts(SecondsFormat::Secs, false);
+        let mut alerts = Vec::new();
+        for (index, endpoint, violated, message) in changes {
+            let key = (index, endpoint);
+            let status = if violated && self.firing.insert(key.clone()) {
+                AlertStatus::Firing
+            } else if !violated && self.firing.remove(&key) {
+                AlertStatus::Resolved
+            } else {
+                continue;
+            };
+            alerts.push(Alert { status, rule: self.rules[index].to_string(), endpoint: key.1, message, at: at.clone() });
+        }
+        alerts
+    }
+}
+
+/// Where alerts are delivered.
+#[derive(Debug, Clone, PartialEq)]
+pub enum AlertSink {
+    /// POSTs the alert as JSON.
+    Webhook(String),
+    /// Runs the command with `sh -c`; the alert is passed in `ALERT_STATUS`, `ALERT_RULE`,
+    /// `ALERT_ENDPOINT`, `ALERT_MESSAGE` and, as JSON, `ALERT_JSON`.
+    Exec(String),
+    /// Appends the alert as one JSON line.
+    File(PathBuf),
+}
+
+impl AlertSink {
+    /// `http(s)://URL` or `webhook:URL`, `exec:COMMAND`, `file:PATH`. Usable directly as a clap
+    /// `value_parser`.
+    pub fn parse(text: &str) -> std::result::Result<Self, String> {
+        let text = text.trim();
+        if let Some(url) = text.strip_prefix("webhook:") {
+            Ok(AlertSink::Webhook(url.to_string()))
+        } else if text.starts_with("http://") || text.starts_with("https://") {
+            Ok(AlertSink::Webhook(text.to_string()))
+        } else if let Some(command) = text.strip_prefix("exec:").filter(|c| !c.is_empty()) {
+            Ok(AlertSink::Exec(command.to_string()))
+        } else if let Some(path) = text.strip_prefix("file:").filter(|p| !p.is_empty()) {
+            Ok(AlertSink::File(PathBuf::from(path)))
+        } else {
+            Err(format!("unknown alert sink '{}', expected an http(s) URL, exec:COMMAND or file:PATH", text))
+        }
+    }
+
+    pub async fn deliver(&self, client: &reqwest::Client, alert: &Alert) -> Result<()> {
+        match self {
+            AlertSink::Webhook(url) => {
+                client.post(url).json(alert).send().await?.error_for_status()?;
+            }
+            AlertSink::Exec(command) => {
+                let mut child = tokio::process::Command::new("sh");
+                child
+                    .arg("-c")
+                    .arg(command)
+                    .env("ALERT_STATUS", alert.status.as_str())
+                    .env("ALERT_RULE", &alert.rule)
+                    .env("ALERT_ENDPOINT", alert.endpoint.as_deref().unwrap_or_default())
+                    .env("ALERT_MESSAGE", &alert.message)
+                    .env("ALERT_JSON", serde_json::to_string(alert)?)
+                    .kill_on_drop(true);
+                let status = tokio::time::timeout(DELIVERY_TIMEOUT, child.status())
+                    .await
+                    .map_err(|_| BenchmarkError::AlertError(format!("'{}' timed out", command)))??;
+                if !status.success() {
+                    return Err(BenchmarkError::AlertError(format!("'{}' exited with {}", command, status)));
+                }
+            }
+            AlertSink::File(path) => {
+                use std::io::Write;
+                let mut file = std::fs::OpenOptions::new().create(true).append(true).open(path)?;
+                writeln!(file, "{}", serde_json::to_string(alert)?)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for AlertSink {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AlertSink::Webhook(url) => f.write_str(url),
+            AlertSink::Exec(command) => write!(f, "exec:{}", command),
+            AlertSink::File(path) => write!(f, "file:{}", path.display()),
+        }
+    }
+}
+
+/// Delivers alerts to every sink, in order, from a background task so slow sinks never hold
+/// up the caller. Failed deliveries are logged and not retried.
+pub struct AlertNotifier {
+    sender: mpsc::UnboundedSender<Alert>,
+    task: JoinHandle<()>,
+}
+
+impl AlertNotifier {
+    pub fn spawn(sinks: Vec<AlertSink>) -> Self {
+        let (sender, mut alerts) = mpsc::unbounded_channel::<Alert>();
+        let task = tokio::spawn(async move {
+            let client = reqwest::Client::builder().timeout(DELIVERY_TIMEOUT).build().unwrap_or_default();
+            while let Some(alert) = alerts.recv().await {
+                for sink in &sinks {
+                    if let Err(e) = sink.deliver(&client, &alert).await {
+                        warn!("alert delivery to {} failed: {}", sink, e);
+                    }
+                }
+            }
+        });
+        Self { sender, task }
+    }
+
+    pub fn notify(&self, alert: Alert) {
+        let _ = self.sender.send(alert);
+    }
+
+    /// Waits until every queued alert was delivered.
+    pub async fn close(self) {
+        drop(self.sender);
+        let _ = self.task.await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::history::EndpointSummary;
+    use crate::window::WindowEndpoint;
+    use std::sync::{Arc, Mutex};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    /// Minimal HTTP/1.1 server that records every request body and answers 200.
+    async fn webhook_server() -> (String, Arc<Mutex<Vec<serde_json::Value>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        let bodies = Arc::new(Mutex::new(Vec::new()));
+
+        let received = bodies.clone();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let mut buffer = Vec::new();
+                let mut chunk = [0u8; 4096];
+                let body_start = loop {
+                    if let Some(pos) = buffer.windows(4).position(|w| w == b"\r\n\r\n") {
+                        break pos + 4;
+                    }
+                    let n = stream.read(&mut chunk).await.unwrap();
+                    buffer.extend_from_slice(&chunk[..n]);
+                };
+                let headers = String::from_utf8_lossy(&buffer[..body_start]).to_ascii_lowercase();
+                let content_length: usize = headers
+                    .lines()
+                    .find_map(|line| line.strip_prefix("content-length:"))
+                    .and_then(|value| value.trim().parse().ok())
+                    .unwrap_or(0);
+                while buffer.len() < body_start + content_length {
+                    let n = stream.read(&mut chunk).await.unwrap();
+                    buffer.extend_from_slice(&chunk[..n]);
+                }
+                received.lock().unwrap().push(serde_json::from_slice(&buffer[body_start..]).unwrap());
+                let _ = stream.write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\nconnection: close\r\n\r\n").await;
+            }
+        });
+
+        (url, bodies)
+    }
+
+    fn window(first: usize) -> WindowReport {
+        let summary = EndpointSummary {
+            endpoint: "A".to_string(),
+            received: 10,
+            scored: 10,
+            first,
+            tied: 0,
+            missed: 0,
+            avg_behind_ms: 1.0,
+            overall_avg_ms: 0.5,
+            p50_ms: 1.0,
+            p99_ms: 2.0,
+        };
+        WindowReport {
+            index: 1,
+            start: String::new(),
+            end: String::new(),
+            duration_sec: 60.0,
+            keys: 10,
+            endpoints: vec![WindowEndpoint { summary, p90_ms: 1.5, messages: 10, bytes: 0, errors: 0, connected: true, max_slot_lag: None }],
+        }
+    }
+
+    #[tokio::test]
+    async fn test_fires_once_resolves_and_delivers() {
+        let rules = ["disconnected<5s", "endpoint=A first_share>=40%"]
+            .iter()
+            .map(|text| AlertRule::parse(text).unwrap())
+            .collect();
+        assert!(AlertRule::parse("disconnected>5s").is_err());
+        let mut monitor = AlertMonitor::new(rules);
+
+        let t0 = Instant::now();
+        let health = |connected| vec![("A".to_string(), HealthSnapshot { connected, ..Default::default() })];
+        assert!(monitor.check_health(t0, &health(false)).is_empty());
+        let fired = monitor.check_health(t0 + Duration::from_secs(6), &health(false));
+        assert_eq!(fired[0].status, AlertStatus::Firing);
+        assert_eq!(fired[0].message, "disconnected<5s: A 已断开 6秒");
+        assert!(monitor.check_health(t0 + Duration::from_secs(7), &health(false)).is_empty());
+        let recovered = monitor.check_health(t0 + Duration::from_secs(8), &health(true));
+        assert_eq!(recovered[0].status, AlertStatus::Resolved);
+
+        let share = monitor.check_window(&window(2));
+        assert_eq!((share[0].status, share[0].endpoint.as_deref()), (AlertStatus::Firing, Some("A")));
+        assert!(monitor.check_window(&window(3)).is_empty());
+        let alerts: Vec<Alert> =
+            fired.into_iter().chain(recovered).chain(share).chain(monitor.check_window(&window(5))).collect();
+
+        let (url, bodies) = webhook_server().await;
+        let path = std::env::temp_dir().join(format!("alerts-{}.jsonl", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let sinks = vec![AlertSink::parse(&url).unwrap(), AlertSink::parse(&format!("file:{}", path.display())).unwrap()];
+        let notifier = AlertNotifier::spawn(sinks);
+        for alert in alerts {
+            notifier.notify(alert);
+        }
+        notifier.close().await;
+
+        let statuses: Vec<String> = bodies.lock().unwrap().iter().map(|b| b["status"].as_str().unwrap().to_string()).collect();
+        assert_eq!(statuses, vec!["firing", "resolved", "firing", "resolved"]);
+        assert_eq!(bodies.lock().unwrap()[2]["rule"], "endpoint=A first_share>=40%");
+        assert_eq!(std::fs::read_to_string(&path).unwrap().lines().count(), 4);
+        let _ = std::fs::remove_file(&path);
+    }
+}
@@ -1,4 +1,6 @@
 use anyhow::Result;
+use grpc_benchmark::history::{EndpointSummary, HistoryStore, RunKind, RunRecord, DEFAULT_HISTORY_DB};
+use grpc_benchmark::slo::{enforce, Assertion, Metrics};
 use reqwest::Client;
 use serde_json::json;
 use std::env;
@@ -6,13 +8,17 @@ use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 use std::time::Duration;
 use tokio::time::{interval, Instant};
-use tracing::{error, info};
+use tracing::{error, info, warn};
 
 #[derive(Clone)]
 struct Statistics {
     total_requests: Arc<AtomicU64>,
     successful_requests: Arc<AtomicU64>,
     error_429_count: Arc<AtomicU64>,
+    // 整个运行期间的累计值，不随每 10 秒的统计清零
+    run_total_requests: Arc<AtomicU64>,
+    run_successful_requests: Arc<AtomicU64>,
+    run_error_429_count: Arc<AtomicU64>,
 }
 
 impl Statistics {
@@ -21,19 +27,32 @@ impl Statistics {
             total_requests: Arc::new(AtomicU64::new(0)),
             successful_requests: Arc::new(AtomicU64::new(0)),
             error_429_count: Arc::new(AtomicU64::new(0)),
+            run_total_requests: Arc::new(AtomicU64::new(0)),
+            run_successful_requests: Arc::new(AtomicU64::new(0)),
+            run_error_429_count: Arc::new(AtomicU64::new(0)),
         }
     }
 
     fn increment_total(&self) {
         self.total_requests.fetch_add(1, Ordering::Relaxed);
+        self.run_total_requests.fetch_add(1, Ordering::Relaxed);
     }
 
     fn increment_success(&self) {
         self.successful_requests.fetch_add(1, Ordering::Relaxed);
+        self.run_successful_requests.fetch_add(1, Ordering::Relaxed);
     }
 
     fn increment_429(&self) {
         self.error_429_count.fetch_add(1, Ordering::Relaxed);
+        self.run_error_429_count.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn totals(&self) -> (u64, u64, u64) {
+        let total = self.run_total_requests.load(Ordering::Relaxed);
+        let success = self.run_successful_requests.load(Ordering::Relaxed);
+        let error_429 = self.run_error_429_count.load(Ordering::Relaxed);
+        (total, success, error_429)
     }
 
     fn reset(&self) -> (u64, u64, u64) {
@@ -60,9 +79,28 @@ async fn main() -> Result<()> {
         .unwrap_or_else(|_| "10".to_string())
         .parse()
         .unwrap_or(10);
+    // 不设置时一直运行; 设置后运行指定秒数并输出总计，SLO 断言需要设置
+    let duration = env::var("JITO_DURATION_SEC")
+        .ok()
+        .and_then(|v| v.parse::<u64>().ok())
+        .filter(|secs| *secs > 0)
+        .map(Duration::from_secs);
+    // 多条断言用分号分隔，例如 "jito.429_rate<1%;jito.success_per_sec>=8"
+    let assertions: Vec<Assertion> = env::var("ASSERT")
+        .map(|v| v.split(';').filter(|a| !a.trim().is_empty()).map(Assertion::parse).collect::<std::result::Result<_, _>>())
+        .unwrap_or_else(|_| Ok(Vec::new()))
+        .map_err(anyhow::Error::msg)?;
+    if !assertions.is_empty() && duration.is_none() {
+        anyhow::bail!("ASSERT requires JITO_DURATION_SEC so the run has an end");
+    }
+    // 有运行时长时把总计保存到 SQLite 历史记录，HISTORY_DB 为空时不保存
+    let history_db = env::var("HISTORY_DB").unwrap_or_else(|_| DEFAULT_HISTORY_DB.to_string());
 
     info!("Jito URL: {}", jito_url);
     info!("请求并发量: {}/s", concurrency);
+    if let Some(duration) = duration {
+        info!("运行时长: {}秒", duration.as_secs());
+    }
     info!("每 10 秒输出统计信息, 请稍后...");
 
     // Create statistics
@@ -133,10 +171,66 @@ async fn main() -> Result<()> {
         })
     };
 
-    // Wait for both tasks (this will run indefinitely)
+    // Wait for both tasks (this will run indefinitely unless JITO_DURATION_SEC is set)
+    let started = Instant::now();
     tokio::select! {
         _ = stats_task => {},
         _ = request_task => {},
+        _ = async {
+            match duration {
+                Some(duration) => tokio::time::sleep(duration).await,
+                None => std::future::pending().await,
+            }
+        } => {},
+    }
+
+    let (total, success, error_429) = stats.totals();
+    let elapsed = started.elapsed().as_secs_f64();
+    let percent = |count: u64| if total > 0 { count as f64 / total as f64 * 100.0 } else { 0.0 };
+    info!(
+        "总计 - {:.0} 秒：发送请求总量: {}, 成功响应量: {} ({:.2}%), 平均每秒成功: {:.1}, 429 错误次数: {} ({:.2}%)",
+        elapsed,
+        total,
+        success,
+        percent(success),
+        success as f64 / elapsed,
+        error_429,
+        percent(error_429)
+    );
+
+    // 记为往返类运行: 发送、成功和 429 的请求数，不计延迟
+    if !history_db.is_empty() {
+        let summary = EndpointSummary {
+            endpoint: jito_url.clone(),
+            received: total as usize,
+            scored: success as usize,
+            first: 0,
+            tied: 0,
+            missed: error_429 as usize,
+            avg_behind_ms: 0.0,
+            overall_avg_ms: 0.0,
+            p50_ms: 0.0,
+            p99_ms: 0.0,
+        };
+        let config = json!({
+            "jito_url": jito_url,
+            "concurrency": concurrency,
+            "duration_sec": duration.map(|d| d.as_secs()),
+        });
+        let run = RunRecord::finished("benchmark-jito", started.elapsed(), config, vec![summary]).with_kind(RunKind::RoundTrip);
+        match HistoryStore::open(&history_db).and_then(|mut store| store.save(&run)) {
+            Ok(id) => info!("运行结果已保存: {} (#{})", history_db, id),
+            Err(e) => warn!("保存运行结果失败: {}", e),
+        }
+    }
+
+    if !assertions.is_empty() {
+        let mut metrics = Metrics::new();
+        metrics.set("jito.requests", total as f64);
+        metrics.set("jito.success_rate", percent(success));
+        metrics.set("jito.429_rate", percent(error_429));
+        metrics.set("jito.success_per_sec", success as f64 / elapsed);
+        enforce(&assertions, &metrics);
     }
 
     Ok(())
@@ -1,20 +1,35 @@
 use anyhow::Result;
 use clap::Parser;
 use dotenvy::dotenv;
-use futures::StreamExt;
-use std::collections::{HashMap, HashSet};
+use std::collections::HashMap;
 use std::env;
-use std::sync::Arc;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
 use std::time::{Duration, Instant};
-use tokio::sync::Mutex;
-use tokio::time::{interval, sleep};
+use tokio::net::TcpListener;
+use tokio::sync::mpsc;
+use tokio::time::{interval, sleep_until};
 use chrono::{DateTime, Local};
-use yellowstone_grpc_client::GeyserGrpcClient;
-use yellowstone_grpc_proto::prelude::{
-    subscribe_update::UpdateOneof, CommitmentLevel, SubscribeRequest, SubscribeRequestFilterSlots,
-};
-use tonic::transport::ClientTlsConfig;
+use grpc_benchmark::engine::{ComparisonConfig, ComparisonEngine};
+use grpc_benchmark::runtime::ExecutionMode;
+use grpc_benchmark::source::{RpcPollingSource, RpcWebSocketSource, StreamSource, YellowstoneSource};
+use grpc_benchmark::aggregator::{AggregatorConfig, AggregatorEvent, SlotLagLog, SlotPolicy};
+use grpc_benchmark::alert::{Alert, AlertMonitor, AlertNotifier, AlertRule, AlertSink, AlertStatus};
+use grpc_benchmark::breakdown::{CategoryBreakdown, TimeBreakdown};
+use grpc_benchmark::dashboard::{Dashboard, DashboardState};
+use grpc_benchmark::events::{parse_protocols, DexProtocol};
 use grpc_benchmark::output::{ColoredOutput, EndpointStatus};
+use grpc_benchmark::grpc_client::{ChannelOptions, GrpcCompression};
+use grpc_benchmark::engine::ComparisonReport;
+use grpc_benchmark::fanout::{sub_endpoint_name, FanOut};
+use grpc_benchmark::history::{diff_runs, HistoryStore, RunEnvironment, RunKind, RunRecord, DEFAULT_HISTORY_DB};
+use grpc_benchmark::merger::MergeSimulator;
+use grpc_benchmark::pairwise::PairedDeltas;
+use grpc_benchmark::report::{ReportFormat, RunReport};
+use grpc_benchmark::slo::{enforce, Assertion, Metrics};
+use grpc_benchmark::stats::LatencyStats;
+use grpc_benchmark::throughput::{format_throughput, format_window, print_throughput, ThroughputSnapshot};
+use grpc_benchmark::window::{serve, RollingWindows, WindowReport};
 
 // Initialize rustls crypto provider
 use rustls;
@@ -29,6 +44,40 @@ struct Args {
     #[arg(long, env = "GRPC_COMPARISON_DURATION_SEC", default_value = "30")]
     duration: u64,
 
+    /// Seconds after the first resolved key that are reported separately instead of scored
+    #[arg(long, env = "WARMUP_SECS", default_value = "0")]
+    warmup_secs: f64,
+
+    /// Keep warming up until this many keys were resolved
+    #[arg(long, env = "WARMUP_KEYS", default_value = "0")]
+    warmup_keys: u64,
+
+    /// Ignore keys first seen in the last N seconds of the run
+    #[arg(long, env = "COOLDOWN_SECS", default_value = "0")]
+    cooldown_secs: f64,
+
+    /// How long to wait for the other endpoints after a key first arrives; those that have not
+    /// delivered it by then are counted as missed
+    #[arg(long, env = "RESOLVE_TIMEOUT_MS", default_value = "500", value_parser = clap::value_parser!(u64).range(1..))]
+    resolve_timeout_ms: u64,
+
+    /// Arrivals within this many microseconds of the earliest one count as tied rather than
+    /// first or delayed
+    #[arg(long, env = "TIE_TOLERANCE_US", default_value = "10")]
+    tie_tolerance_us: u64,
+
+    /// Slots an endpoint may fall behind the newest slot of any endpoint
+    #[arg(long, env = "MAX_SLOT_DIFFERENCE", default_value = "10")]
+    max_slot_difference: u64,
+
+    /// What to do with an endpoint beyond --max-slot-difference: exclude, readmit (once it catches up) or flag
+    #[arg(long, env = "SLOT_POLICY", default_value = "exclude", value_parser = SlotPolicy::parse)]
+    slot_policy: SlotPolicy,
+
+    /// How long an endpoint has to stay behind (or caught up) before the slot policy acts
+    #[arg(long, env = "SLOT_WINDOW_SECS", default_value = "0")]
+    slot_window_secs: f64,
+
     #[arg(long)]
     grpc_url_1: Option<String>,
 
@@ -38,6 +87,14 @@ struct Args {
     #[arg(long)]
     grpc_token_1: Option<String>,
 
+    /// Response compression for --grpc-url-1: none, gzip or zstd
+    #[arg(long, value_parser = parse_compression)]
+    grpc_compression_1: Option<GrpcCompression>,
+
+    /// Independent subscriptions opened to --grpc-url-1, each reported as NAME#i
+    #[arg(long, default_value = "1")]
+    grpc_connections_1: usize,
+
     #[arg(long)]
     grpc_url_2: Option<String>,
 
@@ -46,47 +103,395 @@ struct Args {
 
     #[arg(long)]
     grpc_token_2: Option<String>,
+
+    /// Response compression for --grpc-url-2: none, gzip or zstd
+    #[arg(long, value_parser = parse_compression)]
+    grpc_compression_2: Option<GrpcCompression>,
+
+    /// Independent subscriptions opened to --grpc-url-2, each reported as NAME#i
+    #[arg(long, default_value = "1")]
+    grpc_connections_2: usize,
+
+    /// Also open every uncompressed gRPC endpoint with these compressions, e.g. gzip,zstd,
+    /// and report latency delta and bandwidth savings against the uncompressed connection
+    #[arg(long, env = "COMPARE_COMPRESSION", value_delimiter = ',', value_parser = parse_compression)]
+    compare_compression: Vec<GrpcCompression>,
+
+    /// Solana RPC PubSub websocket to compare against, e.g. wss://api.mainnet-beta.solana.com
+    #[arg(long)]
+    ws_url_1: Option<String>,
+
+    #[arg(long)]
+    ws_name_1: Option<String>,
+
+    /// HTTP JSON-RPC endpoint polled with getSlot as a baseline (slot race only)
+    #[arg(long)]
+    rpc_url_1: Option<String>,
+
+    #[arg(long)]
+    rpc_name_1: Option<String>,
+
+    /// Polling interval for RPC endpoints
+    #[arg(long, env = "RPC_POLL_INTERVAL_MS", default_value = "400")]
+    poll_interval_ms: u64,
+
+    /// Also poll getBlockHeight in the same batch request
+    #[arg(long, env = "RPC_POLL_BLOCK_HEIGHT")]
+    poll_block_height: bool,
+
+    /// What to race: slot updates, or transactions (requires --mentions or --signatures)
+    #[arg(long, env = "RACE", value_enum, default_value = "slot")]
+    race: Race,
+
+    /// Accounts whose transactions are raced (gRPC account_include, websocket logsSubscribe mentions)
+    #[arg(long, env = "MENTIONS", value_delimiter = ',')]
+    mentions: Vec<String>,
+
+    /// Transaction signatures to race (gRPC signature filter, websocket signatureSubscribe)
+    #[arg(long, env = "SIGNATURES", value_delimiter = ',')]
+    signatures: Vec<String>,
+
+    /// DEX protocols for --race protocol, e.g. PumpFun,RaydiumClmm (default: all)
+    #[arg(long, env = "PROTOCOLS", value_delimiter = ',')]
+    protocols: Vec<String>,
+
+    /// Timestamp messages at HTTP/2 DATA frame poll time instead of after decoding
+    #[arg(long, env = "FRAME_TIMESTAMPS")]
+    frame_timestamps: bool,
+
+    /// Run every endpoint on its own thread with a dedicated runtime
+    #[arg(long, env = "DEDICATED_RUNTIME")]
+    dedicated_runtime: bool,
+
+    /// Record every arrival to this binary trace file; re-analyse it later with `replay`
+    #[arg(long, env = "TRACE_FILE")]
+    trace: Option<PathBuf>,
+
+    /// Write a report after the run: .md for a summary table, .html for a page with charts (repeatable)
+    #[arg(long, env = "REPORT_FILE", value_delimiter = ',', value_parser = parse_report)]
+    report: Vec<(PathBuf, ReportFormat)>,
+
+    /// SLO checked after the run, e.g. "endpoint=MyNode p99<20ms" or "first_share>=40%" (repeatable,
+    /// ';'-separated in ASSERT); exits with status 2 if any fails
+    #[arg(long = "assert", env = "ASSERT", value_delimiter = ';', value_parser = Assertion::parse)]
+    assertions: Vec<Assertion>,
+
+    /// Full-screen live dashboard instead of per-slot log lines; q ends the run early
+    #[arg(long, env = "TUI")]
+    tui: bool,
+
+    /// Run until Ctrl-C (or SIGTERM) and report every --window-secs instead of once at the end
+    #[arg(long, env = "DAEMON", conflicts_with_all = ["tui", "trace", "report", "assertions"])]
+    daemon: bool,
+
+    /// Daemon window length; windows start on multiples of it on the wall clock
+    #[arg(long, env = "WINDOW_SECS", default_value = "300", value_parser = clap::value_parser!(u64).range(1..))]
+    window_secs: u64,
+
+    /// Closed windows kept for /windows in daemon mode (288 = one day of 5-minute windows)
+    #[arg(long, env = "WINDOW_RETENTION", default_value = "288")]
+    window_retention: usize,
+
+    /// Append every closed daemon window to this file as one JSON line
+    #[arg(long, env = "WINDOW_JSON")]
+    window_json: Option<PathBuf>,
+
+    /// Serve /metrics (Prometheus, latest window) and /windows (JSON) here in daemon mode, e.g. 0.0.0.0:9100
+    #[arg(long, env = "METRICS_ADDR")]
+    metrics_addr: Option<String>,
+
+    /// Alert rule in daemon mode, e.g. "disconnected<30s", "p99<20ms" or "endpoint=MyNode first_share>=40%"
+    /// (repeatable, ';'-separated in ALERT); window rules are checked on every closed window
+    #[arg(long = "alert", env = "ALERT", value_delimiter = ';', value_parser = AlertRule::parse, requires = "daemon")]
+    alert_rules: Vec<AlertRule>,
+
+    /// Where alerts and recoveries go: an http(s) webhook URL (JSON POST), exec:COMMAND or file:PATH
+    /// (repeatable, ','-separated in ALERT_SINK); alerts are always logged
+    #[arg(long = "alert-sink", env = "ALERT_SINK", value_delimiter = ',', value_parser = AlertSink::parse, requires = "alert_rules")]
+    alert_sinks: Vec<AlertSink>,
+
+    /// SQLite file every run is saved to (empty to disable); see the `history` subcommand
+    #[arg(long, env = "HISTORY_DB", default_value = DEFAULT_HISTORY_DB)]
+    history_db: String,
+
+    /// Windows (ms) over which peak message and byte rates are reported
+    #[arg(long, env = "THROUGHPUT_WINDOWS_MS", value_delimiter = ',', default_value = "1000,10000")]
+    throughput_windows_ms: Vec<u64>,
+
+    /// CPU core ids to pin endpoint threads to, e.g. 2,3 (implies --dedicated-runtime)
+    #[arg(long, env = "CORE_IDS", value_delimiter = ',')]
+    core_ids: Vec<usize>,
+
+    #[command(subcommand)]
+    command: Option<Command>,
 }
 
-#[derive(Debug, Clone)]
-struct GrpcEndpoint {
-    name: String,
-    url: String,
+impl Args {
+    fn aggregator_config(&self) -> AggregatorConfig {
+        AggregatorConfig {
+            resolve_timeout: Duration::from_millis(self.resolve_timeout_ms),
+            tie_tolerance: Duration::from_micros(self.tie_tolerance_us),
+            warmup: Duration::from_secs_f64(self.warmup_secs.max(0.0)),
+            warmup_keys: self.warmup_keys,
+            cooldown: Duration::from_secs_f64(self.cooldown_secs.max(0.0)),
+            max_slot_difference: self.max_slot_difference,
+            slot_policy: self.slot_policy,
+            slot_window: Duration::from_secs_f64(self.slot_window_secs.max(0.0)),
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(clap::Subcommand, Debug)]
+enum Command {
+    /// Race one gRPC endpoint against itself under varied HTTP/2 and channel settings and rank
+    /// the settings by latency. Every combination runs for --duration seconds.
+    Sweep(SweepArgs),
+    /// List saved runs, or diff two runs (or a run against a baseline) for regressions.
+    History(HistoryArgs),
+}
+
+#[derive(clap::Args, Debug)]
+struct HistoryArgs {
+    #[command(subcommand)]
+    action: HistoryAction,
+}
+
+#[derive(clap::Subcommand, Debug)]
+enum HistoryAction {
+    /// Most recent runs first
+    List {
+        #[arg(long, default_value = "20")]
+        limit: usize,
+    },
+    /// Compare a run with --against, --baseline, or else the previous run of the same binary
+    Diff {
+        /// Run id or "latest"
+        #[arg(default_value = "latest")]
+        run: String,
+
+        /// Run id or "latest" to compare with
+        #[arg(long, conflicts_with = "baseline")]
+        against: Option<String>,
+
+        /// Saved baseline name to compare with
+        #[arg(long)]
+        baseline: Option<String>,
+
+        /// Flag first-arrival drops beyond this many points and latency increases beyond this percent
+        #[arg(long, default_value = "10")]
+        threshold_pct: f64,
+    },
+    /// Save a run as a named baseline (replacing an existing one)
+    Baseline {
+        name: String,
+        /// Run id or "latest"
+        #[arg(default_value = "latest")]
+        run: String,
+    },
+}
+
+#[derive(clap::Args, Debug)]
+struct SweepArgs {
+    /// Endpoint to tune (default: the first configured gRPC endpoint)
+    #[arg(long)]
+    url: Option<String>,
+
+    #[arg(long)]
     token: Option<String>,
+
+    /// Initial HTTP/2 stream window sizes, e.g. default,1MiB,4MiB
+    #[arg(long, value_delimiter = ',', default_value = "default", value_parser = parse_size::<u32>)]
+    stream_windows: Vec<Option<u32>>,
+
+    /// Initial HTTP/2 connection window sizes, e.g. default,8MiB,16MiB
+    #[arg(long, value_delimiter = ',', default_value = "default", value_parser = parse_size::<u32>)]
+    connection_windows: Vec<Option<u32>>,
+
+    /// HTTP/2 adaptive flow control window: default, on, off
+    #[arg(long, value_delimiter = ',', default_value = "default", value_parser = parse_switch)]
+    adaptive_window: Vec<Option<bool>>,
+
+    /// Channel buffer sizes, e.g. default,64KiB,1MiB
+    #[arg(long, value_delimiter = ',', default_value = "default", value_parser = parse_size::<usize>)]
+    buffer_sizes: Vec<Option<usize>>,
+
+    /// HTTP/2 keepalive intervals in seconds
+    #[arg(long, value_delimiter = ',', default_value = "30")]
+    keepalive_secs: Vec<u64>,
 }
 
-#[derive(Debug, Clone)]
-struct BlockData {
-    endpoint: String,
-    slot: u64,
-    timestamp: Instant,
+/// `default` or a byte count with an optional KiB/MiB suffix.
+fn parse_size<T: TryFrom<u64>>(value: &str) -> std::result::Result<Option<T>, String> {
+    let value = value.trim();
+    if value.eq_ignore_ascii_case("default") {
+        return Ok(None);
+    }
+    let (number, multiplier) = if let Some(n) = value.strip_suffix("MiB") {
+        (n, 1024 * 1024)
+    } else if let Some(n) = value.strip_suffix("KiB") {
+        (n, 1024)
+    } else {
+        (value, 1)
+    };
+    let bytes = number.trim().parse::<u64>().map_err(|e| format!("invalid size '{}': {}", value, e))? * multiplier;
+    T::try_from(bytes).map(Some).map_err(|_| format!("size '{}' is too large", value))
+}
+
+fn parse_switch(value: &str) -> std::result::Result<Option<bool>, String> {
+    match value.trim().to_ascii_lowercase().as_str() {
+        "default" => Ok(None),
+        "on" | "true" => Ok(Some(true)),
+        "off" | "false" => Ok(Some(false)),
+        other => Err(format!("expected default, on or off, got '{}'", other)),
+    }
+}
+
+impl SweepArgs {
+    /// Every combination of the swept values, on top of `base`.
+    fn configurations(&self, base: &ChannelOptions) -> Vec<ChannelOptions> {
+        let mut configs = Vec::new();
+        for stream_window in &self.stream_windows {
+            for connection_window in &self.connection_windows {
+                for adaptive in &self.adaptive_window {
+                    for buffer in &self.buffer_sizes {
+                        for keepalive in &self.keepalive_secs {
+                            configs.push(ChannelOptions {
+                                initial_stream_window_size: *stream_window,
+                                initial_connection_window_size: *connection_window,
+                                adaptive_window: *adaptive,
+                                buffer_size: *buffer,
+                                keepalive_interval: Duration::from_secs(*keepalive),
+                                ..base.clone()
+                            });
+                        }
+                    }
+                }
+            }
+        }
+        configs
+    }
+}
+
+/// `{PREFIX}_CONNECTIONS_{index}`: number of parallel subscriptions for that endpoint.
+fn env_connections(prefix: &str, index: &str) -> Result<Option<usize>> {
+    match env::var(format!("{}_CONNECTIONS_{}", prefix, index)) {
+        Ok(v) => Ok(Some(v.trim().parse().map_err(|e| {
+            anyhow::anyhow!("invalid {}_CONNECTIONS_{} '{}': {}", prefix, index, v, e)
+        })?)),
+        Err(_) => Ok(None),
+    }
 }
 
-#[derive(Debug)]
-struct EndpointStats {
-    total_latency: f64,
-    latencies: Vec<f64>,
-    first_received: u64,
-    total_received: u64,
-    is_available: bool,
-    has_received_data: bool,
-    first_slot: Option<u64>,
+fn parse_compression(name: &str) -> std::result::Result<GrpcCompression, String> {
+    GrpcCompression::parse(name).ok_or_else(|| format!("unknown compression '{}', expected none, gzip or zstd", name))
 }
 
-impl EndpointStats {
-    fn new() -> Self {
-        Self {
-            total_latency: 0.0,
-            latencies: Vec::new(),
-            first_received: 0,
-            total_received: 0,
-            is_available: true,
-            has_received_data: false,
-            first_slot: None,
+fn parse_report(path: &str) -> std::result::Result<(PathBuf, ReportFormat), String> {
+    let path = PathBuf::from(path);
+    let format = ReportFormat::from_path(&path).ok_or_else(|| format!("unknown report format '{}', expected .md or .html", path.display()))?;
+    Ok((path, format))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum Race {
+    Slot,
+    Transaction,
+    /// Transactions mentioning DEX program ids, broken down per protocol
+    Protocol,
+}
+
+/// The keys every endpoint is subscribed to for the race.
+#[derive(Debug, Clone)]
+enum RaceTarget {
+    Slots,
+    Mentions(Vec<String>),
+    Signatures(Vec<String>),
+    Protocols(Vec<DexProtocol>),
+}
+
+impl RaceTarget {
+    fn from_args(race: Race, mentions: Vec<String>, signatures: Vec<String>, protocols: Vec<String>) -> Result<Self> {
+        match race {
+            Race::Protocol => {
+                let protocols = parse_protocols(&protocols)?;
+                if protocols.is_empty() {
+                    Ok(RaceTarget::Protocols(DexProtocol::ALL.to_vec()))
+                } else {
+                    Ok(RaceTarget::Protocols(protocols))
+                }
+            }
+            Race::Slot => Ok(RaceTarget::Slots),
+            Race::Transaction if !signatures.is_empty() => Ok(RaceTarget::Signatures(signatures)),
+            Race::Transaction if !mentions.is_empty() => Ok(RaceTarget::Mentions(mentions)),
+            Race::Transaction => anyhow::bail!("--race transaction requires --mentions or --signatures"),
+        }
+    }
+
+    /// (中文名称, 单位) used in the report.
+    fn unit(&self) -> (&'static str, &'static str) {
+        match self {
+            RaceTarget::Slots => ("区块", "blocks"),
+            RaceTarget::Mentions(_) | RaceTarget::Signatures(_) | RaceTarget::Protocols(_) => ("交易", "txs"),
         }
     }
 }
 
+#[derive(Debug, Clone)]
+enum EndpointKind {
+    Grpc { token: Option<String>, channel: ChannelOptions },
+    WebSocket,
+    Polling { interval: Duration, block_height: bool },
+}
+
+#[derive(Debug, Clone)]
+struct Endpoint {
+    name: String,
+    url: String,
+    kind: EndpointKind,
+}
+
+impl Endpoint {
+    /// The source for this endpoint, or `None` if it cannot take part in the race.
+    fn source(&self, target: &RaceTarget, frame_timestamps: bool) -> Option<Box<dyn StreamSource>> {
+        let (name, url) = (self.name.clone(), self.url.clone());
+        let source: Box<dyn StreamSource> = match (&self.kind, target) {
+            (EndpointKind::Grpc { token, channel }, _) => {
+                let source = match target {
+                    RaceTarget::Slots => YellowstoneSource::slots(name, url, token.clone()),
+                    RaceTarget::Mentions(accounts) => {
+                        YellowstoneSource::transactions(name, url, token.clone(), accounts.clone())
+                    }
+                    RaceTarget::Signatures(signatures) => {
+                        YellowstoneSource::signatures(name, url, token.clone(), signatures.clone())
+                    }
+                    RaceTarget::Protocols(protocols) => YellowstoneSource::protocols(name, url, token.clone(), protocols),
+                };
+                Box::new(source.with_frame_timestamps(frame_timestamps).with_channel_options(channel.clone()))
+            }
+            (EndpointKind::WebSocket, RaceTarget::Slots) => Box::new(RpcWebSocketSource::slots(name, url)),
+            (EndpointKind::WebSocket, RaceTarget::Mentions(accounts)) => {
+                Box::new(RpcWebSocketSource::logs(name, url, accounts.clone()))
+            }
+            (EndpointKind::WebSocket, RaceTarget::Signatures(signatures)) => {
+                Box::new(RpcWebSocketSource::signatures(name, url, signatures.clone()))
+            }
+            (EndpointKind::WebSocket, RaceTarget::Protocols(protocols)) => {
+                let programs = protocols
+                    .iter()
+                    .map(|p| (p.name().to_string(), p.program_id().to_string()))
+                    .collect();
+                Box::new(RpcWebSocketSource::programs(name, url, programs))
+            }
+            (EndpointKind::Polling { interval, block_height }, RaceTarget::Slots) => Box::new(
+                RpcPollingSource::new(name, url, *interval).with_block_height(*block_height),
+            ),
+            (EndpointKind::Polling { .. }, _) => return None,
+        };
+        Some(source)
+    }
+}
+
 fn log_info(msg: &str) {
     let now: DateTime<Local> = Local::now();
     use colored::*;
@@ -156,504 +561,739 @@ fn extract_log_parts(msg: &str) -> Option<LogParts> {
     None
 }
 
-async fn compare_grpc_endpoints(endpoints: Vec<GrpcEndpoint>, test_duration_sec: u64) -> Result<()> {
-    // 计算最大端点名称长度用于对齐输出
-    let max_name_length = endpoints.iter().map(|e| e.name.len()).max().unwrap_or(0);
-    set_max_name_length(max_name_length);
-    
-    log_info("开始对比多个 GRPC 服务性能...");
-    log_info(&format!("测试持续时间: {}秒", test_duration_sec));
-    log_info(&format!(
-        "测试端点: {}",
-        endpoints.iter().map(|e| e.name.as_str()).collect::<Vec<_>>().join(", ")
-    ));
-
-    let start_time = Instant::now();
-    let end_time = start_time + Duration::from_secs(test_duration_sec);
+/// Views computed from the aggregator's resolutions alongside the overall race.
+#[derive(Debug, Default)]
+struct Analyses {
+    breakdown: CategoryBreakdown,
+    /// Compression variants against their uncompressed connection.
+    paired: PairedDeltas,
+    /// Endpoints opened over several connections, scored best-of-N.
+    fanout: FanOut,
+    /// Every combination of endpoints merged earliest-copy-wins.
+    merger: MergeSimulator,
+    /// First-received share per minute, for reports.
+    timeline: TimeBreakdown,
+}
 
-    let block_data_by_slot = Arc::new(Mutex::new(HashMap::<u64, Vec<BlockData>>::new()));
-    let endpoint_stats = Arc::new(Mutex::new({
-        let mut stats = HashMap::new();
-        for endpoint in &endpoints {
-            stats.insert(endpoint.name.clone(), EndpointStats::new());
+// 将聚合任务的事件输出为日志
+async fn log_aggregator_events(
+    mut events: mpsc::UnboundedReceiver<AggregatorEvent>,
+    mut analyses: Analyses,
+    dashboard: Option<Arc<Mutex<DashboardState>>>,
+) -> Analyses {
+    let mut slot_lags = SlotLagLog::new();
+    while let Some(event) = events.recv().await {
+        analyses.breakdown.observe(&event);
+        analyses.fanout.observe(&event);
+        analyses.timeline.observe(&event);
+        // 多连接端点按 best-of-N 合并后再与压缩变体配对
+        if let AggregatorEvent::Resolved(resolution) = &event {
+            let collapsed = analyses.fanout.collapse(resolution);
+            analyses.paired.record(&collapsed);
+            analyses.merger.record(&collapsed);
         }
-        stats
-    }));
-
-    let first_slot_received = Arc::new(Mutex::new({
-        let mut received = HashMap::new();
-        for endpoint in &endpoints {
-            received.insert(endpoint.name.clone(), false);
+        // 终端界面接管屏幕时不再逐条输出日志
+        if let Some(dashboard) = &dashboard {
+            dashboard.lock().unwrap().observe(&event, Instant::now());
+            continue;
         }
-        received
-    }));
-
-    let active_endpoints = Arc::new(Mutex::new({
-        let mut active = HashSet::new();
-        for endpoint in &endpoints {
-            active.insert(endpoint.name.clone());
+        match event {
+            AggregatorEvent::FirstArrival { endpoint, key } => {
+                log_info(&format!("{} 成功接收到第一个 {}, 确认为可用端点", endpoint, key));
+            }
+            AggregatorEvent::Excluded { endpoint, slot, tip } => {
+                log_info(&format!("使用最新slot {} 作为基准进行对比", tip));
+                log_info(&format!(
+                    "{} 的slot ({}) 比基准值旧 (基准值: {}, 落后: {}个区块)",
+                    endpoint, slot, tip, tip.saturating_sub(slot)
+                ));
+                log_info(&format!("{} 被标记为异常端点，将不参与性能比较", endpoint));
+            }
+            AggregatorEvent::Flagged { endpoint, slot, tip } => {
+                log_info(&format!(
+                    "{} 的slot ({}) 落后最新slot {} 共 {}个区块, 仍参与性能比较",
+                    endpoint, slot, tip, tip.saturating_sub(slot)
+                ));
+            }
+            AggregatorEvent::CaughtUp { endpoint, readmitted } => {
+                if readmitted {
+                    log_info(&format!("{} 已追上最新slot, 重新参与性能比较", endpoint));
+                } else {
+                    log_info(&format!("{} 已追上最新slot", endpoint));
+                }
+            }
+            AggregatorEvent::SlotLag { tip, behind } => {
+                for line in slot_lags.changes(tip, &behind) {
+                    log_info(&line);
+                }
+            }
+            AggregatorEvent::Started { active } => {
+                log_info(&format!("有{}个有效端点, 开始正式统计...", active));
+            }
+            AggregatorEvent::Measuring { keys } => {
+                log_info(&format!("预热结束 (共 {} 个), 之后的数据计入统计", keys));
+            }
+            AggregatorEvent::EndpointLost { endpoint, reason, remaining } => {
+                log_info(&format!("{} 连接中断: {}", endpoint, reason));
+                if remaining < 2 {
+                    log_info(&format!(
+                        "由于 {} 出错, 活跃端点不足两个 (当前{}个), 无法进行对比分析",
+                        endpoint, remaining
+                    ));
+                }
+            }
+            AggregatorEvent::Resolved(resolution) => {
+                for line in resolution.log_lines(get_max_name_length()) {
+                    log_info(&line);
+                }
+            }
         }
-        active
-    }));
+    }
+    analyses
+}
 
-    let first_received_slots = Arc::new(Mutex::new(HashMap::<String, u64>::new()));
-    let started_formal_stats = Arc::new(Mutex::new(false));
-    let pending_block_data = Arc::new(Mutex::new(HashMap::<u64, Vec<BlockData>>::new()));
+// 压缩对比: 同一服务商的压缩连接相对无压缩连接的线上字节数和到达时间差
+fn print_compression_comparison(paired: &PairedDeltas, throughput: &[(String, ThroughputSnapshot)]) {
+    use colored::*;
+    println!("{}", "🗜 gRPC 压缩对比".yellow().bold());
+    println!("{}", "-".repeat(28).yellow());
 
-    // 检查所有slot对齐的函数
-    async fn check_slots_alignment(
-        first_received_slots: Arc<Mutex<HashMap<String, u64>>>,
-        endpoint_stats: Arc<Mutex<HashMap<String, EndpointStats>>>,
-        active_endpoints: Arc<Mutex<HashSet<String>>>,
-    ) -> bool {
-        let max_slot_difference = 10u64;
+    let bytes_per_message = |name: &str| {
+        throughput
+            .iter()
+            .find(|(n, _)| n == name)
+            .filter(|(_, t)| t.messages > 0)
+            .map(|(_, t)| t.bytes as f64 / t.messages as f64)
+    };
+
+    for (baseline, variant, delta) in paired.summaries() {
+        let mut line = format!("{:width$} 相对 {}:", variant, baseline, width = get_max_name_length());
+        if let (Some(base), Some(compressed)) = (bytes_per_message(baseline), bytes_per_message(variant)) {
+            line.push_str(&format!(" 线上 {:.0} → {:.0} 字节/条", base, compressed));
+            if base > 0.0 {
+                line.push_str(&format!(" (节省 {:.1}%)", (1.0 - compressed / base) * 100.0));
+            }
+        }
+        if delta.count > 0 {
+            line.push_str(&format!(
+                ", 到达时间差 平均 {:+.2}ms, p50 {:+.2}ms, p99 {:+.2}ms ({} 个共同消息)",
+                delta.mean, delta.median, delta.p99, delta.count
+            ));
+        } else {
+            line.push_str(", 没有共同接收的消息");
+        }
+        println!("{}", line);
+    }
+}
 
-        let first_slots = first_received_slots.lock().await;
-        let mut stats = endpoint_stats.lock().await;
-        let mut active = active_endpoints.lock().await;
+// 多连接: 每组 best-of-N 的有效延迟，以及组内各连接落后最优连接的程度
+fn print_fanout(fanout: &FanOut) {
+    use colored::*;
+    println!("{}", "🔀 多连接 (best-of-N)".yellow().bold());
+    println!("{}", "-".repeat(28).yellow());
 
-        if first_slots.len() < 2 {
-            return false;
+    let merged = fanout.merged_stats();
+    for (name, keys, members) in fanout.groups() {
+        match merged.get(name) {
+            Some(stat) if stat.scored > 0 => println!(
+                "{:width$} : {} 个连接合并后首先接收 {:6.2}%, 落后时平均延迟 {:7.2}ms, 总体平均延迟 {:7.2}ms",
+                name,
+                members.len(),
+                stat.get_first_percentage(),
+                stat.get_average_latency(),
+                stat.get_overall_average_latency(),
+                width = get_max_name_length()
+            ),
+            _ => println!("{:width$} : 没有收集到数据", name, width = get_max_name_length()),
         }
+        for (member, wins, behind) in members {
+            let win_percent = if keys > 0 { wins as f64 / keys as f64 * 100.0 } else { 0.0 };
+            println!(
+                "  {:width$} : 组内最快 {:6.2}% ({}/{}), 落后最优连接 平均 {:6.2}ms, p99 {:6.2}ms",
+                member,
+                win_percent,
+                wins,
+                keys,
+                behind.mean,
+                behind.p99,
+                width = get_max_name_length()
+            );
+        }
+    }
+}
 
-        let slot_values: Vec<u64> = first_slots.values().copied().collect();
-        let max_slot = *slot_values.iter().max().unwrap();
-        log_info(&format!("使用最大slot {} 作为基准进行对比 (最新区块)", max_slot));
-
-        let mut invalid_endpoints = Vec::new();
+fn find_run(store: &HistoryStore, run: &str) -> Result<RunRecord> {
+    let found = match run {
+        "latest" => store.runs(1)?.pop(),
+        id => {
+            let id = id.parse().map_err(|_| anyhow::anyhow!("invalid run '{}', expected an id or \"latest\"", id))?;
+            store.run(id)?
+        }
+    };
+    found.ok_or_else(|| anyhow::anyhow!("run {} not found", run))
+}
 
-        for (endpoint, slot) in first_slots.iter() {
-            let difference = max_slot.saturating_sub(*slot);
+fn describe_run(run: &RunRecord) -> String {
+    let started = DateTime::from_timestamp(run.started_at, 0)
+        .map(|t| t.with_timezone(&Local).format("%Y-%m-%d %H:%M:%S").to_string())
+        .unwrap_or_default();
+    let host = match &run.environment.region {
+        Some(region) => format!("{}/{}", run.environment.hostname, region),
+        None => run.environment.hostname.clone(),
+    };
+    format!(
+        "#{} {} {} {:.0}秒 @ {} [{}]",
+        run.id, started, run.binary, run.duration_sec, host, run.environment.version
+    )
+}
 
-            if difference > max_slot_difference {
-                log_info(&format!(
-                    "{} 的第一个slot ({}) 比基准值旧 (基准值: {}, 落后: {}个区块)",
-                    endpoint, slot, max_slot, difference
-                ));
-                log_info(&format!("{} 被标记为异常端点，将不参与性能比较", endpoint));
-                if let Some(stat) = stats.get_mut(endpoint) {
-                    stat.is_available = false;
+// 历史记录: 列出运行、设置基线，或对比两次运行并标记回退的端点
+fn run_history(path: &str, history: &HistoryArgs) -> Result<()> {
+    use colored::*;
+    if path.is_empty() {
+        anyhow::bail!("HISTORY_DB is empty, run history is disabled");
+    }
+    let mut store = HistoryStore::open(path)?;
+
+    match &history.action {
+        HistoryAction::List { limit } => {
+            println!("{}", format!("🗂 运行历史 ({})", path).yellow().bold());
+            println!("{}", "-".repeat(28).yellow());
+            for run in store.runs(*limit)? {
+                println!("{}", describe_run(&run).cyan());
+                for e in &run.endpoints {
+                    match run.kind {
+                        RunKind::Race => println!(
+                            "  {:20} : 首先接收 {:6.2}%, 总体平均延迟 {:7.2}ms, p99 {:7.2}ms, 计分 {}",
+                            e.endpoint, e.first_percent(), e.overall_avg_ms, e.p99_ms, e.scored
+                        ),
+                        RunKind::RoundTrip => println!(
+                            "  {:20} : 往返延迟 平均 {:7.2}ms, p99 {:7.2}ms, 请求 {}, 成功 {}",
+                            e.endpoint, e.overall_avg_ms, e.p99_ms, e.received, e.scored
+                        ),
+                    }
                 }
-                active.remove(endpoint);
-                invalid_endpoints.push(endpoint.clone());
+            }
+            for (name, id) in store.baselines()? {
+                println!("基线 {} -> #{}", name, id);
             }
         }
-
-        if !invalid_endpoints.is_empty() {
-            log_info(&format!("以下端点因slot落后过多被排除: {}", invalid_endpoints.join(", ")));
-
-            if active.len() < 2 {
-                log_info(&format!("剩余可用端点不足两个 (当前{}个), 无法进行对比分析", active.len()));
-                return false;
+        HistoryAction::Diff { run, against, baseline, threshold_pct } => {
+            let after = find_run(&store, run)?;
+            let before = match (against, baseline) {
+                (Some(against), _) => find_run(&store, against)?,
+                (None, Some(name)) => {
+                    store.baseline(name)?.ok_or_else(|| anyhow::anyhow!("baseline '{}' not found", name))?
+                }
+                (None, None) => store.previous(&after)?.ok_or_else(|| {
+                    anyhow::anyhow!("run #{} has no earlier {} run to compare with", after.id, after.binary)
+                })?,
+            };
+            if before.kind != after.kind {
+                anyhow::bail!(
+                    "run #{} ({}) and run #{} ({}) measure different things and cannot be compared",
+                    before.id,
+                    before.kind.name(),
+                    after.id,
+                    after.kind.name()
+                );
             }
+            print_run_diff(&before, &after, *threshold_pct);
         }
+        HistoryAction::Baseline { name, run } => {
+            let run = find_run(&store, run)?;
+            store.set_baseline(name, run.id)?;
+            println!("已将运行 {} 保存为基线 {}", describe_run(&run), name);
+        }
+    }
+    Ok(())
+}
 
-        true
+fn print_run_diff(before: &RunRecord, after: &RunRecord, threshold_pct: f64) {
+    use colored::*;
+    println!("{}", "📉 运行对比".yellow().bold());
+    println!("{}", "-".repeat(28).yellow());
+    println!("之前: {}", describe_run(before));
+    println!("之后: {}", describe_run(after));
+    println!("阈值: 首先接收下降 {} 个百分点, 延迟增加 {}%", threshold_pct, threshold_pct);
+
+    let diffs = diff_runs(before, after, threshold_pct);
+    for diff in &diffs {
+        let (b, a) = (&diff.before, &diff.after);
+        println!(
+            "{:20} : 首先接收 {:6.2}% → {:6.2}%, 总体平均延迟 {:7.2} → {:7.2}ms, p99 {:7.2} → {:7.2}ms",
+            a.endpoint,
+            b.first_percent(),
+            a.first_percent(),
+            b.overall_avg_ms,
+            a.overall_avg_ms,
+            b.p99_ms,
+            a.p99_ms
+        );
+        for regression in &diff.regressions {
+            println!("  {}", format!("⚠ 回退: {}", regression).red());
+        }
+    }
+    for e in &after.endpoints {
+        if before.endpoint(&e.endpoint).is_none() {
+            println!("{:20} : 仅在 #{} 中出现", e.endpoint, after.id);
+        }
+    }
+    for e in &before.endpoints {
+        if after.endpoint(&e.endpoint).is_none() {
+            println!("{:20} : 仅在 #{} 中出现", e.endpoint, before.id);
+        }
     }
 
-    // 简化处理函数
-    async fn process_collected_data_fn(
-        pending_block_data: Arc<Mutex<HashMap<u64, Vec<BlockData>>>>,
-        block_data_by_slot: Arc<Mutex<HashMap<u64, Vec<BlockData>>>>,
-        active_endpoints: Arc<Mutex<HashSet<String>>>,
-        endpoint_stats: Arc<Mutex<HashMap<String, EndpointStats>>>,
-    ) {
-        let mut pending = pending_block_data.lock().await;
-        let block_data_by_slot_ref = block_data_by_slot.clone();
-        let block_data = block_data_by_slot_ref.lock().await;
-        let active = active_endpoints.lock().await;
-        let mut stats = endpoint_stats.lock().await;
+    let regressed = diffs.iter().filter(|d| !d.regressions.is_empty()).count();
+    if regressed > 0 {
+        println!("{}", format!("{} 个端点出现回退", regressed).red().bold());
+    } else {
+        println!("{}", "没有端点超过回退阈值".green());
+    }
+}
 
-        for (slot, block_data_list) in pending.drain() {
-            if block_data.contains_key(&slot) {
-                continue;
-            }
+// 保存本次运行的配置、环境和统计摘要；失败时只记录日志，不影响测试结果
+fn save_run(path: &str, run: RunRecord) {
+    if path.is_empty() {
+        return;
+    }
+    match HistoryStore::open(path).and_then(|mut store| store.save(&run)) {
+        Ok(id) => log_info(&format!("运行结果已保存: {} (#{})", path, id)),
+        Err(e) => log_info(&format!("保存运行结果失败: {}", e)),
+    }
+}
 
-            let active_endpoint_data: Vec<_> = block_data_list
-                .iter()
-                .filter(|data| {
-                    active.contains(&data.endpoint) &&
-                    stats.get(&data.endpoint).map_or(false, |s| s.has_received_data)
-                })
-                .cloned()
-                .collect();
+struct SweepResult {
+    channel: ChannelOptions,
+    delta: LatencyStats,
+    first_percent: f64,
+    /// Keys the candidate delivered, scored or not.
+    received: usize,
+}
 
-            if active_endpoint_data.len() >= 2 {
-                // 注意：total_received在主任务循环中计算，这里不重复计算
+// 参数扫描: 每种配置与默认配置的同一端点并行订阅，按到达时间差排序
+async fn run_sweep(
+    endpoint: Endpoint,
+    target: RaceTarget,
+    configs: Vec<ChannelOptions>,
+    run_duration_sec: u64,
+    frame_timestamps: bool,
+    execution_mode: ExecutionMode,
+    aggregator: AggregatorConfig,
+) -> Result<()> {
+    use colored::*;
+    let EndpointKind::Grpc { token, channel: baseline_channel } = &endpoint.kind else {
+        anyhow::bail!("{} is not a gRPC endpoint", endpoint.name);
+    };
+    let baseline = Endpoint {
+        name: "baseline".to_string(),
+        url: endpoint.url.clone(),
+        kind: endpoint.kind.clone(),
+    };
+    set_max_name_length("candidate".len());
 
-                let _earliest_timestamp = active_endpoint_data
-                    .iter()
-                    .map(|bd| bd.timestamp)
-                    .min()
-                    .unwrap();
-
-                // 按时间戳排序，确保稳定的排序结果
-                let mut sorted_data = active_endpoint_data.clone();
-                sorted_data.sort_by(|a, b| {
-                    a.timestamp.cmp(&b.timestamp)
-                        .then_with(|| a.endpoint.cmp(&b.endpoint))
-                });
-
-                // 找到真正最早的端点（第一个）
-                let first_endpoint = sorted_data
-                    .first()
-                    .unwrap();
-
-                // 首先输出首次接收的端点
-                if let Some(stat) = stats.get_mut(&first_endpoint.endpoint) {
-                    stat.first_received += 1;
-                    log_info(&format!(
-                        "{:width$} 接收 slot {}: 首次接收",
-                        first_endpoint.endpoint,
-                        first_endpoint.slot,
-                        width = get_max_name_length()
-                    ));
-                }
+    log_info(&format!(
+        "开始参数扫描: {} ({}), 共 {} 种配置, 每种 {}秒, 预计 {}秒",
+        endpoint.name,
+        endpoint.url,
+        configs.len(),
+        run_duration_sec,
+        configs.len() as u64 * run_duration_sec
+    ));
+    log_info(&format!("基准配置: {}", baseline_channel.describe()));
 
-                // 然后按排序顺序输出延迟的端点
-                for bd in &sorted_data {
-                    if bd.endpoint != first_endpoint.endpoint {
-                        let latency = bd.timestamp.duration_since(first_endpoint.timestamp).as_nanos() as f64 / 1_000_000.0;
-                        if latency >= 0.01 { // 只显示大于0.01ms的延迟
-                            if let Some(stat) = stats.get_mut(&bd.endpoint) {
-                                stat.latencies.push(latency);
-                                stat.total_latency += latency;
-                                log_info(&format!(
-                                    "{:width$} 接收 slot {}: 延迟 {:>6.2}ms (相对于 {})",
-                                    bd.endpoint,
-                                    bd.slot,
-                                    latency,
-                                    first_endpoint.endpoint,
-                                    width = get_max_name_length()
-                                ));
-                            }
-                        }
+    let mut results = Vec::new();
+    for (index, channel) in configs.into_iter().enumerate() {
+        log_info(&format!("===== 配置 {}: {} =====", index + 1, channel.describe()));
+
+        let candidate = Endpoint {
+            name: "candidate".to_string(),
+            url: endpoint.url.clone(),
+            kind: EndpointKind::Grpc { token: token.clone(), channel: channel.clone() },
+        };
+        let mut engine = ComparisonEngine::new(ComparisonConfig {
+            execution_mode: execution_mode.clone(),
+            aggregator: aggregator.clone(),
+            ..ComparisonConfig::new(Duration::from_secs(run_duration_sec))
+        });
+        for sweep_endpoint in [&baseline, &candidate] {
+            let Some(source) = sweep_endpoint.source(&target, frame_timestamps) else {
+                anyhow::bail!("{} cannot take part in the {:?} race", endpoint.name, target);
+            };
+            engine.add_boxed_source(source);
+        }
+
+        let mut comparison = engine.start();
+        // 扫描时不逐条输出日志，只累计基准与候选配置的到达时间差
+        let delta_task = comparison.take_events().map(|mut events| {
+            tokio::spawn(async move {
+                let mut paired = PairedDeltas::new(vec![("baseline".to_string(), "candidate".to_string())]);
+                while let Some(event) = events.recv().await {
+                    if let AggregatorEvent::EndpointLost { endpoint, reason, .. } = &event {
+                        log_info(&format!("{} 连接中断: {}", endpoint, reason));
                     }
+                    paired.observe(&event);
                 }
-            }
-        }
+                paired
+            })
+        });
+
+        let report = comparison.finish().await?;
+        let paired = match delta_task {
+            Some(task) => task.await.unwrap_or_default(),
+            None => PairedDeltas::default(),
+        };
+        let delta = paired.summaries().next().map(|(_, _, stats)| stats).unwrap_or_default();
+        let (first_percent, received) = report
+            .stats
+            .get("candidate")
+            .map(|stat| (stat.get_first_percentage(), stat.received))
+            .unwrap_or_default();
+
+        log_info(&format!(
+            "配置 {} 完成: 到达时间差 平均 {:+.2}ms, p50 {:+.2}ms, p99 {:+.2}ms, 首先接收 {:.2}% ({} 个样本)",
+            index + 1, delta.mean, delta.median, delta.p99, first_percent, delta.count
+        ));
+        results.push(SweepResult { channel, delta, first_percent, received });
     }
 
-    // 为每个端点创建连接和订阅
-    let mut tasks = Vec::new();
+    // 没有共同样本的配置排在最后
+    results.sort_by(|a, b| {
+        (a.delta.count == 0)
+            .cmp(&(b.delta.count == 0))
+            .then(a.delta.mean.partial_cmp(&b.delta.mean).unwrap_or(std::cmp::Ordering::Equal))
+    });
 
-    for endpoint in endpoints.clone() {
-        log_info(&format!("连接到 {}: {}", endpoint.name, endpoint.url));
+    let output = ColoredOutput::new();
+    output.separator();
+    println!("{}", "🏁 参数扫描排名 (相对基准配置的到达时间差，负值表示更快)".yellow().bold());
+    println!("{}", "-".repeat(28).yellow());
+    for (rank, result) in results.iter().enumerate() {
+        if result.delta.count == 0 {
+            println!("{:>2}. 无共同样本 (接收 {} 条)        {}", rank + 1, result.received, result.channel.describe());
+            continue;
+        }
+        println!(
+            "{:>2}. 平均 {:+7.2}ms, p50 {:+7.2}ms, p99 {:+7.2}ms, 首先接收 {:6.2}%  {}",
+            rank + 1,
+            result.delta.mean,
+            result.delta.median,
+            result.delta.p99,
+            result.first_percent,
+            result.channel.describe()
+        );
+    }
+    output.separator();
 
-        // 使用官方示例的连接方法，明确配置TLS
-        let mut client = match (async {
-            let mut builder = GeyserGrpcClient::build_from_shared(endpoint.url.clone())?;
+    Ok(())
+}
 
-            // 配置认证token
-            if let Some(token) = &endpoint.token {
-                builder = builder.x_token(Some(token.clone()))?;
+fn add_sources(engine: &mut ComparisonEngine, endpoints: &[Endpoint], target: &RaceTarget, frame_timestamps: bool) {
+    for endpoint in endpoints {
+        log_info(&format!("连接到 {}: {}", endpoint.name, endpoint.url));
+        match endpoint.source(target, frame_timestamps) {
+            Some(source) => {
+                engine.add_boxed_source(source);
             }
+            None => log_info(&format!("{} 不支持当前比较类型，已跳过", endpoint.name)),
+        }
+    }
+}
 
-            // 对于HTTPS端点，配置TLS
-            if endpoint.url.starts_with("https://") {
-                let tls_config = ClientTlsConfig::new().with_native_roots();
-                builder = builder.tls_config(tls_config)?;
-            }
+fn print_window(window: &WindowReport, noun: &str) {
+    use colored::*;
+    println!(
+        "{}",
+        format!(
+            "🕐 窗口 #{}: {} ~ {} ({:.0}秒, {} 个{})",
+            window.index, window.start, window.end, window.duration_sec, window.keys, noun
+        )
+        .yellow()
+        .bold()
+    );
+    for endpoint in &window.endpoints {
+        let summary = &endpoint.summary;
+        println!(
+            "  {:width$}: 首先接收 {:>6.2}% ({}/{}), 落后时平均 {:>6.2}ms, p50 {:>6.2}ms, p90 {:>6.2}ms, p99 {:>6.2}ms, {} 条消息, {} 个错误{}",
+            summary.endpoint,
+            summary.first_percent(),
+            summary.first,
+            summary.scored,
+            summary.avg_behind_ms,
+            summary.p50_ms,
+            endpoint.p90_ms,
+            summary.p99_ms,
+            endpoint.messages,
+            endpoint.errors,
+            if endpoint.connected { "" } else { " (已断开)" },
+            width = get_max_name_length()
+        );
+    }
+}
 
-            log_info(&format!("尝试连接到 {}...", endpoint.name));
-            builder.connect().await.map_err(anyhow::Error::from)
-        }).await {
-            Ok(client) => client,
-            Err(e) => {
-                log_info(&format!("连接 {} 失败: {:?}", endpoint.name, e));
-                let mut stats = endpoint_stats.lock().await;
-                if let Some(stat) = stats.get_mut(&endpoint.name) {
-                    stat.is_available = false;
+fn append_window_json(path: &PathBuf, window: &WindowReport) {
+    use std::io::Write;
+    let written = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .and_then(|mut file| writeln!(file, "{}", serde_json::to_string(window).unwrap_or_default()));
+    if let Err(e) = written {
+        log_info(&format!("写入窗口 JSON 失败: {}", e));
+    }
+}
+
+// SIGTERM 也按 Ctrl-C 处理，这样在 systemd 等进程管理器下停止时最后一个窗口不会丢失
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                tokio::select! {
+                    _ = tokio::signal::ctrl_c() => {}
+                    _ = terminate.recv() => {}
                 }
-                let mut active = active_endpoints.lock().await;
-                active.remove(&endpoint.name);
-                continue;
             }
-        };
-
-
-        let endpoint_name = endpoint.name.clone();
-        let block_data_by_slot = block_data_by_slot.clone();
-        let endpoint_stats = endpoint_stats.clone();
-        let first_slot_received = first_slot_received.clone();
-        let active_endpoints = active_endpoints.clone();
-        let first_received_slots = first_received_slots.clone();
-        let started_formal_stats = started_formal_stats.clone();
-        let pending_block_data = pending_block_data.clone();
-        let test_end_time = end_time;
-
-        let task = tokio::spawn(async move {
-            let mut slots_filter = HashMap::new();
-            slots_filter.insert("slot".to_string(), SubscribeRequestFilterSlots {
-                filter_by_commitment: Some(true),
-                ..Default::default()
-            });
+            Err(_) => {
+                let _ = tokio::signal::ctrl_c().await;
+            }
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = tokio::signal::ctrl_c().await;
+}
 
-            let request = SubscribeRequest {
-                slots: slots_filter,
-                commitment: Some(CommitmentLevel::Processed as i32),
-                ..Default::default()
-            };
+struct DaemonOptions {
+    window: Duration,
+    retention: usize,
+    window_json: Option<PathBuf>,
+    metrics_addr: Option<String>,
+    alert_rules: Vec<AlertRule>,
+    alert_sinks: Vec<AlertSink>,
+    /// Fan-out groups, scored best-of-N in every window.
+    fanout: FanOut,
+}
 
-            let mut stream = match client.subscribe_once(request).await {
-                Ok(stream) => stream,
-                Err(e) => {
-                    log_info(&format!("{} 订阅失败: {}", endpoint_name, e));
-                    let mut stats = endpoint_stats.lock().await;
-                    if let Some(stat) = stats.get_mut(&endpoint_name) {
-                        stat.is_available = false;
-                    }
-                    let mut active = active_endpoints.lock().await;
-                    active.remove(&endpoint_name);
-                    return;
-                }
-            };
+// 守护模式: 一直运行到收到停止信号，按固定窗口输出统计；
+// 聚合器不累计全程统计，也不做按类别/合并等分析，内存占用不随运行时间增长
+async fn run_daemon(
+    endpoints: Vec<Endpoint>,
+    target: RaceTarget,
+    config: ComparisonConfig,
+    frame_timestamps: bool,
+    options: DaemonOptions,
+) -> Result<()> {
+    let max_name_length = endpoints.iter().map(|e| e.name.len()).max().unwrap_or(0);
+    set_max_name_length(max_name_length);
+    let (noun, _) = target.unit();
+
+    log_info("守护模式: 持续对比多个 GRPC 服务性能, 按 Ctrl-C 停止");
+    log_info(&format!("窗口长度: {}, 保留最近 {} 个窗口", format_window(options.window), options.retention));
+    log_info(&format!("执行模式: {}", config.execution_mode.describe()));
+
+    let mut engine = ComparisonEngine::new(config);
+    add_sources(&mut engine, &endpoints, &target, frame_timestamps);
+    let names: Vec<String> = engine.endpoints().into_iter().map(|(name, _)| name).collect();
+
+    let mut comparison = engine.start();
+    let throughput = comparison.throughput();
+    let Some(mut events) = comparison.take_events() else {
+        anyhow::bail!("aggregator events are not available");
+    };
+    // 多连接端点的 NAME 在断开告警中代表整组: 任一连接在线即在线
+    let fanout = options.fanout.clone();
+    let health = || {
+        let mut health = throughput.health();
+        health.extend(fanout.group_health(&health));
+        health
+    };
+    let windows = Arc::new(Mutex::new(RollingWindows::new(
+        names,
+        options.window,
+        options.retention,
+        Instant::now(),
+        Local::now(),
+    )
+    .with_fanout(options.fanout)));
+
+    let server = match &options.metrics_addr {
+        Some(addr) => {
+            let listener = TcpListener::bind(addr).await?;
+            log_info(&format!("指标服务: http://{}/metrics, http://{}/windows", addr, addr));
+            Some(tokio::spawn(serve(listener, windows.clone())))
+        }
+        None => None,
+    };
+    if let Some(path) = &options.window_json {
+        log_info(&format!("窗口 JSON 输出: {}", path.display()));
+    }
 
-            while let Some(message) = stream.next().await {
-                if Instant::now() >= test_end_time {
+    // 告警只在状态变化时发送: 开始违反规则时一次，恢复时一次
+    let mut monitor = AlertMonitor::new(options.alert_rules);
+    for rule in monitor.rules() {
+        log_info(&format!("告警规则: {}", rule));
+    }
+    for sink in &options.alert_sinks {
+        log_info(&format!("告警通知: {}", sink));
+    }
+    let notifier = AlertNotifier::spawn(options.alert_sinks);
+    let raise = |alerts: Vec<Alert>| {
+        for alert in alerts {
+            match alert.status {
+                AlertStatus::Firing => log_info(&format!("🚨 告警: {}", alert.message)),
+                AlertStatus::Resolved => log_info(&format!("✅ 恢复: {}", alert.message)),
+            }
+            notifier.notify(alert);
+        }
+    };
+    let close_window = |windows: &Mutex<RollingWindows>| {
+        let report = windows.lock().unwrap().close(Instant::now(), Local::now(), &throughput.health());
+        print_window(&report, noun);
+        if let Some(path) = &options.window_json {
+            append_window_json(path, &report);
+        }
+        report
+    };
+
+    let mut health_check = interval(Duration::from_secs(1));
+    let shutdown = shutdown_signal();
+    tokio::pin!(shutdown);
+    let mut slot_lags = SlotLagLog::new();
+    loop {
+        let boundary = windows.lock().unwrap().boundary();
+        tokio::select! {
+            event = events.recv() => {
+                let Some(event) = event else {
                     break;
-                }
-
-                match message {
-                    Ok(update) => {
-                        if let Some(UpdateOneof::Pong(_)) = update.update_oneof {
-                            continue;
-                        }
-
-                        if let Some(UpdateOneof::Slot(slot_update)) = update.update_oneof {
-                            let current_slot = slot_update.slot;
-                            let timestamp = Instant::now();
-
-                            // 标记此端点已收到数据
-                            let mut first_received = first_slot_received.lock().await;
-                            if !first_received.get(&endpoint_name).copied().unwrap_or(false) {
-                                first_received.insert(endpoint_name.clone(), true);
-                                drop(first_received);
-
-                                let mut stats = endpoint_stats.lock().await;
-                                if let Some(stat) = stats.get_mut(&endpoint_name) {
-                                    stat.has_received_data = true;
-                                    stat.first_slot = Some(current_slot);
-                                }
-                                drop(stats);
-
-                                let mut first_slots = first_received_slots.lock().await;
-                                first_slots.insert(endpoint_name.clone(), current_slot);
-                                drop(first_slots);
-
-                                log_info(&format!(
-                                    "{} 成功接收到第一个 slot {}, 确认为可用端点",
-                                    endpoint_name,
-                                    current_slot
-                                ));
-
-                                // 检查是否可以开始正式统计
-                                let started = *started_formal_stats.lock().await;
-                                if !started {
-                                    let first_slots = first_received_slots.lock().await;
-                                    let active = active_endpoints.lock().await;
-                                    let received_data_count = first_slots.len();
-                                    let total_active_endpoints = active.len();
-
-                                    if received_data_count == total_active_endpoints && received_data_count >= 2 {
-                                        drop(first_slots);
-                                        drop(active);
-
-                                        log_info("所有活跃端点都已收到第一个slot，开始检查slot差异...");
-
-                                        if check_slots_alignment(
-                                            first_received_slots.clone(),
-                                            endpoint_stats.clone(),
-                                            active_endpoints.clone()
-                                        ).await {
-                                            let mut started = started_formal_stats.lock().await;
-                                            *started = true;
-                                            drop(started);
-
-                                            let active = active_endpoints.lock().await;
-                                            log_info(&format!("有{}个有效端点, 开始正式统计...", active.len()));
-                                            drop(active);
-
-                                            process_collected_data_fn(
-                                                pending_block_data.clone(),
-                                                block_data_by_slot.clone(),
-                                                active_endpoints.clone(),
-                                                endpoint_stats.clone(),
-                                            ).await;
-                                        }
-                                    }
-                                }
-                            }
-
-                            // 记录区块数据
-                            let mut block_data = block_data_by_slot.lock().await;
-                            block_data
-                                .entry(current_slot)
-                                .or_insert_with(Vec::new)
-                                .push(BlockData {
-                                    endpoint: endpoint_name.clone(),
-                                    slot: current_slot,
-                                    timestamp,
-                                });
-                            drop(block_data);
-
-                            // 如果尚未开始正式统计，先保存数据
-                            let started = *started_formal_stats.lock().await;
-                            if !started {
-                                let mut pending = pending_block_data.lock().await;
-                                pending
-                                    .entry(current_slot)
-                                    .or_insert_with(Vec::new)
-                                    .push(BlockData {
-                                        endpoint: endpoint_name.clone(),
-                                        slot: current_slot,
-                                        timestamp,
-                                    });
-                                drop(pending);
-                                continue;
-                            }
-
-                            // 等待其他端点数据以进行比较
-                            sleep(Duration::from_millis(50)).await;
-
-                            // 进行实时统计
-                            let active = active_endpoints.lock().await;
-                            if active.len() < 2 {
-                                drop(active);
-                                continue;
-                            }
-
-                            let block_data = block_data_by_slot.lock().await;
-                            let block_data_list = match block_data.get(&current_slot) {
-                                Some(list) => list.clone(),
-                                None => continue,
-                            };
-                            drop(block_data);
-
-                            let received_endpoints: HashSet<_> = block_data_list
-                                .iter()
-                                .map(|bd| bd.endpoint.clone())
-                                .collect();
-
-                            let all_active_endpoints_received = active
-                                .iter()
-                                .all(|ep| received_endpoints.contains(ep));
-
-                            if all_active_endpoints_received {
-                                let mut stats = endpoint_stats.lock().await;
-                                for endpoint in active.iter() {
-                                    if block_data_list
-                                        .iter()
-                                        .any(|bd| &bd.endpoint == endpoint)
-                                    {
-                                        if let Some(stat) = stats.get_mut(endpoint) {
-                                            stat.total_received += 1;
-                                        }
-                                    }
-                                }
-                                drop(stats);
-
-                                let active_endpoint_data: Vec<_> = block_data_list
-                                    .iter()
-                                    .filter(|bd| active.contains(&bd.endpoint))
-                                    .cloned()
-                                    .collect();
-
-                                let _earliest_timestamp = active_endpoint_data
-                                    .iter()
-                                    .map(|bd| bd.timestamp)
-                                    .min()
-                                    .unwrap();
-
-                                // 按时间戳排序，确保稳定的排序结果
-                                let mut sorted_data = active_endpoint_data.clone();
-                                sorted_data.sort_by(|a, b| {
-                                    a.timestamp.cmp(&b.timestamp)
-                                        .then_with(|| a.endpoint.cmp(&b.endpoint))
-                                });
-
-                                // 找到真正最早的端点（第一个）
-                                let first_endpoint = sorted_data
-                                    .first()
-                                    .unwrap();
-
-                                let mut stats = endpoint_stats.lock().await;
-
-                                // 首先输出首次接收的端点
-                                if let Some(stat) = stats.get_mut(&first_endpoint.endpoint) {
-                                    stat.first_received += 1;
-                                    log_info(&format!(
-                                        "{:width$} 接收 slot {}: 首次接收",
-                                        first_endpoint.endpoint,
-                                        current_slot,
-                                        width = get_max_name_length()
-                                    ));
-                                }
-
-                                // 然后按排序顺序输出延迟的端点
-                                for bd in &sorted_data {
-                                    if bd.endpoint != first_endpoint.endpoint {
-                                        let latency = bd.timestamp.duration_since(first_endpoint.timestamp).as_nanos() as f64 / 1_000_000.0;
-                                        if latency >= 0.01 { // 只显示大于0.01ms的延迟
-                                            if let Some(stat) = stats.get_mut(&bd.endpoint) {
-                                                stat.latencies.push(latency);
-                                                stat.total_latency += latency;
-                                                log_info(&format!(
-                                                    "{:width$} 接收 slot {}: 延迟 {:>6.2}ms (相对于 {})",
-                                                    bd.endpoint,
-                                                    current_slot,
-                                                    latency,
-                                                    first_endpoint.endpoint,
-                                                    width = get_max_name_length()
-                                                ));
-                                            }
-                                        }
-                                    }
-                                }
-                                drop(stats);
-
-                                // 清理旧数据
-                                let mut block_data = block_data_by_slot.lock().await;
-                                let old_slots: Vec<_> = block_data
-                                    .keys()
-                                    .filter(|&&slot| slot < current_slot.saturating_sub(100))
-                                    .copied()
-                                    .collect();
-                                for slot in old_slots {
-                                    block_data.remove(&slot);
-                                }
-                                drop(block_data);
-                            }
-                            drop(active);
-                        }
+                };
+                match &event {
+                    AggregatorEvent::FirstArrival { endpoint, key } => {
+                        log_info(&format!("{} 成功接收到第一个 {}, 确认为可用端点", endpoint, key));
                     }
-                    Err(error) => {
-                        log_info(&format!("{} GRPC 流错误: {}", endpoint_name, error));
-                        let mut stats = endpoint_stats.lock().await;
-                        if let Some(stat) = stats.get_mut(&endpoint_name) {
-                            stat.is_available = false;
-                        }
-                        let mut active = active_endpoints.lock().await;
-                        active.remove(&endpoint_name);
-
-                        if active.len() < 2 {
-                            log_info(&format!(
-                                "由于 {} 出错, 活跃端点不足两个 (当前{}个), 无法进行对比分析",
-                                endpoint_name,
-                                active.len()
-                            ));
+                    AggregatorEvent::Excluded { endpoint, slot, tip } => {
+                        log_info(&format!("{} 的slot ({}) 比最新slot旧 (最新: {}), 暂不参与性能比较", endpoint, slot, tip));
+                    }
+                    AggregatorEvent::Flagged { endpoint, slot, tip } => {
+                        log_info(&format!("{} 的slot ({}) 比最新slot旧 (最新: {}), 仍参与性能比较", endpoint, slot, tip));
+                    }
+                    AggregatorEvent::CaughtUp { endpoint, readmitted } => {
+                        log_info(&format!("{} 已追上最新slot{}", endpoint, if *readmitted { ", 重新参与性能比较" } else { "" }));
+                    }
+                    AggregatorEvent::SlotLag { tip, behind } => {
+                        for line in slot_lags.changes(*tip, behind) {
+                            log_info(&line);
                         }
-                        break;
                     }
+                    AggregatorEvent::Started { active } => {
+                        log_info(&format!("有{}个有效端点, 开始正式统计...", active));
+                    }
+                    AggregatorEvent::Measuring { keys } => {
+                        log_info(&format!("预热结束 (共 {} 个), 之后的数据计入窗口统计", keys));
+                    }
+                    AggregatorEvent::EndpointLost { endpoint, reason, .. } => {
+                        log_info(&format!("{} 连接中断: {}", endpoint, reason));
+                    }
+                    AggregatorEvent::Resolved(_) => {}
                 }
+                windows.lock().unwrap().observe(&event);
             }
-        });
+            _ = health_check.tick() => raise(monitor.check_health(Instant::now(), &health())),
+            _ = sleep_until(boundary.into()) => {
+                let report = close_window(&windows);
+                raise(monitor.check_window(&report));
+            }
+            _ = &mut shutdown => {
+                log_info("收到停止信号, 输出最后一个窗口...");
+                break;
+            }
+        }
+    }
+
+    comparison.stop().await?;
+    while let Ok(event) = events.try_recv() {
+        windows.lock().unwrap().observe(&event);
+    }
+    // 最后一个窗口通常不完整，不再据此告警
+    close_window(&windows);
+    notifier.close().await;
+    if let Some(server) = server {
+        server.abort();
+    }
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn compare_grpc_endpoints(
+    endpoints: Vec<Endpoint>,
+    target: RaceTarget,
+    config: ComparisonConfig,
+    frame_timestamps: bool,
+    trace: Option<PathBuf>,
+    tui: bool,
+    reports: &[(PathBuf, ReportFormat)],
+    analyses: Analyses,
+) -> Result<ComparisonReport> {
+    let test_duration_sec = config.duration.as_secs();
+    let execution_mode = config.execution_mode.describe();
+    let aggregator_config = config.aggregator.clone();
+    // 计算最大端点名称长度用于对齐输出
+    let max_name_length = endpoints.iter().map(|e| e.name.len()).max().unwrap_or(0);
+    set_max_name_length(max_name_length);
+    
+    log_info("开始对比多个 GRPC 服务性能...");
+    log_info(&format!("测试持续时间: {}秒", test_duration_sec));
+    if aggregator_config.has_warmup() || !aggregator_config.cooldown.is_zero() {
+        log_info(&format!(
+            "预热: {:.1}秒 / 至少 {} 个, 冷却: 最后 {:.1}秒",
+            aggregator_config.warmup.as_secs_f64(),
+            aggregator_config.warmup_keys,
+            aggregator_config.cooldown.as_secs_f64()
+        ));
+    }
+    if frame_timestamps {
+        log_info("时间戳模式: 传输层帧拉取时间 (解码耗时单独统计)");
+    }
+    log_info(&format!("执行模式: {}", config.execution_mode.describe()));
+    log_info(&format!(
+        "测试端点: {}",
+        endpoints.iter().map(|e| e.name.as_str()).collect::<Vec<_>>().join(", ")
+    ));
 
-        tasks.push(task);
+    // 所有统计状态由聚合任务独占，数据源只负责打时间戳并发送
+    let mut engine = ComparisonEngine::new(config);
+    if let Some(path) = &trace {
+        engine.record_trace(path)?;
+        log_info(&format!("记录到达轨迹: {}", path.display()));
     }
+    add_sources(&mut engine, &endpoints, &target, frame_timestamps);
 
-    // 进度监控
-    let progress_task = tokio::spawn(async move {
+    let names: Vec<String> = engine.endpoints().into_iter().map(|(name, _)| name).collect();
+
+    let mut comparison = engine.start();
+    let start_time = comparison.started_at();
+    let end_time = comparison.deadline();
+    let throughput = comparison.throughput();
+
+    let dashboard = if tui {
+        let state = DashboardState::new("gRPC 端点对比", &names, start_time, end_time);
+        match Dashboard::open(state, throughput.clone()) {
+            Ok(dashboard) => Some(dashboard),
+            Err(e) => {
+                log_info(&format!("无法启动终端界面, 改用日志输出: {}", e));
+                None
+            }
+        }
+    } else {
+        None
+    };
+    let log_task = comparison
+        .take_events()
+        .map(|events| tokio::spawn(log_aggregator_events(events, analyses, dashboard.as_ref().map(Dashboard::state))));
+
+    // 进度监控 (终端界面自带进度条)
+    let progress_task = dashboard.is_none().then(|| tokio::spawn(async move {
         let mut progress_interval = interval(Duration::from_secs(5));
         loop {
             progress_interval.tick().await;
@@ -667,63 +1307,61 @@ async fn compare_grpc_endpoints(endpoints: Vec<GrpcEndpoint>, test_duration_sec:
                     "===== 测试进度: {}% [{}/{}秒] - 剩余时间: {}秒 =====",
                     progress_percent, elapsed_sec, test_duration_sec, remaining_sec
                 ));
+                for (name, snapshot) in throughput.snapshot() {
+                    log_info(&format!(
+                        "{:width$} 吞吐 {}",
+                        name,
+                        format_throughput(&snapshot, snapshot.current),
+                        width = get_max_name_length()
+                    ));
+                }
             }
 
             if Instant::now() >= end_time {
                 break;
             }
         }
-    });
+    }));
 
-    // 等待测试结束
-    tokio::select! {
-        _ = progress_task => {},
-        _ = sleep(Duration::from_secs(test_duration_sec)) => {},
+    // 等待测试结束并取消所有任务; 终端界面中按 q 提前结束
+    let report = match &dashboard {
+        Some(dashboard) => {
+            tokio::select! {
+                _ = sleep_until(end_time.into()) => {}
+                _ = dashboard.quit_requested() => {}
+            }
+            comparison.stop().await?
+        }
+        None => comparison.finish().await?,
+    };
+    if let Some(progress_task) = progress_task {
+        progress_task.abort();
     }
-
-    // 取消所有任务
-    for task in tasks {
-        task.abort();
+    if let Some(dashboard) = dashboard {
+        dashboard.close().await?;
     }
+    let Analyses { breakdown, paired, fanout, merger, timeline } = match log_task {
+        Some(log_task) => log_task.await.unwrap_or_default(),
+        None => Analyses::default(),
+    };
+    // 多连接端点的 best-of-N 结果以 NAME 计入报告、历史记录和 SLO 断言
+    let mut report = report;
+    fanout.add_to_report(&mut report);
+    let stats = &report.stats;
+    let (noun, unit) = target.unit();
 
     let output = ColoredOutput::new();
     output.success("测试完成，正在分析结果...");
+    if let (Some(path), Some(records)) = (&trace, report.trace_records) {
+        output.info(&format!("到达轨迹: {} ({} 条记录)", path.display(), records));
+    }
     output.separator();
 
     // 分析和输出结果
-    let stats = endpoint_stats.lock().await;
-
     for endpoint in &endpoints {
         if let Some(stat) = stats.get(&endpoint.name) {
-            if stat.total_received > 0 {
-                let avg_latency = if stat.latencies.is_empty() {
-                    0.0
-                } else {
-                    stat.total_latency / stat.latencies.len() as f64
-                };
-
-                let min_latency = stat.latencies.iter().fold(f64::INFINITY, |a, &b| a.min(b));
-                let max_latency = stat.latencies.iter().fold(f64::NEG_INFINITY, |a, &b| a.max(b));
-
-                output.subheader(&format!("📊 {} 性能分析", endpoint.name));
-                output.metric("总接收区块数", &stat.total_received.to_string(), "blocks");
-                
-                let first_percent = (stat.first_received as f64 / stat.total_received as f64) * 100.0;
-                output.metric("首先接收区块数", &format!("{} ({:.2}%)", stat.first_received, first_percent), "blocks");
-
-                let delayed_count = stat.latencies.len();
-                let delayed_percent = (delayed_count as f64 / stat.total_received as f64) * 100.0;
-                output.metric("落后接收区块数", &format!("{} ({:.2}%)", delayed_count, delayed_percent), "blocks");
-
-                if !stat.latencies.is_empty() {
-                    output.info("延迟统计 (相对于最快端点):");
-                    output.metric("  平均延迟", &format!("{:.2}", avg_latency), "ms");
-                    output.metric("  最小延迟", &format!("{:.2}", min_latency), "ms");
-                    output.metric("  最大延迟", &format!("{:.2}", max_latency), "ms");
-                } else {
-                    output.success("该端点始终是最快的，没有延迟数据");
-                }
-                
+            if stat.scored > 0 {
+                stat.print_summary(&endpoint.name, noun, unit);
                 output.separator();
             } else {
                 output.warning(&format!("{}: 没有收集到数据", endpoint.name));
@@ -731,8 +1369,35 @@ async fn compare_grpc_endpoints(endpoints: Vec<GrpcEndpoint>, test_duration_sec:
         }
     }
 
+    report.warmup.print_summary(&aggregator_config, &names, noun, get_max_name_length());
+    output.separator();
+
     // 性能对比
     use colored::*;
+    if !breakdown.is_empty() {
+        let names: Vec<String> = endpoints.iter().map(|e| e.name.clone()).collect();
+        breakdown.print_summary("📈 按协议统计", &names, get_max_name_length());
+        output.separator();
+    }
+
+    print_throughput(&report.throughput, get_max_name_length());
+    output.separator();
+
+    if !fanout.is_empty() {
+        print_fanout(&fanout);
+        output.separator();
+    }
+
+    if !paired.is_empty() {
+        print_compression_comparison(&paired, &report.throughput);
+        output.separator();
+    }
+
+    if merger.endpoint_count() > 1 {
+        merger.print_summary();
+        output.separator();
+    }
+
     let title = "🏆 端点性能对比";
     println!("{}", title.yellow().bold());
     println!("{}", "-".repeat(28).yellow());
@@ -741,11 +1406,11 @@ async fn compare_grpc_endpoints(endpoints: Vec<GrpcEndpoint>, test_duration_sec:
         .iter()
         .filter_map(|e| {
             stats.get(&e.name).and_then(|stat| {
-                if stat.total_received > 0 {
+                if stat.scored > 0 {
                     Some((
                         &e.name,
                         stat,
-                        stat.first_received as f64 / stat.total_received as f64,
+                        stat.first as f64 / stat.scored as f64,
                     ))
                 } else {
                     None
@@ -758,23 +1423,13 @@ async fn compare_grpc_endpoints(endpoints: Vec<GrpcEndpoint>, test_duration_sec:
 
     if sorted_endpoints.len() >= 2 {
         for (name, stat, first_percent) in sorted_endpoints {
-            let avg_latency_when_slower = if stat.latencies.is_empty() {
-                0.0
-            } else {
-                stat.total_latency / stat.latencies.len() as f64
-            };
-
-            let avg_latency_total = if stat.total_received > 0 {
-                stat.total_latency / stat.total_received as f64
-            } else {
-                0.0
-            };
-
-            println!("{:12}: 首先接收 {:>6.2}%, 落后时平均延迟 {:>6.2}ms, 总体平均延迟 {:>6.2}ms",
+            println!("{:12}: 首先接收 {:>6.2}%, 同时接收 {:>6.2}%, 落后时平均延迟 {:>6.2}ms, 总体平均延迟 {:>6.2}ms, 超时未收到 {:>5.2}%",
                 name,
                 first_percent * 100.0,
-                avg_latency_when_slower,
-                avg_latency_total
+                stat.percent_of_scored(stat.tied),
+                stat.get_average_latency(),
+                stat.get_overall_average_latency(),
+                stat.get_miss_rate()
             );
         }
     } else if sorted_endpoints.len() == 1 {
@@ -784,10 +1439,42 @@ async fn compare_grpc_endpoints(endpoints: Vec<GrpcEndpoint>, test_duration_sec:
     }
 
     output.separator();
+
+    if !reports.is_empty() {
+        let environment = RunEnvironment::detect();
+        let started: DateTime<Local> = Local::now() - report.elapsed;
+        let mut run_report = RunReport::new("gRPC 端点对比报告", &report.endpoints, stats, timeline)
+            .with_meta("开始时间", started.format("%Y-%m-%d %H:%M:%S"))
+            .with_meta("时长", format!("{:.0}秒", report.elapsed.as_secs_f64()))
+            .with_meta("比较对象", noun)
+            .with_meta("执行模式", &execution_mode)
+            .with_meta("预热", format!("{} 个, 共 {:.1}秒", report.warmup.keys, report.warmup.measuring_after.unwrap_or_default().as_secs_f64()))
+            .with_meta("主机", &environment.hostname);
+        if let Some(region) = &environment.region {
+            run_report = run_report.with_meta("区域", region);
+        }
+        run_report = run_report.with_meta("版本", &environment.version);
+        for endpoint in &endpoints {
+            run_report = run_report.with_meta(&endpoint.name, &endpoint.url);
+        }
+        write_reports(reports, &run_report);
+        output.separator();
+    }
+
     output.success("测试完成，正在关闭连接...");
     output.success("所有连接已关闭，测试结束");
 
-    Ok(())
+    Ok(report)
+}
+
+fn write_reports(reports: &[(PathBuf, ReportFormat)], report: &RunReport) {
+    let output = ColoredOutput::new();
+    for (path, format) in reports {
+        match report.write(path, *format) {
+            Ok(()) => output.success(&format!("报告已生成: {}", path.display())),
+            Err(e) => output.error(&format!("生成报告 {} 失败: {}", path.display(), e)),
+        }
+    }
 }
 
 #[tokio::main]
@@ -803,8 +1490,14 @@ async fn main() -> Result<()> {
     
     let args = Args::parse();
 
-    // 收集所有GRPC端点
+    if let Some(Command::History(history)) = &args.command {
+        return run_history(&args.history_db, history);
+    }
+
+    // 收集所有端点
     let mut endpoints = Vec::new();
+    // 端点名称 -> 并行订阅数 (connections = N)
+    let mut connections = HashMap::new();
 
     // 从环境变量读取端点
     let env_vars: Vec<_> = env::vars()
@@ -816,50 +1509,309 @@ async fn main() -> Result<()> {
         let name = env::var(format!("GRPC_NAME_{}", index))
             .unwrap_or_else(|_| format!("GRPC_{}", index));
         let token = env::var(format!("GRPC_TOKEN_{}", index)).ok();
+        let mut channel = ChannelOptions::default();
+        if let Ok(v) = env::var(format!("GRPC_COMPRESSION_{}", index)) {
+            channel.compression = parse_compression(&v).map_err(anyhow::Error::msg)?;
+        }
+
+        if let Some(n) = env_connections("GRPC", index)? {
+            connections.insert(name.clone(), n);
+        }
+        endpoints.push(Endpoint { name, url, kind: EndpointKind::Grpc { token, channel } });
+    }
+
+    // RPC PubSub websocket 端点
+    let ws_vars: Vec<_> = env::vars()
+        .filter(|(key, _)| key.starts_with("WS_URL_"))
+        .collect();
+
+    for (key, url) in ws_vars {
+        let index = key.strip_prefix("WS_URL_").unwrap();
+        let name = env::var(format!("WS_NAME_{}", index))
+            .unwrap_or_else(|_| format!("WS_{}", index));
+
+        if let Some(n) = env_connections("WS", index)? {
+            connections.insert(name.clone(), n);
+        }
+        endpoints.push(Endpoint { name, url, kind: EndpointKind::WebSocket });
+    }
 
-        endpoints.push(GrpcEndpoint { name, url, token });
+    // HTTP JSON-RPC 轮询端点，作为基准对照
+    let polling = EndpointKind::Polling {
+        interval: Duration::from_millis(args.poll_interval_ms),
+        block_height: args.poll_block_height,
+    };
+    let rpc_vars: Vec<_> = env::vars()
+        .filter(|(key, _)| key.starts_with("RPC_URL_"))
+        .collect();
+
+    for (key, url) in rpc_vars {
+        let index = key.strip_prefix("RPC_URL_").unwrap();
+        let name = env::var(format!("RPC_NAME_{}", index))
+            .unwrap_or_else(|_| format!("RPC_{}", index));
+
+        if let Some(n) = env_connections("RPC", index)? {
+            connections.insert(name.clone(), n);
+        }
+        endpoints.push(Endpoint { name, url, kind: polling.clone() });
     }
 
     // 从命令行参数添加端点
     if let Some(url) = args.grpc_url_1 {
-        endpoints.push(GrpcEndpoint {
-            name: args.grpc_name_1.unwrap_or_else(|| "GRPC_1".to_string()),
+        let name = args.grpc_name_1.unwrap_or_else(|| "GRPC_1".to_string());
+        connections.insert(name.clone(), args.grpc_connections_1);
+        endpoints.push(Endpoint {
+            name,
             url,
-            token: args.grpc_token_1,
+            kind: EndpointKind::Grpc {
+                token: args.grpc_token_1,
+                channel: ChannelOptions {
+                    compression: args.grpc_compression_1.unwrap_or_default(),
+                    ..Default::default()
+                },
+            },
         });
     }
 
     if let Some(url) = args.grpc_url_2 {
-        endpoints.push(GrpcEndpoint {
-            name: args.grpc_name_2.unwrap_or_else(|| "GRPC_2".to_string()),
+        let name = args.grpc_name_2.unwrap_or_else(|| "GRPC_2".to_string());
+        connections.insert(name.clone(), args.grpc_connections_2);
+        endpoints.push(Endpoint {
+            name,
+            url,
+            kind: EndpointKind::Grpc {
+                token: args.grpc_token_2,
+                channel: ChannelOptions {
+                    compression: args.grpc_compression_2.unwrap_or_default(),
+                    ..Default::default()
+                },
+            },
+        });
+    }
+
+    if let Some(url) = args.ws_url_1 {
+        endpoints.push(Endpoint {
+            name: args.ws_name_1.unwrap_or_else(|| "WS_1".to_string()),
             url,
-            token: args.grpc_token_2,
+            kind: EndpointKind::WebSocket,
+        });
+    }
+
+    if let Some(url) = args.rpc_url_1 {
+        endpoints.push(Endpoint {
+            name: args.rpc_name_1.unwrap_or_else(|| "RPC_1".to_string()),
+            url,
+            kind: polling.clone(),
         });
     }
 
     // 如果没有配置任何端点，使用默认值
     if endpoints.is_empty() {
         output.warning("No endpoints configured, using default endpoints");
-        endpoints.push(GrpcEndpoint {
+        endpoints.push(Endpoint {
             name: "PublicNode_1".to_string(),
             url: "https://solana-yellowstone-grpc.publicnode.com:443".to_string(),
-            token: None,
+            kind: EndpointKind::Grpc { token: None, channel: ChannelOptions::default() },
         });
-        endpoints.push(GrpcEndpoint {
+        endpoints.push(Endpoint {
             name: "PublicNode_2".to_string(),
             url: "https://solana-yellowstone-grpc.publicnode.com:443".to_string(),
-            token: None,
+            kind: EndpointKind::Grpc { token: None, channel: ChannelOptions::default() },
         });
     }
 
+    if let Some(Command::Sweep(sweep)) = &args.command {
+        let endpoint = match &sweep.url {
+            Some(url) => Endpoint {
+                name: url.clone(),
+                url: url.clone(),
+                kind: EndpointKind::Grpc { token: sweep.token.clone(), channel: ChannelOptions::default() },
+            },
+            None => endpoints
+                .iter()
+                .find(|e| matches!(e.kind, EndpointKind::Grpc { .. }))
+                .cloned()
+                .ok_or_else(|| anyhow::anyhow!("no gRPC endpoint configured for the sweep"))?,
+        };
+        let EndpointKind::Grpc { channel, .. } = &endpoint.kind else {
+            unreachable!("sweep endpoint is always gRPC");
+        };
+        let configs = sweep.configurations(channel);
+        let target = RaceTarget::from_args(args.race, args.mentions, args.signatures, args.protocols)?;
+        let execution_mode = ExecutionMode::new(args.dedicated_runtime, args.core_ids);
+        output.info(&format!("Race: {:?}", target));
+        output.info(&format!("Execution mode: {}", execution_mode.describe()));
+        output.separator();
+        let aggregator = args.aggregator_config();
+        return run_sweep(endpoint, target, configs, args.duration, args.frame_timestamps, execution_mode, aggregator).await;
+    }
+
+    // 压缩对比: 为每个无压缩的 gRPC 端点额外建立各压缩方式的连接
+    let mut compression_pairs = Vec::new();
+    let mut variants = Vec::new();
+    for endpoint in &endpoints {
+        let EndpointKind::Grpc { token, channel } = &endpoint.kind else {
+            continue;
+        };
+        if channel.compression != GrpcCompression::None {
+            continue;
+        }
+        for compression in args.compare_compression.iter().filter(|c| **c != GrpcCompression::None) {
+            let name = format!("{}[{}]", endpoint.name, compression.name());
+            compression_pairs.push((endpoint.name.clone(), name.clone()));
+            variants.push(Endpoint {
+                name,
+                url: endpoint.url.clone(),
+                kind: EndpointKind::Grpc {
+                    token: token.clone(),
+                    channel: ChannelOptions { compression: *compression, ..channel.clone() },
+                },
+            });
+        }
+    }
+    for variant in &variants {
+        // 压缩变体沿用原端点的连接数
+        if let Some((base, _)) = compression_pairs.iter().find(|(_, name)| name == &variant.name) {
+            if let Some(n) = connections.get(base).copied() {
+                connections.insert(variant.name.clone(), n);
+            }
+        }
+    }
+    endpoints.extend(variants);
+
+    // 多连接: connections = N 的端点拆分为 N 个独立订阅的子端点 NAME#1..NAME#N
+    let mut fanout_groups = Vec::new();
+    let endpoints: Vec<Endpoint> = endpoints
+        .into_iter()
+        .flat_map(|endpoint| {
+            let n = connections.get(&endpoint.name).copied().unwrap_or(1);
+            if n <= 1 {
+                return vec![endpoint];
+            }
+            let members: Vec<String> = (0..n).map(|i| sub_endpoint_name(&endpoint.name, i)).collect();
+            fanout_groups.push((endpoint.name.clone(), members.clone()));
+            members
+                .into_iter()
+                .map(|name| Endpoint { name, ..endpoint.clone() })
+                .collect()
+        })
+        .collect();
+
     output.subheader("📋 Configured Endpoints");
     for endpoint in &endpoints {
         output.endpoint_status(&format!("{} - {}", endpoint.name, endpoint.url), EndpointStatus::Connecting);
     }
     
-    output.info(&format!("Test duration: {} seconds", args.duration));
+    // 断言中的端点名写错时在测试开始前就失败，而不是等整个测试跑完;
+    // 多连接端点的 NAME 指 best-of-N 合并结果，NAME#i 指单个连接
+    let known: Vec<&str> = endpoints
+        .iter()
+        .map(|e| e.name.as_str())
+        .chain(fanout_groups.iter().map(|(name, _)| name.as_str()))
+        .collect();
+    for assertion in &args.assertions {
+        if let Some(name) = &assertion.endpoint {
+            if !known.contains(&name.as_str()) {
+                anyhow::bail!("assertion '{}' names unknown endpoint {} (endpoints: {})", assertion, name, known.join(", "));
+            }
+        }
+    }
+    for rule in &args.alert_rules {
+        if let Some(name) = rule.endpoint() {
+            if !known.contains(&name) {
+                anyhow::bail!("alert '{}' names unknown endpoint {} (endpoints: {})", rule, name, known.join(", "));
+            }
+        }
+    }
+
+    let target = RaceTarget::from_args(args.race, args.mentions, args.signatures, args.protocols)?;
+    if args.daemon {
+        output.info(&format!("Daemon mode: {}s windows until Ctrl-C", args.window_secs));
+    } else {
+        output.info(&format!("Test duration: {} seconds", args.duration));
+        if args.cooldown_secs >= args.duration as f64 {
+            anyhow::bail!("cooldown ({}s) must be shorter than the test duration ({}s)", args.cooldown_secs, args.duration);
+        }
+    }
+    output.info(&format!("Race: {:?}", target));
     output.separator();
 
-    compare_grpc_endpoints(endpoints, args.duration).await
+    // 压缩后的线上字节数只能在传输层统计，因此压缩对比总是使用帧时间戳
+    let frame_timestamps = args.frame_timestamps || !compression_pairs.is_empty();
+    if frame_timestamps {
+        output.info("Timestamp mode: HTTP/2 frame poll time");
+    }
+    if !compression_pairs.is_empty() {
+        output.info("Compression comparison: wire bytes and arrival delta vs the uncompressed connection");
+    }
+    let execution_mode = ExecutionMode::new(args.dedicated_runtime, args.core_ids);
+    output.info(&format!("Execution mode: {}", execution_mode.describe()));
+
+    if !fanout_groups.is_empty() {
+        output.info("Connection fan-out: sub-endpoints are also scored best-of-N per endpoint");
+    }
+    if !args.assertions.is_empty() {
+        output.info(&format!("SLO assertions: {}", args.assertions.len()));
+    }
+
+    // 保存到历史记录的运行参数，不包含 token
+    let run_config = serde_json::json!({
+        "race": format!("{:?}", target),
+        "duration_sec": args.duration,
+        "warmup_secs": args.warmup_secs,
+        "warmup_keys": args.warmup_keys,
+        "cooldown_secs": args.cooldown_secs,
+        "resolve_timeout_ms": args.resolve_timeout_ms,
+        "tie_tolerance_us": args.tie_tolerance_us,
+        "max_slot_difference": args.max_slot_difference,
+        "slot_policy": args.slot_policy.name(),
+        "slot_window_secs": args.slot_window_secs,
+        "frame_timestamps": frame_timestamps,
+        "execution_mode": execution_mode.describe(),
+        "compare_compression": args.compare_compression.iter().map(|c| c.name()).collect::<Vec<_>>(),
+        "endpoints": endpoints
+            .iter()
+            .map(|e| serde_json::json!({ "name": e.name, "url": e.url }))
+            .collect::<Vec<_>>(),
+    });
+    let config = ComparisonConfig {
+        duration: if args.daemon { Duration::MAX } else { Duration::from_secs(args.duration) },
+        execution_mode,
+        aggregator: AggregatorConfig { keep_totals: !args.daemon, ..args.aggregator_config() },
+        throughput_windows: args.throughput_windows_ms.iter().copied().map(Duration::from_millis).collect(),
+    };
+    if args.daemon {
+        let options = DaemonOptions {
+            window: Duration::from_secs(args.window_secs),
+            retention: args.window_retention,
+            window_json: args.window_json,
+            metrics_addr: args.metrics_addr,
+            alert_rules: args.alert_rules,
+            alert_sinks: args.alert_sinks,
+            fanout: FanOut::new(fanout_groups),
+        };
+        return run_daemon(endpoints, target, config, frame_timestamps, options).await;
+    }
+    let analyses = Analyses {
+        paired: PairedDeltas::new(compression_pairs),
+        fanout: FanOut::new(fanout_groups),
+        ..Default::default()
+    };
+    let report = compare_grpc_endpoints(
+        endpoints,
+        target,
+        config,
+        frame_timestamps,
+        args.trace,
+        args.tui,
+        &args.report,
+        analyses,
+    )
+    .await?;
+
+    save_run(&args.history_db, RunRecord::from_report("grpc-comparison", run_config, &report));
+
+    enforce(&args.assertions, &Metrics::from_report(&report));
+    Ok(())
 }
 
@@ -1,39 +1,26 @@
-use grpc_benchmark::Result;
-use fzstream_client::{FzStreamClient, StreamClientConfig};
-use fzstream_common::EventTypeFilter;
+use grpc_benchmark::aggregator::{AggregatorConfig, AggregatorEvent, SlotLagLog, SlotPolicy, DEFAULT_TIE_TOLERANCE};
+use grpc_benchmark::breakdown::CategoryBreakdown;
+use grpc_benchmark::merger::MergeSimulator;
+use grpc_benchmark::events::{parse_event_types, parse_protocols, DexProtocol};
+use grpc_benchmark::engine::{ComparisonConfig, ComparisonEngine};
+use grpc_benchmark::runtime::ExecutionMode;
+use grpc_benchmark::engine::ComparisonReport;
+use grpc_benchmark::history::{HistoryStore, RunRecord, DEFAULT_HISTORY_DB};
+use grpc_benchmark::slo::{enforce, Assertion, Metrics};
+use grpc_benchmark::throughput::{format_throughput, print_throughput};
+use grpc_benchmark::source::{FzCompression, FzStreamSource, StreamSource, StreamerSdkSource, YellowstoneSource};
 use solana_streamer_sdk::streaming::event_parser::common::EventType;
-use solana_streamer_sdk::match_event as solana_match_event;
-use solana_streamer_sdk::streaming::event_parser::core::UnifiedEvent;
-use solana_streamer_sdk::streaming::event_parser::protocols::BlockMetaEvent;
-use solana_streamer_sdk::streaming::YellowstoneGrpc;
-use solana_streamer_sdk::streaming::yellowstone_grpc::{TransactionFilter, AccountFilter};
-use solana_streamer_sdk::streaming::event_parser::common::filter::EventTypeFilter as GrpcEventTypeFilter;
-use solana_streamer_sdk::streaming::event_parser::common::types::EventType as GrpcEventType;
+use grpc_benchmark::{BenchmarkError, Result};
 // 移除 tracing，直接使用 println!
 use std::env;
+use std::path::PathBuf;
 use chrono::{DateTime, Local};
 use colored::*;
 use grpc_benchmark::output::ColoredOutput;
 use std::time::{Duration, Instant};
-use std::collections::{HashMap, HashSet};
-use std::sync::{Arc, Mutex as StdMutex};
-use tokio::sync::Mutex;
-use tokio::time::{interval, sleep};
-
-
-// Program IDs for major Solana DeFi protocols
-#[allow(dead_code)]
-const PUMPFUN_PROGRAM_ID: &str = "6EF8rrecthR5Dkzon8Nwu78hRvfCKubJ14M5uBEwF6P";
-#[allow(dead_code)]
-const PUMPSWAP_PROGRAM_ID: &str = "PSwpkKNJhTNm5CbhHTNNfCEEF7ZdA8fxh4Wj1S6GzPo";
-#[allow(dead_code)]
-const BONK_PROGRAM_ID: &str = "treaf4wWBBty3fHdyBpo35Mz84M8k3heKXmjmi9vFt5";
-#[allow(dead_code)]
-const RAYDIUM_CPMM_PROGRAM_ID: &str = "CPMMoo8L3F4NbTegBCKVNunggL7H1ZpdTHKxQB5qKP1C";
-#[allow(dead_code)]
-const RAYDIUM_CLMM_PROGRAM_ID: &str = "CAMMCzo5YL8w4VFF8KVHrK22GGUQpMAS4ZnukSFGUvJ";
-#[allow(dead_code)]
-const RAYDIUM_AMM_V4_PROGRAM_ID: &str = "675kPX9MHTjS2zt1qfr1NYHuzeLXfQM9H24wFSUt1Mp8";
+use tokio::sync::mpsc;
+use tokio::time::interval;
+
 
 // 添加格式化日志函数，参考 grpc_comparison.rs
 fn log_info(msg: &str) {
@@ -105,54 +92,191 @@ fn extract_log_parts(msg: &str) -> Option<LogParts> {
 }
 
 #[derive(Debug, Clone)]
-struct BlockData {
-    endpoint: String,
-    #[allow(dead_code)]
-    slot: u64,
-    timestamp: Instant,
+struct Endpoint {
+    name: String,
+    endpoint_type: EndpointType,
+}
+
+#[derive(Debug, Clone)]
+enum EndpointType {
+    FzStream { address: String, auth_token: String, compression: Option<FzCompression> },
+    Grpc { url: String, token: Option<String> },
 }
 
-#[derive(Debug)]
-struct EndpointStats {
-    total_latency: f64,
-    latencies: Vec<f64>,
-    first_received: u64,
-    total_received: u64,
-    is_available: bool,
-    has_received_data: bool,
-    first_slot: Option<u64>,
-    #[allow(dead_code)]
-    processed_slots: HashSet<u64>, // 记录已处理的slot，避免重复
+/// 比较内容: BlockMeta (按 slot)、指定的 DEX 事件 (按签名 + 事件类型) 或 DEX 协议 (按签名 + 协议)
+#[derive(Debug, Clone)]
+enum RaceMode {
+    BlockMeta,
+    Events(Vec<EventType>),
+    Protocols(Vec<DexProtocol>),
 }
 
-impl EndpointStats {
-    fn new() -> Self {
-        Self {
-            total_latency: 0.0,
-            latencies: Vec::new(),
-            first_received: 0,
-            total_received: 0,
-            is_available: true,
-            has_received_data: false,
-            first_slot: None,
-            processed_slots: HashSet::new(), // 初始化已处理slots集合
+impl Endpoint {
+    fn source(&self, mode: &RaceMode, frame_timestamps: bool, compare_compression: bool) -> Box<dyn StreamSource> {
+        let name = self.name.clone();
+        match (&self.endpoint_type, mode) {
+            (EndpointType::FzStream { address, auth_token, compression }, _) => {
+                let mut source = match mode {
+                    RaceMode::BlockMeta => FzStreamSource::block_meta(name, address.clone(), auth_token.clone()),
+                    RaceMode::Events(event_types) => {
+                        FzStreamSource::events(name, address.clone(), auth_token.clone(), event_types.clone())
+                    }
+                    RaceMode::Protocols(protocols) => {
+                        FzStreamSource::protocols(name, address.clone(), auth_token.clone(), protocols)
+                    }
+                };
+                if let Some(compression) = compression {
+                    source = source.with_compression(*compression);
+                }
+                // 压缩对比时经本地 UDP 中继统计线上字节数；中继跑在独立线程上，不计入端点 CPU，
+                // 但客户端连的是 127.0.0.1，TLS 不再按真实主机名校验，只适合可信服务器
+                if compare_compression {
+                    source = source.with_wire_accounting();
+                }
+                Box::new(source)
+            }
+            // 帧时间戳模式下直接订阅 BlockMeta，跳过 solana-streamer-sdk 的事件解析
+            (EndpointType::Grpc { url, token }, RaceMode::BlockMeta) if frame_timestamps => Box::new(
+                YellowstoneSource::block_meta(name, url.clone(), token.clone()).with_frame_timestamps(true),
+            ),
+            (EndpointType::Grpc { url, token }, RaceMode::BlockMeta) => {
+                Box::new(StreamerSdkSource::block_meta(name, url.clone(), token.clone()))
+            }
+            // 事件比较需要与 FzStream 相同的解析逻辑，因此始终经过 solana-streamer-sdk
+            (EndpointType::Grpc { url, token }, RaceMode::Events(event_types)) => {
+                Box::new(StreamerSdkSource::events(name, url.clone(), token.clone(), event_types.clone()))
+            }
+            // 协议比较直接订阅提及各协议程序的交易
+            (EndpointType::Grpc { url, token }, RaceMode::Protocols(protocols)) => Box::new(
+                YellowstoneSource::protocols(name, url.clone(), token.clone(), protocols)
+                    .with_frame_timestamps(frame_timestamps),
+            ),
         }
     }
 }
 
-#[derive(Debug, Clone)]
-struct Endpoint {
-    name: String,
-    endpoint_type: EndpointType,
+// 将聚合任务的事件输出为日志
+async fn log_aggregator_events(
+    mut events: mpsc::UnboundedReceiver<AggregatorEvent>,
+) -> (CategoryBreakdown, MergeSimulator) {
+    let mut breakdown = CategoryBreakdown::new();
+    let mut merger = MergeSimulator::new();
+    let mut slot_lags = SlotLagLog::new();
+    while let Some(event) = events.recv().await {
+        breakdown.observe(&event);
+        merger.observe(&event);
+        match event {
+            AggregatorEvent::FirstArrival { endpoint, key } => {
+                log_info(&format!("{} 成功接收到第一个 {}, 确认为可用端点", endpoint, key));
+            }
+            AggregatorEvent::Excluded { endpoint, slot, tip } => {
+                log_info(&format!(
+                    "{} 的slot ({}) 比最新slot旧 (最新: {}, 落后: {}个区块), 将不参与性能比较",
+                    endpoint, slot, tip, tip.saturating_sub(slot)
+                ));
+            }
+            AggregatorEvent::Flagged { endpoint, slot, tip } => {
+                log_info(&format!(
+                    "{} 的slot ({}) 比最新slot旧 (最新: {}, 落后: {}个区块), 仍参与性能比较",
+                    endpoint, slot, tip, tip.saturating_sub(slot)
+                ));
+            }
+            AggregatorEvent::CaughtUp { endpoint, readmitted } => {
+                if readmitted {
+                    log_info(&format!("{} 已追上最新slot, 重新参与性能比较", endpoint));
+                } else {
+                    log_info(&format!("{} 已追上最新slot", endpoint));
+                }
+            }
+            AggregatorEvent::SlotLag { tip, behind } => {
+                for line in slot_lags.changes(tip, &behind) {
+                    log_info(&line);
+                }
+            }
+            AggregatorEvent::Started { active } => {
+                log_info(&format!("有{}个有效端点, 开始正式统计...", active));
+            }
+            AggregatorEvent::Measuring { keys } => {
+                log_info(&format!("预热结束 (共 {} 个), 之后的数据计入统计", keys));
+            }
+            AggregatorEvent::EndpointLost { endpoint, reason, remaining } => {
+                log_info(&format!("{} 已断开: {} (剩余 {} 个活跃端点)", endpoint, reason, remaining));
+            }
+            AggregatorEvent::Resolved(resolution) => {
+                for line in resolution.log_lines(get_max_name_length()) {
+                    log_info(&line);
+                }
+            }
+        }
+    }
+    (breakdown, merger)
 }
 
-#[derive(Debug, Clone)]
-enum EndpointType {
-    FzStream { address: String, auth_token: String },
-    Grpc { url: String, token: Option<String> },
+// 压缩对比: 线上字节、CPU 时间 (接收+解压+解析) 与延迟，均以无压缩连接为基准
+fn print_compression_report(report: &ComparisonReport, endpoints: &[Endpoint], unit: &str) {
+    println!("{}", "🗜 压缩对比".yellow().bold());
+    println!("{}", "-".repeat(28).yellow());
+
+    // (端点, 编码, 每条消息字节数, 每条消息 CPU 微秒)
+    let rows: Vec<_> = endpoints
+        .iter()
+        .filter_map(|endpoint| {
+            let EndpointType::FzStream { compression, .. } = &endpoint.endpoint_type else {
+                return None;
+            };
+            let (_, health) = report.health.iter().find(|(name, _)| name == &endpoint.name)?;
+            let messages = health.messages.max(1) as f64;
+            let cpu_per_message = report
+                .cpu_times
+                .get(&endpoint.name)
+                .map(|cpu| cpu.as_secs_f64() * 1_000_000.0 / messages);
+            Some((endpoint, compression.unwrap_or(FzCompression::None), health, health.bytes as f64 / messages, cpu_per_message))
+        })
+        .collect();
+    let baseline = rows
+        .iter()
+        .find(|(_, codec, health, _, _)| *codec == FzCompression::None && health.messages > 0)
+        .map(|(_, _, _, bytes, cpu)| (*bytes, *cpu));
+
+    for (endpoint, codec, health, bytes_per_message, cpu_per_message) in &rows {
+        let mut line = format!(
+            "{:width$} : 编码 {:4}, 线上接收 {:8.2}MB ({:7.1} 字节/{})",
+            endpoint.name,
+            codec.name(),
+            health.bytes as f64 / 1_048_576.0,
+            bytes_per_message,
+            unit,
+            width = get_max_name_length()
+        );
+        let compared = baseline.filter(|_| *codec != FzCompression::None && health.messages > 0);
+        if let Some((base_bytes, _)) = compared.filter(|(base_bytes, _)| *base_bytes > 0.0) {
+            line.push_str(&format!(", 节省 {:5.1}%", (1.0 - bytes_per_message / base_bytes) * 100.0));
+        }
+        match cpu_per_message {
+            Some(cpu) => {
+                line.push_str(&format!(", CPU {:6.2}µs/{}", cpu, unit));
+                if let Some(base_cpu) = compared.and_then(|(_, base_cpu)| base_cpu) {
+                    line.push_str(&format!(" (解压开销 {:+.2}µs)", cpu - base_cpu));
+                }
+            }
+            None => line.push_str(", CPU 时间不可用"),
+        }
+        if let Some(stat) = report.stats.get(&endpoint.name) {
+            line.push_str(&format!(", 落后时平均延迟 {:.2}ms", stat.get_average_latency()));
+        }
+        println!("{}", line);
+    }
 }
 
-async fn compare_endpoints(endpoints: Vec<Endpoint>, test_duration_sec: u64) -> Result<()> {
+async fn compare_endpoints(
+    endpoints: Vec<Endpoint>,
+    mode: RaceMode,
+    config: ComparisonConfig,
+    frame_timestamps: bool,
+    compare_compression: bool,
+    trace: Option<PathBuf>,
+) -> Result<ComparisonReport> {
+    let test_duration_sec = config.duration.as_secs();
     // 计算最大端点名称长度用于对齐输出
     let max_name_length = endpoints.iter().map(|e| e.name.len()).max().unwrap_or(0);
     set_max_name_length(max_name_length);
@@ -164,6 +288,9 @@ async fn compare_endpoints(endpoints: Vec<Endpoint>, test_duration_sec: u64) ->
     println!("-------------------------");
     for endpoint in &endpoints {
         match &endpoint.endpoint_type {
+            EndpointType::FzStream { address, compression: Some(compression), .. } => {
+                println!("🟡 {} - {} (compression: {})", endpoint.name, address, compression.name());
+            },
             EndpointType::FzStream { address, .. } => {
                 println!("🟡 {} - {}", endpoint.name, address);
             },
@@ -173,318 +300,62 @@ async fn compare_endpoints(endpoints: Vec<Endpoint>, test_duration_sec: u64) ->
         }
     }
     println!("ℹ Test duration: {} seconds", test_duration_sec);
+    match &mode {
+        RaceMode::BlockMeta => {}
+        RaceMode::Events(event_types) => {
+            println!("ℹ Race: events {:?} (keyed by signature + event type)", event_types);
+        }
+        RaceMode::Protocols(protocols) => {
+            let names: Vec<_> = protocols.iter().map(|p| p.name()).collect();
+            println!("ℹ Race: transactions of {} (keyed by signature + protocol)", names.join(", "));
+        }
+    }
+    if frame_timestamps {
+        match mode {
+            RaceMode::BlockMeta | RaceMode::Protocols(_) => println!("ℹ gRPC timestamp mode: HTTP/2 frame poll time (FzStream is still stamped after decoding)"),
+            RaceMode::Events(_) => println!("ℹ FRAME_TIMESTAMPS is ignored for the event race (gRPC events are parsed by solana-streamer-sdk)"),
+        }
+    }
+    println!("ℹ Execution mode: {}", config.execution_mode.describe());
     println!("────────────────────────────────────────────────────────────────────────────────");
     
     log_info("开始对比 gRPC vs FzStream 性能...");
     log_info(&format!("测试持续时间: {}秒", test_duration_sec));
+    let aggregator_config = config.aggregator.clone();
+    if aggregator_config.has_warmup() || !aggregator_config.cooldown.is_zero() {
+        log_info(&format!(
+            "预热: {:.1}秒 / 至少 {} 个, 冷却: 最后 {:.1}秒",
+            aggregator_config.warmup.as_secs_f64(),
+            aggregator_config.warmup_keys,
+            aggregator_config.cooldown.as_secs_f64()
+        ));
+    }
     log_info(&format!(
         "测试端点: {}",
         endpoints.iter().map(|e| e.name.as_str()).collect::<Vec<_>>().join(", ")
     ));
 
-    let start_time = Instant::now();
-    let end_time = start_time + Duration::from_secs(test_duration_sec);
-
-    let block_data_by_slot = Arc::new(Mutex::new(HashMap::<u64, Vec<BlockData>>::new()));
-    let endpoint_stats = Arc::new(Mutex::new({
-        let mut stats = HashMap::new();
-        for endpoint in &endpoints {
-            stats.insert(endpoint.name.clone(), EndpointStats::new());
-        }
-        stats
-    }));
-    
-    // 用于去重的同步Mutex，避免竞态条件
-    let dedup_mutex = Arc::new(StdMutex::new(HashMap::<String, HashSet<u64>>::new()));
-
-    let first_slot_received = Arc::new(Mutex::new({
-        let mut received = HashMap::new();
-        for endpoint in &endpoints {
-            received.insert(endpoint.name.clone(), false);
-        }
-        received
-    }));
-
-    let active_endpoints = Arc::new(Mutex::new({
-        let mut active = HashSet::new();
-        for endpoint in &endpoints {
-            active.insert(endpoint.name.clone());
-        }
-        active
-    }));
-
-    let first_received_slots = Arc::new(Mutex::new(HashMap::<String, u64>::new()));
-    let started_formal_stats = Arc::new(Mutex::new(false));
-    let processed_slots = Arc::new(Mutex::new(HashSet::<u64>::new())); // 跟踪已处理的slot
-
-    // 为每个端点创建连接和订阅
-    let mut tasks = Vec::new();
-
-    for endpoint in endpoints.clone() {
-        let endpoint_name = endpoint.name.clone();
-        let block_data_by_slot = block_data_by_slot.clone();
-        let endpoint_stats = endpoint_stats.clone();
-        let first_slot_received = first_slot_received.clone();
-        let active_endpoints = active_endpoints.clone();
-        let first_received_slots = first_received_slots.clone();
-        let started_formal_stats = started_formal_stats.clone();
-        let processed_slots = processed_slots.clone();
-        let dedup_mutex = dedup_mutex.clone();
-        let _test_end_time = end_time;
-
-        match endpoint.endpoint_type {
-            EndpointType::FzStream { address, auth_token } => {
-                let task = tokio::spawn(async move {
-                    log_info(&format!("连接到 FzStream: {}", address));
-
-                    let mut client = FzStreamClient::builder()
-                        .server_address(&address)
-                        .auth_token(&auth_token)
-                        .connection_timeout(Duration::from_secs(5))
-                        .build()
-                        .expect("Failed to create client");
-                    
-                    if let Err(e) = client.connect().await {
-                        log_info(&format!("连接 {} 失败: {:?}", endpoint_name, e));
-                        let mut stats = endpoint_stats.lock().await;
-                        if let Some(stat) = stats.get_mut(&endpoint_name) {
-                            stat.is_available = false;
-                        }
-                        let mut active = active_endpoints.lock().await;
-                        active.remove(&endpoint_name);
-                        return;
-                    }
-
-                    log_info(&format!("{} 连接成功", endpoint_name));
-                    
-                    // 创建事件回调 - 参考 basic_quic_test.rs 的简单模式
-                    let endpoint_name_for_callback = endpoint_name.clone();
-                    let block_data_by_slot_for_callback = block_data_by_slot.clone();
-                    let endpoint_stats_for_callback = endpoint_stats.clone();
-                    let first_slot_received_for_callback = first_slot_received.clone();
-                    let active_endpoints_for_callback = active_endpoints.clone();
-                    let first_received_slots_for_callback = first_received_slots.clone();
-                    let started_formal_stats_for_callback = started_formal_stats.clone();
-                    let processed_slots_for_callback = processed_slots.clone();
-                    let dedup_mutex_for_callback = dedup_mutex.clone();
-                    
-                    let event_callback = move |event: Box<dyn UnifiedEvent>| {
-                        
-                        // 只处理 BlockMetaEvent
-                        solana_match_event!(event, {
-                            BlockMetaEvent => |e: BlockMetaEvent| {
-                                let current_slot = e.slot;
-                                let timestamp = Instant::now();
-
-                                // 在同步回调中进行去重检查，避免竞态条件
-                                let endpoint_name_clone = endpoint_name_for_callback.clone();
-                                let endpoint_stats_clone = endpoint_stats_for_callback.clone();
-                                
-                                // 使用同步mutex进行去重检查，避免竞态条件
-                                let should_process = {
-                                    let mut dedup_map = dedup_mutex_for_callback.lock().unwrap();
-                                    let endpoint_slots = dedup_map.entry(endpoint_name_clone.clone()).or_insert_with(HashSet::new);
-                                    if endpoint_slots.contains(&current_slot) {
-                                        false // 已处理过
-                                    } else {
-                                        endpoint_slots.insert(current_slot);
-                                        true // 可以处理
-                                    }
-                                };
-                                
-                                if should_process {
-                                    let block_data_by_slot_clone = block_data_by_slot_for_callback.clone();
-                                    let endpoint_stats_clone = endpoint_stats_for_callback.clone();
-                                    let first_slot_received_clone = first_slot_received_for_callback.clone();
-                                    let active_endpoints_clone = active_endpoints_for_callback.clone();
-                                    let first_received_slots_clone = first_received_slots_for_callback.clone();
-                                    let started_formal_stats_clone = started_formal_stats_for_callback.clone();
-                                    let processed_slots_clone = processed_slots_for_callback.clone();
-                                    
-                                    tokio::spawn(async move {
-                                        handle_block_event(
-                                            endpoint_name_clone,
-                                            current_slot,
-                                            timestamp,
-                                            block_data_by_slot_clone,
-                                            endpoint_stats_clone,
-                                            first_slot_received_clone,
-                                            active_endpoints_clone,
-                                            first_received_slots_clone,
-                                            started_formal_stats_clone,
-                                            processed_slots_clone,
-                                        ).await;
-                                    });
-                                }
-                            },
-                        });
-                    };
-                    
-                    // 设置事件过滤器
-                    let event_filter = EventTypeFilter::allow_only(vec![
-                        EventType::BlockMeta,
-                    ]);
-                
-                    let _handle = client.subscribe_with_filter(event_filter, event_callback).await.unwrap();   
-
-                    // 给事件流一些时间来建立连接，参考 basic_quic_test.rs
-                    sleep(Duration::from_millis(5000)).await;
-                    
-                    // 保持任务运行
-                    let _ = _handle.await;
-
-                    println!("================✅ 事件订阅成功==================");     
-                });
-                tasks.push(task);
-            },
+    // 所有统计状态由聚合任务独占，数据源只负责打时间戳并发送
+    let mut engine = ComparisonEngine::new(config);
+    if let Some(path) = &trace {
+        engine.record_trace(path)?;
+        log_info(&format!("记录到达轨迹: {}", path.display()));
+    }
 
-            EndpointType::Grpc { url, token } => {
-                let task = tokio::spawn(async move {
-                    log_info(&format!("连接到 gRPC: {}", url));
-                    
-                    let grpc_client = match YellowstoneGrpc::new(
-                        url.clone(),
-                        token.clone(),
-                    ) {
-                        Ok(client) => client,
-                        Err(e) => {
-                            log_info(&format!("连接 {} 失败: {:?}", endpoint_name, e));
-                            let mut stats = endpoint_stats.lock().await;
-                            if let Some(stat) = stats.get_mut(&endpoint_name) {
-                                stat.is_available = false;
-                            }
-                            let mut active = active_endpoints.lock().await;
-                            active.remove(&endpoint_name);
-                            return;
-                        }
-                    };
-
-                    // gRPC 连接逻辑 - 监听 BlockMeta 事件
-                    // BlockMeta事件不需要特定的协议过滤
-                    let protocols = vec![];
-
-                    // BlockMeta 不需要特定的账户过滤
-                    let transaction_filter = TransactionFilter {
-                        account_include: vec![],
-                        account_exclude: vec![],
-                        account_required: vec![],
-                    };
-                    
-                    let account_filter = AccountFilter {
-                        account: vec![],
-                        owner: vec![],
-                    };
-                    
-                    log_info("🚀 Starting to listen for BlockMeta events...");
-                    log_info("📡 Starting subscription...");
-
-                    // 使用正确的EventType和事件回调
-                    let event_type_filter = GrpcEventTypeFilter{
-                        include: vec![GrpcEventType::BlockMeta],
-                    };
-                    
-                    let endpoint_name_for_callback = endpoint_name.clone();
-                    let block_data_by_slot_for_callback = block_data_by_slot.clone();
-                    let endpoint_stats_for_callback = endpoint_stats.clone();
-                    let first_slot_received_for_callback = first_slot_received.clone();
-                    let active_endpoints_for_callback = active_endpoints.clone();
-                    let first_received_slots_for_callback = first_received_slots.clone();
-                    let started_formal_stats_for_callback = started_formal_stats.clone();
-                    let processed_slots_for_callback = processed_slots.clone();
-                    let dedup_mutex_for_callback = dedup_mutex.clone();
-                    
-                    let grpc_event_callback = move |event: Box<dyn UnifiedEvent>| {
-                        let mut handled = false;
-                        
-                        // 只监听 BlockMetaEvent 与 FzStream 进行比较
-                        solana_match_event!(event, {
-                            BlockMetaEvent => |e: BlockMetaEvent| {
-                                let current_slot = e.slot;
-                                let timestamp = Instant::now();
-
-                                // 在同步回调中进行去重检查，避免竞态条件
-                                let endpoint_name_clone = endpoint_name_for_callback.clone();
-                                let endpoint_stats_clone = endpoint_stats_for_callback.clone();
-                                
-                                // 使用同步mutex进行去重检查，避免竞态条件
-                                let should_process = {
-                                    let mut dedup_map = dedup_mutex_for_callback.lock().unwrap();
-                                    let endpoint_slots = dedup_map.entry(endpoint_name_clone.clone()).or_insert_with(HashSet::new);
-                                    if endpoint_slots.contains(&current_slot) {
-                                        false // 已处理过
-                                    } else {
-                                        endpoint_slots.insert(current_slot);
-                                        true // 可以处理
-                                    }
-                                };
-                                
-                                if should_process {
-                                    let block_data_by_slot_clone = block_data_by_slot_for_callback.clone();
-                                    let endpoint_stats_clone = endpoint_stats_for_callback.clone();
-                                    let first_slot_received_clone = first_slot_received_for_callback.clone();
-                                    let active_endpoints_clone = active_endpoints_for_callback.clone();
-                                    let first_received_slots_clone = first_received_slots_for_callback.clone();
-                                    let started_formal_stats_clone = started_formal_stats_for_callback.clone();
-                                    let processed_slots_clone = processed_slots_for_callback.clone();
-                                    
-                                    // 使用单独的线程和 Tokio 运行时来执行异步任务
-                                    std::thread::spawn(move || {
-                                        let rt = tokio::runtime::Builder::new_current_thread()
-                                            .enable_time()
-                                            .enable_io()
-                                            .build()
-                                            .unwrap();
-                                        rt.block_on(async move {
-                                            handle_block_event(
-                                                endpoint_name_clone,
-                                                current_slot,
-                                                timestamp,
-                                                block_data_by_slot_clone,
-                                                endpoint_stats_clone,
-                                                first_slot_received_clone,
-                                                active_endpoints_clone,
-                                                first_received_slots_clone,
-                                                started_formal_stats_clone,
-                                                processed_slots_clone,
-                                            ).await;
-                                        });
-                                    });
-                                }
-                                handled = true;
-                            },
-                        });
-                        
-                        if !handled {
-                            // 其他事件类型暂不处理
-                        }
-                    };
-                    
-                    if let Err(e) = grpc_client.subscribe_events_immediate(
-                        protocols,
-                        None,
-                        transaction_filter,
-                        account_filter,
-                        Some(event_type_filter),
-                        None,
-                        grpc_event_callback,
-                    )
-                    .await {
-                        log_info(&format!("{} gRPC订阅失败: {:?}", endpoint_name, e));
-                        return;
-                    };
-                    
-                    log_info(&format!("{} 连接成功", endpoint_name));
-
-                    // Keep connection alive
-                    loop {
-                        sleep(Duration::from_millis(100)).await;
-                    }
-                });
-                tasks.push(task);
-            }
+    for endpoint in &endpoints {
+        match &endpoint.endpoint_type {
+            EndpointType::FzStream { address, .. } => log_info(&format!("连接到 FzStream: {}", address)),
+            EndpointType::Grpc { url, .. } => log_info(&format!("连接到 gRPC: {}", url)),
         }
+        engine.add_boxed_source(endpoint.source(&mode, frame_timestamps, compare_compression));
     }
 
+    let mut comparison = engine.start();
+    let start_time = comparison.started_at();
+    let end_time = comparison.deadline();
+    let log_task = comparison.take_events().map(|events| tokio::spawn(log_aggregator_events(events)));
+    let throughput = comparison.throughput();
+
     // 进度监控
     let progress_task = tokio::spawn(async move {
         let mut progress_interval = interval(Duration::from_secs(5));
@@ -500,6 +371,14 @@ async fn compare_endpoints(endpoints: Vec<Endpoint>, test_duration_sec: u64) ->
                     "===== 测试进度: {}% [{}/{}秒] - 剩余时间: {}秒 =====",
                     progress_percent, elapsed_sec, test_duration_sec, remaining_sec
                 ));
+                for (name, snapshot) in throughput.snapshot() {
+                    log_info(&format!(
+                        "{:width$} 吞吐 {}",
+                        name,
+                        format_throughput(&snapshot, snapshot.current),
+                        width = get_max_name_length()
+                    ));
+                }
             }
 
             if Instant::now() >= end_time {
@@ -508,281 +387,89 @@ async fn compare_endpoints(endpoints: Vec<Endpoint>, test_duration_sec: u64) ->
         }
     });
 
-    // 等待测试结束
-    tokio::select! {
-        _ = progress_task => {},
-        _ = sleep(Duration::from_secs(test_duration_sec)) => {},
-    }
-
-    // 取消所有任务
-    for task in tasks {
-        task.abort();
-    }
+    // 等待测试结束并取消所有任务
+    let report = comparison.finish().await?;
+    progress_task.abort();
+    let (breakdown, merger) = match log_task {
+        Some(log_task) => log_task.await.unwrap_or_default(),
+        None => Default::default(),
+    };
+    let stats = &report.stats;
+    let (noun, unit) = match mode {
+        RaceMode::BlockMeta => ("区块", "blocks"),
+        RaceMode::Events(_) => ("事件", "events"),
+        RaceMode::Protocols(_) => ("交易", "txs"),
+    };
 
     let output = ColoredOutput::new();
     output.success("测试完成，正在分析结果...");
+    if let (Some(path), Some(records)) = (&trace, report.trace_records) {
+        output.info(&format!("到达轨迹: {} ({} 条记录)", path.display(), records));
+    }
     output.separator();
     
     // 生成统计报告
-    let stats = endpoint_stats.lock().await;
     let mut endpoint_results = Vec::new();
     
-    for (endpoint_name, stat) in stats.iter() {
-        let total_received = stat.total_received;
-        let first_received_count = stat.first_received;
-        let behind_count = total_received - first_received_count;
-        let first_percentage = if total_received > 0 { 
-            (first_received_count as f64 / total_received as f64) * 100.0 
-        } else { 0.0 };
-        let behind_percentage = if total_received > 0 { 
-            (behind_count as f64 / total_received as f64) * 100.0 
-        } else { 0.0 };
-        
-        let avg_latency = if !stat.latencies.is_empty() {
-            stat.latencies.iter().sum::<f64>() / stat.latencies.len() as f64
-        } else { 0.0 };
-        
-        let min_latency = stat.latencies.iter().copied().fold(f64::INFINITY, f64::min);
-        let max_latency = stat.latencies.iter().copied().fold(f64::NEG_INFINITY, f64::max);
-        
-        let overall_avg_latency = if total_received > 0 {
-            stat.total_latency / total_received as f64
-        } else { 0.0 };
-
-        output.subheader(&format!("📊 {} 性能分析", endpoint_name));
-        output.metric("总接收区块数", &total_received.to_string(), "blocks");
-        output.metric("首先接收区块数", &format!("{} ({:.2}%)", first_received_count, first_percentage), "blocks");
-        output.metric("落后接收区块数", &format!("{} ({:.2}%)", behind_count, behind_percentage), "blocks");
-        
-        if !stat.latencies.is_empty() {
-            output.info("ℹ 延迟统计 (相对于最快端点):");
-            output.metric("  平均延迟", &format!("{:.2}", avg_latency), "ms");
-            output.metric("  最小延迟", &format!("{:.2}", min_latency), "ms");
-            output.metric("  最大延迟", &format!("{:.2}", max_latency), "ms");
-        } else {
-            output.success("该端点始终是最快的，没有延迟数据");
-        }
-        
+    for endpoint in &endpoints {
+        let endpoint_name = &endpoint.name;
+        let Some(stat) = stats.get(endpoint_name) else {
+            continue;
+        };
+        stat.print_summary(endpoint_name, noun, unit);
         output.separator();
-        
-        endpoint_results.push((endpoint_name.clone(), first_percentage, avg_latency, overall_avg_latency, total_received));
-    }
-    
-    let title = "🏆 端点性能对比";
-    println!("{}", title.yellow().bold());
-    println!("{}", "-".repeat(28).yellow());
-    
-    for (endpoint_name, first_percentage, avg_behind_latency, overall_avg_latency, _total_received) in endpoint_results {
-        println!("{:width$} : 首先接收 {:6.2}%, 落后时平均延迟 {:7.2}ms, 总体平均延迟 {:7.2}ms", 
-                endpoint_name, first_percentage, avg_behind_latency, overall_avg_latency,
-                width = get_max_name_length());
-    }
-    
-    output.separator();
-    output.success("✓ 测试完成，正在关闭连接...");
-    output.success("✓ 所有连接已关闭，测试结束");
-    
-    Ok(())
-}
-
-async fn handle_block_event(
-    endpoint_name: String,
-    current_slot: u64,
-    timestamp: Instant,
-    block_data_by_slot: Arc<Mutex<HashMap<u64, Vec<BlockData>>>>,
-    endpoint_stats: Arc<Mutex<HashMap<String, EndpointStats>>>,
-    first_slot_received: Arc<Mutex<HashMap<String, bool>>>,
-    active_endpoints: Arc<Mutex<HashSet<String>>>,
-    first_received_slots: Arc<Mutex<HashMap<String, u64>>>,
-    started_formal_stats: Arc<Mutex<bool>>,
-    processed_slots: Arc<Mutex<HashSet<u64>>>,
-) {
-    // 在handle_block_event中进行原子去重检查和数据添加，避免竞态条件
-    {
-        let mut block_data = block_data_by_slot.lock().await;
-        if let Some(existing_data) = block_data.get(&current_slot) {
-            if existing_data.iter().any(|bd| bd.endpoint == endpoint_name) {
-                return;
-            }
-        }
-        
-        // 原子操作：检查通过后立即添加数据，避免并发竞态
-        block_data
-            .entry(current_slot)
-            .or_insert_with(Vec::new)
-            .push(BlockData {
-                endpoint: endpoint_name.clone(),
-                slot: current_slot,
-                timestamp,
-            });
-    }
-
-    // 标记此端点已收到数据
-    let mut first_received = first_slot_received.lock().await;
-    let already_received = first_received.get(&endpoint_name).copied().unwrap_or(false);
-    
-    if !already_received {
-        first_received.insert(endpoint_name.clone(), true);
-        drop(first_received);
-
-        let mut stats = endpoint_stats.lock().await;
-        if let Some(stat) = stats.get_mut(&endpoint_name) {
-            stat.has_received_data = true;
-            stat.first_slot = Some(current_slot);
-        }
-        drop(stats);
 
-        let mut first_slots = first_received_slots.lock().await;
-        first_slots.insert(endpoint_name.clone(), current_slot);
-        drop(first_slots);
-
-        log_info(&format!(
-            "{} 成功接收到第一个 slot {}, 确认为可用端点",
-            endpoint_name,
-            current_slot
+        endpoint_results.push((
+            endpoint_name.clone(),
+            stat.get_first_percentage(),
+            stat.percent_of_scored(stat.tied),
+            stat.get_average_latency(),
+            stat.get_overall_average_latency(),
+            stat.get_miss_rate(),
         ));
-
-        // 检查是否可以开始正式统计
-        let started = *started_formal_stats.lock().await;
-        if !started {
-            let first_slots = first_received_slots.lock().await;
-            let active = active_endpoints.lock().await;
-            let received_data_count = first_slots.len();
-            let total_active_endpoints = active.len();
-            
-            // 修改条件：只要有2个或以上端点接收到数据就开始统计
-            // 原条件太严格，要求所有端点都接收到数据
-            if received_data_count >= 2 {
-                drop(first_slots);
-                drop(active);
-
-                let mut started = started_formal_stats.lock().await;
-                *started = true;
-                drop(started);
-
-                let active = active_endpoints.lock().await;
-                log_info(&format!("有{}个有效端点, 开始正式统计...", active.len()));
-                drop(active);
-            }
-        }
     }
 
-    let started = *started_formal_stats.lock().await;
-    if !started {
-        return;
-    }
-
-    // 等待其他端点数据以进行比较 - 增加等待时间以适应高延迟场景
-    tokio::time::sleep(Duration::from_millis(500)).await;
+    report.warmup.print_summary(&aggregator_config, &report.endpoints, noun, get_max_name_length());
+    output.separator();
 
-    // 检查这个slot是否已经被处理过（避免重复输出）
-    {
-        let mut processed = processed_slots.lock().await;
-        if processed.contains(&current_slot) {
-            return;
-        }
-        processed.insert(current_slot);
+    if !breakdown.is_empty() {
+        let title = match mode {
+            RaceMode::Protocols(_) => "📈 按协议统计",
+            _ => "📈 按事件类型统计",
+        };
+        let names: Vec<String> = endpoints.iter().map(|e| e.name.clone()).collect();
+        breakdown.print_summary(title, &names, get_max_name_length());
+        output.separator();
     }
 
-    // 进行实时统计 - 改为检查实际收到数据的端点数
-    let active = active_endpoints.lock().await;
+    print_throughput(&report.throughput, get_max_name_length());
+    output.separator();
 
-    let block_data = block_data_by_slot.lock().await;
-    let block_data_list = match block_data.get(&current_slot) {
-        Some(list) => list.clone(),
-        None => return,
-    };
-    drop(block_data);
+    if compare_compression {
+        print_compression_report(&report, &endpoints, unit);
+        output.separator();
+    }
 
-    let received_endpoints: HashSet<_> = block_data_list
-        .iter()
-        .map(|bd| bd.endpoint.clone())
-        .collect();
+    if merger.endpoint_count() > 1 {
+        merger.print_summary();
+        output.separator();
+    }
 
-    // 只有当有2个或以上端点收到同一个slot的数据时才进行比较
-    // 这样才能计算延迟差异
+    let title = "🏆 端点性能对比";
+    println!("{}", title.yellow().bold());
+    println!("{}", "-".repeat(28).yellow());
     
-    if received_endpoints.len() >= 2 {  // 至少有2个端点有数据才能比较
-        let mut stats = endpoint_stats.lock().await;
-        for endpoint in active.iter() {
-            if block_data_list
-                .iter()
-                .any(|bd| &bd.endpoint == endpoint)
-            {
-                if let Some(stat) = stats.get_mut(endpoint) {
-                    stat.total_received += 1;
-                }
-            }
-        }
-        drop(stats);
-
-        let active_endpoint_data: Vec<_> = block_data_list
-            .iter()
-            .filter(|bd| active.contains(&bd.endpoint))
-            .cloned()
-            .collect();
-
-        // 按时间戳排序，确保稳定的排序结果
-        let mut sorted_data = active_endpoint_data.clone();
-        sorted_data.sort_by(|a, b| {
-            a.timestamp.cmp(&b.timestamp)
-                .then_with(|| a.endpoint.cmp(&b.endpoint))
-        });
-
-        // 找到真正最早的端点（第一个）
-        let first_endpoint = sorted_data
-            .first()
-            .unwrap();
-        
-
-        let mut stats = endpoint_stats.lock().await;
-
-        // 首先输出首次接收的端点
-        if let Some(stat) = stats.get_mut(&first_endpoint.endpoint) {
-            stat.first_received += 1;
-            log_info(&format!(
-                "{:width$} 接收 slot {}: 首次接收",
-                first_endpoint.endpoint,
-                current_slot,
-                width = get_max_name_length()
-            ));
-        }
-
-        // 然后按排序顺序输出延迟的端点
-        for bd in &sorted_data {
-            if bd.endpoint != first_endpoint.endpoint {
-                let latency = bd.timestamp.duration_since(first_endpoint.timestamp).as_nanos() as f64 / 1_000_000.0;
-                if latency >= 0.01 { // 只显示大于0.01ms的延迟
-                    if let Some(stat) = stats.get_mut(&bd.endpoint) {
-                        stat.latencies.push(latency);
-                        stat.total_latency += latency;
-                        log_info(&format!(
-                            "{:width$} 接收 slot {}: 延迟 {:>6.2}ms (相对于 {})",
-                            bd.endpoint,
-                            current_slot,
-                            latency,
-                            first_endpoint.endpoint,
-                            width = get_max_name_length()
-                        ));
-                    }
-                }
-            }
-        }
-        drop(stats);
-
-        // 清理旧数据
-        let mut block_data = block_data_by_slot.lock().await;
-        let old_slots: Vec<_> = block_data
-            .keys()
-            .filter(|&&slot| slot < current_slot.saturating_sub(100))
-            .copied()
-            .collect();
-        for slot in old_slots {
-            block_data.remove(&slot);
-        }
-        drop(block_data);
+    for (endpoint_name, first_percentage, tie_percentage, avg_behind_latency, overall_avg_latency, miss_rate) in endpoint_results {
+        println!("{:width$} : 首先接收 {:6.2}%, 同时接收 {:6.2}%, 落后时平均延迟 {:7.2}ms, 总体平均延迟 {:7.2}ms, 超时未收到 {:5.2}%", 
+                endpoint_name, first_percentage, tie_percentage, avg_behind_latency, overall_avg_latency, miss_rate,
+                width = get_max_name_length());
     }
-    drop(active);
+    
+    output.separator();
+    output.success("✓ 测试完成，正在关闭连接...");
+    output.success("✓ 所有连接已关闭，测试结束");
+    
+    Ok(report)
 }
 
 #[tokio::main] 
@@ -795,25 +482,196 @@ async fn main() -> Result<()> {
         .unwrap_or_else(|_| "https://solana-yellowstone-grpc.publicnode.com:443".to_string());
     let auth_token = env::var("AUTH_TOKEN").unwrap_or_else(|_| "demo_token_12345".to_string());
     let grpc_token = env::var("GRPC_TOKEN").ok();
+    // 开启后 gRPC 端在传输层记录 HTTP/2 帧拉取时间
+    let frame_timestamps = env::var("FRAME_TIMESTAMPS")
+        .map(|v| v == "1" || v.eq_ignore_ascii_case("true"))
+        .unwrap_or(false);
     
     let test_duration = Duration::from_secs(30);
 
-    let endpoints = vec![
-        Endpoint {
-            name: "FzStream".to_string(),
-            endpoint_type: EndpointType::FzStream { 
-                address: fzstream_address, 
-                auth_token 
+    // FZSTREAM_COMPRESSION: 单个编码 (none/lz4/zstd) 设置 FzStream 压缩方式;
+    // 多个编码或 "compare" 时对同一服务器并行建立各编码的连接进行对比
+    let compressions: Vec<FzCompression> = match env::var("FZSTREAM_COMPRESSION") {
+        Ok(v) if v.trim().eq_ignore_ascii_case("compare") => FzCompression::ALL.to_vec(),
+        Ok(v) => v
+            .split(',')
+            .filter(|c| !c.trim().is_empty())
+            .map(|c| {
+                FzCompression::parse(c).ok_or_else(|| {
+                    BenchmarkError::ConfigError(format!("unknown FzStream compression '{}', expected none, lz4 or zstd", c))
+                })
+            })
+            .collect::<Result<_>>()?,
+        Err(_) => Vec::new(),
+    };
+    let compare_compression = compressions.len() > 1;
+
+    let endpoints = if compare_compression {
+        compressions
+            .iter()
+            .map(|compression| Endpoint {
+                name: format!("FzStream[{}]", compression.name()),
+                endpoint_type: EndpointType::FzStream {
+                    address: fzstream_address.clone(),
+                    auth_token: auth_token.clone(),
+                    compression: Some(*compression),
+                },
+            })
+            .collect()
+    } else {
+        vec![
+            Endpoint {
+                name: "FzStream".to_string(),
+                endpoint_type: EndpointType::FzStream { 
+                    address: fzstream_address, 
+                    auth_token,
+                    compression: compressions.first().copied(),
+                },
             },
-        },
-        Endpoint {
-            name: "gRPC".to_string(),
-            endpoint_type: EndpointType::Grpc { 
-                url: grpc_url, 
-                token: grpc_token 
+            Endpoint {
+                name: "gRPC".to_string(),
+                endpoint_type: EndpointType::Grpc { 
+                    url: grpc_url, 
+                    token: grpc_token 
+                },
             },
+        ]
+    };
+
+    // 每个端点使用独立线程和运行时，可选绑定 CPU 核心
+    let dedicated_runtime = env::var("DEDICATED_RUNTIME")
+        .map(|v| v == "1" || v.eq_ignore_ascii_case("true"))
+        .unwrap_or(false);
+    let core_ids = env::var("CORE_IDS")
+        .map(|v| v.split(',').filter_map(|id| id.trim().parse().ok()).collect())
+        .unwrap_or_default();
+    // 压缩对比需要按线程统计 CPU 时间，因此总是使用独立运行时
+    let execution_mode = ExecutionMode::new(dedicated_runtime || compare_compression, core_ids);
+
+    // EVENT_TYPES 设置后按 DEX 事件 (签名 + 事件类型) 比较，否则比较 BlockMeta
+    let event_types: Vec<String> = env::var("EVENT_TYPES")
+        .map(|v| v.split(',').map(|t| t.to_string()).collect())
+        .unwrap_or_default();
+    let event_types = parse_event_types(&event_types)?;
+    // PROTOCOLS 设置后按 DEX 协议比较 ("all" 表示全部协议)
+    let protocols = match env::var("PROTOCOLS") {
+        Ok(v) if v.trim().eq_ignore_ascii_case("all") => DexProtocol::ALL.to_vec(),
+        Ok(v) => parse_protocols(&v.split(',').map(|p| p.to_string()).collect::<Vec<_>>())?,
+        Err(_) => Vec::new(),
+    };
+    let mode = if !protocols.is_empty() {
+        RaceMode::Protocols(protocols)
+    } else if !event_types.is_empty() {
+        RaceMode::Events(event_types)
+    } else {
+        RaceMode::BlockMeta
+    };
+
+    // 峰值吞吐统计窗口 (毫秒)，例如 "1000,10000"
+    let throughput_windows = env::var("THROUGHPUT_WINDOWS_MS")
+        .map(|v| v.split(',').filter_map(|ms| ms.trim().parse().ok()).map(Duration::from_millis).collect())
+        .unwrap_or_else(|_| vec![Duration::from_secs(1), Duration::from_secs(10)]);
+
+    // 预热/冷却: 开始后的 WARMUP_SECS 秒 (且至少 WARMUP_KEYS 个) 和结束前的 COOLDOWN_SECS 秒不计入统计
+    let env_secs = |name: &str| {
+        env::var(name).ok().and_then(|v| v.parse::<f64>().ok()).map(|secs| Duration::from_secs_f64(secs.max(0.0)))
+    };
+    let aggregator = AggregatorConfig {
+        // 首次到达后等待其他端点的时间，超时仍未收到的端点记为错过
+        resolve_timeout: env::var("RESOLVE_TIMEOUT_MS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .filter(|ms| *ms > 0)
+            .map(Duration::from_millis)
+            .unwrap_or(Duration::from_millis(500)),
+        // 与最早到达相差不到 TIE_TOLERANCE_US 微秒的记为同时接收
+        tie_tolerance: env::var("TIE_TOLERANCE_US")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .map(Duration::from_micros)
+            .unwrap_or(DEFAULT_TIE_TOLERANCE),
+        warmup: env_secs("WARMUP_SECS").unwrap_or_default(),
+        warmup_keys: env::var("WARMUP_KEYS").ok().and_then(|v| v.parse().ok()).unwrap_or(0),
+        cooldown: env_secs("COOLDOWN_SECS").unwrap_or_default(),
+        // 落后最新slot超过 MAX_SLOT_DIFFERENCE 个区块 (持续 SLOT_WINDOW_SECS 秒) 时按 SLOT_POLICY 处理
+        max_slot_difference: env::var("MAX_SLOT_DIFFERENCE").ok().and_then(|v| v.parse().ok()).unwrap_or(10),
+        slot_policy: match env::var("SLOT_POLICY") {
+            Ok(policy) => SlotPolicy::parse(&policy).map_err(BenchmarkError::ConfigError)?,
+            Err(_) => SlotPolicy::default(),
         },
-    ];
+        slot_window: env_secs("SLOT_WINDOW_SECS").unwrap_or_default(),
+        ..Default::default()
+    };
+    if aggregator.cooldown >= test_duration {
+        return Err(BenchmarkError::ConfigError(format!(
+            "COOLDOWN_SECS ({}s) must be shorter than the test duration ({}s)",
+            aggregator.cooldown.as_secs_f64(),
+            test_duration.as_secs()
+        )));
+    }
+
+    // 记录到达轨迹，之后可以用 replay 重新分析
+    let trace = env::var("TRACE_FILE").ok().filter(|v| !v.is_empty()).map(PathBuf::from);
+
+    // SLO 断言，多条用分号分隔，例如 "endpoint=FzStream p99<5ms;first_share>=40%"
+    let assertions: Vec<Assertion> = env::var("ASSERT")
+        .map(|v| v.split(';').filter(|a| !a.trim().is_empty()).map(Assertion::parse).collect::<std::result::Result<_, _>>())
+        .unwrap_or_else(|_| Ok(Vec::new()))
+        .map_err(BenchmarkError::ConfigError)?;
+    for assertion in &assertions {
+        if let Some(name) = &assertion.endpoint {
+            if !endpoints.iter().any(|e| &e.name == name) {
+                return Err(BenchmarkError::ConfigError(format!("assertion '{}' names unknown endpoint {}", assertion, name)));
+            }
+        }
+    }
+
+    // 每次运行的配置和统计摘要保存到 SQLite 历史记录，HISTORY_DB 为空时不保存
+    let history_db = env::var("HISTORY_DB").unwrap_or_else(|_| DEFAULT_HISTORY_DB.to_string());
+    let run_config = serde_json::json!({
+        "race": format!("{:?}", mode),
+        "duration_sec": test_duration.as_secs(),
+        "frame_timestamps": frame_timestamps,
+        "execution_mode": execution_mode.describe(),
+        "warmup_secs": aggregator.warmup.as_secs_f64(),
+        "warmup_keys": aggregator.warmup_keys,
+        "cooldown_secs": aggregator.cooldown.as_secs_f64(),
+        "resolve_timeout_ms": aggregator.resolve_timeout.as_millis() as u64,
+        "tie_tolerance_us": aggregator.tie_tolerance.as_micros() as u64,
+        "max_slot_difference": aggregator.max_slot_difference,
+        "slot_policy": aggregator.slot_policy.name(),
+        "slot_window_secs": aggregator.slot_window.as_secs_f64(),
+        "endpoints": endpoints
+            .iter()
+            .map(|e| match &e.endpoint_type {
+                EndpointType::FzStream { address, compression, .. } => serde_json::json!({
+                    "name": e.name,
+                    "fzstream": address,
+                    "compression": compression.map(|c| c.name()),
+                }),
+                EndpointType::Grpc { url, .. } => serde_json::json!({ "name": e.name, "grpc": url }),
+            })
+            .collect::<Vec<_>>(),
+    });
+
+    let config = ComparisonConfig {
+        duration: test_duration,
+        execution_mode,
+        aggregator,
+        throughput_windows,
+    };
+    let report = compare_endpoints(endpoints, mode, config, frame_timestamps, compare_compression, trace).await?;
+
+    if !history_db.is_empty() {
+        match HistoryStore::open(&history_db).and_then(|mut store| {
+            store.save(&RunRecord::from_report("grpc-vs-fzstream", run_config, &report))
+        }) {
+            Ok(id) => log_info(&format!("运行结果已保存: {} (#{})", history_db, id)),
+            Err(e) => log_info(&format!("保存运行结果失败: {}", e)),
+        }
+    }
+
+    enforce(&assertions, &Metrics::from_report(&report));
+    Ok(())
+}
 
-    compare_endpoints(endpoints, test_duration.as_secs()).await
-}
\ No newline at end of file
@@ -6,6 +6,7 @@ use yellowstone_grpc_proto::prelude::{
     subscribe_update::UpdateOneof, SubscribeRequest, SubscribeRequestPing,
 };
 
+use grpc_benchmark::history::{EndpointSummary, HistoryStore, RunKind, RunRecord, DEFAULT_HISTORY_DB};
 use std::collections::HashMap;
 use std::time::{Duration, Instant};
 use tokio::time::{sleep, timeout, interval};
@@ -39,6 +40,10 @@ struct Args {
     /// Test timeout in seconds
     #[arg(long, default_value = "120")]
     timeout: u64,
+
+    /// SQLite file every run is saved to (empty to disable)
+    #[arg(long, env = "HISTORY_DB", default_value = DEFAULT_HISTORY_DB)]
+    history_db: String,
 }
 
 #[allow(dead_code)]
@@ -126,7 +131,7 @@ async fn test_grpc_latency_serial(
     ping_interval_ms: u64,
     token: Option<&str>,
     test_timeout: Duration,
-) -> Result<()> {
+) -> Result<Option<LatencyStats>> {
     info!("开始串行延迟测试...");
     info!("总轮数: {}, 间隔: {}ms", total_rounds, ping_interval_ms);
 
@@ -224,7 +229,7 @@ async fn test_grpc_latency_serial(
     // 显示统计结果
     if latencies.is_empty() {
         warn!("没有收集到任何延迟数据");
-        return Ok(());
+        return Ok(None);
     }
 
     let mut stats = LatencyStats::new();
@@ -234,7 +239,7 @@ async fn test_grpc_latency_serial(
     stats.calculate();
     stats.display();
 
-    Ok(())
+    Ok(Some(stats))
 }
 
 #[allow(dead_code)]
@@ -398,7 +403,34 @@ async fn main() -> Result<()> {
     let test_timeout = Duration::from_secs(args.timeout);
 
     // Use serial approach for simplicity and reliability
-    test_grpc_latency_serial(&grpc_url, args.total_rounds, args.ping_interval_ms, args.grpc_token.as_deref(), test_timeout).await?;
+    let started = Instant::now();
+    let stats = test_grpc_latency_serial(&grpc_url, args.total_rounds, args.ping_interval_ms, args.grpc_token.as_deref(), test_timeout).await?;
+
+    // 保存到历史记录: 记为往返延迟类运行，延迟字段是 Ping 往返时间，不与端点对比的运行比较
+    if let (Some(stats), false) = (stats, args.history_db.is_empty()) {
+        let summary = EndpointSummary {
+            endpoint: grpc_url.clone(),
+            received: stats.count,
+            scored: stats.count,
+            first: 0,
+            tied: 0,
+            missed: 0,
+            avg_behind_ms: stats.mean,
+            overall_avg_ms: stats.mean,
+            p50_ms: stats.median,
+            p99_ms: stats.p99,
+        };
+        let config = serde_json::json!({
+            "grpc_url": grpc_url,
+            "total_rounds": args.total_rounds,
+            "ping_interval_ms": args.ping_interval_ms,
+        });
+        let run = RunRecord::finished("latency-test", started.elapsed(), config, vec![summary]).with_kind(RunKind::RoundTrip);
+        match HistoryStore::open(&args.history_db).and_then(|mut store| store.save(&run)) {
+            Ok(id) => info!("运行结果已保存: {} (#{})", args.history_db, id),
+            Err(e) => warn!("保存运行结果失败: {}", e),
+        }
+    }
 
     Ok(())
 }
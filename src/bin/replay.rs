@@ -0,0 +1,343 @@
+use anyhow::Result;
+use chrono::{DateTime, Local};
+use clap::Parser;
+use colored::*;
+use grpc_benchmark::aggregator::{Aggregator, AggregatorConfig, AggregatorEvent, SlotPolicy};
+use grpc_benchmark::breakdown::{CategoryBreakdown, TimeBreakdown};
+use grpc_benchmark::history::{EndpointSummary, HistoryStore, RunRecord, DEFAULT_HISTORY_DB};
+use grpc_benchmark::merger::MergeSimulator;
+use grpc_benchmark::output::ColoredOutput;
+use grpc_benchmark::report::{ReportFormat, RunReport};
+use grpc_benchmark::stats::{calculate_stats, EndpointStatsMap};
+use grpc_benchmark::throughput::{format_byte_rate, format_window, ThroughputMeter, SAMPLE_INTERVAL};
+use grpc_benchmark::trace::{TraceReader, TraceRecord};
+use std::io;
+use std::path::PathBuf;
+use std::time::{Duration, Instant, UNIX_EPOCH};
+use tokio::sync::mpsc;
+
+/// Recompute comparison statistics, rankings and reports from an arrival trace
+#[derive(Parser, Debug)]
+#[command(name = "replay")]
+struct Args {
+    /// Trace file written by grpc-comparison --trace or TRACE_FILE
+    trace: PathBuf,
+
+    /// Ignore everything during the first N seconds of the run
+    #[arg(long, default_value = "0")]
+    warmup_secs: f64,
+
+    /// Leave an endpoint out of the analysis (repeatable)
+    #[arg(long)]
+    exclude: Vec<String>,
+
+    /// How long to wait for the remaining endpoints after the first arrival of a key
+    #[arg(long, default_value = "500")]
+    resolve_timeout_ms: u64,
+
+    /// Arrivals within this many microseconds of the earliest one count as tied
+    #[arg(long, default_value = "10")]
+    tie_tolerance_us: u64,
+
+    /// Slots an endpoint may fall behind the newest slot of any endpoint
+    #[arg(long, default_value = "10")]
+    max_slot_difference: u64,
+
+    /// What to do with an endpoint beyond --max-slot-difference: exclude, readmit or flag
+    #[arg(long, default_value = "exclude", value_parser = SlotPolicy::parse)]
+    slot_policy: SlotPolicy,
+
+    /// How long an endpoint has to stay behind (or caught up) before the slot policy acts
+    #[arg(long, default_value = "0")]
+    slot_window_secs: f64,
+
+    /// Windows (ms) over which peak message and byte rates are reported
+    #[arg(long, value_delimiter = ',', default_value = "1000,10000")]
+    throughput_windows_ms: Vec<u64>,
+
+    /// Write a report: .md for a summary table, .html for a page with charts (repeatable)
+    #[arg(long, value_delimiter = ',', value_parser = parse_report)]
+    report: Vec<(PathBuf, ReportFormat)>,
+
+    /// SQLite file every run is saved to (empty to disable)
+    #[arg(long, env = "HISTORY_DB", default_value = DEFAULT_HISTORY_DB)]
+    history_db: String,
+}
+
+fn parse_report(path: &str) -> std::result::Result<(PathBuf, ReportFormat), String> {
+    let path = PathBuf::from(path);
+    let format = ReportFormat::from_path(&path).ok_or_else(|| format!("unknown report format '{}', expected .md or .html", path.display()))?;
+    Ok((path, format))
+}
+
+// 回放结果中每个端点的吞吐: 只统计预热之后的 key 与字节
+struct EndpointTraffic {
+    keys: u64,
+    bytes: u64,
+    meter: ThroughputMeter,
+}
+
+fn main() -> Result<()> {
+    let args = Args::parse();
+    let output = ColoredOutput::new();
+
+    let mut reader = TraceReader::open(&args.trace)?;
+    let names = reader.endpoints().to_vec();
+    for excluded in &args.exclude {
+        if !names.contains(excluded) {
+            anyhow::bail!("{} is not in the trace (endpoints: {})", excluded, names.join(", "));
+        }
+    }
+    let included: Vec<String> = names.iter().filter(|n| !args.exclude.contains(n)).cloned().collect();
+    let width = names.iter().map(|n| n.len()).max().unwrap_or(0);
+
+    let started: DateTime<Local> = (UNIX_EPOCH + Duration::from_nanos(reader.origin_wall_ns())).into();
+    output.header("🔁 到达轨迹回放");
+    output.info(&format!("轨迹文件: {}", args.trace.display()));
+    output.info(&format!("运行开始时间: {}", started.format("%Y-%m-%d %H:%M:%S%.3f")));
+    output.info(&format!("端点: {}", names.join(", ")));
+    if !args.exclude.is_empty() {
+        output.info(&format!("排除端点: {}", args.exclude.join(", ")));
+    }
+    if args.warmup_secs > 0.0 {
+        output.info(&format!("预热: 忽略前 {} 秒", args.warmup_secs));
+    }
+    output.separator();
+
+    // 聚合器与实时运行完全相同，只是时间来自轨迹中的单调时间
+    let config = AggregatorConfig {
+        resolve_timeout: Duration::from_millis(args.resolve_timeout_ms),
+        tie_tolerance: Duration::from_micros(args.tie_tolerance_us),
+        max_slot_difference: args.max_slot_difference,
+        slot_policy: args.slot_policy,
+        slot_window: Duration::from_secs_f64(args.slot_window_secs.max(0.0)),
+        ..Default::default()
+    };
+    let sweep_period = (config.resolve_timeout / 4).max(Duration::from_millis(1));
+    let resolve_timeout = config.resolve_timeout;
+    let (events_tx, mut events) = mpsc::unbounded_channel();
+    let mut aggregator = Aggregator::new(included.clone(), config, events_tx);
+
+    let windows: Vec<Duration> = args.throughput_windows_ms.iter().copied().map(Duration::from_millis).collect();
+    let mut traffic: Vec<EndpointTraffic> = names
+        .iter()
+        .map(|_| EndpointTraffic { keys: 0, bytes: 0, meter: ThroughputMeter::new(windows.clone()) })
+        .collect();
+
+    let origin = Instant::now();
+    let warmup = Duration::from_secs_f64(args.warmup_secs.max(0.0));
+    let mut breakdown = CategoryBreakdown::new();
+    let mut merger = MergeSimulator::new();
+    let mut timeline = TimeBreakdown::default();
+    let mut records = 0u64;
+    let mut last = Duration::ZERO;
+    let mut next_sweep = Duration::ZERO;
+    let mut next_sample = warmup;
+
+    loop {
+        let record = match reader.next_record() {
+            Ok(Some(record)) => record,
+            Ok(None) => break,
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => {
+                output.warning(&format!("轨迹在第 {} 条记录后被截断，只分析完整的记录", records));
+                break;
+            }
+            Err(e) => return Err(e.into()),
+        };
+        records += 1;
+
+        let at = Duration::from_nanos(record.mono_ns());
+        last = last.max(at);
+        if at < warmup {
+            continue;
+        }
+
+        // 按实时运行的节奏采样吞吐和清理超时 key
+        while next_sample <= at {
+            for endpoint in &mut traffic {
+                endpoint.meter.sample(origin + next_sample, endpoint.keys, endpoint.bytes);
+            }
+            next_sample += SAMPLE_INTERVAL;
+        }
+        if at >= next_sweep {
+            aggregator.sweep(origin + at);
+            next_sweep = at + sweep_period;
+        }
+
+        match record {
+            TraceRecord::Arrival(arrival) => {
+                let endpoint = &mut traffic[arrival.endpoint];
+                endpoint.keys += 1;
+                endpoint.bytes += arrival.size as u64;
+                aggregator.on_arrival(arrival.to_arrival(&names, origin));
+            }
+            TraceRecord::EndpointFailed { endpoint, reason, .. } => {
+                aggregator.on_endpoint_failed(&names[endpoint], reason);
+            }
+        }
+
+        drain_events(&mut events, &mut breakdown, &mut merger, &mut timeline);
+    }
+
+    aggregator.sweep(origin + last + resolve_timeout);
+    drain_events(&mut events, &mut breakdown, &mut merger, &mut timeline);
+
+    output.success(&format!("回放完成: {} 条记录, 时长 {:.1} 秒", records, last.as_secs_f64()));
+    output.separator();
+
+    print_ranking(aggregator.stats(), &included, width);
+    output.separator();
+
+    if !breakdown.is_empty() {
+        breakdown.print_summary("📈 按类别统计", &included, width);
+        output.separator();
+    }
+
+    print_traffic(&names, &traffic, width);
+    output.separator();
+
+    if merger.endpoint_count() > 1 {
+        merger.print_summary();
+        output.separator();
+    }
+
+    if !args.report.is_empty() {
+        let mut report = RunReport::new("gRPC 端点对比报告 (轨迹回放)", &included, aggregator.stats(), timeline)
+            .with_meta("轨迹文件", args.trace.display())
+            .with_meta("运行开始时间", started.format("%Y-%m-%d %H:%M:%S"))
+            .with_meta("时长", format!("{:.0}秒", last.as_secs_f64()));
+        if args.warmup_secs > 0.0 {
+            report = report.with_meta("预热", format!("忽略前 {} 秒", args.warmup_secs));
+        }
+        if !args.exclude.is_empty() {
+            report = report.with_meta("排除端点", args.exclude.join(", "));
+        }
+        for (path, format) in &args.report {
+            match report.write(path, *format) {
+                Ok(()) => output.success(&format!("报告已生成: {}", path.display())),
+                Err(e) => output.error(&format!("生成报告 {} 失败: {}", path.display(), e)),
+            }
+        }
+    }
+
+    // 回放结果与实时运行一样保存到历史记录，开始时间取轨迹中记录的运行开始时间
+    if !args.history_db.is_empty() {
+        let config = serde_json::json!({
+            "trace": args.trace.display().to_string(),
+            "warmup_secs": args.warmup_secs,
+            "exclude": args.exclude,
+            "resolve_timeout_ms": args.resolve_timeout_ms,
+            "tie_tolerance_us": args.tie_tolerance_us,
+            "max_slot_difference": args.max_slot_difference,
+            "slot_policy": args.slot_policy.name(),
+            "slot_window_secs": args.slot_window_secs,
+        });
+        let endpoints = included
+            .iter()
+            .filter_map(|name| aggregator.stats().get(name).map(|stats| EndpointSummary::from_stats(name, stats)))
+            .collect();
+        let mut run = RunRecord::finished("replay", last, config, endpoints);
+        run.started_at = started.timestamp();
+        match HistoryStore::open(&args.history_db).and_then(|mut store| store.save(&run)) {
+            Ok(id) => output.success(&format!("运行结果已保存: {} (#{})", args.history_db, id)),
+            Err(e) => output.error(&format!("保存运行结果失败: {}", e)),
+        }
+    }
+    Ok(())
+}
+
+fn drain_events(
+    events: &mut mpsc::UnboundedReceiver<AggregatorEvent>,
+    breakdown: &mut CategoryBreakdown,
+    merger: &mut MergeSimulator,
+    timeline: &mut TimeBreakdown,
+) {
+    while let Ok(event) = events.try_recv() {
+        match &event {
+            AggregatorEvent::Excluded { endpoint, slot, tip } => {
+                println!("⚠ {} 的slot ({}) 比最新slot {} 旧, 未参与比较", endpoint, slot, tip);
+            }
+            AggregatorEvent::Flagged { endpoint, slot, tip } => {
+                println!("⚠ {} 的slot ({}) 比最新slot {} 旧, 仍参与比较", endpoint, slot, tip);
+            }
+            AggregatorEvent::CaughtUp { endpoint, readmitted: true } => {
+                println!("✓ {} 已追上最新slot, 重新参与比较", endpoint);
+            }
+            _ => {}
+        }
+        breakdown.observe(&event);
+        merger.observe(&event);
+        timeline.observe(&event);
+    }
+}
+
+// 端点排名: 首先接收占比、落后延迟分布以及解码耗时
+fn print_ranking(stats: &EndpointStatsMap, endpoints: &[String], width: usize) {
+    println!("{}", "🏆 端点性能对比".yellow().bold());
+    println!("{}", "-".repeat(28).yellow());
+
+    let mut ranked: Vec<_> = endpoints.iter().filter_map(|name| stats.get(name).map(|s| (name, s))).collect();
+    ranked.sort_by(|(_, a), (_, b)| b.get_first_percentage().total_cmp(&a.get_first_percentage()));
+
+    for (name, stat) in ranked {
+        if stat.scored == 0 {
+            println!("{:width$} : 没有收集到数据", name, width = width);
+            continue;
+        }
+        let latency = calculate_stats(&stat.latencies);
+        let mut line = format!(
+            "{:width$} : 接收 {} (计分 {}), 首先接收 {:6.2}% ({}), 同时接收 {:6.2}% ({}), 落后接收 {}, 落后时平均延迟 {:7.2}ms, p50 {:7.2}ms, p99 {:7.2}ms, 总体平均延迟 {:7.2}ms",
+            name,
+            stat.received,
+            stat.scored,
+            stat.get_first_percentage(),
+            stat.first,
+            stat.percent_of_scored(stat.tied),
+            stat.tied,
+            stat.delayed,
+            stat.get_average_latency(),
+            latency.median,
+            latency.p99,
+            stat.get_overall_average_latency(),
+            width = width
+        );
+        if !stat.decode_times.is_empty() {
+            let decode = stat.get_decode_stats();
+            line.push_str(&format!(", 解码 平均 {:.3}ms p99 {:.3}ms", decode.mean, decode.p99));
+        }
+        if stat.missed > 0 {
+            line.push_str(&format!(", 超时未收到 {:.2}% ({})", stat.get_miss_rate(), stat.missed));
+        }
+        if stat.slot_lag.samples > 0 {
+            line.push_str(&format!(", 落后最新slot 平均 {:.2} 最大 {}", stat.slot_lag.average(), stat.slot_lag.max));
+        }
+        println!("{}", line);
+    }
+}
+
+// 轨迹中的 key 数和消息字节数 (字节只记在每条消息的第一个 key 上)
+fn print_traffic(names: &[String], traffic: &[EndpointTraffic], width: usize) {
+    println!("{}", "📶 吞吐统计".yellow().bold());
+    println!("{}", "-".repeat(28).yellow());
+
+    for (name, endpoint) in names.iter().zip(traffic) {
+        let snapshot = endpoint.meter.snapshot();
+        let bytes = if endpoint.bytes > 0 { format_byte_rate(snapshot.average.bytes_per_sec) } else { "-".to_string() };
+        let peaks: Vec<String> = snapshot
+            .peaks
+            .iter()
+            .map(|(window, rate)| format!("{} 峰值 {:.1} key/秒", format_window(*window), rate.messages_per_sec))
+            .collect();
+        println!(
+            "{:width$} : 共 {} 个 key, {:.2}MB, 平均 {:.1} key/秒, {}, {}",
+            name,
+            endpoint.keys,
+            endpoint.bytes as f64 / 1_048_576.0,
+            snapshot.average.messages_per_sec,
+            bytes,
+            peaks.join(", "),
+            width = width
+        );
+    }
+}
+
@@ -0,0 +1,166 @@
+use crate::aggregator::{AggregatorEvent, Resolution};
+use crate::stats::EndpointStatsMap;
+use colored::*;
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+
+/// Race results split by key category (event type, protocol, ...).
+///
+/// Fed from the aggregator's `Resolved` events and scored exactly like the overall
+/// statistics, so every category row is directly comparable with the totals.
+#[derive(Debug, Default)]
+pub struct CategoryBreakdown {
+    categories: BTreeMap<String, EndpointStatsMap>,
+}
+
+impl CategoryBreakdown {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record(&mut self, resolution: &Resolution) {
+        if let Some(category) = resolution.key.category() {
+            resolution.score(self.categories.entry(category.to_string()).or_default());
+        }
+    }
+
+    pub fn observe(&mut self, event: &AggregatorEvent) {
+        if let AggregatorEvent::Resolved(resolution) = event {
+            self.record(resolution);
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.categories.is_empty()
+    }
+
+    /// Categories in name order.
+    pub fn categories(&self) -> impl Iterator<Item = (&str, &EndpointStatsMap)> {
+        self.categories.iter().map(|(name, stats)| (name.as_str(), stats))
+    }
+
+    /// Prints each category's first-received share and latency when behind, one line per endpoint.
+    pub fn print_summary(&self, title: &str, endpoints: &[String], width: usize) {
+        println!("{}", title.yellow().bold());
+        println!("{}", "-".repeat(28).yellow());
+
+        for (category, stats) in self.categories() {
+            println!("{}", category.cyan().bold());
+            for name in endpoints {
+                let Some(stat) = stats.get(name) else {
+                    continue;
+                };
+                println!(
+                    "  {:width$} : 首先接收 {:6.2}% ({}/{}), 同时接收 {}, 落后时平均延迟 {:7.2}ms",
+                    name,
+                    stat.get_first_percentage(),
+                    stat.first,
+                    stat.scored,
+                    stat.tied,
+                    stat.get_average_latency(),
+                    width = width
+                );
+            }
+        }
+    }
+}
+
+/// Race results split into fixed time buckets, counted from the first resolved key.
+///
+/// Shows how an endpoint's first-received share moves over a run, e.g. during a provider's
+/// busy hour, which the run-wide totals average away.
+#[derive(Debug)]
+pub struct TimeBreakdown {
+    bucket: Duration,
+    origin: Option<Instant>,
+    buckets: Vec<EndpointStatsMap>,
+}
+
+impl Default for TimeBreakdown {
+    /// One-minute buckets.
+    fn default() -> Self {
+        Self::new(Duration::from_secs(60))
+    }
+}
+
+impl TimeBreakdown {
+    pub fn new(bucket: Duration) -> Self {
+        Self { bucket, origin: None, buckets: Vec::new() }
+    }
+
+    pub fn record(&mut self, resolution: &Resolution) {
+        let at = resolution.first().timestamp;
+        let origin = *self.origin.get_or_insert(at);
+        let index = (at.saturating_duration_since(origin).as_nanos() / self.bucket.as_nanos().max(1)) as usize;
+        if self.buckets.len() <= index {
+            self.buckets.resize_with(index + 1, Default::default);
+        }
+        resolution.score(&mut self.buckets[index]);
+    }
+
+    pub fn observe(&mut self, event: &AggregatorEvent) {
+        if let AggregatorEvent::Resolved(resolution) = event {
+            self.record(resolution);
+        }
+    }
+
+    pub fn bucket(&self) -> Duration {
+        self.bucket
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.buckets.is_empty()
+    }
+
+    /// Buckets in time order; buckets without resolved keys are empty.
+    pub fn buckets(&self) -> &[EndpointStatsMap] {
+        &self.buckets
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::aggregator::{Arrival, ArrivalKey, DEFAULT_TIE_TOLERANCE};
+    use std::time::{Duration, Instant};
+
+    fn resolution(kind: &str, signature: &str, order: &[(&str, u64)]) -> Resolution {
+        let t0 = Instant::now();
+        let arrivals = order
+            .iter()
+            .map(|(endpoint, ms)| Arrival {
+                endpoint: endpoint.to_string(),
+                key: ArrivalKey::Event { signature: signature.to_string(), kind: kind.to_string() },
+                timestamp: t0 + Duration::from_millis(*ms),
+                decode_time: None,
+            })
+            .collect();
+        Resolution {
+            key: ArrivalKey::Event { signature: signature.to_string(), kind: kind.to_string() },
+            arrivals,
+            timed_out: false,
+            missed: Vec::new(),
+            tie_tolerance: DEFAULT_TIE_TOLERANCE,
+        }
+    }
+
+    #[test]
+    fn test_scores_each_category_separately() {
+        let mut breakdown = CategoryBreakdown::new();
+        breakdown.record(&resolution("PumpFunBuy", "s1", &[("a", 0), ("b", 2)]));
+        breakdown.record(&resolution("PumpFunBuy", "s2", &[("a", 0), ("b", 4)]));
+        breakdown.record(&resolution("RaydiumClmmSwap", "s3", &[("b", 0), ("a", 1)]));
+
+        let categories: Vec<_> = breakdown.categories().collect();
+        assert_eq!(categories.len(), 2);
+
+        let (name, pump) = categories[0];
+        assert_eq!(name, "PumpFunBuy");
+        assert_eq!(pump["a"].first, 2);
+        assert_eq!(pump["b"].latencies, vec![2.0, 4.0]);
+
+        let (_, raydium) = categories[1];
+        assert_eq!(raydium["b"].first, 1);
+        assert_eq!(raydium["a"].latencies, vec![1.0]);
+    }
+}
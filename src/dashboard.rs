@@ -0,0 +1,494 @@
+use crate::aggregator::AggregatorEvent;
+use crate::output::EndpointStatus;
+use crate::source::HealthSnapshot;
+use crate::stats::{calculate_stats, LatencyStats, Placement};
+use crate::throughput::{ThroughputMonitor, ThroughputSnapshot};
+use chrono::Local;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Color, Modifier, Style, Stylize};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, Borders, Cell, Gauge, Paragraph, Row, Sparkline, Table};
+use ratatui::Frame;
+use std::collections::VecDeque;
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::Notify;
+use tokio::task::JoinHandle;
+
+/// Relative latencies older than this drop out of the rolling percentiles.
+pub const ROLLING_WINDOW: Duration = Duration::from_secs(30);
+
+/// Resolved keys kept for each endpoint's sparkline.
+const SPARKLINE_POINTS: usize = 240;
+
+const LOG_LINES: usize = 100;
+
+const REDRAW_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Live view of one endpoint.
+#[derive(Debug)]
+pub struct EndpointPanel {
+    pub name: String,
+    pub status: EndpointStatus,
+    pub received: usize,
+    pub first_received: usize,
+    /// Keys delivered within the tie tolerance of the earliest arrival.
+    pub tied: usize,
+    /// Keys that timed out before this endpoint delivered them.
+    pub missed: usize,
+    pub errors: u64,
+    pub throughput: ThroughputSnapshot,
+    /// Slots behind the tip at the last sample; `None` unless racing slots.
+    pub behind: Option<u64>,
+    /// `(resolved at, latency behind the first arrival in ms)`, including zeros when first.
+    recent: VecDeque<(Instant, f64)>,
+    sparkline: VecDeque<f64>,
+}
+
+impl EndpointPanel {
+    fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            status: EndpointStatus::Connecting,
+            received: 0,
+            first_received: 0,
+            tied: 0,
+            missed: 0,
+            errors: 0,
+            throughput: ThroughputSnapshot::default(),
+            behind: None,
+            recent: VecDeque::new(),
+            sparkline: VecDeque::with_capacity(SPARKLINE_POINTS),
+        }
+    }
+
+    pub fn first_percent(&self) -> f64 {
+        if self.received == 0 {
+            0.0
+        } else {
+            self.first_received as f64 / self.received as f64 * 100.0
+        }
+    }
+
+    /// Relative latency distribution over the last `ROLLING_WINDOW`.
+    pub fn rolling(&self) -> LatencyStats {
+        let latencies: Vec<f64> = self.recent.iter().map(|(_, latency)| *latency).collect();
+        calculate_stats(&latencies)
+    }
+
+    fn record(&mut self, now: Instant, latency: f64, placement: Placement) {
+        self.received += 1;
+        match placement {
+            Placement::First => self.first_received += 1,
+            Placement::Tied => self.tied += 1,
+            Placement::Delayed => {}
+        }
+        self.recent.push_back((now, latency));
+        if self.sparkline.len() == SPARKLINE_POINTS {
+            self.sparkline.pop_front();
+        }
+        self.sparkline.push_back(latency);
+    }
+
+    fn expire(&mut self, now: Instant) {
+        while let Some((at, _)) = self.recent.front() {
+            if now.saturating_duration_since(*at) <= ROLLING_WINDOW {
+                break;
+            }
+            self.recent.pop_front();
+        }
+    }
+}
+
+/// Everything the dashboard shows, fed from the aggregator events and the throughput monitor.
+#[derive(Debug)]
+pub struct DashboardState {
+    title: String,
+    started_at: Instant,
+    deadline: Instant,
+    scoring: Option<usize>,
+    endpoints: Vec<EndpointPanel>,
+    log: VecDeque<String>,
+}
+
+impl DashboardState {
+    pub fn new(title: &str, endpoints: &[String], started_at: Instant, deadline: Instant) -> Self {
+        Self {
+            title: title.to_string(),
+            started_at,
+            deadline,
+            scoring: None,
+            endpoints: endpoints.iter().map(|name| EndpointPanel::new(name)).collect(),
+            log: VecDeque::with_capacity(LOG_LINES),
+        }
+    }
+
+    pub fn endpoints(&self) -> &[EndpointPanel] {
+        &self.endpoints
+    }
+
+    pub fn endpoint(&self, name: &str) -> Option<&EndpointPanel> {
+        self.endpoints.iter().find(|e| e.name == name)
+    }
+
+    fn endpoint_mut(&mut self, name: &str) -> &mut EndpointPanel {
+        match self.endpoints.iter().position(|e| e.name == name) {
+            Some(index) => &mut self.endpoints[index],
+            None => {
+                self.endpoints.push(EndpointPanel::new(name));
+                self.endpoints.last_mut().unwrap()
+            }
+        }
+    }
+
+    /// Appends a timestamped line to the event pane.
+    pub fn log(&mut self, message: impl AsRef<str>) {
+        if self.log.len() == LOG_LINES {
+            self.log.pop_front();
+        }
+        self.log.push_back(format!("[{}] {}", Local::now().format("%H:%M:%S%.3f"), message.as_ref()));
+    }
+
+    pub fn observe(&mut self, event: &AggregatorEvent, now: Instant) {
+        match event {
+            AggregatorEvent::FirstArrival { endpoint, key } => {
+                let panel = self.endpoint_mut(endpoint);
+                if panel.status != EndpointStatus::Failed {
+                    panel.status = EndpointStatus::Connected;
+                }
+                self.log(format!("{} 成功接收到第一个 {}", endpoint, key));
+            }
+            AggregatorEvent::Excluded { endpoint, slot, tip } => {
+                self.endpoint_mut(endpoint).status = EndpointStatus::Failed;
+                self.log(format!(
+                    "{} 的slot ({}) 落后最新slot {} 共 {} 个区块, 不参与比较",
+                    endpoint,
+                    slot,
+                    tip,
+                    tip.saturating_sub(*slot)
+                ));
+            }
+            AggregatorEvent::Flagged { endpoint, slot, tip } => {
+                self.log(format!(
+                    "{} 的slot ({}) 落后最新slot {} 共 {} 个区块, 仍参与比较",
+                    endpoint,
+                    slot,
+                    tip,
+                    tip.saturating_sub(*slot)
+                ));
+            }
+            AggregatorEvent::CaughtUp { endpoint, readmitted } => {
+                if *readmitted {
+                    self.endpoint_mut(endpoint).status = EndpointStatus::Testing;
+                    self.log(format!("{} 已追上最新slot, 重新参与比较", endpoint));
+                } else {
+                    self.log(format!("{} 已追上最新slot", endpoint));
+                }
+            }
+            AggregatorEvent::SlotLag { behind, .. } => {
+                for (endpoint, lag) in behind {
+                    self.endpoint_mut(endpoint).behind = Some(*lag);
+                }
+            }
+            AggregatorEvent::Started { active } => {
+                self.scoring = Some(*active);
+                for panel in &mut self.endpoints {
+                    if panel.status == EndpointStatus::Connected {
+                        panel.status = EndpointStatus::Testing;
+                    }
+                }
+                self.log(format!("有{}个有效端点, 开始正式统计", active));
+            }
+            AggregatorEvent::Measuring { keys } => {
+                self.log(format!("预热结束 (共 {} 个), 开始计入统计", keys));
+            }
+            AggregatorEvent::EndpointLost { endpoint, reason, remaining } => {
+                self.endpoint_mut(endpoint).status = EndpointStatus::Failed;
+                self.scoring = Some(*remaining);
+                self.log(format!("{} 连接中断: {} (剩余 {} 个端点)", endpoint, reason, remaining));
+            }
+            AggregatorEvent::Resolved(resolution) => {
+                for (arrival, placement, latency) in resolution.placements() {
+                    self.endpoint_mut(&arrival.endpoint).record(now, latency, placement);
+                }
+                for endpoint in &resolution.missed {
+                    self.endpoint_mut(endpoint).missed += 1;
+                }
+            }
+        }
+        for panel in &mut self.endpoints {
+            panel.expire(now);
+        }
+    }
+
+    /// Connection state and error counters from the sources.
+    pub fn update_health(&mut self, health: &[(String, HealthSnapshot)]) {
+        for (name, snapshot) in health {
+            let panel = self.endpoint_mut(name);
+            panel.errors = snapshot.errors;
+            panel.status = match (panel.status, snapshot.connected) {
+                (EndpointStatus::Failed, _) => EndpointStatus::Failed,
+                (EndpointStatus::Connecting, true) => EndpointStatus::Connected,
+                (_, false) => EndpointStatus::Connecting,
+                (status, true) => status,
+            };
+        }
+    }
+
+    pub fn update_throughput(&mut self, throughput: Vec<(String, ThroughputSnapshot)>) {
+        for (name, snapshot) in throughput {
+            self.endpoint_mut(&name).throughput = snapshot;
+        }
+    }
+
+    fn render(&self, frame: &mut Frame) {
+        let rows = self.endpoints.len() as u16;
+        let [progress, table, sparklines, log] = Layout::vertical([
+            Constraint::Length(3),
+            Constraint::Length(rows + 3),
+            Constraint::Length(rows + 2),
+            Constraint::Min(4),
+        ])
+        .areas(frame.area());
+
+        self.render_progress(frame, progress);
+        self.render_table(frame, table);
+        self.render_sparklines(frame, sparklines);
+        self.render_log(frame, log);
+    }
+
+    fn render_progress(&self, frame: &mut Frame, area: Rect) {
+        let total = self.deadline.saturating_duration_since(self.started_at).as_secs_f64();
+        let elapsed = self.started_at.elapsed().as_secs_f64().min(total);
+        let ratio = if total > 0.0 { elapsed / total } else { 1.0 };
+        let scoring = match self.scoring {
+            Some(active) => format!("{} 个有效端点", active),
+            None => "等待所有端点的第一条数据".to_string(),
+        };
+        let gauge = Gauge::default()
+            .block(Block::default().borders(Borders::ALL).title(format!(" {} · 按 q 提前结束 ", self.title)))
+            .gauge_style(Style::default().fg(Color::Cyan))
+            .ratio(ratio)
+            .label(format!("{:.0}/{:.0}秒 · 剩余 {:.0}秒 · {}", elapsed, total, total - elapsed, scoring));
+        frame.render_widget(gauge, area);
+    }
+
+    fn render_table(&self, frame: &mut Frame, area: Rect) {
+        let header = Row::new(["端点", "状态", "接收", "错过", "首先接收", "p50", "p90", "p99", "吞吐", "落后slot", "错误"])
+            .style(Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD));
+        let rows = self.endpoints.iter().map(|panel| {
+            let (status, color) = status_label(panel.status);
+            let rolling = panel.rolling();
+            let latency = |value: f64| if rolling.count == 0 { "-".to_string() } else { format!("{:.2}ms", value) };
+            Row::new([
+                Cell::from(panel.name.clone()),
+                Cell::from(status).style(Style::default().fg(color)),
+                Cell::from(panel.received.to_string()),
+                Cell::from(panel.missed.to_string())
+                    .style(Style::default().fg(if panel.missed > 0 { Color::Red } else { Color::Reset })),
+                Cell::from(format!("{:6.2}%", panel.first_percent())),
+                Cell::from(latency(rolling.median)),
+                Cell::from(latency(rolling.p90)),
+                Cell::from(latency(rolling.p99)),
+                Cell::from(format!("{:.1}/秒", panel.throughput.current.messages_per_sec)),
+                Cell::from(panel.behind.map_or("-".to_string(), |lag| lag.to_string())),
+                Cell::from(panel.errors.to_string())
+                    .style(Style::default().fg(if panel.errors > 0 { Color::Red } else { Color::Reset })),
+            ])
+        });
+        let name_width = self.endpoints.iter().map(|e| e.name.len()).max().unwrap_or(0).max(4) as u16;
+        let widths = [
+            Constraint::Length(name_width),
+            Constraint::Length(10),
+            Constraint::Length(8),
+            Constraint::Length(6),
+            Constraint::Length(9),
+            Constraint::Length(10),
+            Constraint::Length(10),
+            Constraint::Length(10),
+            Constraint::Length(10),
+            Constraint::Length(9),
+            Constraint::Length(6),
+        ];
+        let title = format!(" 端点 (落后延迟为最近 {} 秒) ", ROLLING_WINDOW.as_secs());
+        let table = Table::new(rows, widths).header(header).block(Block::default().borders(Borders::ALL).title(title));
+        frame.render_widget(table, area);
+    }
+
+    // 每个端点最近若干个 key 的相对延迟，所有端点共用同一纵轴
+    fn render_sparklines(&self, frame: &mut Frame, area: Rect) {
+        let max = self.endpoints.iter().flat_map(|e| e.sparkline.iter().copied()).fold(0.0, f64::max);
+        let block = Block::default()
+            .borders(Borders::ALL)
+            .title(format!(" 最近相对延迟 (纵轴最大 {:.2}ms) ", max));
+        let inner = block.inner(area);
+        frame.render_widget(block, area);
+
+        let name_width = self.endpoints.iter().map(|e| e.name.len()).max().unwrap_or(0) as u16 + 1;
+        let lines = Layout::vertical(vec![Constraint::Length(1); self.endpoints.len()]).split(inner);
+        for (panel, line) in self.endpoints.iter().zip(lines.iter()) {
+            let [label, chart] = Layout::horizontal([Constraint::Length(name_width), Constraint::Min(1)]).areas(*line);
+            frame.render_widget(Paragraph::new(panel.name.as_str()), label);
+
+            // 以微秒为单位取最近能显示下的点
+            let width = chart.width as usize;
+            let skip = panel.sparkline.len().saturating_sub(width);
+            let data: Vec<u64> = panel.sparkline.iter().skip(skip).map(|ms| (ms * 1000.0) as u64).collect();
+            let sparkline = Sparkline::default()
+                .data(&data)
+                .max(((max * 1000.0) as u64).max(1))
+                .style(Style::default().fg(status_label(panel.status).1));
+            frame.render_widget(sparkline, chart);
+        }
+    }
+
+    fn render_log(&self, frame: &mut Frame, area: Rect) {
+        let visible = area.height.saturating_sub(2) as usize;
+        let skip = self.log.len().saturating_sub(visible);
+        let lines: Vec<Line> = self.log.iter().skip(skip).map(|line| Line::from(line.as_str())).collect();
+        let log = Paragraph::new(lines).block(Block::default().borders(Borders::ALL).title(" 事件 ".bold()));
+        frame.render_widget(log, area);
+    }
+}
+
+fn status_label(status: EndpointStatus) -> (&'static str, Color) {
+    match status {
+        EndpointStatus::Connected => ("🟢 已连接", Color::Green),
+        EndpointStatus::Connecting => ("🟡 连接中", Color::Yellow),
+        EndpointStatus::Failed => ("🔴 失败", Color::Red),
+        EndpointStatus::Testing => ("🔵 统计中", Color::Cyan),
+    }
+}
+
+/// Full-screen view of a running comparison.
+///
+/// Takes over the terminal (alternate screen, raw mode) until `close`, redrawing a shared
+/// `DashboardState` a few times per second. Nothing else may print to stdout meanwhile.
+/// Dropping it without `close` still restores the terminal, just without waiting.
+pub struct Dashboard {
+    state: Arc<Mutex<DashboardState>>,
+    stop: Arc<AtomicBool>,
+    quit: Arc<Notify>,
+    task: Option<JoinHandle<io::Result<()>>>,
+}
+
+impl Dashboard {
+    pub fn open(state: DashboardState, monitor: ThroughputMonitor) -> io::Result<Self> {
+        let mut terminal = ratatui::try_init()?;
+        let state = Arc::new(Mutex::new(state));
+        let stop = Arc::new(AtomicBool::new(false));
+        let quit = Arc::new(Notify::new());
+
+        let task = {
+            let state = state.clone();
+            let stop = stop.clone();
+            let quit = quit.clone();
+            tokio::task::spawn_blocking(move || {
+                let result = (|| {
+                    while !stop.load(Ordering::Relaxed) {
+                        {
+                            let mut state = state.lock().unwrap();
+                            state.update_health(&monitor.health());
+                            state.update_throughput(monitor.snapshot());
+                            terminal.draw(|frame| state.render(frame))?;
+                        }
+                        // 等待按键的同时控制刷新频率; raw 模式下 Ctrl-C 也以按键形式到达
+                        if event::poll(REDRAW_INTERVAL)? {
+                            if let Event::Key(key) = event::read()? {
+                                let ctrl_c = key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL);
+                                if key.kind == KeyEventKind::Press && (key.code == KeyCode::Char('q') || ctrl_c) {
+                                    quit.notify_one();
+                                }
+                            }
+                        }
+                    }
+                    Ok(())
+                })();
+                ratatui::restore();
+                result
+            })
+        };
+
+        Ok(Self { state, stop, quit, task: Some(task) })
+    }
+
+    /// Shared with the task forwarding aggregator events.
+    pub fn state(&self) -> Arc<Mutex<DashboardState>> {
+        self.state.clone()
+    }
+
+    /// Completes once the user asked to end the run early.
+    pub async fn quit_requested(&self) {
+        self.quit.notified().await
+    }
+
+    /// Restores the terminal.
+    pub async fn close(mut self) -> io::Result<()> {
+        self.stop.store(true, Ordering::Relaxed);
+        match self.task.take() {
+            Some(task) => task.await.map_err(io::Error::other)?,
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for Dashboard {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::aggregator::{Arrival, ArrivalKey, Resolution, DEFAULT_TIE_TOLERANCE};
+
+    fn resolved(slot: u64, order: &[(&str, u64)], t0: Instant) -> AggregatorEvent {
+        AggregatorEvent::Resolved(Resolution {
+            key: ArrivalKey::Slot(slot),
+            arrivals: order
+                .iter()
+                .map(|(endpoint, ms)| Arrival {
+                    endpoint: endpoint.to_string(),
+                    key: ArrivalKey::Slot(slot),
+                    timestamp: t0 + Duration::from_millis(*ms),
+                    decode_time: None,
+                })
+                .collect(),
+            timed_out: false,
+            missed: Vec::new(),
+            tie_tolerance: DEFAULT_TIE_TOLERANCE,
+        })
+    }
+
+    #[test]
+    fn test_rolling_window_and_status() {
+        let t0 = Instant::now();
+        let names = vec!["A".to_string(), "B".to_string()];
+        let mut state = DashboardState::new("test", &names, t0, t0 + Duration::from_secs(120));
+
+        state.observe(&resolved(1, &[("A", 0), ("B", 4)], t0), t0);
+        state.observe(&resolved(2, &[("B", 0), ("A", 2)], t0), t0 + Duration::from_secs(10));
+        state.observe(&resolved(3, &[("A", 0), ("B", 6)], t0), t0 + Duration::from_secs(35));
+
+        let a = state.endpoint("A").unwrap();
+        assert_eq!((a.received, a.first_received), (3, 2));
+        // 第一个 slot 已超出 30 秒窗口
+        let b = state.endpoint("B").unwrap();
+        assert_eq!(b.rolling().latencies, vec![0.0, 6.0]);
+        assert_eq!(b.sparkline.len(), 3);
+
+        state.update_health(&[("A".to_string(), HealthSnapshot { connected: true, errors: 2, ..Default::default() })]);
+        state.observe(
+            &AggregatorEvent::EndpointLost { endpoint: "B".to_string(), reason: "reset".to_string(), remaining: 1 },
+            t0 + Duration::from_secs(36),
+        );
+        state.update_health(&[("B".to_string(), HealthSnapshot { connected: true, ..Default::default() })]);
+        assert_eq!(state.endpoint("A").unwrap().status, EndpointStatus::Connected);
+        assert_eq!(state.endpoint("A").unwrap().errors, 2);
+        assert_eq!(state.endpoint("B").unwrap().status, EndpointStatus::Failed);
+    }
+}
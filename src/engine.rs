@@ -0,0 +1,300 @@
+use crate::aggregator::{Aggregator, AggregatorConfig, AggregatorEvent, AggregatorHandle, Arrival, WarmupReport};
+use crate::error::Result;
+use crate::runtime::{EndpointTask, ExecutionMode};
+use crate::source::{HealthSnapshot, SourceHealth, StreamSource};
+use crate::stats::EndpointStatsMap;
+use crate::throughput::{ThroughputMonitor, ThroughputSnapshot, SAMPLE_INTERVAL};
+use crate::trace::{TraceRecorder, TraceWriter};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tokio::time::{interval, sleep_until};
+use tracing::{info, warn};
+
+#[derive(Debug, Clone)]
+pub struct ComparisonConfig {
+    /// `Duration::MAX` runs until `RunningComparison::stop`.
+    pub duration: Duration,
+    pub execution_mode: ExecutionMode,
+    pub aggregator: AggregatorConfig,
+    /// Windows over which peak message and byte rates are tracked.
+    pub throughput_windows: Vec<Duration>,
+}
+
+impl ComparisonConfig {
+    pub fn new(duration: Duration) -> Self {
+        Self {
+            duration,
+            execution_mode: ExecutionMode::default(),
+            aggregator: AggregatorConfig::default(),
+            throughput_windows: vec![Duration::from_secs(1)],
+        }
+    }
+}
+
+/// Runs a set of `StreamSource`s against each other through the aggregator.
+pub struct ComparisonEngine {
+    config: ComparisonConfig,
+    sources: Vec<Box<dyn StreamSource>>,
+    trace: Option<File>,
+}
+
+impl ComparisonEngine {
+    pub fn new(config: ComparisonConfig) -> Self {
+        Self {
+            config,
+            sources: Vec::new(),
+            trace: None,
+        }
+    }
+
+    /// Records every arrival to a binary trace at `path` (see `trace::TraceWriter`).
+    /// The file is created now so a bad path fails before any connection is made.
+    pub fn record_trace(&mut self, path: impl AsRef<Path>) -> std::io::Result<&mut Self> {
+        self.trace = Some(File::create(path)?);
+        Ok(self)
+    }
+
+    pub fn add_source(&mut self, source: impl StreamSource + 'static) -> &mut Self {
+        self.sources.push(Box::new(source));
+        self
+    }
+
+    pub fn add_boxed_source(&mut self, source: Box<dyn StreamSource>) -> &mut Self {
+        self.sources.push(source);
+        self
+    }
+
+    /// `(name, target)` of every source, in the order they were added.
+    pub fn endpoints(&self) -> Vec<(String, String)> {
+        self.sources
+            .iter()
+            .map(|s| (s.name().to_string(), s.target().to_string()))
+            .collect()
+    }
+
+    pub fn config(&self) -> &ComparisonConfig {
+        &self.config
+    }
+
+    /// Spawns the aggregator and one task per source. The run ends at `started_at + duration`.
+    pub fn start(self) -> RunningComparison {
+        let endpoints: Vec<String> = self.sources.iter().map(|s| s.name().to_string()).collect();
+        let health: Vec<_> = self.sources.iter().map(|s| (s.name().to_string(), s.health())).collect();
+        let throughput = ThroughputMonitor::new(&health, &self.config.throughput_windows);
+
+        let started_at = Instant::now();
+        let deadline = started_at
+            .checked_add(self.config.duration)
+            .unwrap_or_else(|| started_at + Duration::from_secs(100 * 365 * 24 * 3600));
+        let (aggregator, events, aggregator_task) =
+            Aggregator::spawn(endpoints.clone(), self.config.aggregator.clone(), started_at, deadline);
+
+        let mut tasks = Vec::new();
+
+        // 轨迹写入在独立的阻塞线程中进行，数据源只做一次无阻塞的发送
+        let (recorder, trace_task) = match self.trace {
+            Some(file) => match TraceWriter::new(BufWriter::new(file), started_at, &endpoints) {
+                Ok(writer) => {
+                    let (recorder, task) = writer.spawn();
+                    (Some(recorder), Some(task))
+                }
+                Err(e) => {
+                    warn!("failed to write trace header: {}", e);
+                    (None, None)
+                }
+            },
+            None => (None, None),
+        };
+
+        for (index, source) in self.sources.into_iter().enumerate() {
+            let name = source.name().to_string();
+            let source_aggregator = aggregator.clone();
+            let trace = recorder.clone().map(|recorder| (index, recorder));
+
+            let spawned = EndpointTask::spawn(&self.config.execution_mode, index, &name, move || {
+                drive(source, source_aggregator, trace)
+            });
+
+            match spawned {
+                Ok(task) => tasks.push((name, task)),
+                Err(e) => aggregator.endpoint_failed(&name, format!("failed to start endpoint runtime: {}", e)),
+            }
+        }
+
+        let sampler = throughput.clone();
+        let sampler_task = tokio::spawn(async move {
+            let mut ticker = interval(SAMPLE_INTERVAL);
+            loop {
+                ticker.tick().await;
+                sampler.sample();
+            }
+        });
+
+        RunningComparison {
+            endpoints,
+            health,
+            throughput,
+            sampler_task,
+            aggregator,
+            events: Some(events),
+            aggregator_task,
+            tasks,
+            trace_task,
+            started_at,
+            deadline,
+        }
+    }
+}
+
+/// Connects a source and forwards its events until it ends or the task is aborted.
+async fn drive(mut source: Box<dyn StreamSource>, aggregator: AggregatorHandle, trace: Option<(usize, TraceRecorder)>) {
+    let name = source.name().to_string();
+    let failed = |reason: String| {
+        if let Some((index, recorder)) = &trace {
+            recorder.endpoint_failed(*index, &reason);
+        }
+        aggregator.endpoint_failed(&name, reason);
+    };
+
+    if let Err(e) = source.connect().await {
+        failed(e.to_string());
+        return;
+    }
+    info!("{} connected to {}", name, source.target());
+
+    loop {
+        match source.next_event().await {
+            Some(Ok(event)) => {
+                let arrival = Arrival {
+                    endpoint: name.clone(),
+                    key: event.key,
+                    timestamp: event.timestamp,
+                    decode_time: event.decode_time,
+                };
+                if let Some((index, recorder)) = &trace {
+                    recorder.arrival(*index, &arrival, event.size);
+                }
+                aggregator.submit(arrival);
+            }
+            Some(Err(e)) => {
+                failed(e.to_string());
+                return;
+            }
+            None => {
+                failed("stream ended".to_string());
+                return;
+            }
+        }
+    }
+}
+
+pub struct RunningComparison {
+    endpoints: Vec<String>,
+    health: Vec<(String, Arc<SourceHealth>)>,
+    throughput: ThroughputMonitor,
+    sampler_task: JoinHandle<()>,
+    aggregator: AggregatorHandle,
+    events: Option<mpsc::UnboundedReceiver<AggregatorEvent>>,
+    aggregator_task: JoinHandle<(EndpointStatsMap, WarmupReport)>,
+    tasks: Vec<(String, EndpointTask)>,
+    trace_task: Option<JoinHandle<std::io::Result<u64>>>,
+    started_at: Instant,
+    deadline: Instant,
+}
+
+impl RunningComparison {
+    /// The aggregator event stream. Can be taken once.
+    pub fn take_events(&mut self) -> Option<mpsc::UnboundedReceiver<AggregatorEvent>> {
+        self.events.take()
+    }
+
+    pub fn started_at(&self) -> Instant {
+        self.started_at
+    }
+
+    pub fn deadline(&self) -> Instant {
+        self.deadline
+    }
+
+    pub fn health(&self) -> Vec<(String, HealthSnapshot)> {
+        self.health
+            .iter()
+            .map(|(name, health)| (name.clone(), health.snapshot()))
+            .collect()
+    }
+
+    /// Live per-endpoint message and byte rates, e.g. for progress lines.
+    pub fn throughput(&self) -> ThroughputMonitor {
+        self.throughput.clone()
+    }
+
+    /// Waits for the configured duration, then stops all sources and collects the stats.
+    pub async fn finish(self) -> Result<ComparisonReport> {
+        sleep_until(self.deadline.into()).await;
+        self.stop().await
+    }
+
+    /// Stops all sources now and collects the stats.
+    pub async fn stop(self) -> Result<ComparisonReport> {
+        // 独立线程需要 join 才能拿到 CPU 时间，放到阻塞线程池里等待
+        self.sampler_task.abort();
+        self.throughput.sample();
+        let mut cpu_times = HashMap::new();
+        for (name, task) in self.tasks {
+            if let Ok(Some(cpu)) = tokio::task::spawn_blocking(move || task.stop()).await {
+                cpu_times.insert(name, cpu);
+            }
+        }
+        self.aggregator.shutdown();
+        let (stats, warmup) = self.aggregator_task.await.map_err(std::io::Error::other)?;
+
+        // 所有数据源停止后记录器随之释放，写入线程刷新文件后退出
+        let trace_records = match self.trace_task {
+            Some(task) => match task.await.map_err(std::io::Error::other)? {
+                Ok(records) => Some(records),
+                Err(e) => {
+                    warn!("failed to write trace: {}", e);
+                    None
+                }
+            },
+            None => None,
+        };
+
+        Ok(ComparisonReport {
+            health: self
+                .health
+                .iter()
+                .map(|(name, health)| (name.clone(), health.snapshot()))
+                .collect(),
+            endpoints: self.endpoints,
+            stats,
+            warmup,
+            throughput: self.throughput.snapshot(),
+            cpu_times,
+            trace_records,
+            elapsed: self.started_at.elapsed(),
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct ComparisonReport {
+    /// Endpoint names in configuration order.
+    pub endpoints: Vec<String>,
+    /// Measured keys only; see `warmup` for the start and end of the run.
+    pub stats: EndpointStatsMap,
+    pub warmup: WarmupReport,
+    pub health: Vec<(String, HealthSnapshot)>,
+    pub throughput: Vec<(String, ThroughputSnapshot)>,
+    /// CPU time of each endpoint's thread; only filled in dedicated execution mode.
+    pub cpu_times: HashMap<String, Duration>,
+    /// Records written when `ComparisonEngine::record_trace` was used and the trace was written.
+    pub trace_records: Option<u64>,
+    pub elapsed: Duration,
+}
@@ -14,6 +14,9 @@ pub enum BenchmarkError {
     #[error("Configuration error: {0}")]
     ConfigError(String),
     
+    #[error("Stream error: {0}")]
+    StreamError(String),
+    
     #[error("Connection timeout")]
     Timeout,
     
@@ -22,6 +25,12 @@ pub enum BenchmarkError {
     
     #[error("IO error: {0}")]
     IoError(#[from] std::io::Error),
+
+    #[error("Database error: {0}")]
+    DatabaseError(#[from] rusqlite::Error),
+
+    #[error("Alert delivery failed: {0}")]
+    AlertError(String),
 }
 
 pub type Result<T> = std::result::Result<T, BenchmarkError>;
\ No newline at end of file
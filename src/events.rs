@@ -0,0 +1,170 @@
+use crate::aggregator::ArrivalKey;
+use crate::error::{BenchmarkError, Result};
+use solana_streamer_sdk::streaming::event_parser::common::EventType;
+use solana_streamer_sdk::streaming::event_parser::core::UnifiedEvent;
+use solana_streamer_sdk::streaming::event_parser::Protocol;
+
+// Program IDs for major Solana DeFi protocols
+pub const PUMPFUN_PROGRAM_ID: &str = "6EF8rrecthR5Dkzon8Nwu78hRvfCKubJ14M5uBEwF6P";
+pub const PUMPSWAP_PROGRAM_ID: &str = "PSwpkKNJhTNm5CbhHTNNfCEEF7ZdA8fxh4Wj1S6GzPo";
+pub const BONK_PROGRAM_ID: &str = "treaf4wWBBty3fHdyBpo35Mz84M8k3heKXmjmi9vFt5";
+pub const RAYDIUM_CPMM_PROGRAM_ID: &str = "CPMMoo8L3F4NbTegBCKVNunggL7H1ZpdTHKxQB5qKP1C";
+pub const RAYDIUM_CLMM_PROGRAM_ID: &str = "CAMMCzo5YL8w4VFF8KVHrK22GGUQpMAS4ZnukSFGUvJ";
+pub const RAYDIUM_AMM_V4_PROGRAM_ID: &str = "675kPX9MHTjS2zt1qfr1NYHuzeLXfQM9H24wFSUt1Mp8";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DexProtocol {
+    PumpFun,
+    PumpSwap,
+    Bonk,
+    RaydiumCpmm,
+    RaydiumClmm,
+    RaydiumAmmV4,
+}
+
+impl DexProtocol {
+    pub const ALL: [DexProtocol; 6] = [
+        DexProtocol::PumpFun,
+        DexProtocol::PumpSwap,
+        DexProtocol::Bonk,
+        DexProtocol::RaydiumCpmm,
+        DexProtocol::RaydiumClmm,
+        DexProtocol::RaydiumAmmV4,
+    ];
+
+    /// Also the prefix of the protocol's `EventType` names.
+    pub fn name(self) -> &'static str {
+        match self {
+            DexProtocol::PumpFun => "PumpFun",
+            DexProtocol::PumpSwap => "PumpSwap",
+            DexProtocol::Bonk => "Bonk",
+            DexProtocol::RaydiumCpmm => "RaydiumCpmm",
+            DexProtocol::RaydiumClmm => "RaydiumClmm",
+            DexProtocol::RaydiumAmmV4 => "RaydiumAmmV4",
+        }
+    }
+
+    pub fn program_id(self) -> &'static str {
+        match self {
+            DexProtocol::PumpFun => PUMPFUN_PROGRAM_ID,
+            DexProtocol::PumpSwap => PUMPSWAP_PROGRAM_ID,
+            DexProtocol::Bonk => BONK_PROGRAM_ID,
+            DexProtocol::RaydiumCpmm => RAYDIUM_CPMM_PROGRAM_ID,
+            DexProtocol::RaydiumClmm => RAYDIUM_CLMM_PROGRAM_ID,
+            DexProtocol::RaydiumAmmV4 => RAYDIUM_AMM_V4_PROGRAM_ID,
+        }
+    }
+
+    pub fn sdk_protocol(self) -> Protocol {
+        match self {
+            DexProtocol::PumpFun => Protocol::PumpFun,
+            DexProtocol::PumpSwap => Protocol::PumpSwap,
+            DexProtocol::Bonk => Protocol::Bonk,
+            DexProtocol::RaydiumCpmm => Protocol::RaydiumCpmm,
+            DexProtocol::RaydiumClmm => Protocol::RaydiumClmm,
+            DexProtocol::RaydiumAmmV4 => Protocol::RaydiumAmmV4,
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|p| p.name().eq_ignore_ascii_case(name.trim()))
+    }
+
+    pub fn of_event_type(event_type: &EventType) -> Option<Self> {
+        let name = format!("{:?}", event_type);
+        Self::ALL.into_iter().find(|p| name.starts_with(p.name()))
+    }
+}
+
+/// Event types that can be selected by name from the command line or environment.
+pub fn selectable_event_types() -> Vec<EventType> {
+    vec![
+        EventType::PumpFunCreateToken,
+        EventType::PumpFunBuy,
+        EventType::PumpFunSell,
+        EventType::PumpSwapBuy,
+        EventType::PumpSwapSell,
+        EventType::PumpSwapCreatePool,
+        EventType::PumpSwapDeposit,
+        EventType::PumpSwapWithdraw,
+        EventType::BonkBuyExactIn,
+        EventType::BonkBuyExactOut,
+        EventType::BonkSellExactIn,
+        EventType::BonkSellExactOut,
+        EventType::BonkInitialize,
+        EventType::RaydiumCpmmSwapBaseInput,
+        EventType::RaydiumCpmmSwapBaseOutput,
+        EventType::RaydiumClmmSwap,
+        EventType::RaydiumClmmSwapV2,
+        EventType::RaydiumAmmV4SwapBaseIn,
+        EventType::RaydiumAmmV4SwapBaseOut,
+    ]
+}
+
+/// Parses event type names such as `PumpFunBuy` (case-insensitive).
+pub fn parse_event_types(names: &[String]) -> Result<Vec<EventType>> {
+    let selectable = selectable_event_types();
+    let mut event_types = Vec::new();
+
+    for name in names.iter().map(|n| n.trim()).filter(|n| !n.is_empty()) {
+        let event_type = selectable
+            .iter()
+            .find(|t| format!("{:?}", t).eq_ignore_ascii_case(name))
+            .ok_or_else(|| {
+                let valid: Vec<_> = selectable.iter().map(|t| format!("{:?}", t)).collect();
+                BenchmarkError::ConfigError(format!("unknown event type '{}', expected one of: {}", name, valid.join(", ")))
+            })?;
+        if !event_types.contains(event_type) {
+            event_types.push(event_type.clone());
+        }
+    }
+
+    Ok(event_types)
+}
+
+/// Protocols that produce `event_types`, in `DexProtocol::ALL` order.
+pub fn protocols_for(event_types: &[EventType]) -> Vec<DexProtocol> {
+    DexProtocol::ALL
+        .into_iter()
+        .filter(|p| event_types.iter().any(|t| DexProtocol::of_event_type(t) == Some(*p)))
+        .collect()
+}
+
+/// Race key for a parsed event: its transaction signature plus its event type.
+pub fn event_key(event: &dyn UnifiedEvent) -> ArrivalKey {
+    ArrivalKey::Event {
+        signature: event.signature().to_string(),
+        kind: format!("{:?}", event.event_type()),
+    }
+}
+
+/// Parses protocol names such as `PumpFun` or `RaydiumClmm` (case-insensitive).
+pub fn parse_protocols(names: &[String]) -> Result<Vec<DexProtocol>> {
+    let mut protocols = Vec::new();
+    for name in names.iter().map(|n| n.trim()).filter(|n| !n.is_empty()) {
+        let protocol = DexProtocol::parse(name).ok_or_else(|| {
+            let valid: Vec<_> = DexProtocol::ALL.iter().map(|p| p.name()).collect();
+            BenchmarkError::ConfigError(format!("unknown protocol '{}', expected one of: {}", name, valid.join(", ")))
+        })?;
+        if !protocols.contains(&protocol) {
+            protocols.push(protocol);
+        }
+    }
+    Ok(protocols)
+}
+
+/// Every selectable event type produced by `protocols`.
+pub fn protocol_event_types(protocols: &[DexProtocol]) -> Vec<EventType> {
+    selectable_event_types()
+        .into_iter()
+        .filter(|t| DexProtocol::of_event_type(t).is_some_and(|p| protocols.contains(&p)))
+        .collect()
+}
+
+/// Race key for the protocol race: the transaction signature plus the protocol name.
+pub fn protocol_key(signature: impl Into<String>, protocol: DexProtocol) -> ArrivalKey {
+    ArrivalKey::Event {
+        signature: signature.into(),
+        kind: protocol.name().to_string(),
+    }
+}
@@ -0,0 +1,290 @@
+use crate::aggregator::{AggregatorEvent, Arrival, Resolution};
+use crate::engine::ComparisonReport;
+use crate::source::HealthSnapshot;
+use crate::stats::{calculate_stats, EndpointStatsMap, LatencyStats};
+use std::collections::HashMap;
+
+/// Name of connection `index` (0-based) of a fanned-out endpoint.
+pub fn sub_endpoint_name(endpoint: &str, index: usize) -> String {
+    format!("{}#{}", endpoint, index + 1)
+}
+
+#[derive(Debug, Clone, Default)]
+struct Group {
+    name: String,
+    members: Vec<String>,
+    /// Keys at least one member delivered.
+    keys: usize,
+    /// Per member: keys it delivered first within the group.
+    wins: HashMap<String, usize>,
+    /// Per member: how far behind the group's earliest arrival it was, in ms.
+    behind_best: HashMap<String, Vec<f64>>,
+}
+
+/// Best-of-N view of endpoints opened over several parallel connections.
+///
+/// Every resolution is collapsed so each group counts as one endpoint arriving at its
+/// earliest member's time, then scored like the overall race. This is the latency a
+/// consumer taking the first copy from N redundant connections would see.
+#[derive(Debug, Clone, Default)]
+pub struct FanOut {
+    groups: Vec<Group>,
+    merged: EndpointStatsMap,
+}
+
+impl FanOut {
+    /// `groups` are `(endpoint name, sub-endpoint names)`.
+    pub fn new(groups: Vec<(String, Vec<String>)>) -> Self {
+        Self {
+            groups: groups
+                .into_iter()
+                .map(|(name, members)| Group { name, members, ..Default::default() })
+                .collect(),
+            merged: EndpointStatsMap::new(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.groups.is_empty()
+    }
+
+    fn group_of(&self, endpoint: &str) -> Option<&Group> {
+        self.groups.iter().find(|g| g.members.iter().any(|m| m == endpoint))
+    }
+
+    /// Group names, in configuration order.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.groups.iter().map(|g| g.name.as_str())
+    }
+
+    fn is_group(&self, name: &str) -> bool {
+        self.groups.iter().any(|g| g.name == name)
+    }
+
+    /// `resolution` with every group's members replaced by the group's earliest arrival.
+    pub fn collapse(&self, resolution: &Resolution) -> Resolution {
+        let mut arrivals: Vec<Arrival> = Vec::with_capacity(resolution.arrivals.len());
+        for arrival in &resolution.arrivals {
+            match self.group_of(&arrival.endpoint) {
+                // 已按到达时间排序，组内第一个到达即为最优连接
+                Some(group) if arrivals.iter().any(|a| a.endpoint == group.name) => {}
+                Some(group) => {
+                    let mut best = arrival.clone();
+                    best.endpoint = group.name.clone();
+                    arrivals.push(best);
+                }
+                None => arrivals.push(arrival.clone()),
+            }
+        }
+        // 组内所有连接都没有收到时，整个组才算错过
+        let mut missed: Vec<String> =
+            resolution.missed.iter().filter(|name| self.group_of(name).is_none()).cloned().collect();
+        for group in &self.groups {
+            if !arrivals.iter().any(|a| a.endpoint == group.name)
+                && resolution.missed.iter().any(|name| group.members.contains(name))
+            {
+                missed.push(group.name.clone());
+            }
+        }
+        Resolution {
+            key: resolution.key.clone(),
+            arrivals,
+            timed_out: resolution.timed_out,
+            missed,
+            tie_tolerance: resolution.tie_tolerance,
+        }
+    }
+
+    /// Scores only the groups of `resolution`, each as one best-of-N endpoint, leaving
+    /// members and ungrouped endpoints to `Resolution::score`.
+    pub fn score_groups(&self, resolution: &Resolution, stats: &mut EndpointStatsMap) {
+        let collapsed = self.collapse(resolution);
+        for endpoint in collapsed.missed.iter().filter(|name| self.is_group(name)) {
+            stats.entry(endpoint.clone()).or_default().increment_missed();
+        }
+        for (arrival, placement, latency) in collapsed.placements() {
+            if self.is_group(&arrival.endpoint) {
+                stats.entry(arrival.endpoint.clone()).or_default().record(placement, latency);
+            }
+        }
+    }
+
+    /// Per group: member counters summed, connected while any member is.
+    pub fn group_health(&self, health: &[(String, HealthSnapshot)]) -> Vec<(String, HealthSnapshot)> {
+        self.groups
+            .iter()
+            .map(|group| {
+                let mut total = HealthSnapshot::default();
+                for (_, member) in health.iter().filter(|(name, _)| group.members.contains(name)) {
+                    total.connected |= member.connected;
+                    total.messages += member.messages;
+                    total.errors += member.errors;
+                    total.bytes += member.bytes;
+                }
+                (group.name.clone(), total)
+            })
+            .collect()
+    }
+
+    /// Adds every group to `report` as an endpoint after its last member, with its best-of-N
+    /// stats, so SLO checks and history see it like any other endpoint. A group's `received`
+    /// is its busiest member's: distinct keys across members are not tracked.
+    pub fn add_to_report(&self, report: &mut ComparisonReport) {
+        for group in &self.groups {
+            let mut stats = self.merged.get(&group.name).cloned().unwrap_or_default();
+            stats.received = group
+                .members
+                .iter()
+                .filter_map(|member| report.stats.get(member))
+                .map(|member| member.received)
+                .max()
+                .unwrap_or(0);
+            report.stats.insert(group.name.clone(), stats);
+            let position = report
+                .endpoints
+                .iter()
+                .rposition(|name| group.members.contains(name))
+                .map_or(report.endpoints.len(), |index| index + 1);
+            report.endpoints.insert(position, group.name.clone());
+        }
+        let health = self.group_health(&report.health);
+        report.health.extend(health);
+    }
+
+    pub fn record(&mut self, resolution: &Resolution) {
+        if self.groups.is_empty() {
+            return;
+        }
+        self.collapse(resolution).score(&mut self.merged);
+
+        for group in &mut self.groups {
+            let mut members = resolution.arrivals.iter().filter(|a| group.members.contains(&a.endpoint));
+            let Some(best) = members.next() else {
+                continue;
+            };
+            group.keys += 1;
+            *group.wins.entry(best.endpoint.clone()).or_default() += 1;
+            for arrival in std::iter::once(best).chain(members) {
+                let behind = arrival.timestamp.duration_since(best.timestamp).as_secs_f64() * 1000.0;
+                group.behind_best.entry(arrival.endpoint.clone()).or_default().push(behind);
+            }
+        }
+    }
+
+    pub fn observe(&mut self, event: &AggregatorEvent) {
+        if let AggregatorEvent::Resolved(resolution) = event {
+            self.record(resolution);
+        }
+    }
+
+    /// Race statistics with each group scored as a single best-of-N endpoint.
+    pub fn merged_stats(&self) -> &EndpointStatsMap {
+        &self.merged
+    }
+
+    /// Per group: `(name, keys seen, [(member, group wins, lag behind the best connection)])`.
+    pub fn groups(&self) -> impl Iterator<Item = (&str, usize, Vec<(&str, usize, LatencyStats)>)> {
+        self.groups.iter().map(|group| {
+            let members = group
+                .members
+                .iter()
+                .map(|member| {
+                    let wins = group.wins.get(member).copied().unwrap_or(0);
+                    let behind = group.behind_best.get(member).map(|v| calculate_stats(v)).unwrap_or_default();
+                    (member.as_str(), wins, behind)
+                })
+                .collect();
+            (group.name.as_str(), group.keys, members)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::aggregator::{ArrivalKey, DEFAULT_TIE_TOLERANCE};
+    use std::time::{Duration, Instant};
+
+    fn resolution(slot: u64, order: &[(&str, u64)]) -> Resolution {
+        let t0 = Instant::now();
+        Resolution {
+            key: ArrivalKey::Slot(slot),
+            arrivals: order
+                .iter()
+                .map(|(endpoint, ms)| Arrival {
+                    endpoint: endpoint.to_string(),
+                    key: ArrivalKey::Slot(slot),
+                    timestamp: t0 + Duration::from_millis(*ms),
+                    decode_time: None,
+                })
+                .collect(),
+            timed_out: false,
+            missed: Vec::new(),
+            tie_tolerance: DEFAULT_TIE_TOLERANCE,
+        }
+    }
+
+    #[test]
+    fn test_best_of_n_scores_group_as_one_endpoint() {
+        let members = vec![sub_endpoint_name("A", 0), sub_endpoint_name("A", 1)];
+        let mut fanout = FanOut::new(vec![("A".to_string(), members)]);
+
+        // A#2 赢下 slot 1，B 赢下 slot 2
+        fanout.record(&resolution(1, &[("A#2", 0), ("B", 1), ("A#1", 3)]));
+        fanout.record(&resolution(2, &[("B", 0), ("A#1", 2), ("A#2", 5)]));
+
+        let merged = fanout.merged_stats();
+        assert_eq!(merged["A"].scored, 2);
+        assert_eq!(merged["A"].first, 1);
+        assert_eq!(merged["A"].latencies, vec![2.0]);
+        assert_eq!(merged["B"].first, 1);
+        assert!(!merged.contains_key("A#1"));
+
+        let (name, keys, members) = fanout.groups().next().unwrap();
+        assert_eq!((name, keys), ("A", 2));
+        let (member, wins, behind) = &members[0];
+        assert_eq!((*member, *wins), ("A#1", 1));
+        assert!((behind.mean - 1.5).abs() < 1e-6);
+        assert_eq!(members[1].1, 1);
+    }
+
+    #[test]
+    fn test_groups_are_added_to_report_as_endpoints() {
+        let members = vec![sub_endpoint_name("A", 0), sub_endpoint_name("A", 1)];
+        let mut fanout = FanOut::new(vec![("A".to_string(), members.clone())]);
+        let resolutions = [resolution(1, &[("A#2", 0), ("B", 1), ("A#1", 3)]), resolution(2, &[("B", 0), ("A#1", 2)])];
+
+        let mut report = ComparisonReport {
+            endpoints: vec!["A#1".to_string(), "A#2".to_string(), "B".to_string()],
+            stats: EndpointStatsMap::new(),
+            warmup: Default::default(),
+            health: vec![
+                ("A#1".to_string(), HealthSnapshot { connected: false, messages: 3, errors: 1, bytes: 30 }),
+                ("A#2".to_string(), HealthSnapshot { connected: true, messages: 2, errors: 0, bytes: 20 }),
+            ],
+            throughput: Vec::new(),
+            cpu_times: HashMap::new(),
+            trace_records: None,
+            elapsed: Duration::from_secs(1),
+        };
+        for resolution in &resolutions {
+            resolution.score(&mut report.stats);
+            fanout.record(resolution);
+        }
+        report.stats.get_mut("A#1").unwrap().received = 4;
+        fanout.add_to_report(&mut report);
+
+        assert_eq!(report.endpoints, vec!["A#1", "A#2", "A", "B"]);
+        let group = &report.stats["A"];
+        assert_eq!((group.received, group.scored, group.first), (4, 2, 1));
+        let (_, health) = report.health.iter().find(|(name, _)| name == "A").unwrap();
+        assert!(health.connected);
+        assert_eq!((health.messages, health.errors, health.bytes), (5, 1, 50));
+
+        // 窗口统计只补上组本身，成员和其他端点仍由 Resolution::score 计分
+        let mut window = EndpointStatsMap::new();
+        fanout.score_groups(&resolutions[0], &mut window);
+        assert_eq!(window.keys().collect::<Vec<_>>(), vec!["A"]);
+        assert_eq!(window["A"].first, 1);
+    }
+}
@@ -48,12 +48,17 @@ impl FzsClient {
         self.auth_token.as_deref()
     }
 
-    pub fn create_event_filter() -> EventTypeFilter {
-        EventTypeFilter::allow_only(vec![
+    /// Event types subscribed to when none are configured.
+    pub fn default_event_types() -> Vec<EventType> {
+        vec![
             EventType::PumpFunBuy,
             EventType::PumpFunSell,
             EventType::PumpSwapCreatePool,
-        ])
+        ]
+    }
+
+    pub fn create_event_filter(event_types: &[EventType]) -> EventTypeFilter {
+        EventTypeFilter::allow_only(event_types.to_vec())
     }
 
     pub async fn create_client(&self) -> Result<FzStreamClient> {
@@ -1,25 +1,180 @@
 use crate::error::{BenchmarkError, Result};
+use crate::transport::{FrameClock, FrameStampLayer};
+use futures::StreamExt;
 use std::collections::HashMap;
 use std::time::Duration;
-use tonic::transport::ClientTlsConfig;
+use tonic::codec::CompressionEncoding;
+use tonic::metadata::AsciiMetadataValue;
+use tonic::service::Interceptor;
+use tonic::transport::{ClientTlsConfig, Endpoint};
+use tonic::{Request, Status, Streaming};
+use tower::Layer;
 use tracing::info;
 
-use yellowstone_grpc_client::GeyserGrpcClient;
+use futures::stream::BoxStream;
+use yellowstone_grpc_client::{GeyserGrpcBuilder, GeyserGrpcClient};
+use yellowstone_grpc_proto::geyser::geyser_client::GeyserClient;
 use yellowstone_grpc_proto::prelude::{
-    CommitmentLevel, SubscribeRequest, SubscribeRequestFilterSlots,
+    CommitmentLevel, SubscribeRequest, SubscribeRequestFilterSlots, SubscribeUpdate,
 };
 
+pub type UpdateStream = BoxStream<'static, std::result::Result<SubscribeUpdate, Status>>;
+
+/// Adds the `x-token` header to every request.
+#[derive(Debug, Clone)]
+pub struct XTokenInterceptor(Option<AsciiMetadataValue>);
+
+impl Interceptor for XTokenInterceptor {
+    fn call(&mut self, mut request: Request<()>) -> std::result::Result<Request<()>, Status> {
+        if let Some(token) = &self.0 {
+            request.metadata_mut().insert("x-token", token.clone());
+        }
+        Ok(request)
+    }
+}
+
+/// Compression the client asks the server to use for responses.
+///
+/// Only `grpc-accept-encoding` is set: subscribe requests are tiny, and not every provider
+/// accepts compressed requests.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum GrpcCompression {
+    #[default]
+    None,
+    Gzip,
+    Zstd,
+}
+
+impl GrpcCompression {
+    pub const ALL: [GrpcCompression; 3] = [GrpcCompression::None, GrpcCompression::Gzip, GrpcCompression::Zstd];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            GrpcCompression::None => "none",
+            GrpcCompression::Gzip => "gzip",
+            GrpcCompression::Zstd => "zstd",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|c| c.name().eq_ignore_ascii_case(name.trim()))
+    }
+
+    pub fn encoding(self) -> Option<CompressionEncoding> {
+        match self {
+            GrpcCompression::None => None,
+            GrpcCompression::Gzip => Some(CompressionEncoding::Gzip),
+            GrpcCompression::Zstd => Some(CompressionEncoding::Zstd),
+        }
+    }
+}
+
+/// HTTP/2 and channel parameters. `Default` is what every tool has always used.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChannelOptions {
+    pub compression: GrpcCompression,
+    pub tcp_nodelay: bool,
+    pub keepalive_interval: Duration,
+    pub max_decoding_message_size: usize,
+    /// `None` keeps hyper's default (64KiB stream window, 5MiB connection window, 1MiB buffer).
+    pub initial_stream_window_size: Option<u32>,
+    pub initial_connection_window_size: Option<u32>,
+    pub adaptive_window: Option<bool>,
+    pub buffer_size: Option<usize>,
+}
+
+impl Default for ChannelOptions {
+    fn default() -> Self {
+        Self {
+            compression: GrpcCompression::None,
+            tcp_nodelay: true,
+            keepalive_interval: Duration::from_secs(30),
+            max_decoding_message_size: 64 * 1024 * 1024, // 64MB
+            initial_stream_window_size: None,
+            initial_connection_window_size: None,
+            adaptive_window: None,
+            buffer_size: None,
+        }
+    }
+}
+
+impl ChannelOptions {
+    /// Short label for reports, e.g. `stream_win=4MiB conn_win=default adaptive=on ...`.
+    pub fn describe(&self) -> String {
+        fn size(value: Option<u64>) -> String {
+            match value {
+                None => "default".to_string(),
+                Some(v) if v >= 1024 * 1024 && v % (1024 * 1024) == 0 => format!("{}MiB", v / (1024 * 1024)),
+                Some(v) if v >= 1024 && v % 1024 == 0 => format!("{}KiB", v / 1024),
+                Some(v) => v.to_string(),
+            }
+        }
+        let adaptive = match self.adaptive_window {
+            None => "default",
+            Some(true) => "on",
+            Some(false) => "off",
+        };
+        format!(
+            "stream_win={} conn_win={} adaptive={} buffer={} keepalive={}s nodelay={} compression={}",
+            size(self.initial_stream_window_size.map(u64::from)),
+            size(self.initial_connection_window_size.map(u64::from)),
+            adaptive,
+            size(self.buffer_size.map(|v| v as u64)),
+            self.keepalive_interval.as_secs(),
+            self.tcp_nodelay,
+            self.compression.name()
+        )
+    }
+
+    fn apply_to_endpoint(&self, mut endpoint: Endpoint) -> Endpoint {
+        endpoint = endpoint
+            .tcp_nodelay(self.tcp_nodelay)
+            .http2_keep_alive_interval(self.keepalive_interval)
+            .initial_stream_window_size(self.initial_stream_window_size)
+            .initial_connection_window_size(self.initial_connection_window_size)
+            .buffer_size(self.buffer_size);
+        if let Some(adaptive) = self.adaptive_window {
+            endpoint = endpoint.http2_adaptive_window(adaptive);
+        }
+        endpoint
+    }
+}
+
 pub struct GrpcClient {
     endpoint_name: String,
     url: String,
     token: Option<String>,
+    options: ChannelOptions,
 }
 
 impl GrpcClient {
     pub async fn connect(url: &str, token: Option<&str>, endpoint_name: String) -> Result<Self> {
-        info!("Connecting to {}: {}", endpoint_name, url);
+        Self::connect_with_options(url, token, endpoint_name, ChannelOptions::default()).await
+    }
+
+    pub async fn connect_with_options(
+        url: &str,
+        token: Option<&str>,
+        endpoint_name: String,
+        options: ChannelOptions,
+    ) -> Result<Self> {
+        info!("Connecting to {}: {} ({})", endpoint_name, url, options.describe());
+
+        // Test the connection
+        let _test_client = Self::builder(url, token, &options)?.connect().await
+            .map_err(|e| BenchmarkError::GrpcError(tonic::Status::unavailable(e.to_string())))?;
+
+        // Connection successful, store the parameters
+        Ok(Self {
+            endpoint_name,
+            url: url.to_string(),
+            token: token.map(|s| s.to_string()),
+            options,
+        })
+    }
 
-        // Test connection by creating a client and connecting
+    /// Client builder with authentication, TLS and the given channel parameters.
+    pub fn builder(url: &str, token: Option<&str>, options: &ChannelOptions) -> Result<GeyserGrpcBuilder> {
         let mut builder = GeyserGrpcClient::build_from_shared(url.to_string())
             .map_err(|e| BenchmarkError::ConfigError(format!("Invalid URL: {}", e)))?;
 
@@ -38,24 +193,37 @@ impl GrpcClient {
 
         // Set performance optimization parameters
         builder = builder
-            .max_decoding_message_size(64 * 1024 * 1024) // 64MB
+            .max_decoding_message_size(options.max_decoding_message_size)
             .connect_timeout(Duration::from_secs(10))
             .timeout(Duration::from_secs(30))
-            .tcp_nodelay(true)
-            .http2_keep_alive_interval(Duration::from_secs(30))
+            .tcp_nodelay(options.tcp_nodelay)
+            .http2_keep_alive_interval(options.keepalive_interval)
             .keep_alive_timeout(Duration::from_secs(5))
-            .keep_alive_while_idle(true);
+            .keep_alive_while_idle(true)
+            .initial_stream_window_size(options.initial_stream_window_size)
+            .initial_connection_window_size(options.initial_connection_window_size)
+            .buffer_size(options.buffer_size);
+        if let Some(adaptive) = options.adaptive_window {
+            builder = builder.http2_adaptive_window(adaptive);
+        }
+        if let Some(encoding) = options.compression.encoding() {
+            builder = builder.accept_compressed(encoding);
+        }
+        Ok(builder)
+    }
 
-        // Test the connection
-        let _test_client = builder.connect().await
+    /// Connects and opens a Subscribe stream with a single request.
+    pub async fn subscribe(
+        url: &str,
+        token: Option<&str>,
+        request: SubscribeRequest,
+        options: &ChannelOptions,
+    ) -> Result<UpdateStream> {
+        let mut client = Self::builder(url, token, options)?.connect().await
             .map_err(|e| BenchmarkError::GrpcError(tonic::Status::unavailable(e.to_string())))?;
-
-        // Connection successful, store the parameters
-        Ok(Self {
-            endpoint_name,
-            url: url.to_string(),
-            token: token.map(|s| s.to_string()),
-        })
+        let stream = client.subscribe_once(request).await
+            .map_err(|e| BenchmarkError::GrpcError(tonic::Status::unavailable(e.to_string())))?;
+        Ok(stream.boxed())
     }
 
     pub fn get_endpoint_name(&self) -> &str {
@@ -70,6 +238,10 @@ impl GrpcClient {
         self.token.as_deref()
     }
 
+    pub fn get_options(&self) -> &ChannelOptions {
+        &self.options
+    }
+
     pub fn create_slot_subscription_request() -> SubscribeRequest {
         let mut slots_filter = HashMap::new();
         slots_filter.insert("slot".to_string(), SubscribeRequestFilterSlots {
@@ -83,4 +255,52 @@ impl GrpcClient {
             ..Default::default()
         }
     }
+
+    /// Opens a Subscribe stream on a channel wrapped in `FrameStampLayer`.
+    ///
+    /// Every message yielded by the returned stream has a matching `MessageStamp` in the
+    /// returned clock, taken at poll time of its HTTP/2 DATA frame and before decompression and
+    /// protobuf decoding.
+    pub async fn subscribe_frame_stamped(
+        url: &str,
+        token: Option<&str>,
+        request: SubscribeRequest,
+        options: &ChannelOptions,
+    ) -> Result<(Streaming<SubscribeUpdate>, FrameClock)> {
+        let endpoint = Endpoint::from_shared(url.to_string())
+            .map_err(|e| BenchmarkError::ConfigError(format!("Invalid URL: {}", e)))?
+            .connect_timeout(Duration::from_secs(10))
+            .timeout(Duration::from_secs(30))
+            .keep_alive_timeout(Duration::from_secs(5))
+            .keep_alive_while_idle(true);
+        let mut endpoint = options.apply_to_endpoint(endpoint);
+
+        if url.starts_with("https://") {
+            let tls_config = ClientTlsConfig::new().with_native_roots();
+            endpoint = endpoint.tls_config(tls_config)
+                .map_err(|e| BenchmarkError::ConfigError(format!("TLS config error: {}", e)))?;
+        }
+
+        let x_token = token
+            .map(|t| t.parse::<AsciiMetadataValue>())
+            .transpose()
+            .map_err(|e| BenchmarkError::ConfigError(format!("Token error: {}", e)))?;
+
+        let channel = endpoint.connect().await
+            .map_err(|e| BenchmarkError::GrpcError(Status::unavailable(e.to_string())))?;
+
+        let clock = FrameClock::new();
+        let service = FrameStampLayer::new(clock.clone()).layer(channel);
+        let mut client = GeyserClient::with_interceptor(service, XTokenInterceptor(x_token))
+            .max_decoding_message_size(options.max_decoding_message_size);
+        if let Some(encoding) = options.compression.encoding() {
+            client = client.accept_compressed(encoding);
+        }
+
+        // 保持请求流不结束，避免服务端认为客户端已关闭订阅
+        let requests = futures::stream::iter([request]).chain(futures::stream::pending());
+        let stream = client.subscribe(requests).await?.into_inner();
+
+        Ok((stream, clock))
+    }
 }
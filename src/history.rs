@@ -0,0 +1,535 @@
+use crate::engine::ComparisonReport;
+use crate::error::Result;
+use crate::stats::{calculate_stats, EndpointStats};
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::Serialize;
+use std::path::Path;
+use std::process::Command;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Results file used when none is configured.
+pub const DEFAULT_HISTORY_DB: &str = "benchmark-history.db";
+
+/// Below this many milliseconds a latency change is noise, whatever the percentage.
+const MIN_LATENCY_CHANGE_MS: f64 = 0.1;
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS runs (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    binary TEXT NOT NULL,
+    started_at INTEGER NOT NULL,
+    duration_sec REAL NOT NULL,
+    hostname TEXT NOT NULL,
+    region TEXT,
+    version TEXT NOT NULL,
+    config TEXT NOT NULL,
+    kind TEXT NOT NULL DEFAULT 'race'
+);
+CREATE TABLE IF NOT EXISTS endpoint_results (
+    run_id INTEGER NOT NULL REFERENCES runs(id) ON DELETE CASCADE,
+    endpoint TEXT NOT NULL,
+    received INTEGER NOT NULL DEFAULT 0,
+    scored INTEGER NOT NULL,
+    first INTEGER NOT NULL,
+    tied INTEGER NOT NULL DEFAULT 0,
+    missed INTEGER NOT NULL DEFAULT 0,
+    avg_behind_ms REAL NOT NULL,
+    overall_avg_ms REAL NOT NULL,
+    p50_ms REAL NOT NULL,
+    p99_ms REAL NOT NULL,
+    PRIMARY KEY (run_id, endpoint)
+);
+CREATE TABLE IF NOT EXISTS baselines (
+    name TEXT PRIMARY KEY,
+    run_id INTEGER NOT NULL REFERENCES runs(id) ON DELETE CASCADE
+);
+";
+
+/// Where and with what a run was made.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RunEnvironment {
+    pub hostname: String,
+    /// Free-form label from `REGION_LABEL`, e.g. `fra-1`.
+    pub region: Option<String>,
+    /// Package version, plus `git describe` when run from a source checkout.
+    pub version: String,
+}
+
+impl RunEnvironment {
+    pub fn detect() -> Self {
+        let hostname = hostname().or_else(|| std::env::var("HOSTNAME").ok()).unwrap_or_else(|| "unknown".to_string());
+        let region = std::env::var("REGION_LABEL").ok().filter(|r| !r.is_empty());
+        let version = match git_describe() {
+            Some(git) => format!("{} ({})", env!("CARGO_PKG_VERSION"), git),
+            None => env!("CARGO_PKG_VERSION").to_string(),
+        };
+        Self { hostname, region, version }
+    }
+}
+
+#[cfg(unix)]
+fn hostname() -> Option<String> {
+    let mut buf = [0u8; 256];
+    // SAFETY: gethostname writes at most `buf.len()` bytes into `buf`.
+    if unsafe { libc::gethostname(buf.as_mut_ptr().cast(), buf.len()) } != 0 {
+        return None;
+    }
+    let len = buf.iter().position(|b| *b == 0).unwrap_or(buf.len());
+    Some(String::from_utf8_lossy(&buf[..len]).into_owned())
+}
+
+#[cfg(not(unix))]
+fn hostname() -> Option<String> {
+    std::env::var("COMPUTERNAME").ok()
+}
+
+fn git_describe() -> Option<String> {
+    let output = Command::new("git")
+        .args(["-C", env!("CARGO_MANIFEST_DIR"), "describe", "--always", "--dirty"])
+        .output()
+        .ok()?;
+    let described = String::from_utf8(output.stdout).ok()?.trim().to_string();
+    (output.status.success() && !described.is_empty()).then_some(described)
+}
+
+/// Per-endpoint summary statistics kept for every run.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct EndpointSummary {
+    pub endpoint: String,
+    /// Keys delivered, including those that were not scored; 0 for runs saved before it was kept.
+    pub received: usize,
+    /// Keys scored.
+    pub scored: usize,
+    /// Keys delivered first, ahead of every other endpoint by at least the tie tolerance.
+    pub first: usize,
+    /// Keys delivered within the tie tolerance of the earliest arrival.
+    pub tied: usize,
+    /// Keys that timed out before this endpoint delivered them.
+    pub missed: usize,
+    /// Mean latency when behind the fastest endpoint.
+    pub avg_behind_ms: f64,
+    /// Mean latency over everything received, counting first and tied arrivals as 0.
+    pub overall_avg_ms: f64,
+    pub p50_ms: f64,
+    pub p99_ms: f64,
+}
+
+impl EndpointSummary {
+    pub fn from_stats(endpoint: &str, stats: &EndpointStats) -> Self {
+        let latency = calculate_stats(&stats.latencies);
+        Self {
+            endpoint: endpoint.to_string(),
+            received: stats.received,
+            scored: stats.scored,
+            first: stats.first,
+            tied: stats.tied,
+            missed: stats.missed,
+            avg_behind_ms: stats.get_average_latency(),
+            overall_avg_ms: stats.get_overall_average_latency(),
+            p50_ms: latency.median,
+            p99_ms: latency.p99,
+        }
+    }
+
+    pub fn first_percent(&self) -> f64 {
+        if self.scored == 0 {
+            0.0
+        } else {
+            self.first as f64 / self.scored as f64 * 100.0
+        }
+    }
+
+    pub fn tie_percent(&self) -> f64 {
+        if self.scored == 0 {
+            0.0
+        } else {
+            self.tied as f64 / self.scored as f64 * 100.0
+        }
+    }
+
+    /// Share of keys, scored or missed, that this endpoint missed.
+    pub fn miss_percent(&self) -> f64 {
+        let keys = self.scored + self.missed;
+        if keys == 0 {
+            0.0
+        } else {
+            self.missed as f64 / keys as f64 * 100.0
+        }
+    }
+}
+
+/// What the counts and latencies of a run's endpoints measure.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RunKind {
+    /// Endpoints raced each other; latencies are behind the fastest endpoint.
+    Race,
+    /// Each endpoint was timed on its own. `received` counts requests sent, `scored` the
+    /// successful ones and `missed` the rejected ones; latencies are round-trip times, 0 when
+    /// not timed.
+    RoundTrip,
+}
+
+impl RunKind {
+    pub fn name(self) -> &'static str {
+        match self {
+            RunKind::Race => "race",
+            RunKind::RoundTrip => "round-trip",
+        }
+    }
+
+    fn from_name(name: &str) -> Self {
+        match name {
+            "round-trip" => RunKind::RoundTrip,
+            _ => RunKind::Race,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RunRecord {
+    /// Assigned by `HistoryStore::save`; 0 before.
+    pub id: i64,
+    /// Binary that made the run, e.g. `grpc-comparison`.
+    pub binary: String,
+    /// Runs of different kinds are never compared.
+    pub kind: RunKind,
+    /// Unix seconds.
+    pub started_at: i64,
+    pub duration_sec: f64,
+    pub environment: RunEnvironment,
+    /// Run parameters as JSON. Never put tokens here.
+    pub config: serde_json::Value,
+    pub endpoints: Vec<EndpointSummary>,
+}
+
+impl RunRecord {
+    /// A run that has just finished after `duration`, in the detected environment.
+    pub fn finished(binary: &str, duration: Duration, config: serde_json::Value, endpoints: Vec<EndpointSummary>) -> Self {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        Self {
+            id: 0,
+            binary: binary.to_string(),
+            kind: RunKind::Race,
+            started_at: now.saturating_sub(duration).as_secs() as i64,
+            duration_sec: duration.as_secs_f64(),
+            environment: RunEnvironment::detect(),
+            config,
+            endpoints,
+        }
+    }
+
+    /// A finished comparison, endpoints in configuration order.
+    pub fn from_report(binary: &str, config: serde_json::Value, report: &ComparisonReport) -> Self {
+        let endpoints = report
+            .endpoints
+            .iter()
+            .filter_map(|name| report.stats.get(name).map(|stats| EndpointSummary::from_stats(name, stats)))
+            .collect();
+        Self::finished(binary, report.elapsed, config, endpoints)
+    }
+
+    pub fn with_kind(mut self, kind: RunKind) -> Self {
+        self.kind = kind;
+        self
+    }
+
+    pub fn endpoint(&self, name: &str) -> Option<&EndpointSummary> {
+        self.endpoints.iter().find(|e| e.endpoint == name)
+    }
+}
+
+/// SQLite file holding every saved run.
+pub struct HistoryStore {
+    conn: Connection,
+}
+
+impl HistoryStore {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        Self::init(Connection::open(path)?)
+    }
+
+    pub fn open_in_memory() -> Result<Self> {
+        Self::init(Connection::open_in_memory()?)
+    }
+
+    fn init(conn: Connection) -> Result<Self> {
+        conn.execute_batch("PRAGMA foreign_keys = ON;")?;
+        conn.execute_batch(SCHEMA)?;
+        // 早期版本的 received/first_received 列记的是计分数和首先接收数，没有原始接收数
+        if conn.prepare("SELECT scored FROM endpoint_results LIMIT 0").is_err() {
+            conn.execute_batch(
+                "ALTER TABLE endpoint_results RENAME COLUMN received TO scored;
+                 ALTER TABLE endpoint_results RENAME COLUMN first_received TO first;
+                 ALTER TABLE endpoint_results ADD COLUMN received INTEGER NOT NULL DEFAULT 0;",
+            )?;
+        }
+        // 早期版本的运行都是端点对比
+        if conn.prepare("SELECT kind FROM runs LIMIT 0").is_err() {
+            conn.execute_batch("ALTER TABLE runs ADD COLUMN kind TEXT NOT NULL DEFAULT 'race';")?;
+        }
+        // 早期版本的历史库没有这些列
+        for column in ["tied", "missed"] {
+            if conn.prepare(&format!("SELECT {} FROM endpoint_results LIMIT 0", column)).is_err() {
+                conn.execute_batch(&format!(
+                    "ALTER TABLE endpoint_results ADD COLUMN {} INTEGER NOT NULL DEFAULT 0;",
+                    column
+                ))?;
+            }
+        }
+        Ok(Self { conn })
+    }
+
+    /// Stores the run and returns its id.
+    pub fn save(&mut self, run: &RunRecord) -> Result<i64> {
+        let tx = self.conn.transaction()?;
+        tx.execute(
+            "INSERT INTO runs (binary, started_at, duration_sec, hostname, region, version, config, kind)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            params![
+                run.binary,
+                run.started_at,
+                run.duration_sec,
+                run.environment.hostname,
+                run.environment.region,
+                run.environment.version,
+                run.config.to_string(),
+                run.kind.name()
+            ],
+        )?;
+        let id = tx.last_insert_rowid();
+        for e in &run.endpoints {
+            tx.execute(
+                "INSERT INTO endpoint_results
+                 (run_id, endpoint, received, scored, first, tied, missed, avg_behind_ms, overall_avg_ms, p50_ms, p99_ms)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+                params![
+                    id,
+                    e.endpoint,
+                    e.received as i64,
+                    e.scored as i64,
+                    e.first as i64,
+                    e.tied as i64,
+                    e.missed as i64,
+                    e.avg_behind_ms,
+                    e.overall_avg_ms,
+                    e.p50_ms,
+                    e.p99_ms
+                ],
+            )?;
+        }
+        tx.commit()?;
+        Ok(id)
+    }
+
+    fn load(&self, where_clause: &str, args: impl rusqlite::Params) -> Result<Vec<RunRecord>> {
+        let sql = format!(
+            "SELECT id, binary, started_at, duration_sec, hostname, region, version, config, kind FROM runs {}",
+            where_clause
+        );
+        let mut stmt = self.conn.prepare(&sql)?;
+        let mut runs = stmt
+            .query_map(args, |row| {
+                let config: String = row.get(7)?;
+                let kind: String = row.get(8)?;
+                Ok(RunRecord {
+                    id: row.get(0)?,
+                    binary: row.get(1)?,
+                    kind: RunKind::from_name(&kind),
+                    started_at: row.get(2)?,
+                    duration_sec: row.get(3)?,
+                    environment: RunEnvironment { hostname: row.get(4)?, region: row.get(5)?, version: row.get(6)? },
+                    config: serde_json::from_str(&config).unwrap_or(serde_json::Value::Null),
+                    endpoints: Vec::new(),
+                })
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+
+        let mut stmt = self.conn.prepare(
+            "SELECT endpoint, received, scored, first, tied, missed, avg_behind_ms, overall_avg_ms, p50_ms, p99_ms
+             FROM endpoint_results WHERE run_id = ?1 ORDER BY rowid",
+        )?;
+        for run in &mut runs {
+            run.endpoints = stmt
+                .query_map([run.id], |row| {
+                    Ok(EndpointSummary {
+                        endpoint: row.get(0)?,
+                        received: row.get::<_, i64>(1)? as usize,
+                        scored: row.get::<_, i64>(2)? as usize,
+                        first: row.get::<_, i64>(3)? as usize,
+                        tied: row.get::<_, i64>(4)? as usize,
+                        missed: row.get::<_, i64>(5)? as usize,
+                        avg_behind_ms: row.get(6)?,
+                        overall_avg_ms: row.get(7)?,
+                        p50_ms: row.get(8)?,
+                        p99_ms: row.get(9)?,
+                    })
+                })?
+                .collect::<rusqlite::Result<_>>()?;
+        }
+        Ok(runs)
+    }
+
+    /// Most recent runs first.
+    pub fn runs(&self, limit: usize) -> Result<Vec<RunRecord>> {
+        self.load("ORDER BY id DESC LIMIT ?1", [limit as i64])
+    }
+
+    pub fn run(&self, id: i64) -> Result<Option<RunRecord>> {
+        Ok(self.load("WHERE id = ?1", [id])?.pop())
+    }
+
+    /// The run made by the same binary just before `run`.
+    pub fn previous(&self, run: &RunRecord) -> Result<Option<RunRecord>> {
+        Ok(self.load("WHERE binary = ?1 AND id < ?2 ORDER BY id DESC LIMIT 1", params![run.binary, run.id])?.pop())
+    }
+
+    pub fn set_baseline(&mut self, name: &str, run_id: i64) -> Result<()> {
+        self.conn.execute(
+            "INSERT INTO baselines (name, run_id) VALUES (?1, ?2)
+             ON CONFLICT(name) DO UPDATE SET run_id = excluded.run_id",
+            params![name, run_id],
+        )?;
+        Ok(())
+    }
+
+    pub fn baseline(&self, name: &str) -> Result<Option<RunRecord>> {
+        let id: Option<i64> = self
+            .conn
+            .query_row("SELECT run_id FROM baselines WHERE name = ?1", [name], |row| row.get(0))
+            .optional()?;
+        match id {
+            Some(id) => self.run(id),
+            None => Ok(None),
+        }
+    }
+
+    /// `(name, run id)` in name order.
+    pub fn baselines(&self) -> Result<Vec<(String, i64)>> {
+        let mut stmt = self.conn.prepare("SELECT name, run_id FROM baselines ORDER BY name")?;
+        let baselines = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?.collect::<rusqlite::Result<_>>()?;
+        Ok(baselines)
+    }
+}
+
+/// One endpoint present in both runs of a diff.
+#[derive(Debug, Clone)]
+pub struct EndpointDiff {
+    pub before: EndpointSummary,
+    pub after: EndpointSummary,
+    /// Empty unless something got worse by more than the threshold.
+    pub regressions: Vec<String>,
+}
+
+/// Compares the endpoints both runs have in common; nothing when the runs are of different kinds.
+///
+/// An endpoint regresses when its first-arrival share drops (or its miss rate rises) by more
+/// than `threshold_pct` points, or its overall mean or p99 latency grows by more than
+/// `threshold_pct` percent.
+pub fn diff_runs(before: &RunRecord, after: &RunRecord, threshold_pct: f64) -> Vec<EndpointDiff> {
+    if before.kind != after.kind {
+        return Vec::new();
+    }
+    after
+        .endpoints
+        .iter()
+        .filter_map(|after| {
+            let before = before.endpoint(&after.endpoint)?;
+            let mut regressions = Vec::new();
+
+            let first_drop = before.first_percent() - after.first_percent();
+            if first_drop > threshold_pct {
+                regressions.push(format!("first arrivals down {:.2} points", first_drop));
+            }
+            let miss_rise = after.miss_percent() - before.miss_percent();
+            if miss_rise > threshold_pct {
+                regressions.push(format!("miss rate up {:.2} points", miss_rise));
+            }
+            for (metric, was, now) in [
+                ("overall mean", before.overall_avg_ms, after.overall_avg_ms),
+                ("p99", before.p99_ms, after.p99_ms),
+            ] {
+                let change = now - was;
+                if change > MIN_LATENCY_CHANGE_MS && change > was * threshold_pct / 100.0 {
+                    regressions.push(format!("{} latency up {:.2}ms ({:.2} -> {:.2})", metric, change, was, now));
+                }
+            }
+            Some(EndpointDiff { before: before.clone(), after: after.clone(), regressions })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn summary(endpoint: &str, first: usize, overall_avg_ms: f64, p99_ms: f64) -> EndpointSummary {
+        EndpointSummary {
+            endpoint: endpoint.to_string(),
+            received: 120,
+            scored: 100,
+            first,
+            tied: 0,
+            missed: 0,
+            avg_behind_ms: overall_avg_ms,
+            overall_avg_ms,
+            p50_ms: overall_avg_ms,
+            p99_ms,
+        }
+    }
+
+    fn run(endpoints: Vec<EndpointSummary>) -> RunRecord {
+        RunRecord::finished("grpc-comparison", Duration::from_secs(30), serde_json::json!({"race": "slot"}), endpoints)
+    }
+
+    #[test]
+    fn test_save_load_and_flag_regressions() {
+        let mut store = HistoryStore::open_in_memory().unwrap();
+        let first = store.save(&run(vec![summary("A", 60, 1.0, 5.0), summary("B", 40, 2.0, 8.0)])).unwrap();
+        let second = store.save(&run(vec![summary("A", 45, 1.05, 9.0), summary("B", 55, 1.5, 8.0)])).unwrap();
+        store.set_baseline("prod", first).unwrap();
+
+        let latest = store.run(second).unwrap().unwrap();
+        assert_eq!(latest.config["race"], "slot");
+        assert_eq!(latest.endpoints.len(), 2);
+        let baseline = store.baseline("prod").unwrap().unwrap();
+        assert_eq!(store.previous(&latest).unwrap().unwrap(), baseline);
+        assert_eq!(store.runs(10).unwrap()[0].id, second);
+
+        let diffs = diff_runs(&baseline, &latest, 10.0);
+        // A: 首先接收下降 15 个百分点，p99 增加 80%；均值只增加 0.05ms 不算回退
+        assert_eq!(diffs[0].regressions.len(), 2);
+        assert!(diffs[1].regressions.is_empty());
+    }
+
+    #[test]
+    fn test_migrates_scored_counts_from_old_schema() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "CREATE TABLE runs (id INTEGER PRIMARY KEY AUTOINCREMENT, binary TEXT NOT NULL, started_at INTEGER NOT NULL,
+                 duration_sec REAL NOT NULL, hostname TEXT NOT NULL, region TEXT, version TEXT NOT NULL, config TEXT NOT NULL);
+             CREATE TABLE endpoint_results (run_id INTEGER NOT NULL, endpoint TEXT NOT NULL, received INTEGER NOT NULL,
+                 first_received INTEGER NOT NULL, avg_behind_ms REAL NOT NULL, overall_avg_ms REAL NOT NULL,
+                 p50_ms REAL NOT NULL, p99_ms REAL NOT NULL, PRIMARY KEY (run_id, endpoint));
+             INSERT INTO runs VALUES (1, 'grpc-comparison', 0, 30.0, 'host', NULL, '0.1.0', '{}');
+             INSERT INTO endpoint_results VALUES (1, 'A', 100, 60, 1.0, 1.0, 1.0, 5.0);",
+        )
+        .unwrap();
+
+        let mut store = HistoryStore::init(conn).unwrap();
+        let old = store.run(1).unwrap().unwrap();
+        assert_eq!((old.endpoints[0].received, old.endpoints[0].scored, old.endpoints[0].first), (0, 100, 60));
+        let id = store.save(&run(vec![summary("A", 45, 1.0, 5.0)])).unwrap();
+        assert_eq!(store.run(id).unwrap().unwrap().endpoints[0].received, 120);
+    }
+
+    #[test]
+    fn test_round_trip_runs_are_not_diffed_against_races() {
+        let mut store = HistoryStore::open_in_memory().unwrap();
+        let race = store.save(&run(vec![summary("A", 60, 1.0, 5.0)])).unwrap();
+        let ping = store.save(&run(vec![summary("A", 0, 80.0, 120.0)]).with_kind(RunKind::RoundTrip)).unwrap();
+
+        let (race, ping) = (store.run(race).unwrap().unwrap(), store.run(ping).unwrap().unwrap());
+        assert_eq!((race.kind, ping.kind), (RunKind::Race, RunKind::RoundTrip));
+        assert!(diff_runs(&race, &ping, 10.0).is_empty());
+    }
+}
@@ -1,13 +1,37 @@
+pub mod aggregator;
+pub mod alert;
+pub mod breakdown;
 pub mod config;
+pub mod dashboard;
+pub mod engine;
 pub mod stats;
 pub mod grpc_client;
 pub mod fzs_client;
 pub mod error;
+pub mod events;
+pub mod fanout;
+pub mod history;
+pub mod merger;
 pub mod output;
+pub mod pairwise;
+pub mod relay;
+pub mod report;
+pub mod runtime;
+pub mod slo;
+pub mod source;
+pub mod throughput;
+pub mod trace;
+pub mod transport;
+pub mod window;
 
+pub use aggregator::{Aggregator, AggregatorConfig, AggregatorHandle, ArrivalKey};
+pub use breakdown::CategoryBreakdown;
 pub use config::Config;
+pub use engine::{ComparisonConfig, ComparisonEngine, ComparisonReport};
 pub use stats::{LatencyStats, calculate_stats};
 pub use grpc_client::GrpcClient;
 pub use fzs_client::FzsClient;
 pub use error::{BenchmarkError, Result};
 pub use output::ColoredOutput;
+pub use source::{SourceEvent, StreamSource};
+pub use transport::{FrameClock, MessageStamp};
@@ -0,0 +1,210 @@
+use crate::aggregator::{AggregatorEvent, Resolution};
+use crate::stats::{calculate_stats, LatencyStats};
+use colored::*;
+
+/// Combinations are only simulated up to this many endpoints (2^8 - 1 = 255 feeds).
+pub const MAX_MERGE_ENDPOINTS: usize = 8;
+
+/// A simulated feed taking the earliest copy of every key from `members`.
+#[derive(Debug, Clone)]
+pub struct MergedFeed {
+    pub members: Vec<String>,
+    /// Keys at least one member delivered.
+    pub covered: usize,
+    /// Lag behind the earliest arrival over all endpoints, in ms, on covered keys.
+    pub lag: LatencyStats,
+}
+
+#[derive(Debug, Clone)]
+pub struct MergeReport {
+    /// Keys seen by any endpoint.
+    pub keys: usize,
+    /// Index into `feeds` of the single endpoint covering the most keys, then with the lowest mean lag.
+    pub best_single: usize,
+    /// Every non-empty subset, smallest first, then by mean lag.
+    pub feeds: Vec<MergedFeed>,
+}
+
+impl MergeReport {
+    pub fn best_single(&self) -> &MergedFeed {
+        &self.feeds[self.best_single]
+    }
+}
+
+/// Replays resolved keys against every subset of the endpoints.
+///
+/// Shows what a consumer taking the first copy of each key from several redundant feeds would
+/// have seen, and so how much each extra provider actually buys over the best single one.
+#[derive(Debug, Default)]
+pub struct MergeSimulator {
+    endpoints: Vec<String>,
+    /// Per key: `(endpoint index, lag behind the earliest arrival in ms)`.
+    keys: Vec<Vec<(usize, f64)>>,
+}
+
+impl MergeSimulator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn index_of(&mut self, endpoint: &str) -> usize {
+        match self.endpoints.iter().position(|e| e == endpoint) {
+            Some(index) => index,
+            None => {
+                self.endpoints.push(endpoint.to_string());
+                self.endpoints.len() - 1
+            }
+        }
+    }
+
+    pub fn record(&mut self, resolution: &Resolution) {
+        let lags: Vec<(usize, f64)> = resolution
+            .relative_latencies()
+            .map(|(arrival, latency)| (self.index_of(&arrival.endpoint), latency))
+            .collect();
+        self.keys.push(lags);
+    }
+
+    pub fn observe(&mut self, event: &AggregatorEvent) {
+        if let AggregatorEvent::Resolved(resolution) = event {
+            self.record(resolution);
+        }
+    }
+
+    pub fn endpoint_count(&self) -> usize {
+        self.endpoints.len()
+    }
+
+    /// Simulates every combination; `None` without keys or with more than `MAX_MERGE_ENDPOINTS`.
+    pub fn report(&self) -> Option<MergeReport> {
+        let n = self.endpoints.len();
+        if self.keys.is_empty() || n == 0 || n > MAX_MERGE_ENDPOINTS {
+            return None;
+        }
+
+        let subsets = 1usize << n;
+        let mut lags = vec![Vec::with_capacity(self.keys.len()); subsets];
+        let mut merged = vec![f64::INFINITY; subsets];
+        for key in &self.keys {
+            let mut single = vec![f64::INFINITY; n];
+            for &(index, lag) in key {
+                single[index] = single[index].min(lag);
+            }
+            // 去掉最低位后的子集已算出，只需再与最低位端点取最小值
+            for mask in 1..subsets {
+                let lowest = mask.trailing_zeros() as usize;
+                merged[mask] = merged[mask & (mask - 1)].min(single[lowest]);
+                if merged[mask].is_finite() {
+                    lags[mask].push(merged[mask]);
+                }
+            }
+        }
+
+        let mut feeds: Vec<MergedFeed> = (1..subsets)
+            .map(|mask| MergedFeed {
+                members: (0..n).filter(|i| mask & (1 << i) != 0).map(|i| self.endpoints[i].clone()).collect(),
+                covered: lags[mask].len(),
+                lag: calculate_stats(&lags[mask]),
+            })
+            .collect();
+        feeds.sort_by(|a, b| a.members.len().cmp(&b.members.len()).then(a.lag.mean.total_cmp(&b.lag.mean)));
+
+        let best_single = feeds
+            .iter()
+            .enumerate()
+            .filter(|(_, feed)| feed.members.len() == 1)
+            .min_by(|(_, a), (_, b)| b.covered.cmp(&a.covered).then(a.lag.mean.total_cmp(&b.lag.mean)))
+            .map(|(index, _)| index)
+            .unwrap_or(0);
+        Some(MergeReport { keys: self.keys.len(), best_single, feeds })
+    }
+
+    /// Prints every combination's coverage and lag against the best single endpoint.
+    pub fn print_summary(&self) {
+        println!("{}", "🧮 多源合并模拟".yellow().bold());
+        println!("{}", "-".repeat(28).yellow());
+
+        let Some(report) = self.report() else {
+            if self.endpoint_count() > MAX_MERGE_ENDPOINTS {
+                println!("端点数 {} 超过 {}, 跳过组合模拟", self.endpoint_count(), MAX_MERGE_ENDPOINTS);
+            } else {
+                println!("没有收集到数据");
+            }
+            return;
+        };
+
+        let best = report.best_single();
+        println!(
+            "最佳单源: {} (覆盖 {:.2}%, 平均落后 {:.2}ms, p99 {:.2}ms)",
+            best.members[0],
+            best.covered as f64 / report.keys as f64 * 100.0,
+            best.lag.mean,
+            best.lag.p99
+        );
+        let width = report.feeds.iter().map(|f| f.members.join(" + ").len()).max().unwrap_or(0);
+        for feed in &report.feeds {
+            println!(
+                "{:width$} : 覆盖 {:6.2}%, 落后 平均 {:6.2}ms p50 {:6.2}ms p99 {:6.2}ms, 相比最佳单源 平均 {:+6.2}ms p99 {:+6.2}ms",
+                feed.members.join(" + "),
+                feed.covered as f64 / report.keys as f64 * 100.0,
+                feed.lag.mean,
+                feed.lag.median,
+                feed.lag.p99,
+                feed.lag.mean - best.lag.mean,
+                feed.lag.p99 - best.lag.p99,
+                width = width
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::aggregator::{Arrival, ArrivalKey, DEFAULT_TIE_TOLERANCE};
+    use std::time::{Duration, Instant};
+
+    fn resolution(slot: u64, order: &[(&str, u64)]) -> Resolution {
+        let t0 = Instant::now();
+        Resolution {
+            key: ArrivalKey::Slot(slot),
+            arrivals: order
+                .iter()
+                .map(|(endpoint, ms)| Arrival {
+                    endpoint: endpoint.to_string(),
+                    key: ArrivalKey::Slot(slot),
+                    timestamp: t0 + Duration::from_millis(*ms),
+                    decode_time: None,
+                })
+                .collect(),
+            timed_out: false,
+            missed: Vec::new(),
+            tie_tolerance: DEFAULT_TIE_TOLERANCE,
+        }
+    }
+
+    #[test]
+    fn test_every_subset_takes_earliest_member() {
+        let mut simulator = MergeSimulator::new();
+        simulator.record(&resolution(1, &[("A", 0), ("B", 2), ("C", 6)]));
+        simulator.record(&resolution(2, &[("B", 0), ("C", 1), ("A", 4)]));
+        simulator.record(&resolution(3, &[("C", 0), ("A", 2)]));
+
+        let report = simulator.report().unwrap();
+        assert_eq!(report.keys, 3);
+        assert_eq!(report.feeds.len(), 7);
+
+        // B 平均落后最少但缺失 slot 3，覆盖全部 slot 的 A (2ms) 优于 C (2.33ms)
+        let best = report.best_single();
+        assert_eq!(best.members, vec!["A"]);
+        assert_eq!(best.covered, 3);
+
+        let pair = report.feeds.iter().find(|f| f.members == vec!["A", "B"]).unwrap();
+        assert_eq!(pair.covered, 3);
+        assert_eq!(pair.lag.latencies, vec![0.0, 0.0, 2.0]);
+
+        let all = report.feeds.last().unwrap();
+        assert_eq!(all.members.len(), 3);
+        assert_eq!(all.lag.max, 0.0);
+    }
+}
@@ -178,7 +178,7 @@ impl ColoredOutput {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum EndpointStatus {
     Connected,
     Connecting,
@@ -0,0 +1,92 @@
+use crate::aggregator::{AggregatorEvent, Resolution};
+use crate::stats::{calculate_stats, LatencyStats};
+
+/// Arrival-time differences between fixed endpoint pairs, on the keys both delivered.
+///
+/// Unlike the race statistics this compares two endpoints directly, so it shows what a
+/// single setting (e.g. compression) costs even when neither endpoint is the overall winner.
+#[derive(Debug, Default)]
+pub struct PairedDeltas {
+    pairs: Vec<(String, String)>,
+    deltas: Vec<Vec<f64>>,
+}
+
+impl PairedDeltas {
+    /// `pairs` are `(baseline, variant)` endpoint names.
+    pub fn new(pairs: Vec<(String, String)>) -> Self {
+        let deltas = vec![Vec::new(); pairs.len()];
+        Self { pairs, deltas }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pairs.is_empty()
+    }
+
+    pub fn record(&mut self, resolution: &Resolution) {
+        let arrival_of = |name: &str| resolution.arrivals.iter().find(|a| a.endpoint == name);
+        for ((baseline, variant), deltas) in self.pairs.iter().zip(self.deltas.iter_mut()) {
+            if let (Some(baseline), Some(variant)) = (arrival_of(baseline), arrival_of(variant)) {
+                let delta = if variant.timestamp >= baseline.timestamp {
+                    variant.timestamp.duration_since(baseline.timestamp).as_secs_f64()
+                } else {
+                    -baseline.timestamp.duration_since(variant.timestamp).as_secs_f64()
+                };
+                deltas.push(delta * 1000.0);
+            }
+        }
+    }
+
+    pub fn observe(&mut self, event: &AggregatorEvent) {
+        if let AggregatorEvent::Resolved(resolution) = event {
+            self.record(resolution);
+        }
+    }
+
+    /// `(baseline, variant, variant − baseline in ms)`; positive means the variant was later.
+    pub fn summaries(&self) -> impl Iterator<Item = (&str, &str, LatencyStats)> {
+        self.pairs
+            .iter()
+            .zip(&self.deltas)
+            .map(|((baseline, variant), deltas)| (baseline.as_str(), variant.as_str(), calculate_stats(deltas)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::aggregator::{Arrival, ArrivalKey, DEFAULT_TIE_TOLERANCE};
+    use std::time::{Duration, Instant};
+
+    fn resolution(slot: u64, order: &[(&str, u64)]) -> Resolution {
+        let t0 = Instant::now();
+        Resolution {
+            key: ArrivalKey::Slot(slot),
+            arrivals: order
+                .iter()
+                .map(|(endpoint, ms)| Arrival {
+                    endpoint: endpoint.to_string(),
+                    key: ArrivalKey::Slot(slot),
+                    timestamp: t0 + Duration::from_millis(*ms),
+                    decode_time: None,
+                })
+                .collect(),
+            timed_out: false,
+            missed: Vec::new(),
+            tie_tolerance: DEFAULT_TIE_TOLERANCE,
+        }
+    }
+
+    #[test]
+    fn test_signed_deltas_on_shared_keys_only() {
+        let mut deltas = PairedDeltas::new(vec![("A".to_string(), "A[zstd]".to_string())]);
+        deltas.record(&resolution(1, &[("A", 0), ("B", 1), ("A[zstd]", 4)]));
+        deltas.record(&resolution(2, &[("A[zstd]", 0), ("A", 2)]));
+        deltas.record(&resolution(3, &[("A", 0), ("B", 1)]));
+
+        let (baseline, variant, stats) = deltas.summaries().next().unwrap();
+        assert_eq!((baseline, variant), ("A", "A[zstd]"));
+        assert_eq!(stats.count, 2);
+        assert!((stats.mean - 1.0).abs() < 1e-6);
+        assert!((stats.min + 2.0).abs() < 1e-6);
+    }
+}
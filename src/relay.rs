@@ -0,0 +1,139 @@
+use crate::source::SourceHealth;
+use std::io;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::net::{lookup_host, UdpSocket};
+use tokio::sync::oneshot;
+use tracing::warn;
+
+/// Largest UDP payload we forward; QUIC datagrams are far smaller.
+const MAX_DATAGRAM: usize = 65_535;
+
+/// Local UDP relay in front of a QUIC server that counts downstream bytes.
+///
+/// Clients connect to `local_addr()`; every datagram is forwarded unchanged, so the
+/// count is exactly what the server put on the wire. Serves a single client.
+///
+/// The relay runs on its own thread so the copying is not charged to the CPU time of the
+/// endpoint thread that owns the client.
+///
+/// The client only sees 127.0.0.1, so a TLS server name derived from the address is the
+/// relay's, not the real host's; only use it against servers you already trust.
+pub struct UdpRelay {
+    local_addr: SocketAddr,
+    // 丢弃即通知中继线程退出
+    _shutdown: oneshot::Sender<()>,
+}
+
+impl UdpRelay {
+    /// `upstream` is a `host:port`, optionally prefixed with a URL scheme.
+    pub async fn start(upstream: &str, health: Arc<SourceHealth>) -> io::Result<Self> {
+        let host_port = upstream.split("://").last().unwrap_or(upstream).trim_end_matches('/');
+        let upstream_addr = lookup_host(host_port)
+            .await?
+            .next()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("cannot resolve {}", upstream)))?;
+
+        // 套接字先用标准库创建，再在中继线程自己的运行时里注册
+        let local = std::net::UdpSocket::bind("127.0.0.1:0")?;
+        let bind_addr: SocketAddr = if upstream_addr.is_ipv4() { "0.0.0.0:0" } else { "[::]:0" }.parse().unwrap();
+        let remote = std::net::UdpSocket::bind(bind_addr)?;
+        remote.connect(upstream_addr)?;
+        local.set_nonblocking(true)?;
+        remote.set_nonblocking(true)?;
+
+        let local_addr = local.local_addr()?;
+        let (shutdown, shutdown_rx) = oneshot::channel();
+        std::thread::Builder::new().name(format!("relay-{}", local_addr.port())).spawn(move || {
+            let runtime = match tokio::runtime::Builder::new_current_thread().enable_io().build() {
+                Ok(runtime) => runtime,
+                Err(e) => {
+                    warn!("Failed to build relay runtime: {}", e);
+                    return;
+                }
+            };
+            runtime.block_on(async move {
+                let (Ok(local), Ok(remote)) = (UdpSocket::from_std(local), UdpSocket::from_std(remote)) else {
+                    warn!("Failed to register relay sockets");
+                    return;
+                };
+                tokio::select! {
+                    _ = forward(local, remote, health) => {},
+                    _ = shutdown_rx => {},
+                }
+            });
+        })?;
+        Ok(Self { local_addr, _shutdown: shutdown })
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    /// `local_addr()` written the way `upstream` was, i.e. keeping its scheme.
+    pub fn rewrite(&self, upstream: &str) -> String {
+        match upstream.split_once("://") {
+            Some((scheme, _)) => format!("{}://{}", scheme, self.local_addr),
+            None => self.local_addr.to_string(),
+        }
+    }
+}
+
+async fn forward(local: UdpSocket, remote: UdpSocket, health: Arc<SourceHealth>) {
+    let mut client: Option<SocketAddr> = None;
+    let mut up = vec![0u8; MAX_DATAGRAM];
+    let mut down = vec![0u8; MAX_DATAGRAM];
+
+    loop {
+        tokio::select! {
+            received = local.recv_from(&mut up) => {
+                let Ok((len, from)) = received else { return };
+                client = Some(from);
+                if remote.send(&up[..len]).await.is_err() {
+                    return;
+                }
+            }
+            received = remote.recv(&mut down) => {
+                let Ok(len) = received else { return };
+                health.record_bytes(len);
+                if let Some(client) = client {
+                    let _ = local.send_to(&down[..len], client).await;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_forwards_both_ways_and_counts_downstream() {
+        // 上游回显服务器，回复内容加倍以区分方向
+        let server = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let server_addr = server.local_addr().unwrap();
+        tokio::spawn(async move {
+            let mut buf = [0u8; 1024];
+            while let Ok((len, from)) = server.recv_from(&mut buf).await {
+                let reply = [&buf[..len], &buf[..len]].concat();
+                let _ = server.send_to(&reply, from).await;
+            }
+        });
+
+        let health = Arc::new(SourceHealth::default());
+        let relay = UdpRelay::start(&format!("http://{}", server_addr), health.clone()).await.unwrap();
+        assert_eq!(relay.rewrite("http://example:1"), format!("http://{}", relay.local_addr()));
+
+        let client = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        client.connect(relay.local_addr()).await.unwrap();
+        let mut buf = [0u8; 1024];
+        for payload in [&b"ping"[..], &b"hello"[..]] {
+            client.send(payload).await.unwrap();
+            let len = client.recv(&mut buf).await.unwrap();
+            assert_eq!(len, payload.len() * 2);
+        }
+
+        assert_eq!(health.snapshot().bytes, 18);
+    }
+}
@@ -0,0 +1,503 @@
+use crate::breakdown::TimeBreakdown;
+use crate::history::EndpointSummary;
+use crate::stats::{calculate_stats, EndpointStatsMap, LatencyStats};
+use std::fmt::Write as _;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+const PALETTE: [&str; 10] = [
+    "#1f77b4", "#ff7f0e", "#2ca02c", "#d62728", "#9467bd", "#8c564b", "#e377c2", "#7f7f7f", "#bcbd22", "#17becf",
+];
+
+const WIDTH: f64 = 720.0;
+const MARGIN_LEFT: f64 = 60.0;
+const MARGIN_RIGHT: f64 = 20.0;
+const MARGIN_TOP: f64 = 20.0;
+const MARGIN_BOTTOM: f64 = 44.0;
+
+/// Points kept per CDF line; larger runs are thinned evenly.
+const CDF_POINTS: usize = 400;
+const HISTOGRAM_BINS: usize = 40;
+
+/// Output format, chosen from the file extension.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReportFormat {
+    Markdown,
+    /// Self-contained: inline CSS and SVG charts, no scripts or external assets.
+    Html,
+}
+
+impl ReportFormat {
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()?.to_ascii_lowercase().as_str() {
+            "md" | "markdown" => Some(Self::Markdown),
+            "html" | "htm" => Some(Self::Html),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct EndpointSection {
+    pub summary: EndpointSummary,
+    /// Latencies when behind the fastest endpoint, sorted.
+    pub latency: LatencyStats,
+}
+
+/// Results of one run, rendered as a Markdown summary or an HTML page with charts.
+#[derive(Debug)]
+pub struct RunReport {
+    pub title: String,
+    /// `(label, value)` rows shown above the results, e.g. start time and duration.
+    pub meta: Vec<(String, String)>,
+    /// Highest first-received share first.
+    pub endpoints: Vec<EndpointSection>,
+    pub timeline: TimeBreakdown,
+}
+
+impl RunReport {
+    pub fn new(title: &str, endpoints: &[String], stats: &EndpointStatsMap, timeline: TimeBreakdown) -> Self {
+        let mut sections: Vec<EndpointSection> = endpoints
+            .iter()
+            .filter_map(|name| stats.get(name).map(|stat| (name, stat)))
+            .filter(|(_, stat)| stat.scored > 0)
+            .map(|(name, stat)| EndpointSection {
+                summary: EndpointSummary::from_stats(name, stat),
+                latency: calculate_stats(&stat.latencies),
+            })
+            .collect();
+        sections.sort_by(|a, b| b.summary.first_percent().total_cmp(&a.summary.first_percent()));
+        Self { title: title.to_string(), meta: Vec::new(), endpoints: sections, timeline }
+    }
+
+    pub fn with_meta(mut self, label: &str, value: impl ToString) -> Self {
+        self.meta.push((label.to_string(), value.to_string()));
+        self
+    }
+
+    pub fn write(&self, path: &Path, format: ReportFormat) -> io::Result<()> {
+        let rendered = match format {
+            ReportFormat::Markdown => self.to_markdown(),
+            ReportFormat::Html => self.to_html(),
+        };
+        fs::write(path, rendered)
+    }
+
+    fn bucket_label(&self) -> String {
+        let secs = self.timeline.bucket().as_secs_f64();
+        if secs >= 60.0 && secs % 60.0 == 0.0 {
+            format!("{} 分钟", secs / 60.0)
+        } else {
+            format!("{} 秒", secs)
+        }
+    }
+
+    pub fn to_markdown(&self) -> String {
+        let mut md = String::new();
+        let _ = writeln!(md, "# {}\n", self.title);
+        if !self.meta.is_empty() {
+            md.push_str("| 项目 | 值 |\n|---|---|\n");
+            for (label, value) in &self.meta {
+                let _ = writeln!(md, "| {} | {} |", label, markdown_cell(value));
+            }
+            md.push('\n');
+        }
+
+        md.push_str("## 结果\n\n");
+        md.push_str("落后延迟只统计落后收到的数据，总体平均把首先和同时收到的数据计为 0ms。\n\n");
+        md.push_str("| 端点 | 接收 | 计分 | 错过 | 首先接收 | 同时接收 | 落后时平均 | p50 | p90 | p99 | 最大 | 总体平均 |\n");
+        md.push_str("|---|---:|---:|---:|---:|---:|---:|---:|---:|---:|---:|---:|\n");
+        for section in &self.endpoints {
+            let summary = &section.summary;
+            let _ = writeln!(
+                md,
+                "| {} | {} | {} | {:.2}% ({}) | {:.2}% ({}) | {:.2}% ({}) | {:.2}ms | {:.2}ms | {:.2}ms | {:.2}ms | {:.2}ms | {:.2}ms |",
+                markdown_cell(&summary.endpoint),
+                summary.received,
+                summary.scored,
+                summary.miss_percent(),
+                summary.missed,
+                summary.first_percent(),
+                summary.first,
+                summary.tie_percent(),
+                summary.tied,
+                summary.avg_behind_ms,
+                section.latency.median,
+                section.latency.p90,
+                section.latency.p99,
+                section.latency.max,
+                summary.overall_avg_ms
+            );
+        }
+
+        if self.timeline.buckets().len() > 1 {
+            let _ = writeln!(md, "\n## 首先接收占比 (每 {})\n", self.bucket_label());
+            md.push_str("| 时段 |");
+            for section in &self.endpoints {
+                let _ = write!(md, " {} |", markdown_cell(&section.summary.endpoint));
+            }
+            md.push_str("\n|---|");
+            md.push_str(&"---:|".repeat(self.endpoints.len()));
+            md.push('\n');
+            for (index, bucket) in self.timeline.buckets().iter().enumerate() {
+                let _ = write!(md, "| {} |", index + 1);
+                for section in &self.endpoints {
+                    match bucket.get(&section.summary.endpoint).filter(|s| s.scored > 0) {
+                        Some(stat) => {
+                            let _ = write!(md, " {:.2}% |", stat.get_first_percentage());
+                        }
+                        None => md.push_str(" - |"),
+                    }
+                }
+                md.push('\n');
+            }
+        }
+        md
+    }
+
+    pub fn to_html(&self) -> String {
+        let mut html = String::new();
+        let _ = write!(
+            html,
+            "<!DOCTYPE html>\n<html lang=\"zh\">\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n<style>{}</style>\n</head>\n<body>\n",
+            escape(&self.title),
+            STYLE
+        );
+        let _ = writeln!(html, "<h1>{}</h1>", escape(&self.title));
+        if !self.meta.is_empty() {
+            html.push_str("<table class=\"meta\">\n");
+            for (label, value) in &self.meta {
+                let _ = writeln!(html, "<tr><th>{}</th><td>{}</td></tr>", escape(label), escape(value));
+            }
+            html.push_str("</table>\n");
+        }
+
+        html.push_str("<h2>结果</h2>\n<p>落后延迟只统计落后收到的数据，总体平均把首先和同时收到的数据计为 0ms。</p>\n<table>\n");
+        html.push_str("<tr><th>端点</th><th>接收</th><th>计分</th><th>错过</th><th>首先接收</th><th>同时接收</th><th>落后时平均</th><th>p50</th><th>p90</th><th>p99</th><th>最大</th><th>总体平均</th></tr>\n");
+        for (index, section) in self.endpoints.iter().enumerate() {
+            let summary = &section.summary;
+            let _ = writeln!(
+                html,
+                "<tr><td><span class=\"swatch\" style=\"background:{}\"></span>{}</td><td>{}</td><td>{}</td><td>{:.2}% ({})</td><td>{:.2}% ({})</td><td>{:.2}% ({})</td><td>{:.2}ms</td><td>{:.2}ms</td><td>{:.2}ms</td><td>{:.2}ms</td><td>{:.2}ms</td><td>{:.2}ms</td></tr>",
+                color(index),
+                escape(&summary.endpoint),
+                summary.received,
+                summary.scored,
+                summary.miss_percent(),
+                summary.missed,
+                summary.first_percent(),
+                summary.first,
+                summary.tie_percent(),
+                summary.tied,
+                summary.avg_behind_ms,
+                section.latency.median,
+                section.latency.p90,
+                section.latency.p99,
+                section.latency.max,
+                summary.overall_avg_ms
+            );
+        }
+        html.push_str("</table>\n");
+
+        // 所有图共用 p99 最大的端点决定的横轴，极端值压在右边界上
+        let x_max = self.endpoints.iter().map(|s| s.latency.p99).fold(0.0, f64::max);
+        if self.endpoints.iter().any(|s| s.latency.count > 0) {
+            html.push_str("<h2>落后时相对延迟 CDF</h2>\n");
+            html.push_str(&self.cdf_svg(x_max));
+        }
+        if self.timeline.buckets().len() > 1 {
+            let _ = writeln!(html, "<h2>首先接收占比 (每 {})</h2>", self.bucket_label());
+            html.push_str(&self.timeline_svg());
+        }
+        if self.endpoints.iter().any(|s| s.latency.count > 0) {
+            html.push_str("<h2>落后时相对延迟分布</h2>\n");
+            for (index, section) in self.endpoints.iter().enumerate() {
+                if section.latency.count == 0 {
+                    continue;
+                }
+                let _ = writeln!(html, "<h3>{}</h3>", escape(&section.summary.endpoint));
+                html.push_str(&histogram_svg(&section.latency.latencies, x_max, color(index)));
+            }
+        }
+        html.push_str("</body>\n</html>\n");
+        html
+    }
+
+    fn cdf_svg(&self, x_max: f64) -> String {
+        let (x_max, x_step) = nice_axis(x_max);
+        let mut plot = Plot::new(320.0, x_max, 100.0);
+        plot.axes(x_step, 20.0, "落后延迟 (ms)", "累计占比 (%)");
+        let mut legend = Vec::new();
+        for (index, section) in self.endpoints.iter().enumerate() {
+            let latencies = &section.latency.latencies;
+            if latencies.is_empty() {
+                continue;
+            }
+            let stride = latencies.len().div_ceil(CDF_POINTS);
+            let mut points: Vec<(f64, f64)> = vec![(latencies[0], 0.0)];
+            points.extend(
+                (0..latencies.len())
+                    .step_by(stride)
+                    .chain(std::iter::once(latencies.len() - 1))
+                    .map(|i| (latencies[i], (i + 1) as f64 / latencies.len() as f64 * 100.0)),
+            );
+            plot.polyline(&points, color(index));
+            legend.push((section.summary.endpoint.as_str(), color(index)));
+        }
+        plot.legend(&legend);
+        plot.finish()
+    }
+
+    fn timeline_svg(&self) -> String {
+        let buckets = self.timeline.buckets();
+        let (x_max, x_step) = nice_axis(buckets.len() as f64);
+        let mut plot = Plot::new(280.0, x_max, 100.0);
+        plot.axes(x_step, 20.0, &format!("时段 (每 {})", self.bucket_label()), "首先接收 (%)");
+        let mut legend = Vec::new();
+        for (index, section) in self.endpoints.iter().enumerate() {
+            let points: Vec<(f64, f64)> = buckets
+                .iter()
+                .enumerate()
+                .filter_map(|(i, bucket)| {
+                    let stat = bucket.get(&section.summary.endpoint).filter(|s| s.scored > 0)?;
+                    Some((i as f64 + 0.5, stat.get_first_percentage()))
+                })
+                .collect();
+            plot.polyline(&points, color(index));
+            plot.markers(&points, color(index));
+            legend.push((section.summary.endpoint.as_str(), color(index)));
+        }
+        plot.legend(&legend);
+        plot.finish()
+    }
+}
+
+// 固定宽度的直方图，超出横轴的值计入最后一格
+fn histogram_svg(latencies: &[f64], x_max: f64, color: &str) -> String {
+    let (x_max, x_step) = nice_axis(x_max);
+    let width = x_max / HISTOGRAM_BINS as f64;
+    let mut counts = [0usize; HISTOGRAM_BINS];
+    for latency in latencies {
+        counts[((latency / width) as usize).min(HISTOGRAM_BINS - 1)] += 1;
+    }
+    let (y_max, y_step) = nice_axis(counts.iter().copied().max().unwrap_or(0) as f64);
+    let mut plot = Plot::new(200.0, x_max, y_max);
+    plot.axes(x_step, y_step, "落后延迟 (ms, 最后一格含更大的值)", "次数");
+    for (bin, count) in counts.iter().enumerate() {
+        plot.bar(bin as f64 * width, (bin + 1) as f64 * width, *count as f64, color);
+    }
+    plot.finish()
+}
+
+/// A single SVG chart with linear axes starting at 0.
+struct Plot {
+    height: f64,
+    x_max: f64,
+    y_max: f64,
+    svg: String,
+}
+
+impl Plot {
+    fn new(height: f64, x_max: f64, y_max: f64) -> Self {
+        let svg = format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{w}\" height=\"{h}\" viewBox=\"0 0 {w} {h}\" font-size=\"11\" font-family=\"sans-serif\">\n",
+            w = WIDTH,
+            h = height
+        );
+        Self { height, x_max, y_max, svg }
+    }
+
+    fn x(&self, value: f64) -> f64 {
+        MARGIN_LEFT + value.clamp(0.0, self.x_max) / self.x_max * (WIDTH - MARGIN_LEFT - MARGIN_RIGHT)
+    }
+
+    fn y(&self, value: f64) -> f64 {
+        self.height - MARGIN_BOTTOM - value.clamp(0.0, self.y_max) / self.y_max * (self.height - MARGIN_TOP - MARGIN_BOTTOM)
+    }
+
+    fn axes(&mut self, x_step: f64, y_step: f64, x_label: &str, y_label: &str) {
+        let (left, right) = (self.x(0.0), self.x(self.x_max));
+        let (bottom, top) = (self.y(0.0), self.y(self.y_max));
+        let mut svg = String::new();
+        for i in 0..=(self.y_max / y_step).round() as usize {
+            let value = i as f64 * y_step;
+            let y = self.y(value);
+            let _ = writeln!(svg, "<line x1=\"{left:.1}\" y1=\"{y:.1}\" x2=\"{right:.1}\" y2=\"{y:.1}\" stroke=\"#e5e5e5\"/>");
+            let _ = writeln!(
+                svg,
+                "<text x=\"{:.1}\" y=\"{:.1}\" text-anchor=\"end\" fill=\"#555\">{}</text>",
+                left - 6.0,
+                y + 4.0,
+                format_tick(value, y_step)
+            );
+        }
+        for i in 0..=(self.x_max / x_step).round() as usize {
+            let value = i as f64 * x_step;
+            let x = self.x(value);
+            let _ = writeln!(svg, "<line x1=\"{x:.1}\" y1=\"{bottom:.1}\" x2=\"{x:.1}\" y2=\"{:.1}\" stroke=\"#999\"/>", bottom + 4.0);
+            let _ = writeln!(
+                svg,
+                "<text x=\"{x:.1}\" y=\"{:.1}\" text-anchor=\"middle\" fill=\"#555\">{}</text>",
+                bottom + 16.0,
+                format_tick(value, x_step)
+            );
+        }
+        let _ = writeln!(
+            svg,
+            "<polyline points=\"{left:.1},{top:.1} {left:.1},{bottom:.1} {right:.1},{bottom:.1}\" fill=\"none\" stroke=\"#999\"/>"
+        );
+        let _ = writeln!(
+            svg,
+            "<text x=\"{:.1}\" y=\"{:.1}\" text-anchor=\"middle\" fill=\"#333\">{}</text>",
+            (left + right) / 2.0,
+            self.height - 8.0,
+            escape(x_label)
+        );
+        let _ = writeln!(
+            svg,
+            "<text transform=\"translate(14 {:.1}) rotate(-90)\" text-anchor=\"middle\" fill=\"#333\">{}</text>",
+            (top + bottom) / 2.0,
+            escape(y_label)
+        );
+        self.svg.push_str(&svg);
+    }
+
+    fn polyline(&mut self, points: &[(f64, f64)], color: &str) {
+        let points: Vec<String> = points.iter().map(|(x, y)| format!("{:.1},{:.1}", self.x(*x), self.y(*y))).collect();
+        let _ = writeln!(
+            self.svg,
+            "<polyline points=\"{}\" fill=\"none\" stroke=\"{}\" stroke-width=\"1.5\"/>",
+            points.join(" "),
+            color
+        );
+    }
+
+    fn markers(&mut self, points: &[(f64, f64)], color: &str) {
+        for (x, y) in points {
+            let _ = writeln!(self.svg, "<circle cx=\"{:.1}\" cy=\"{:.1}\" r=\"2.5\" fill=\"{}\"/>", self.x(*x), self.y(*y), color);
+        }
+    }
+
+    fn bar(&mut self, from: f64, to: f64, value: f64, color: &str) {
+        if value <= 0.0 {
+            return;
+        }
+        let (left, right, top) = (self.x(from), self.x(to), self.y(value));
+        let _ = writeln!(
+            self.svg,
+            "<rect x=\"{:.1}\" y=\"{:.1}\" width=\"{:.1}\" height=\"{:.1}\" fill=\"{}\"/>",
+            left,
+            top,
+            (right - left - 1.0).max(1.0),
+            self.y(0.0) - top,
+            color
+        );
+    }
+
+    // 放在右下角: CDF 曲线不会经过那里
+    fn legend(&mut self, entries: &[(&str, &str)]) {
+        let x = self.x(self.x_max) - 160.0;
+        for (index, (name, color)) in entries.iter().enumerate() {
+            let y = self.y(0.0) - 8.0 - (entries.len() - 1 - index) as f64 * 16.0;
+            let _ = writeln!(self.svg, "<rect x=\"{:.1}\" y=\"{:.1}\" width=\"10\" height=\"10\" fill=\"{}\"/>", x, y - 9.0, color);
+            let _ = writeln!(self.svg, "<text x=\"{:.1}\" y=\"{:.1}\" fill=\"#333\">{}</text>", x + 14.0, y, escape(name));
+        }
+    }
+
+    fn finish(mut self) -> String {
+        self.svg.push_str("</svg>\n");
+        self.svg
+    }
+}
+
+fn color(index: usize) -> &'static str {
+    PALETTE[index % PALETTE.len()]
+}
+
+/// Rounds `max` up to a multiple of a 1/2/5 step giving about five ticks.
+fn nice_axis(max: f64) -> (f64, f64) {
+    let max = if max > 0.0 { max } else { 1.0 };
+    let raw = max / 5.0;
+    let magnitude = 10f64.powf(raw.log10().floor());
+    let step = [1.0, 2.0, 5.0, 10.0].iter().map(|m| m * magnitude).find(|s| *s >= raw).unwrap_or(10.0 * magnitude);
+    ((max / step).ceil() * step, step)
+}
+
+fn format_tick(value: f64, step: f64) -> String {
+    let decimals = if step >= 1.0 { 0 } else { (-step.log10()).ceil() as usize };
+    format!("{:.*}", decimals, value)
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+fn markdown_cell(text: &str) -> String {
+    text.replace('|', "\\|")
+}
+
+const STYLE: &str = "body{font-family:-apple-system,'Segoe UI',sans-serif;max-width:960px;margin:2em auto;color:#222}\
+table{border-collapse:collapse;margin:1em 0}th,td{border:1px solid #ddd;padding:4px 10px;text-align:right}\
+th:first-child,td:first-child{text-align:left}table.meta td{text-align:left}th{background:#f5f5f5}\
+.swatch{display:inline-block;width:10px;height:10px;margin-right:6px}svg{display:block;margin:1em 0}";
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::aggregator::{Arrival, ArrivalKey, Resolution, DEFAULT_TIE_TOLERANCE};
+    use std::time::{Duration, Instant};
+
+    fn resolution(t0: Instant, slot: u64, at_secs: u64, order: &[(&str, u64)]) -> Resolution {
+        let start = t0 + Duration::from_secs(at_secs);
+        Resolution {
+            key: ArrivalKey::Slot(slot),
+            arrivals: order
+                .iter()
+                .map(|(endpoint, ms)| Arrival {
+                    endpoint: endpoint.to_string(),
+                    key: ArrivalKey::Slot(slot),
+                    timestamp: start + Duration::from_millis(*ms),
+                    decode_time: None,
+                })
+                .collect(),
+            timed_out: false,
+            missed: Vec::new(),
+            tie_tolerance: DEFAULT_TIE_TOLERANCE,
+        }
+    }
+
+    #[test]
+    fn test_renders_summary_timeline_and_charts() {
+        let t0 = Instant::now();
+        let mut stats = EndpointStatsMap::new();
+        let mut timeline = TimeBreakdown::default();
+        for (slot, at_secs, order) in [
+            (1, 0, [("A", 0), ("B|x", 3)]),
+            (2, 30, [("A", 0), ("B|x", 5)]),
+            (3, 70, [("B|x", 0), ("A", 2)]),
+        ] {
+            let resolution = resolution(t0, slot, at_secs, &order);
+            resolution.score(&mut stats);
+            timeline.record(&resolution);
+        }
+        assert_eq!(timeline.buckets().len(), 2);
+
+        let names = vec!["B|x".to_string(), "A".to_string()];
+        let report = RunReport::new("对比 <test>", &names, &stats, timeline).with_meta("时长", "70秒");
+        assert_eq!(report.endpoints[0].summary.endpoint, "A");
+
+        let md = report.to_markdown();
+        assert!(md.contains("| A | 0 | 3 | 0.00% (0) | 66.67% (2) | 0.00% (0) | 2.00ms |"));
+        assert!(md.contains("| 1 | 100.00% | 0.00% |"));
+        assert!(md.contains("| 2 | 0.00% | 100.00% |"));
+        assert!(md.contains("B\\|x"));
+
+        let html = report.to_html();
+        assert!(html.contains("对比 &lt;test&gt;"));
+        assert_eq!(html.matches("<svg").count(), 4);
+        assert!(!html.contains("<script"));
+        assert_eq!(ReportFormat::from_path(Path::new("out.HTML")), Some(ReportFormat::Html));
+        assert_eq!(ReportFormat::from_path(Path::new("out.txt")), None);
+    }
+}
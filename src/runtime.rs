@@ -0,0 +1,169 @@
+use std::future::Future;
+use std::time::Duration;
+use tokio::sync::oneshot;
+use tokio::task::JoinHandle;
+use tracing::warn;
+
+/// How endpoint stream tasks are scheduled.
+#[derive(Debug, Clone, Default)]
+pub enum ExecutionMode {
+    /// All endpoints share the main multi-thread runtime.
+    #[default]
+    Shared,
+    /// Every endpoint gets its own OS thread running a current-thread runtime.
+    /// Endpoint `i` is pinned to `core_ids[i % core_ids.len()]` when core ids are given.
+    Dedicated { core_ids: Vec<usize> },
+}
+
+impl ExecutionMode {
+    pub fn new(dedicated: bool, core_ids: Vec<usize>) -> Self {
+        if dedicated || !core_ids.is_empty() {
+            ExecutionMode::Dedicated { core_ids }
+        } else {
+            ExecutionMode::Shared
+        }
+    }
+
+    fn core_for(&self, index: usize) -> Option<usize> {
+        match self {
+            ExecutionMode::Dedicated { core_ids } if !core_ids.is_empty() => {
+                Some(core_ids[index % core_ids.len()])
+            }
+            _ => None,
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        match self {
+            ExecutionMode::Shared => "shared runtime".to_string(),
+            ExecutionMode::Dedicated { core_ids } if core_ids.is_empty() => {
+                "dedicated runtime per endpoint".to_string()
+            }
+            ExecutionMode::Dedicated { core_ids } => format!(
+                "dedicated runtime per endpoint, pinned to cores {:?}",
+                core_ids
+            ),
+        }
+    }
+}
+
+/// A running endpoint task, either on the shared runtime or on its own thread.
+pub enum EndpointTask {
+    Shared(JoinHandle<()>),
+    Dedicated {
+        shutdown: oneshot::Sender<()>,
+        thread: std::thread::JoinHandle<Option<Duration>>,
+    },
+}
+
+/// CPU time consumed so far by the calling thread.
+#[cfg(unix)]
+pub fn thread_cpu_time() -> Option<Duration> {
+    let mut ts = libc::timespec { tv_sec: 0, tv_nsec: 0 };
+    // SAFETY: `ts` is a valid, writable timespec for the duration of the call.
+    if unsafe { libc::clock_gettime(libc::CLOCK_THREAD_CPUTIME_ID, &mut ts) } != 0 {
+        return None;
+    }
+    Some(Duration::new(ts.tv_sec as u64, ts.tv_nsec as u32))
+}
+
+#[cfg(not(unix))]
+pub fn thread_cpu_time() -> Option<Duration> {
+    None
+}
+
+impl EndpointTask {
+    /// Spawns `make_future` according to `mode`.
+    ///
+    /// The future is created inside the runtime it will run on, so connections opened by it
+    /// (and their background I/O tasks) stay on that endpoint's thread.
+    pub fn spawn<F, Fut>(mode: &ExecutionMode, index: usize, name: &str, make_future: F) -> std::io::Result<Self>
+    where
+        F: FnOnce() -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        if let ExecutionMode::Shared = mode {
+            return Ok(EndpointTask::Shared(tokio::spawn(make_future())));
+        }
+
+        let core = mode.core_for(index);
+        let (shutdown, shutdown_rx) = oneshot::channel();
+        let thread = std::thread::Builder::new()
+            .name(format!("endpoint-{}", name))
+            .spawn(move || {
+                if let Some(id) = core {
+                    if !core_affinity::set_for_current(core_affinity::CoreId { id }) {
+                        warn!("Failed to pin thread to core {}", id);
+                    }
+                }
+
+                let runtime = match tokio::runtime::Builder::new_current_thread().enable_all().build() {
+                    Ok(runtime) => runtime,
+                    Err(e) => {
+                        warn!("Failed to build endpoint runtime: {}", e);
+                        return None;
+                    }
+                };
+
+                runtime.block_on(async move {
+                    tokio::select! {
+                        _ = make_future() => {},
+                        _ = shutdown_rx => {},
+                    }
+                });
+                let cpu_time = thread_cpu_time();
+                runtime.shutdown_background();
+                cpu_time
+            })?;
+
+        Ok(EndpointTask::Dedicated { shutdown, thread })
+    }
+
+    pub fn abort(self) {
+        match self {
+            EndpointTask::Shared(handle) => handle.abort(),
+            EndpointTask::Dedicated { shutdown, thread } => {
+                let _ = shutdown.send(());
+                // 线程在运行时退出后自行结束，这里不阻塞等待
+                drop(thread);
+            }
+        }
+    }
+
+    /// Stops the task and, for a dedicated thread, waits for it and returns the CPU time
+    /// the thread used (stream I/O, decompression, decoding and callbacks).
+    pub fn stop(self) -> Option<Duration> {
+        match self {
+            EndpointTask::Shared(handle) => {
+                handle.abort();
+                None
+            }
+            EndpointTask::Dedicated { shutdown, thread } => {
+                let _ = shutdown.send(());
+                thread.join().ok().flatten()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dedicated_stop_reports_cpu_time() {
+        let mode = ExecutionMode::Dedicated { core_ids: vec![] };
+        let task = EndpointTask::spawn(&mode, 0, "busy", || async {
+            let mut x = 0u64;
+            for i in 0..2_000_000u64 {
+                x = std::hint::black_box(x.wrapping_add(i));
+            }
+            std::future::pending::<()>().await;
+        })
+        .unwrap();
+
+        std::thread::sleep(Duration::from_millis(50));
+        let cpu = task.stop().expect("cpu time");
+        assert!(cpu > Duration::ZERO);
+    }
+}
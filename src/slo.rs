@@ -0,0 +1,363 @@
+use crate::engine::ComparisonReport;
+use crate::output::ColoredOutput;
+use crate::stats::calculate_stats;
+use crate::window::WindowReport;
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// Process exit status when at least one assertion failed, so CI can tell it from a crash (1).
+pub const VIOLATION_EXIT_CODE: i32 = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Unit {
+    /// Milliseconds; also accepts `s` and `us` values.
+    Millis,
+    /// Percentage points, e.g. `40%` or `40`.
+    Percent,
+    Count,
+    PerSecond,
+}
+
+impl Unit {
+    /// Converts `number` written with `suffix` to this unit.
+    fn convert(self, number: f64, suffix: &str) -> Option<f64> {
+        match (self, suffix) {
+            (Unit::Millis, "" | "ms") => Some(number),
+            (Unit::Millis, "s") => Some(number * 1000.0),
+            (Unit::Millis, "us" | "µs") => Some(number / 1000.0),
+            (Unit::Percent, "" | "%") | (Unit::Count, "") | (Unit::PerSecond, "" | "/s") => Some(number),
+            _ => None,
+        }
+    }
+
+    pub fn format(self, value: f64) -> String {
+        match self {
+            Unit::Millis => format!("{:.2}ms", value),
+            Unit::Percent => format!("{:.2}%", value),
+            Unit::Count => format!("{:.0}", value),
+            Unit::PerSecond => format!("{:.1}/s", value),
+        }
+    }
+}
+
+/// Every metric an assertion can name. Latencies are relative to the fastest endpoint and
+/// only count arrivals that were behind, like the summary's "落后时" figures.
+pub const METRICS: &[(&str, Unit)] = &[
+    ("received", Unit::Count),
+    ("scored", Unit::Count),
+    ("first_share", Unit::Percent),
+    ("tie_share", Unit::Percent),
+    ("miss_rate", Unit::Percent),
+    ("avg_behind", Unit::Millis),
+    ("overall_avg", Unit::Millis),
+    ("p50", Unit::Millis),
+    ("p90", Unit::Millis),
+    ("p99", Unit::Millis),
+    ("max", Unit::Millis),
+    ("errors", Unit::Count),
+    ("max_slot_lag", Unit::Count),
+    ("jito.requests", Unit::Count),
+    ("jito.success_rate", Unit::Percent),
+    ("jito.429_rate", Unit::Percent),
+    ("jito.success_per_sec", Unit::PerSecond),
+];
+
+fn metric_unit(metric: &str) -> Option<Unit> {
+    METRICS.iter().find(|(name, _)| *name == metric).map(|(_, unit)| *unit)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparison {
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+impl Comparison {
+    fn holds(self, actual: f64, threshold: f64) -> bool {
+        match self {
+            Comparison::Less => actual < threshold,
+            Comparison::LessOrEqual => actual <= threshold,
+            Comparison::Greater => actual > threshold,
+            Comparison::GreaterOrEqual => actual >= threshold,
+        }
+    }
+}
+
+/// One `--assert` expression: `[endpoint=NAME] metric OP value[unit]`, e.g.
+/// `endpoint=MyNode p99<20ms` or `first_share>=40%`.
+///
+/// Without `endpoint=` a per-endpoint metric must hold for every endpoint with data.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Assertion {
+    pub text: String,
+    pub endpoint: Option<String>,
+    pub metric: String,
+    pub unit: Unit,
+    pub comparison: Comparison,
+    /// In `unit`.
+    pub threshold: f64,
+}
+
+impl Assertion {
+    /// Usable directly as a clap `value_parser`.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut endpoint = None;
+        let mut condition = String::new();
+        for token in text.split_whitespace() {
+            match token.strip_prefix("endpoint=") {
+                Some(name) if !name.is_empty() => endpoint = Some(name.to_string()),
+                Some(_) => return Err(format!("empty endpoint name in '{}'", text)),
+                None => condition.push_str(token),
+            }
+        }
+
+        let op_at = condition
+            .find(['<', '>'])
+            .ok_or_else(|| format!("missing comparison (<, <=, > or >=) in '{}'", text))?;
+        let (metric, rest) = condition.split_at(op_at);
+        let (comparison, value) = if let Some(value) = rest.strip_prefix("<=") {
+            (Comparison::LessOrEqual, value)
+        } else if let Some(value) = rest.strip_prefix(">=") {
+            (Comparison::GreaterOrEqual, value)
+        } else if let Some(value) = rest.strip_prefix('<') {
+            (Comparison::Less, value)
+        } else {
+            (Comparison::Greater, &rest[1..])
+        };
+
+        let unit = metric_unit(metric).ok_or_else(|| {
+            let known: Vec<&str> = METRICS.iter().map(|(name, _)| *name).collect();
+            format!("unknown metric '{}' in '{}', expected one of {}", metric, text, known.join(", "))
+        })?;
+        let split = value.find(|c: char| !(c.is_ascii_digit() || c == '.' || c == '-')).unwrap_or(value.len());
+        let (number, suffix) = value.split_at(split);
+        let number: f64 = number.parse().map_err(|_| format!("invalid number '{}' in '{}'", number, text))?;
+        let threshold = unit
+            .convert(number, suffix)
+            .ok_or_else(|| format!("unit '{}' does not fit {} in '{}'", suffix, metric, text))?;
+
+        Ok(Self { text: text.trim().to_string(), endpoint, metric: metric.to_string(), unit, comparison, threshold })
+    }
+}
+
+impl fmt::Display for Assertion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.text)
+    }
+}
+
+/// Metric values of a finished run or window, run-wide and per endpoint.
+#[derive(Debug, Default)]
+pub struct Metrics {
+    global: BTreeMap<String, f64>,
+    endpoints: Vec<(String, BTreeMap<String, f64>)>,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set(&mut self, metric: &str, value: f64) {
+        self.global.insert(metric.to_string(), value);
+    }
+
+    pub fn set_endpoint(&mut self, endpoint: &str, metric: &str, value: f64) {
+        let index = match self.endpoints.iter().position(|(name, _)| name == endpoint) {
+            Some(index) => index,
+            None => {
+                self.endpoints.push((endpoint.to_string(), BTreeMap::new()));
+                self.endpoints.len() - 1
+            }
+        };
+        self.endpoints[index].1.insert(metric.to_string(), value);
+    }
+
+    pub fn has_endpoint(&self, endpoint: &str) -> bool {
+        self.endpoints.iter().any(|(name, _)| name == endpoint)
+    }
+
+    /// Per-endpoint metrics of a comparison; endpoints that scored nothing only get the counts
+    /// and `errors`, so latency assertions on them fail for lack of data.
+    pub fn from_report(report: &ComparisonReport) -> Self {
+        let mut metrics = Self::new();
+        for name in &report.endpoints {
+            let errors = report.health.iter().find(|(n, _)| n == name).map_or(0, |(_, h)| h.errors);
+            metrics.set_endpoint(name, "errors", errors as f64);
+            let Some(stat) = report.stats.get(name) else {
+                metrics.set_endpoint(name, "received", 0.0);
+                metrics.set_endpoint(name, "scored", 0.0);
+                continue;
+            };
+            metrics.set_endpoint(name, "received", stat.received as f64);
+            metrics.set_endpoint(name, "scored", stat.scored as f64);
+            metrics.set_endpoint(name, "miss_rate", stat.get_miss_rate());
+            if stat.slot_lag.samples > 0 {
+                metrics.set_endpoint(name, "max_slot_lag", stat.slot_lag.max as f64);
+            }
+            if stat.scored == 0 {
+                continue;
+            }
+            let latency = calculate_stats(&stat.latencies);
+            metrics.set_endpoint(name, "first_share", stat.get_first_percentage());
+            metrics.set_endpoint(name, "tie_share", stat.percent_of_scored(stat.tied));
+            metrics.set_endpoint(name, "avg_behind", stat.get_average_latency());
+            metrics.set_endpoint(name, "overall_avg", stat.get_overall_average_latency());
+            metrics.set_endpoint(name, "p50", latency.median);
+            metrics.set_endpoint(name, "p90", latency.p90);
+            metrics.set_endpoint(name, "p99", latency.p99);
+            metrics.set_endpoint(name, "max", latency.max);
+        }
+        metrics
+    }
+
+    /// Per-endpoint metrics of one daemon window; `received` and `max` are not tracked per window.
+    pub fn from_window(window: &WindowReport) -> Self {
+        let mut metrics = Self::new();
+        for endpoint in &window.endpoints {
+            let summary = &endpoint.summary;
+            metrics.set_endpoint(&summary.endpoint, "scored", summary.scored as f64);
+            metrics.set_endpoint(&summary.endpoint, "miss_rate", summary.miss_percent());
+            metrics.set_endpoint(&summary.endpoint, "errors", endpoint.errors as f64);
+            if let Some(lag) = endpoint.max_slot_lag {
+                metrics.set_endpoint(&summary.endpoint, "max_slot_lag", lag as f64);
+            }
+            if summary.scored == 0 {
+                continue;
+            }
+            metrics.set_endpoint(&summary.endpoint, "first_share", summary.first_percent());
+            metrics.set_endpoint(&summary.endpoint, "tie_share", summary.tie_percent());
+            metrics.set_endpoint(&summary.endpoint, "avg_behind", summary.avg_behind_ms);
+            metrics.set_endpoint(&summary.endpoint, "overall_avg", summary.overall_avg_ms);
+            metrics.set_endpoint(&summary.endpoint, "p50", summary.p50_ms);
+            metrics.set_endpoint(&summary.endpoint, "p90", endpoint.p90_ms);
+            metrics.set_endpoint(&summary.endpoint, "p99", summary.p99_ms);
+        }
+        metrics
+    }
+}
+
+/// Result of one assertion against one subject.
+#[derive(Debug, Clone)]
+pub struct Outcome {
+    pub assertion: Assertion,
+    /// The endpoint checked; `None` for run-wide metrics or when no endpoint had the metric.
+    pub endpoint: Option<String>,
+    /// `None` when the metric was not available, which fails the assertion.
+    pub actual: Option<f64>,
+    pub passed: bool,
+}
+
+impl Outcome {
+    fn new(assertion: &Assertion, endpoint: Option<&str>, actual: Option<f64>) -> Self {
+        Self {
+            assertion: assertion.clone(),
+            endpoint: endpoint.map(str::to_string),
+            actual,
+            passed: actual.is_some_and(|actual| assertion.comparison.holds(actual, assertion.threshold)),
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        let subject = match &self.endpoint {
+            Some(endpoint) => format!("{} {}", endpoint, self.assertion.metric),
+            None => self.assertion.metric.clone(),
+        };
+        match self.actual {
+            Some(actual) => format!("{}: {} = {}", self.assertion, subject, self.assertion.unit.format(actual)),
+            None => format!("{}: {} 没有数据", self.assertion, subject),
+        }
+    }
+}
+
+/// Checks every assertion, one outcome per assertion and endpoint it applies to.
+pub fn evaluate(assertions: &[Assertion], metrics: &Metrics) -> Vec<Outcome> {
+    let mut outcomes = Vec::new();
+    for assertion in assertions {
+        let metric = assertion.metric.as_str();
+        if let Some(endpoint) = &assertion.endpoint {
+            let actual = metrics.endpoints.iter().find(|(name, _)| name == endpoint).and_then(|(_, m)| m.get(metric));
+            outcomes.push(Outcome::new(assertion, Some(endpoint), actual.copied()));
+        } else if let Some(actual) = metrics.global.get(metric) {
+            outcomes.push(Outcome::new(assertion, None, Some(*actual)));
+        } else {
+            let before = outcomes.len();
+            for (name, values) in &metrics.endpoints {
+                if let Some(actual) = values.get(metric) {
+                    outcomes.push(Outcome::new(assertion, Some(name), Some(*actual)));
+                }
+            }
+            if outcomes.len() == before {
+                outcomes.push(Outcome::new(assertion, None, None));
+            }
+        }
+    }
+    outcomes
+}
+
+/// Prints every outcome and exits with `VIOLATION_EXIT_CODE` if any assertion failed.
+pub fn enforce(assertions: &[Assertion], metrics: &Metrics) {
+    if assertions.is_empty() {
+        return;
+    }
+    let output = ColoredOutput::new();
+    output.subheader("🎯 SLO 断言");
+    let outcomes = evaluate(assertions, metrics);
+    for outcome in &outcomes {
+        if outcome.passed {
+            output.success(&outcome.describe());
+        } else {
+            output.error(&outcome.describe());
+        }
+    }
+    let failed = outcomes.iter().filter(|o| !o.passed).count();
+    if failed == 0 {
+        output.success(&format!("全部 {} 项断言通过", outcomes.len()));
+    } else {
+        output.error(&format!("{}/{} 项断言未通过", failed, outcomes.len()));
+        std::process::exit(VIOLATION_EXIT_CODE);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_and_evaluate() {
+        let scoped = Assertion::parse("endpoint=MyNode p99 < 0.02s").unwrap();
+        assert_eq!(scoped.endpoint.as_deref(), Some("MyNode"));
+        assert_eq!((scoped.comparison, scoped.threshold), (Comparison::Less, 20.0));
+        assert_eq!(Assertion::parse("first_share>=40%").unwrap().comparison, Comparison::GreaterOrEqual);
+        assert!(Assertion::parse("p99<20%").is_err());
+        assert!(Assertion::parse("latency<20ms").is_err());
+        assert!(Assertion::parse("p99=20ms").is_err());
+
+        let mut metrics = Metrics::new();
+        metrics.set_endpoint("MyNode", "p99", 12.5);
+        metrics.set_endpoint("MyNode", "first_share", 55.0);
+        metrics.set_endpoint("Other", "first_share", 30.0);
+        metrics.set("jito.429_rate", 0.4);
+
+        let assertions: Vec<Assertion> = ["endpoint=MyNode p99<20ms", "first_share>=40%", "jito.429_rate<1%", "endpoint=Other p99<20ms"]
+            .iter()
+            .map(|text| Assertion::parse(text).unwrap())
+            .collect();
+        let outcomes = evaluate(&assertions, &metrics);
+        let passed: Vec<(Option<&str>, bool)> = outcomes.iter().map(|o| (o.endpoint.as_deref(), o.passed)).collect();
+        assert_eq!(
+            passed,
+            vec![
+                (Some("MyNode"), true),
+                (Some("MyNode"), true),
+                (Some("Other"), false),
+                (None, true),
+                (Some("Other"), false),
+            ]
+        );
+        assert_eq!(outcomes[4].actual, None);
+        assert_eq!(outcomes[2].describe(), "first_share>=40%: Other first_share = 30.00%");
+    }
+}
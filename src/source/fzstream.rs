@@ -0,0 +1,215 @@
+use super::streamer::{block_meta_slot, event_callback, event_protocol, event_signature_kind, EventKeyExtractor};
+use super::{SourceEvent, SourceHealth, StreamSource};
+use crate::error::{BenchmarkError, Result};
+use crate::events::{protocol_event_types, DexProtocol};
+use crate::relay::UdpRelay;
+use futures::future::BoxFuture;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+use fzstream_client::FzStreamClient;
+use fzstream_common::{CompressionLevel, EventTypeFilter};
+use solana_streamer_sdk::streaming::event_parser::common::EventType;
+
+/// Payload compression requested from the FzStream server.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FzCompression {
+    None,
+    Lz4,
+    Zstd,
+}
+
+impl FzCompression {
+    pub const ALL: [FzCompression; 3] = [FzCompression::None, FzCompression::Lz4, FzCompression::Zstd];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            FzCompression::None => "none",
+            FzCompression::Lz4 => "lz4",
+            FzCompression::Zstd => "zstd",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|c| c.name().eq_ignore_ascii_case(name.trim()))
+    }
+
+    fn level(self) -> CompressionLevel {
+        match self {
+            FzCompression::None => CompressionLevel::None,
+            FzCompression::Lz4 => CompressionLevel::LZ4Fast,
+            FzCompression::Zstd => CompressionLevel::ZstdFast,
+        }
+    }
+}
+
+/// FzStream subscription, timestamped in the client's event callback.
+pub struct FzStreamSource {
+    name: String,
+    address: String,
+    auth_token: String,
+    filter: Option<EventTypeFilter>,
+    extractor: EventKeyExtractor,
+    compression: Option<FzCompression>,
+    count_wire_bytes: bool,
+    relay: Option<UdpRelay>,
+    client: Option<FzStreamClient>,
+    rx: Option<mpsc::UnboundedReceiver<Result<SourceEvent>>>,
+    health: Arc<SourceHealth>,
+}
+
+impl FzStreamSource {
+    pub fn new(
+        name: impl Into<String>,
+        address: impl Into<String>,
+        auth_token: impl Into<String>,
+        filter: EventTypeFilter,
+        extractor: EventKeyExtractor,
+    ) -> Self {
+        Self {
+            name: name.into(),
+            address: address.into(),
+            auth_token: auth_token.into(),
+            filter: Some(filter),
+            extractor,
+            compression: None,
+            count_wire_bytes: false,
+            relay: None,
+            client: None,
+            rx: None,
+            health: Arc::new(SourceHealth::default()),
+        }
+    }
+
+    /// BlockMeta events keyed by slot.
+    pub fn block_meta(name: impl Into<String>, address: impl Into<String>, auth_token: impl Into<String>) -> Self {
+        Self::new(
+            name,
+            address,
+            auth_token,
+            EventTypeFilter::allow_only(vec![EventType::BlockMeta]),
+            Arc::new(block_meta_slot),
+        )
+    }
+
+    /// Parsed DEX events of `event_types`, keyed by signature and event type.
+    pub fn events(
+        name: impl Into<String>,
+        address: impl Into<String>,
+        auth_token: impl Into<String>,
+        event_types: Vec<EventType>,
+    ) -> Self {
+        Self::new(
+            name,
+            address,
+            auth_token,
+            EventTypeFilter::allow_only(event_types),
+            Arc::new(event_signature_kind),
+        )
+    }
+
+    /// Every parsed event of `protocols`, keyed by signature and protocol.
+    pub fn protocols(
+        name: impl Into<String>,
+        address: impl Into<String>,
+        auth_token: impl Into<String>,
+        protocols: &[DexProtocol],
+    ) -> Self {
+        Self::new(
+            name,
+            address,
+            auth_token,
+            EventTypeFilter::allow_only(protocol_event_types(protocols)),
+            Arc::new(event_protocol),
+        )
+    }
+
+    /// Requests `compression` instead of the client's default.
+    pub fn with_compression(mut self, compression: FzCompression) -> Self {
+        self.compression = Some(compression);
+        self
+    }
+
+    /// Routes the QUIC connection through a local `UdpRelay` so the bytes the server sends
+    /// show up in `HealthSnapshot::bytes`.
+    ///
+    /// The client is pointed at 127.0.0.1, so TLS server-name checks no longer see the real
+    /// host; meant for trusted servers only.
+    pub fn with_wire_accounting(mut self) -> Self {
+        self.count_wire_bytes = true;
+        self
+    }
+}
+
+impl StreamSource for FzStreamSource {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn target(&self) -> &str {
+        &self.address
+    }
+
+    fn health(&self) -> Arc<SourceHealth> {
+        self.health.clone()
+    }
+
+    fn connect(&mut self) -> BoxFuture<'_, Result<()>> {
+        Box::pin(async move {
+            let filter = self.filter.take()
+                .ok_or_else(|| BenchmarkError::ConfigError(format!("{} is already connected", self.name)))?;
+
+            let address = if self.count_wire_bytes {
+                let relay = UdpRelay::start(&self.address, self.health.clone()).await?;
+                let address = relay.rewrite(&self.address);
+                self.relay = Some(relay);
+                address
+            } else {
+                self.address.clone()
+            };
+
+            let mut builder = FzStreamClient::builder()
+                .server_address(&address)
+                .auth_token(&self.auth_token)
+                .connection_timeout(Duration::from_secs(5));
+            if let Some(compression) = self.compression {
+                builder = builder.compression_level(compression.level());
+            }
+            let mut client = builder
+                .build()
+                .map_err(|e| BenchmarkError::ConfigError(format!("FzStream client creation failed: {:?}", e)))?;
+
+            client.connect().await
+                .map_err(|e| BenchmarkError::ConfigError(format!("FzStream connection failed: {:?}", e)))?;
+
+            let (tx, rx) = mpsc::unbounded_channel();
+            let callback = event_callback(self.extractor.clone(), self.health.clone(), tx.clone());
+            let handle = client.subscribe_with_filter(filter, callback).await
+                .map_err(|e| BenchmarkError::StreamError(format!("{:?}", e)))?;
+
+            // 订阅句柄结束即视为流中断
+            let health = self.health.clone();
+            tokio::spawn(async move {
+                let _ = handle.await;
+                health.set_connected(false);
+                let _ = tx.send(Err(BenchmarkError::StreamError("FzStream subscription ended".to_string())));
+            });
+
+            self.client = Some(client);
+            self.rx = Some(rx);
+            self.health.set_connected(true);
+            Ok(())
+        })
+    }
+
+    fn next_event(&mut self) -> BoxFuture<'_, Option<Result<SourceEvent>>> {
+        Box::pin(async move {
+            let event = self.rx.as_mut()?.recv().await;
+            if event.is_none() {
+                self.health.set_connected(false);
+            }
+            event
+        })
+    }
+}
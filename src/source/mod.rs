@@ -0,0 +1,121 @@
+pub mod fzstream;
+pub mod polling;
+pub mod streamer;
+pub mod websocket;
+pub mod yellowstone;
+
+pub use fzstream::{FzCompression, FzStreamSource};
+pub use polling::RpcPollingSource;
+pub use streamer::StreamerSdkSource;
+pub use websocket::{PubSubSubscription, RpcWebSocketSource};
+pub use yellowstone::YellowstoneSource;
+
+use crate::aggregator::ArrivalKey;
+use crate::error::Result;
+use futures::future::BoxFuture;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// A keyed message as timestamped by a source.
+#[derive(Debug, Clone)]
+pub struct SourceEvent {
+    pub key: ArrivalKey,
+    pub timestamp: Instant,
+    /// Set when the source stamps at the transport layer and decodes afterwards.
+    pub decode_time: Option<Duration>,
+    /// Encoded size of the message carrying the key, counted on its first key only; 0 if unknown.
+    pub size: u32,
+}
+
+impl SourceEvent {
+    pub fn new(key: ArrivalKey, timestamp: Instant) -> Self {
+        Self {
+            key,
+            timestamp,
+            decode_time: None,
+            size: 0,
+        }
+    }
+
+    pub fn stamped(key: ArrivalKey, frame_at: Instant, decoded_at: Instant) -> Self {
+        Self {
+            key,
+            timestamp: frame_at,
+            decode_time: Some(decoded_at.saturating_duration_since(frame_at)),
+            size: 0,
+        }
+    }
+
+    pub fn with_size(mut self, size: usize) -> Self {
+        self.size = size.min(u32::MAX as usize) as u32;
+        self
+    }
+}
+
+/// Connection health counters shared between a source and whoever is watching it.
+#[derive(Debug, Default)]
+pub struct SourceHealth {
+    connected: AtomicBool,
+    messages: AtomicU64,
+    errors: AtomicU64,
+    bytes: AtomicU64,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct HealthSnapshot {
+    pub connected: bool,
+    pub messages: u64,
+    pub errors: u64,
+    /// Encoded payload bytes: gRPC message size on the wire with frame timestamps (protobuf
+    /// size otherwise), frame or body size for RPC sources, UDP payload for a relayed FzStream.
+    /// Zero for sources that only see parsed events.
+    pub bytes: u64,
+}
+
+impl SourceHealth {
+    pub fn set_connected(&self, connected: bool) {
+        self.connected.store(connected, Ordering::Relaxed);
+    }
+
+    pub fn record_message(&self) {
+        self.messages.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_error(&self) {
+        self.errors.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_bytes(&self, bytes: usize) {
+        self.bytes.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    pub fn snapshot(&self) -> HealthSnapshot {
+        HealthSnapshot {
+            connected: self.connected.load(Ordering::Relaxed),
+            messages: self.messages.load(Ordering::Relaxed),
+            errors: self.errors.load(Ordering::Relaxed),
+            bytes: self.bytes.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// A feed that can take part in a comparison.
+///
+/// Implementations decide how to subscribe and how to turn their native messages into
+/// `ArrivalKey`s; the comparison engine only sees timestamped keyed events.
+pub trait StreamSource: Send {
+    /// Endpoint name used in logs and reports.
+    fn name(&self) -> &str;
+
+    /// Address the source connects to, for display.
+    fn target(&self) -> &str;
+
+    fn health(&self) -> Arc<SourceHealth>;
+
+    /// Connects and subscribes. Called once, on the runtime that will poll `next_event`.
+    fn connect(&mut self) -> BoxFuture<'_, Result<()>>;
+
+    /// Next keyed event, or `None` once the subscription has ended.
+    fn next_event(&mut self) -> BoxFuture<'_, Option<Result<SourceEvent>>>;
+}
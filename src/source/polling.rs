@@ -0,0 +1,327 @@
+use super::{SourceEvent, SourceHealth, StreamSource};
+use crate::aggregator::ArrivalKey;
+use crate::error::{BenchmarkError, Result};
+use futures::future::BoxFuture;
+use serde_json::{json, Value};
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::time::{interval, Interval, MissedTickBehavior};
+
+/// Most slots emitted for a single poll. Larger jumps only emit the newest slots.
+const MAX_SLOTS_PER_POLL: u64 = 64;
+
+/// Latest values seen by a polling source.
+#[derive(Debug, Default)]
+pub struct PollObservations {
+    slot: AtomicU64,
+    block_height: AtomicU64,
+    /// Round trip of the last poll, in microseconds.
+    round_trip_us: AtomicU64,
+}
+
+impl PollObservations {
+    pub fn slot(&self) -> u64 {
+        self.slot.load(Ordering::Relaxed)
+    }
+
+    pub fn block_height(&self) -> u64 {
+        self.block_height.load(Ordering::Relaxed)
+    }
+
+    pub fn round_trip(&self) -> Duration {
+        Duration::from_micros(self.round_trip_us.load(Ordering::Relaxed))
+    }
+}
+
+/// Polls `getSlot` over HTTP JSON-RPC. A baseline for how much streaming gains over polling.
+///
+/// Every slot between the previous and the current answer is reported as observed when the
+/// response arrived, since that is when a polling client learns about it.
+pub struct RpcPollingSource {
+    name: String,
+    url: String,
+    poll_interval: Duration,
+    commitment: String,
+    block_height: bool,
+    client: Option<reqwest::Client>,
+    ticker: Option<Interval>,
+    last_slot: Option<u64>,
+    pending: VecDeque<SourceEvent>,
+    observations: Arc<PollObservations>,
+    health: Arc<SourceHealth>,
+}
+
+impl RpcPollingSource {
+    pub fn new(name: impl Into<String>, url: impl Into<String>, poll_interval: Duration) -> Self {
+        Self {
+            name: name.into(),
+            url: url.into(),
+            poll_interval,
+            commitment: "processed".to_string(),
+            block_height: false,
+            client: None,
+            ticker: None,
+            last_slot: None,
+            pending: VecDeque::new(),
+            observations: Arc::new(PollObservations::default()),
+            health: Arc::new(SourceHealth::default()),
+        }
+    }
+
+    pub fn with_commitment(mut self, commitment: impl Into<String>) -> Self {
+        self.commitment = commitment.into();
+        self
+    }
+
+    /// Also poll `getBlockHeight` in the same batch request.
+    pub fn with_block_height(mut self, enabled: bool) -> Self {
+        self.block_height = enabled;
+        self
+    }
+
+    pub fn observations(&self) -> Arc<PollObservations> {
+        self.observations.clone()
+    }
+
+    fn request_body(&self) -> Value {
+        let params = json!([{ "commitment": self.commitment }]);
+        let get_slot = json!({ "jsonrpc": "2.0", "id": 1, "method": "getSlot", "params": params });
+        if !self.block_height {
+            return get_slot;
+        }
+        let get_block_height = json!({ "jsonrpc": "2.0", "id": 2, "method": "getBlockHeight", "params": params });
+        json!([get_slot, get_block_height])
+    }
+
+    async fn poll(&mut self) -> Result<()> {
+        let client = self.client.as_ref()
+            .ok_or_else(|| BenchmarkError::ConfigError(format!("{} is not connected", self.name)))?;
+
+        let sent_at = Instant::now();
+        let body = client.post(&self.url).json(&self.request_body()).send().await?.error_for_status()?.bytes().await?;
+        let received_at = Instant::now();
+        self.health.record_message();
+        self.health.record_bytes(body.len());
+        self.observations
+            .round_trip_us
+            .store(received_at.duration_since(sent_at).as_micros() as u64, Ordering::Relaxed);
+
+        let responses = match serde_json::from_slice(&body)? {
+            Value::Array(responses) => responses,
+            response => vec![response],
+        };
+
+        let mut slot = None;
+        for response in responses {
+            if let Some(error) = response.get("error") {
+                return Err(BenchmarkError::StreamError(format!("RPC error: {}", error)));
+            }
+            let result = response.get("result").and_then(Value::as_u64);
+            match response.get("id").and_then(Value::as_u64) {
+                Some(1) => slot = result,
+                Some(2) => {
+                    if let Some(height) = result {
+                        self.observations.block_height.store(height, Ordering::Relaxed);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        let slot = slot.ok_or_else(|| BenchmarkError::StreamError("getSlot returned no result".to_string()))?;
+        let queued = self.pending.len();
+        self.observe_slot(slot, received_at);
+        if let Some(event) = self.pending.get_mut(queued) {
+            event.size = body.len() as u32;
+        }
+        Ok(())
+    }
+
+    fn observe_slot(&mut self, slot: u64, received_at: Instant) {
+        let first_new = match self.last_slot {
+            Some(last) if slot <= last => return,
+            Some(last) => (last + 1).max(slot.saturating_sub(MAX_SLOTS_PER_POLL - 1)),
+            None => slot,
+        };
+
+        self.last_slot = Some(slot);
+        self.observations.slot.store(slot, Ordering::Relaxed);
+        self.pending
+            .extend((first_new..=slot).map(|s| SourceEvent::new(ArrivalKey::Slot(s), received_at)));
+    }
+}
+
+impl StreamSource for RpcPollingSource {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn target(&self) -> &str {
+        &self.url
+    }
+
+    fn health(&self) -> Arc<SourceHealth> {
+        self.health.clone()
+    }
+
+    fn connect(&mut self) -> BoxFuture<'_, Result<()>> {
+        Box::pin(async move {
+            let client = reqwest::Client::builder()
+                .timeout(Duration::from_secs(5))
+                .tcp_nodelay(true)
+                .build()?;
+            self.client = Some(client);
+
+            // 首次轮询同时验证端点可用
+            self.poll().await?;
+
+            let mut ticker = interval(self.poll_interval);
+            ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+            ticker.reset();
+            self.ticker = Some(ticker);
+            self.health.set_connected(true);
+            Ok(())
+        })
+    }
+
+    fn next_event(&mut self) -> BoxFuture<'_, Option<Result<SourceEvent>>> {
+        Box::pin(async move {
+            loop {
+                if let Some(event) = self.pending.pop_front() {
+                    return Some(Ok(event));
+                }
+
+                self.ticker.as_mut()?.tick().await;
+                if let Err(e) = self.poll().await {
+                    self.health.record_error();
+                    self.health.set_connected(false);
+                    return Some(Err(e));
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::{TcpListener, TcpStream};
+
+    /// Minimal HTTP/1.1 JSON-RPC server. `getSlot` returns the next value of `slots`
+    /// (repeating the last one), `getBlockHeight` returns the current slot minus 10.
+    async fn mock_rpc_server(slots: Vec<u64>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let slots = Arc::new(Mutex::new(VecDeque::from(slots)));
+
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                tokio::spawn(serve_connection(stream, slots.clone()));
+            }
+        });
+
+        url
+    }
+
+    async fn serve_connection(mut stream: TcpStream, slots: Arc<Mutex<VecDeque<u64>>>) {
+        let mut buffer = Vec::new();
+        loop {
+            let header_end = loop {
+                if let Some(pos) = buffer.windows(4).position(|w| w == b"\r\n\r\n") {
+                    break pos + 4;
+                }
+                let mut chunk = [0u8; 4096];
+                match stream.read(&mut chunk).await {
+                    Ok(0) | Err(_) => return,
+                    Ok(n) => buffer.extend_from_slice(&chunk[..n]),
+                }
+            };
+
+            let headers = String::from_utf8_lossy(&buffer[..header_end]).to_ascii_lowercase();
+            let content_length: usize = headers
+                .lines()
+                .find_map(|line| line.strip_prefix("content-length:"))
+                .and_then(|value| value.trim().parse().ok())
+                .unwrap_or(0);
+            while buffer.len() < header_end + content_length {
+                let mut chunk = [0u8; 4096];
+                match stream.read(&mut chunk).await {
+                    Ok(0) | Err(_) => return,
+                    Ok(n) => buffer.extend_from_slice(&chunk[..n]),
+                }
+            }
+
+            let request: Value = serde_json::from_slice(&buffer[header_end..header_end + content_length]).unwrap();
+            buffer.drain(..header_end + content_length);
+
+            let slot = {
+                let mut slots = slots.lock().unwrap();
+                if slots.len() > 1 { slots.pop_front().unwrap() } else { slots[0] }
+            };
+            let answer = |request: &Value| {
+                let result = match request["method"].as_str() {
+                    Some("getSlot") => json!(slot),
+                    Some("getBlockHeight") => json!(slot - 10),
+                    _ => return json!({ "jsonrpc": "2.0", "id": request["id"], "error": { "code": -32601, "message": "Method not found" } }),
+                };
+                json!({ "jsonrpc": "2.0", "id": request["id"], "result": result })
+            };
+            let response = match &request {
+                Value::Array(batch) => Value::Array(batch.iter().map(answer).collect()),
+                single => answer(single),
+            }
+            .to_string();
+
+            let reply = format!(
+                "HTTP/1.1 200 OK\r\ncontent-type: application/json\r\ncontent-length: {}\r\n\r\n{}",
+                response.len(),
+                response
+            );
+            if stream.write_all(reply.as_bytes()).await.is_err() {
+                return;
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_emits_each_new_slot_once() {
+        let url = mock_rpc_server(vec![100, 100, 103, 104]).await;
+        let mut source = RpcPollingSource::new("poll", url, Duration::from_millis(5));
+        source.connect().await.unwrap();
+
+        let mut keys = Vec::new();
+        while keys.len() < 5 {
+            keys.push(source.next_event().await.unwrap().unwrap().key);
+        }
+
+        let expected: Vec<_> = (100..=104).map(ArrivalKey::Slot).collect();
+        assert_eq!(keys, expected);
+        assert_eq!(source.observations().slot(), 104);
+    }
+
+    #[tokio::test]
+    async fn test_batches_block_height_with_slot() {
+        let url = mock_rpc_server(vec![200]).await;
+        let mut source = RpcPollingSource::new("poll", url, Duration::from_millis(5)).with_block_height(true);
+        source.connect().await.unwrap();
+
+        let event = source.next_event().await.unwrap().unwrap();
+        assert_eq!(event.key, ArrivalKey::Slot(200));
+        assert_eq!(source.observations().block_height(), 190);
+    }
+
+    #[test]
+    fn test_large_gaps_are_capped() {
+        let mut source = RpcPollingSource::new("poll", "http://127.0.0.1:0", Duration::from_millis(5));
+        let now = Instant::now();
+        source.observe_slot(10, now);
+        source.observe_slot(1_000, now);
+
+        assert_eq!(source.pending.len() as u64, 1 + MAX_SLOTS_PER_POLL);
+        assert_eq!(source.pending.back().unwrap().key, ArrivalKey::Slot(1_000));
+    }
+}
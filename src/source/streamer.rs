@@ -0,0 +1,221 @@
+use super::{SourceEvent, SourceHealth, StreamSource};
+use crate::aggregator::ArrivalKey;
+use crate::error::{BenchmarkError, Result};
+use crate::events::{event_key, protocol_event_types, protocol_key, protocols_for, DexProtocol};
+use futures::future::BoxFuture;
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::sync::mpsc;
+
+use solana_streamer_sdk::match_event;
+use solana_streamer_sdk::streaming::event_parser::common::filter::EventTypeFilter;
+use solana_streamer_sdk::streaming::event_parser::common::EventType;
+use solana_streamer_sdk::streaming::event_parser::core::UnifiedEvent;
+use solana_streamer_sdk::streaming::event_parser::protocols::BlockMetaEvent;
+use solana_streamer_sdk::streaming::event_parser::Protocol;
+use solana_streamer_sdk::streaming::yellowstone_grpc::{AccountFilter, TransactionFilter};
+use solana_streamer_sdk::streaming::YellowstoneGrpc;
+
+/// Maps a parsed solana-streamer-sdk event to the key it is raced on.
+pub type EventKeyExtractor = Arc<dyn Fn(Box<dyn UnifiedEvent>) -> Option<ArrivalKey> + Send + Sync>;
+
+pub fn block_meta_slot(event: Box<dyn UnifiedEvent>) -> Option<ArrivalKey> {
+    let mut key = None;
+    match_event!(event, {
+        BlockMetaEvent => |e: BlockMetaEvent| {
+            key = Some(ArrivalKey::Slot(e.slot));
+        },
+    });
+    key
+}
+
+/// Keys DEX events by transaction signature and event type; BlockMeta is ignored.
+pub fn event_signature_kind(event: Box<dyn UnifiedEvent>) -> Option<ArrivalKey> {
+    if event.event_type() == EventType::BlockMeta {
+        return None;
+    }
+    Some(event_key(event.as_ref()))
+}
+
+/// Keys DEX events by transaction signature and the protocol that produced them.
+pub fn event_protocol(event: Box<dyn UnifiedEvent>) -> Option<ArrivalKey> {
+    let protocol = DexProtocol::of_event_type(&event.event_type())?;
+    Some(protocol_key(event.signature().to_string(), protocol))
+}
+
+/// Builds the callback handed to the sdk: stamp first, then extract the key and forward it.
+pub(crate) fn event_callback(
+    extractor: EventKeyExtractor,
+    health: Arc<SourceHealth>,
+    tx: mpsc::UnboundedSender<Result<SourceEvent>>,
+) -> impl Fn(Box<dyn UnifiedEvent>) + Send + Sync + 'static {
+    move |event: Box<dyn UnifiedEvent>| {
+        let timestamp = Instant::now();
+        health.record_message();
+        if let Some(key) = extractor(event) {
+            let _ = tx.send(Ok(SourceEvent::new(key, timestamp)));
+        }
+    }
+}
+
+/// Yellowstone gRPC parsed by solana-streamer-sdk, timestamped in the event callback.
+pub struct StreamerSdkSource {
+    name: String,
+    url: String,
+    token: Option<String>,
+    protocols: Vec<Protocol>,
+    transaction_filter: Option<TransactionFilter>,
+    account_filter: Option<AccountFilter>,
+    event_types: Vec<EventType>,
+    extractor: EventKeyExtractor,
+    client: Option<YellowstoneGrpc>,
+    rx: Option<mpsc::UnboundedReceiver<Result<SourceEvent>>>,
+    health: Arc<SourceHealth>,
+}
+
+impl StreamerSdkSource {
+    pub fn new(
+        name: impl Into<String>,
+        url: impl Into<String>,
+        token: Option<String>,
+        protocols: Vec<Protocol>,
+        transaction_filter: TransactionFilter,
+        event_types: Vec<EventType>,
+        extractor: EventKeyExtractor,
+    ) -> Self {
+        Self {
+            name: name.into(),
+            url: url.into(),
+            token,
+            protocols,
+            transaction_filter: Some(transaction_filter),
+            account_filter: Some(AccountFilter {
+                account: vec![],
+                owner: vec![],
+            }),
+            event_types,
+            extractor,
+            client: None,
+            rx: None,
+            health: Arc::new(SourceHealth::default()),
+        }
+    }
+
+    /// BlockMeta events keyed by slot. BlockMeta needs no protocol or account filter.
+    pub fn block_meta(name: impl Into<String>, url: impl Into<String>, token: Option<String>) -> Self {
+        let transaction_filter = TransactionFilter {
+            account_include: vec![],
+            account_exclude: vec![],
+            account_required: vec![],
+        };
+        Self::new(
+            name,
+            url,
+            token,
+            vec![],
+            transaction_filter,
+            vec![EventType::BlockMeta],
+            Arc::new(block_meta_slot),
+        )
+    }
+
+    /// Parsed DEX events of `event_types`, keyed by signature and event type. Subscribes to
+    /// transactions of every protocol that produces one of the selected types.
+    pub fn events(
+        name: impl Into<String>,
+        url: impl Into<String>,
+        token: Option<String>,
+        event_types: Vec<EventType>,
+    ) -> Self {
+        let protocols = protocols_for(&event_types);
+        let transaction_filter = TransactionFilter {
+            account_include: protocols.iter().map(|p| p.program_id().to_string()).collect(),
+            account_exclude: vec![],
+            account_required: vec![],
+        };
+        Self::new(
+            name,
+            url,
+            token,
+            protocols.into_iter().map(|p| p.sdk_protocol()).collect(),
+            transaction_filter,
+            event_types,
+            Arc::new(event_signature_kind),
+        )
+    }
+
+    /// Every parsed event of `protocols`, keyed by signature and protocol.
+    pub fn protocols(
+        name: impl Into<String>,
+        url: impl Into<String>,
+        token: Option<String>,
+        protocols: &[DexProtocol],
+    ) -> Self {
+        let mut source = Self::events(name, url, token, protocol_event_types(protocols));
+        source.extractor = Arc::new(event_protocol);
+        source
+    }
+}
+
+impl StreamSource for StreamerSdkSource {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn target(&self) -> &str {
+        &self.url
+    }
+
+    fn health(&self) -> Arc<SourceHealth> {
+        self.health.clone()
+    }
+
+    fn connect(&mut self) -> BoxFuture<'_, Result<()>> {
+        Box::pin(async move {
+            let (transaction_filter, account_filter) =
+                match (self.transaction_filter.take(), self.account_filter.take()) {
+                    (Some(transaction_filter), Some(account_filter)) => (transaction_filter, account_filter),
+                    _ => {
+                        return Err(BenchmarkError::ConfigError(format!("{} is already connected", self.name)))
+                    }
+                };
+
+            let client = YellowstoneGrpc::new(self.url.clone(), self.token.clone())
+                .map_err(|e| BenchmarkError::StreamError(format!("{:?}", e)))?;
+
+            let (tx, rx) = mpsc::unbounded_channel();
+            let callback = event_callback(self.extractor.clone(), self.health.clone(), tx);
+            let event_type_filter = EventTypeFilter {
+                include: self.event_types.clone(),
+            };
+
+            client
+                .subscribe_events_immediate(
+                    std::mem::take(&mut self.protocols),
+                    None,
+                    transaction_filter,
+                    account_filter,
+                    Some(event_type_filter),
+                    None,
+                    callback,
+                )
+                .await
+                .map_err(|e| BenchmarkError::StreamError(format!("{:?}", e)))?;
+
+            self.client = Some(client);
+            self.rx = Some(rx);
+            self.health.set_connected(true);
+            Ok(())
+        })
+    }
+
+    fn next_event(&mut self) -> BoxFuture<'_, Option<Result<SourceEvent>>> {
+        Box::pin(async move {
+            let event = self.rx.as_mut()?.recv().await;
+            if event.is_none() {
+                self.health.set_connected(false);
+            }
+            event
+        })
+    }
+}
@@ -0,0 +1,417 @@
+use super::{SourceEvent, SourceHealth, StreamSource};
+use crate::aggregator::ArrivalKey;
+use crate::error::{BenchmarkError, Result};
+use futures::future::BoxFuture;
+use futures::{SinkExt, StreamExt};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::net::TcpStream;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::{connect_async, MaybeTlsStream, WebSocketStream};
+
+/// What to subscribe to on a Solana RPC PubSub websocket.
+#[derive(Debug, Clone)]
+pub enum PubSubSubscription {
+    /// `slotSubscribe`, keyed by slot.
+    Slots,
+    /// One `logsSubscribe` per account (the RPC accepts a single mention per
+    /// subscription), keyed by transaction signature.
+    Logs { mentions: Vec<String> },
+    /// One `signatureSubscribe` per signature, keyed by that signature.
+    Signatures(Vec<String>),
+    /// One `logsSubscribe` per `(label, program id)`, keyed by signature and label.
+    /// Failed transactions are skipped.
+    Programs(Vec<(String, String)>),
+}
+
+#[derive(Debug, Deserialize)]
+struct PubSubMessage {
+    id: Option<u64>,
+    result: Option<Value>,
+    error: Option<Value>,
+    method: Option<String>,
+    params: Option<NotificationParams>,
+}
+
+#[derive(Debug, Deserialize)]
+struct NotificationParams {
+    result: Value,
+    subscription: u64,
+}
+
+/// Solana RPC PubSub websocket, timestamped when the text frame is read.
+pub struct RpcWebSocketSource {
+    name: String,
+    url: String,
+    subscription: PubSubSubscription,
+    commitment: String,
+    socket: Option<WebSocketStream<MaybeTlsStream<TcpStream>>>,
+    /// Request id → tag (signature or program label), until the subscription id comes back.
+    requests: HashMap<u64, Option<String>>,
+    /// Subscription id → tag.
+    subscriptions: HashMap<u64, Option<String>>,
+    health: Arc<SourceHealth>,
+}
+
+impl RpcWebSocketSource {
+    pub fn new(name: impl Into<String>, url: impl Into<String>, subscription: PubSubSubscription) -> Self {
+        Self {
+            name: name.into(),
+            url: url.into(),
+            subscription,
+            commitment: "processed".to_string(),
+            socket: None,
+            requests: HashMap::new(),
+            subscriptions: HashMap::new(),
+            health: Arc::new(SourceHealth::default()),
+        }
+    }
+
+    pub fn slots(name: impl Into<String>, url: impl Into<String>) -> Self {
+        Self::new(name, url, PubSubSubscription::Slots)
+    }
+
+    pub fn logs(name: impl Into<String>, url: impl Into<String>, mentions: Vec<String>) -> Self {
+        Self::new(name, url, PubSubSubscription::Logs { mentions })
+    }
+
+    pub fn signatures(name: impl Into<String>, url: impl Into<String>, signatures: Vec<String>) -> Self {
+        Self::new(name, url, PubSubSubscription::Signatures(signatures))
+    }
+
+    pub fn programs(name: impl Into<String>, url: impl Into<String>, programs: Vec<(String, String)>) -> Self {
+        Self::new(name, url, PubSubSubscription::Programs(programs))
+    }
+
+    pub fn with_commitment(mut self, commitment: impl Into<String>) -> Self {
+        self.commitment = commitment.into();
+        self
+    }
+
+    /// JSON-RPC requests for the configured subscription, with the tag each one tracks.
+    fn subscribe_requests(&self) -> Vec<(Value, Option<String>)> {
+        let commitment = json!({ "commitment": self.commitment });
+        let requests: Vec<(&str, Value, Option<String>)> = match &self.subscription {
+            PubSubSubscription::Slots => vec![("slotSubscribe", json!([]), None)],
+            PubSubSubscription::Logs { mentions } => mentions
+                .iter()
+                .map(|account| ("logsSubscribe", json!([{ "mentions": [account] }, commitment]), None))
+                .collect(),
+            PubSubSubscription::Signatures(signatures) => signatures
+                .iter()
+                .map(|signature| {
+                    ("signatureSubscribe", json!([signature, commitment]), Some(signature.clone()))
+                })
+                .collect(),
+            PubSubSubscription::Programs(programs) => programs
+                .iter()
+                .map(|(label, program)| {
+                    ("logsSubscribe", json!([{ "mentions": [program] }, commitment]), Some(label.clone()))
+                })
+                .collect(),
+        };
+
+        requests
+            .into_iter()
+            .enumerate()
+            .map(|(index, (method, params, tag))| {
+                let request = json!({
+                    "jsonrpc": "2.0",
+                    "id": index as u64 + 1,
+                    "method": method,
+                    "params": params,
+                });
+                (request, tag)
+            })
+            .collect()
+    }
+
+    fn handle_message(&mut self, text: &str, timestamp: Instant) -> Result<Option<SourceEvent>> {
+        let message: PubSubMessage = serde_json::from_str(text)?;
+
+        if let Some(id) = message.id {
+            if let Some(error) = message.error {
+                return Err(BenchmarkError::StreamError(format!("subscription request {} rejected: {}", id, error)));
+            }
+            let tag = self.requests.remove(&id).flatten();
+            if let Some(subscription) = message.result.as_ref().and_then(Value::as_u64) {
+                self.subscriptions.insert(subscription, tag);
+            }
+            return Ok(None);
+        }
+
+        let (Some(method), Some(params)) = (message.method, message.params) else {
+            return Ok(None);
+        };
+
+        let key = match method.as_str() {
+            "slotNotification" => params.result.pointer("/slot").and_then(Value::as_u64).map(ArrivalKey::Slot),
+            "logsNotification" => {
+                let signature = params.result.pointer("/value/signature").and_then(Value::as_str);
+                let failed = params.result.pointer("/value/err").is_some_and(|err| !err.is_null());
+                match (signature, self.subscriptions.get(&params.subscription).cloned().flatten()) {
+                    (Some(signature), Some(label)) if !failed => Some(ArrivalKey::Event {
+                        signature: signature.to_string(),
+                        kind: label,
+                    }),
+                    (Some(_), Some(_)) => None,
+                    (Some(signature), None) => Some(ArrivalKey::Signature(signature.to_string())),
+                    (None, _) => None,
+                }
+            }
+            "signatureNotification" => self
+                .subscriptions
+                .get(&params.subscription)
+                .cloned()
+                .flatten()
+                .map(ArrivalKey::Signature),
+            _ => None,
+        };
+
+        Ok(key.map(|key| SourceEvent::new(key, timestamp)))
+    }
+}
+
+impl StreamSource for RpcWebSocketSource {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn target(&self) -> &str {
+        &self.url
+    }
+
+    fn health(&self) -> Arc<SourceHealth> {
+        self.health.clone()
+    }
+
+    fn connect(&mut self) -> BoxFuture<'_, Result<()>> {
+        Box::pin(async move {
+            let (mut socket, _) = connect_async(self.url.as_str())
+                .await
+                .map_err(|e| BenchmarkError::StreamError(format!("websocket connection failed: {}", e)))?;
+
+            for (request, tag) in self.subscribe_requests() {
+                if let Some(id) = request["id"].as_u64() {
+                    self.requests.insert(id, tag);
+                }
+                socket
+                    .send(Message::Text(request.to_string()))
+                    .await
+                    .map_err(|e| BenchmarkError::StreamError(format!("websocket subscribe failed: {}", e)))?;
+            }
+
+            self.socket = Some(socket);
+            self.health.set_connected(true);
+            Ok(())
+        })
+    }
+
+    fn next_event(&mut self) -> BoxFuture<'_, Option<Result<SourceEvent>>> {
+        Box::pin(async move {
+            loop {
+                let message = self.socket.as_mut()?.next().await;
+                let timestamp = Instant::now();
+
+                let text = match message {
+                    Some(Ok(Message::Text(text))) => text,
+                    Some(Ok(Message::Binary(data))) => String::from_utf8_lossy(&data).into_owned(),
+                    Some(Ok(Message::Close(_))) | None => {
+                        self.health.set_connected(false);
+                        return None;
+                    }
+                    Some(Ok(_)) => continue,
+                    Some(Err(e)) => {
+                        self.health.record_error();
+                        self.health.set_connected(false);
+                        return Some(Err(BenchmarkError::StreamError(format!("websocket error: {}", e))));
+                    }
+                };
+
+                self.health.record_message();
+                self.health.record_bytes(text.len());
+                match self.handle_message(&text, timestamp) {
+                    Ok(Some(event)) => return Some(Ok(event.with_size(text.len()))),
+                    Ok(None) => continue,
+                    Err(e) => {
+                        self.health.record_error();
+                        return Some(Err(e));
+                    }
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::TcpListener;
+    use tokio_tungstenite::accept_async;
+
+    /// Accepts one connection, answers each subscribe request with the next subscription id
+    /// and then sends `notifications`, returning the requests it received.
+    async fn mock_pubsub_server(expected_requests: usize, notifications: Vec<Value>) -> (String, tokio::task::JoinHandle<Vec<Value>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut socket = accept_async(stream).await.unwrap();
+            let mut requests = Vec::new();
+
+            while requests.len() < expected_requests {
+                let Some(Ok(Message::Text(text))) = socket.next().await else {
+                    break;
+                };
+                let request: Value = serde_json::from_str(&text).unwrap();
+                let response = json!({ "jsonrpc": "2.0", "result": 100 + requests.len(), "id": request["id"] });
+                socket.send(Message::Text(response.to_string())).await.unwrap();
+                requests.push(request);
+            }
+
+            for notification in notifications {
+                socket.send(Message::Text(notification.to_string())).await.unwrap();
+            }
+            socket.close(None).await.unwrap();
+            requests
+        });
+
+        (url, server)
+    }
+
+    fn notification(method: &str, subscription: u64, result: Value) -> Value {
+        json!({
+            "jsonrpc": "2.0",
+            "method": method,
+            "params": { "result": result, "subscription": subscription },
+        })
+    }
+
+    async fn collect_keys(source: &mut RpcWebSocketSource) -> Vec<ArrivalKey> {
+        source.connect().await.unwrap();
+        let mut keys = Vec::new();
+        while let Some(event) = source.next_event().await {
+            keys.push(event.unwrap().key);
+        }
+        keys
+    }
+
+    #[tokio::test]
+    async fn test_slot_notifications_are_keyed_by_slot() {
+        let (url, server) = mock_pubsub_server(1, vec![
+            notification("slotNotification", 100, json!({ "parent": 41, "root": 10, "slot": 42 })),
+            notification("slotNotification", 100, json!({ "parent": 42, "root": 10, "slot": 43 })),
+        ]).await;
+
+        let mut source = RpcWebSocketSource::slots("ws", url);
+        let keys = collect_keys(&mut source).await;
+
+        assert_eq!(keys, vec![ArrivalKey::Slot(42), ArrivalKey::Slot(43)]);
+        let requests = server.await.unwrap();
+        assert_eq!(requests[0]["method"], "slotSubscribe");
+        assert_eq!(source.health().snapshot().messages, 3);
+    }
+
+    #[tokio::test]
+    async fn test_logs_subscribe_once_per_mention() {
+        let logs = |signature: &str| json!({
+            "context": { "slot": 5 },
+            "value": { "signature": signature, "err": null, "logs": [] },
+        });
+        let (url, server) = mock_pubsub_server(2, vec![
+            notification("logsNotification", 100, logs("sigA")),
+            notification("logsNotification", 101, logs("sigB")),
+        ]).await;
+
+        let mut source = RpcWebSocketSource::logs("ws", url, vec!["acct1".to_string(), "acct2".to_string()]);
+        let keys = collect_keys(&mut source).await;
+
+        assert_eq!(keys, vec![
+            ArrivalKey::Signature("sigA".to_string()),
+            ArrivalKey::Signature("sigB".to_string()),
+        ]);
+        let requests = server.await.unwrap();
+        assert_eq!(requests[0]["method"], "logsSubscribe");
+        assert_eq!(requests[0]["params"][0]["mentions"], json!(["acct1"]));
+        assert_eq!(requests[1]["params"][0]["mentions"], json!(["acct2"]));
+        assert_eq!(requests[1]["params"][1]["commitment"], "processed");
+    }
+
+    #[tokio::test]
+    async fn test_program_logs_are_keyed_by_label() {
+        let logs = |signature: &str, err: Value| json!({
+            "context": { "slot": 5 },
+            "value": { "signature": signature, "err": err, "logs": [] },
+        });
+        let (url, server) = mock_pubsub_server(2, vec![
+            notification("logsNotification", 101, logs("sigA", Value::Null)),
+            notification("logsNotification", 100, logs("sigB", json!({ "InstructionError": [0, "Custom"] }))),
+            notification("logsNotification", 100, logs("sigA", Value::Null)),
+        ]).await;
+
+        let programs = vec![
+            ("PumpFun".to_string(), "programP".to_string()),
+            ("Bonk".to_string(), "programB".to_string()),
+        ];
+        let mut source = RpcWebSocketSource::programs("ws", url, programs);
+        let keys = collect_keys(&mut source).await;
+
+        assert_eq!(keys, vec![
+            ArrivalKey::Event { signature: "sigA".to_string(), kind: "Bonk".to_string() },
+            ArrivalKey::Event { signature: "sigA".to_string(), kind: "PumpFun".to_string() },
+        ]);
+        let requests = server.await.unwrap();
+        assert_eq!(requests[1]["params"][0]["mentions"], json!(["programB"]));
+    }
+
+    #[tokio::test]
+    async fn test_signature_notifications_map_back_to_their_signature() {
+        let processed = json!({ "context": { "slot": 5 }, "value": { "err": null } });
+        let (url, server) = mock_pubsub_server(2, vec![
+            notification("signatureNotification", 101, processed.clone()),
+            notification("signatureNotification", 100, processed),
+        ]).await;
+
+        let mut source = RpcWebSocketSource::signatures("ws", url, vec!["sigA".to_string(), "sigB".to_string()]);
+        let keys = collect_keys(&mut source).await;
+
+        assert_eq!(keys, vec![
+            ArrivalKey::Signature("sigB".to_string()),
+            ArrivalKey::Signature("sigA".to_string()),
+        ]);
+        let requests = server.await.unwrap();
+        assert_eq!(requests[0]["method"], "signatureSubscribe");
+        assert_eq!(requests[0]["params"][0], "sigA");
+    }
+
+    #[tokio::test]
+    async fn test_rejected_subscription_is_an_error() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut socket = accept_async(stream).await.unwrap();
+            let Some(Ok(Message::Text(text))) = socket.next().await else {
+                return;
+            };
+            let request: Value = serde_json::from_str(&text).unwrap();
+            let response = json!({
+                "jsonrpc": "2.0",
+                "error": { "code": -32601, "message": "Method not found" },
+                "id": request["id"],
+            });
+            socket.send(Message::Text(response.to_string())).await.unwrap();
+        });
+
+        let mut source = RpcWebSocketSource::slots("ws", url);
+        source.connect().await.unwrap();
+        let event = source.next_event().await.unwrap();
+
+        assert!(matches!(event, Err(BenchmarkError::StreamError(_))));
+        assert_eq!(source.health().snapshot().errors, 1);
+    }
+}
@@ -0,0 +1,287 @@
+use super::{SourceEvent, SourceHealth, StreamSource};
+use crate::aggregator::ArrivalKey;
+use crate::error::{BenchmarkError, Result};
+use crate::events::{protocol_key, DexProtocol};
+use crate::grpc_client::{ChannelOptions, GrpcClient, GrpcCompression, UpdateStream};
+use crate::transport::FrameClock;
+use futures::future::BoxFuture;
+use futures::StreamExt;
+use prost::Message;
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+use std::time::Instant;
+use yellowstone_grpc_proto::prelude::{
+    subscribe_update::UpdateOneof, CommitmentLevel, SubscribeRequest, SubscribeRequestFilterBlocksMeta,
+    SubscribeRequestFilterTransactions, SubscribeUpdate,
+};
+
+/// Keys carried by one update. Usually one; a transaction seen by several filters can carry more.
+pub type UpdateKeyExtractor = Box<dyn Fn(&SubscribeUpdate) -> Vec<ArrivalKey> + Send + Sync>;
+
+pub fn slot_key(update: &SubscribeUpdate) -> Option<ArrivalKey> {
+    match &update.update_oneof {
+        Some(UpdateOneof::Slot(slot)) => Some(ArrivalKey::Slot(slot.slot)),
+        _ => None,
+    }
+}
+
+pub fn block_meta_key(update: &SubscribeUpdate) -> Option<ArrivalKey> {
+    match &update.update_oneof {
+        Some(UpdateOneof::BlockMeta(block_meta)) => Some(ArrivalKey::Slot(block_meta.slot)),
+        _ => None,
+    }
+}
+
+pub fn transaction_signature_key(update: &SubscribeUpdate) -> Option<ArrivalKey> {
+    match &update.update_oneof {
+        Some(UpdateOneof::Transaction(transaction)) => transaction
+            .transaction
+            .as_ref()
+            .map(|info| ArrivalKey::Signature(bs58::encode(&info.signature).into_string())),
+        _ => None,
+    }
+}
+
+/// One key per DEX protocol filter the transaction matched. Filters are named after the protocol.
+pub fn protocol_keys(update: &SubscribeUpdate) -> Vec<ArrivalKey> {
+    let Some(UpdateOneof::Transaction(transaction)) = &update.update_oneof else {
+        return Vec::new();
+    };
+    let Some(info) = &transaction.transaction else {
+        return Vec::new();
+    };
+    let signature = bs58::encode(&info.signature).into_string();
+    update
+        .filters
+        .iter()
+        .filter_map(|filter| DexProtocol::parse(filter))
+        .map(|protocol| protocol_key(signature.clone(), protocol))
+        .collect()
+}
+
+/// Raw Yellowstone gRPC subscription.
+pub struct YellowstoneSource {
+    name: String,
+    url: String,
+    token: Option<String>,
+    request: Option<SubscribeRequest>,
+    extractor: UpdateKeyExtractor,
+    frame_timestamps: bool,
+    channel: ChannelOptions,
+    pending: VecDeque<SourceEvent>,
+    stream: Option<UpdateStream>,
+    clock: Option<FrameClock>,
+    health: Arc<SourceHealth>,
+}
+
+impl YellowstoneSource {
+    pub fn new(
+        name: impl Into<String>,
+        url: impl Into<String>,
+        token: Option<String>,
+        request: SubscribeRequest,
+        extractor: UpdateKeyExtractor,
+    ) -> Self {
+        Self {
+            name: name.into(),
+            url: url.into(),
+            token,
+            request: Some(request),
+            extractor,
+            frame_timestamps: false,
+            channel: ChannelOptions::default(),
+            pending: VecDeque::new(),
+            stream: None,
+            clock: None,
+            health: Arc::new(SourceHealth::default()),
+        }
+    }
+
+    /// Processed slot updates keyed by slot.
+    pub fn slots(name: impl Into<String>, url: impl Into<String>, token: Option<String>) -> Self {
+        let extractor = Box::new(|update: &SubscribeUpdate| slot_key(update).into_iter().collect());
+        Self::new(name, url, token, GrpcClient::create_slot_subscription_request(), extractor)
+    }
+
+    /// Block meta updates keyed by slot.
+    pub fn block_meta(name: impl Into<String>, url: impl Into<String>, token: Option<String>) -> Self {
+        let mut blocks_meta = HashMap::new();
+        blocks_meta.insert("block_meta".to_string(), SubscribeRequestFilterBlocksMeta::default());
+        let request = SubscribeRequest {
+            blocks_meta,
+            commitment: Some(CommitmentLevel::Processed as i32),
+            ..Default::default()
+        };
+        let extractor = Box::new(|update: &SubscribeUpdate| block_meta_key(update).into_iter().collect());
+        Self::new(name, url, token, request, extractor)
+    }
+
+    /// Processed transactions mentioning any of `accounts`, keyed by signature.
+    /// Matches what `logsSubscribe` with a mention filter delivers over PubSub.
+    pub fn transactions(
+        name: impl Into<String>,
+        url: impl Into<String>,
+        token: Option<String>,
+        accounts: Vec<String>,
+    ) -> Self {
+        let mut transactions = HashMap::new();
+        transactions.insert(
+            "transactions".to_string(),
+            SubscribeRequestFilterTransactions {
+                vote: Some(false),
+                account_include: accounts,
+                ..Default::default()
+            },
+        );
+        Self::new(name, url, token, transaction_request(transactions), signature_extractor())
+    }
+
+    /// Specific transactions, keyed by signature. Counterpart of `signatureSubscribe`.
+    pub fn signatures(
+        name: impl Into<String>,
+        url: impl Into<String>,
+        token: Option<String>,
+        signatures: Vec<String>,
+    ) -> Self {
+        let transactions = signatures
+            .into_iter()
+            .map(|signature| {
+                let filter = SubscribeRequestFilterTransactions {
+                    signature: Some(signature.clone()),
+                    ..Default::default()
+                };
+                (signature, filter)
+            })
+            .collect();
+        Self::new(name, url, token, transaction_request(transactions), signature_extractor())
+    }
+
+    /// Transactions mentioning each protocol's program, keyed by signature and protocol.
+    pub fn protocols(
+        name: impl Into<String>,
+        url: impl Into<String>,
+        token: Option<String>,
+        protocols: &[DexProtocol],
+    ) -> Self {
+        let transactions = protocols
+            .iter()
+            .map(|protocol| {
+                let filter = SubscribeRequestFilterTransactions {
+                    vote: Some(false),
+                    failed: Some(false),
+                    account_include: vec![protocol.program_id().to_string()],
+                    ..Default::default()
+                };
+                (protocol.name().to_string(), filter)
+            })
+            .collect();
+        Self::new(name, url, token, transaction_request(transactions), Box::new(protocol_keys))
+    }
+
+    /// Stamp messages at HTTP/2 DATA frame poll time instead of after decoding.
+    pub fn with_frame_timestamps(mut self, enabled: bool) -> Self {
+        self.frame_timestamps = enabled;
+        self
+    }
+
+    /// Asks the server to compress responses. With frame timestamps on, the recorded bytes
+    /// are the compressed sizes on the wire.
+    pub fn with_compression(mut self, compression: GrpcCompression) -> Self {
+        self.channel.compression = compression;
+        self
+    }
+
+    /// Replaces all channel parameters, including compression.
+    pub fn with_channel_options(mut self, channel: ChannelOptions) -> Self {
+        self.channel = channel;
+        self
+    }
+}
+
+fn signature_extractor() -> UpdateKeyExtractor {
+    Box::new(|update: &SubscribeUpdate| transaction_signature_key(update).into_iter().collect())
+}
+
+fn transaction_request(transactions: HashMap<String, SubscribeRequestFilterTransactions>) -> SubscribeRequest {
+    SubscribeRequest {
+        transactions,
+        commitment: Some(CommitmentLevel::Processed as i32),
+        ..Default::default()
+    }
+}
+
+impl StreamSource for YellowstoneSource {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn target(&self) -> &str {
+        &self.url
+    }
+
+    fn health(&self) -> Arc<SourceHealth> {
+        self.health.clone()
+    }
+
+    fn connect(&mut self) -> BoxFuture<'_, Result<()>> {
+        Box::pin(async move {
+            let request = self.request.take()
+                .ok_or_else(|| BenchmarkError::ConfigError(format!("{} is already connected", self.name)))?;
+
+            if self.frame_timestamps {
+                let (stream, clock) =
+                    GrpcClient::subscribe_frame_stamped(&self.url, self.token.as_deref(), request, &self.channel).await?;
+                self.stream = Some(stream.boxed());
+                self.clock = Some(clock);
+            } else {
+                self.stream = Some(GrpcClient::subscribe(&self.url, self.token.as_deref(), request, &self.channel).await?);
+            }
+
+            self.health.set_connected(true);
+            Ok(())
+        })
+    }
+
+    fn next_event(&mut self) -> BoxFuture<'_, Option<Result<SourceEvent>>> {
+        Box::pin(async move {
+            let Self { stream, clock, extractor, pending, health, .. } = self;
+            let stream = stream.as_mut()?;
+
+            loop {
+                if let Some(event) = pending.pop_front() {
+                    return Some(Ok(event));
+                }
+
+                let Some(message) = stream.next().await else {
+                    health.set_connected(false);
+                    return None;
+                };
+                let decoded_at = Instant::now();
+                // 每条解码后的消息对应传输层记录的一个帧时间戳
+                let frame = clock.as_ref().and_then(|clock| clock.next_message());
+
+                match message {
+                    Ok(update) => {
+                        health.record_message();
+                        // 有帧时间戳时记录线上 (可能已压缩) 的字节数，否则记录 protobuf 编码大小
+                        let size = frame.map_or_else(|| update.encoded_len(), |frame| frame.size);
+                        health.record_bytes(size);
+                        // 消息大小只记在第一个 key 上，一条消息拆出多个 key 时不重复计算
+                        pending.extend(extractor(&update).into_iter().enumerate().map(|(i, key)| {
+                            let event = match &frame {
+                                Some(frame) => SourceEvent::stamped(key, frame.received_at, decoded_at),
+                                None => SourceEvent::new(key, decoded_at),
+                            };
+                            event.with_size(if i == 0 { size } else { 0 })
+                        }));
+                    }
+                    Err(status) => {
+                        health.record_error();
+                        health.set_connected(false);
+                        return Some(Err(status.into()));
+                    }
+                }
+            }
+        })
+    }
+}
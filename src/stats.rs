@@ -1,6 +1,8 @@
+use crate::output::ColoredOutput;
 use serde::{Deserialize, Serialize};
 use statistical::{mean, median, standard_deviation};
 use std::collections::HashMap;
+use std::time::Duration;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct LatencyStats {
@@ -56,7 +58,8 @@ impl LatencyStats {
         
         self.mean = mean(&self.latencies);
         self.median = median(&self.latencies);
-        self.std_dev = standard_deviation(&self.latencies, Some(self.mean));
+        // statistical 在少于两个样本时会 panic
+        self.std_dev = if self.latencies.len() > 1 { standard_deviation(&self.latencies, Some(self.mean)) } else { 0.0 };
         self.p90 = percentile(&self.latencies, 0.90);
         self.p99 = percentile(&self.latencies, 0.99);
     }
@@ -112,41 +115,119 @@ pub fn percentile(data: &[f64], p: f64) -> f64 {
     data[index]
 }
 
+/// Where one arrival of a resolved key placed against the earliest arrival.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Placement {
+    /// Earliest, and ahead of every other endpoint by at least the tie tolerance.
+    First,
+    /// Within the tie tolerance of the earliest arrival, including the earliest one itself.
+    Tied,
+    /// Behind the earliest arrival by the tie tolerance or more.
+    Delayed,
+}
+
+/// Per-endpoint results. Every scored key is exactly one of `first`, `tied` or `delayed`, so
+/// their shares of `scored` add up to 100%.
 #[derive(Debug, Clone)]
 pub struct EndpointStats {
+    /// Messages from this endpoint, scored or not: warm-up, duplicates and late arrivals included.
+    pub received: usize,
+    /// Keys this endpoint delivered that were scored.
+    pub scored: usize,
+    pub first: usize,
+    pub tied: usize,
+    pub delayed: usize,
+    /// Keys that timed out before this endpoint delivered them.
+    pub missed: usize,
+    /// Sum of `latencies`.
     pub total_latency: f64,
+    /// Latency behind the earliest arrival of every delayed key, in ms.
     pub latencies: Vec<f64>,
-    pub first_received: usize,
-    pub total_received: usize,
     pub is_available: bool,
     pub has_received_data: bool,
     pub first_slot: Option<u64>,
+    /// Frame poll to decoded message, in ms; only filled when stamping at the transport layer.
+    pub decode_times: Vec<f64>,
+    /// How far behind the newest slot the endpoint was; empty unless racing slots.
+    pub slot_lag: SlotLag,
+}
+
+/// Slots an endpoint was behind the tip (the newest slot of any endpoint), sampled every time
+/// the tip advances.
+#[derive(Debug, Clone, Default)]
+pub struct SlotLag {
+    pub samples: u64,
+    pub total: u64,
+    pub max: u64,
+    /// Time spent more than the allowed slot difference behind.
+    pub lagging_for: Duration,
+}
+
+impl SlotLag {
+    pub fn add(&mut self, lag: u64) {
+        self.samples += 1;
+        self.total += lag;
+        self.max = self.max.max(lag);
+    }
+
+    pub fn average(&self) -> f64 {
+        if self.samples == 0 {
+            0.0
+        } else {
+            self.total as f64 / self.samples as f64
+        }
+    }
 }
 
 impl EndpointStats {
     pub fn new() -> Self {
         Self {
+            received: 0,
+            scored: 0,
+            first: 0,
+            tied: 0,
+            delayed: 0,
+            missed: 0,
             total_latency: 0.0,
             latencies: Vec::new(),
-            first_received: 0,
-            total_received: 0,
             is_available: true,
             has_received_data: false,
             first_slot: None,
+            decode_times: Vec::new(),
+            slot_lag: SlotLag::default(),
         }
     }
 
-    pub fn add_latency(&mut self, latency: f64) {
-        self.latencies.push(latency);
-        self.total_latency += latency;
+    pub fn increment_received(&mut self) {
+        self.received += 1;
+    }
+
+    /// Scores one arrival; `latency` is behind the earliest arrival, in ms.
+    pub fn record(&mut self, placement: Placement, latency: f64) {
+        self.scored += 1;
+        match placement {
+            Placement::First => self.first += 1,
+            Placement::Tied => self.tied += 1,
+            Placement::Delayed => {
+                self.delayed += 1;
+                self.latencies.push(latency);
+                self.total_latency += latency;
+            }
+        }
     }
 
-    pub fn increment_first_received(&mut self) {
-        self.first_received += 1;
+    pub fn increment_missed(&mut self) {
+        self.missed += 1;
     }
 
-    pub fn increment_total_received(&mut self) {
-        self.total_received += 1;
+    /// Share of keys, received or missed, that this endpoint missed.
+    pub fn get_miss_rate(&self) -> f64 {
+        let keys = self.scored + self.missed;
+        if keys == 0 {
+            0.0
+        } else {
+            self.missed as f64 / keys as f64 * 100.0
+        }
     }
 
     pub fn get_average_latency(&self) -> f64 {
@@ -157,17 +238,79 @@ impl EndpointStats {
         }
     }
 
-    pub fn get_first_received_percentage(&self) -> f64 {
-        if self.total_received == 0 {
+    /// Mean latency over every scored key, counting first and tied arrivals as 0.
+    pub fn get_overall_average_latency(&self) -> f64 {
+        if self.scored == 0 {
             0.0
         } else {
-            (self.first_received as f64 / self.total_received as f64) * 100.0
+            self.total_latency / self.scored as f64
         }
     }
 
+    /// Share of scored keys, in percent.
+    pub fn percent_of_scored(&self, count: usize) -> f64 {
+        if self.scored == 0 {
+            0.0
+        } else {
+            count as f64 / self.scored as f64 * 100.0
+        }
+    }
+
+    pub fn get_first_percentage(&self) -> f64 {
+        self.percent_of_scored(self.first)
+    }
+
     pub fn get_stats(&self) -> LatencyStats {
         calculate_stats(&self.latencies)
     }
+
+    pub fn add_decode_time(&mut self, decode_time: f64) {
+        self.decode_times.push(decode_time);
+    }
+
+    pub fn get_decode_stats(&self) -> LatencyStats {
+        calculate_stats(&self.decode_times)
+    }
+
+    /// Per-endpoint section of a comparison summary; `noun` and `unit` name what was raced.
+    pub fn print_summary(&self, name: &str, noun: &str, unit: &str) {
+        let output = ColoredOutput::new();
+        output.subheader(&format!("📊 {} 性能分析", name));
+        output.metric(&format!("总接收{}数", noun), &self.received.to_string(), unit);
+        output.metric(&format!("计分{}数", noun), &self.scored.to_string(), unit);
+        output.metric(&format!("首先接收{}数", noun), &format!("{} ({:.2}%)", self.first, self.get_first_percentage()), unit);
+        output.metric(&format!("同时接收{}数", noun), &format!("{} ({:.2}%)", self.tied, self.percent_of_scored(self.tied)), unit);
+        output.metric(&format!("落后接收{}数", noun), &format!("{} ({:.2}%)", self.delayed, self.percent_of_scored(self.delayed)), unit);
+        output.metric(&format!("超时未收到{}数", noun), &format!("{} ({:.2}%)", self.missed, self.get_miss_rate()), unit);
+
+        if !self.latencies.is_empty() {
+            let latency = self.get_stats();
+            output.info("延迟统计 (相对于最快端点):");
+            output.metric("  平均延迟", &format!("{:.2}", self.get_average_latency()), "ms");
+            output.metric("  最小延迟", &format!("{:.2}", latency.min), "ms");
+            output.metric("  最大延迟", &format!("{:.2}", latency.max), "ms");
+        } else {
+            output.success("该端点始终是最快的，没有延迟数据");
+        }
+
+        if !self.decode_times.is_empty() {
+            let decode = self.get_decode_stats();
+            output.info("解码耗时 (帧拉取 → 消息解码完成):");
+            output.metric("  平均耗时", &format!("{:.3}", decode.mean), "ms");
+            output.metric("  p99", &format!("{:.3}", decode.p99), "ms");
+            output.metric("  最大耗时", &format!("{:.3}", decode.max), "ms");
+        }
+
+        // 每个新 slot 出现时各端点落后的区块数
+        if self.slot_lag.samples > 0 {
+            output.info("落后最新slot (每出现一个新slot采样一次):");
+            output.metric("  平均", &format!("{:.2}", self.slot_lag.average()), "slots");
+            output.metric("  最大", &self.slot_lag.max.to_string(), "slots");
+            if !self.slot_lag.lagging_for.is_zero() {
+                output.metric("  超过阈值", &format!("{:.1}", self.slot_lag.lagging_for.as_secs_f64()), "s");
+            }
+        }
+    }
 }
 
 impl Default for EndpointStats {
@@ -0,0 +1,247 @@
+use crate::source::{HealthSnapshot, SourceHealth};
+use colored::*;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// How often the engine samples endpoint counters.
+pub const SAMPLE_INTERVAL: Duration = Duration::from_millis(100);
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Rate {
+    pub messages_per_sec: f64,
+    pub bytes_per_sec: f64,
+}
+
+impl Rate {
+    fn between(from: (Instant, u64, u64), to: (Instant, u64, u64)) -> Self {
+        let secs = to.0.saturating_duration_since(from.0).as_secs_f64();
+        if secs <= 0.0 {
+            return Rate::default();
+        }
+        Rate {
+            messages_per_sec: to.1.saturating_sub(from.1) as f64 / secs,
+            bytes_per_sec: to.2.saturating_sub(from.2) as f64 / secs,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ThroughputSnapshot {
+    pub messages: u64,
+    pub bytes: u64,
+    /// Over the whole run.
+    pub average: Rate,
+    /// Over the shortest window (or the run so far, while it is shorter).
+    pub current: Rate,
+    /// Highest rate seen over each full window; messages and bytes peak independently.
+    pub peaks: Vec<(Duration, Rate)>,
+}
+
+/// Message and byte rates of one endpoint, fed with cumulative counters.
+#[derive(Debug)]
+pub struct ThroughputMeter {
+    windows: Vec<Duration>,
+    peaks: Vec<Rate>,
+    first: Option<(Instant, u64, u64)>,
+    samples: VecDeque<(Instant, u64, u64)>,
+}
+
+impl ThroughputMeter {
+    pub fn new(mut windows: Vec<Duration>) -> Self {
+        windows.retain(|w| !w.is_zero());
+        windows.sort();
+        windows.dedup();
+        Self {
+            peaks: vec![Rate::default(); windows.len()],
+            windows,
+            first: None,
+            samples: VecDeque::new(),
+        }
+    }
+
+    pub fn sample(&mut self, now: Instant, messages: u64, bytes: u64) {
+        let sample = (now, messages, bytes);
+        self.first.get_or_insert(sample);
+        self.samples.push_back(sample);
+
+        for (window, peak) in self.windows.iter().zip(self.peaks.iter_mut()) {
+            // 以窗口起点之前最近的样本为基准，窗口未满时不计峰值
+            let Some(anchor) = self.samples.iter().rev().find(|(t, _, _)| now.saturating_duration_since(*t) >= *window)
+            else {
+                continue;
+            };
+            let rate = Rate::between(*anchor, sample);
+            peak.messages_per_sec = peak.messages_per_sec.max(rate.messages_per_sec);
+            peak.bytes_per_sec = peak.bytes_per_sec.max(rate.bytes_per_sec);
+        }
+
+        // 只保留最长窗口所需的样本 (外加一个窗口起点之前的基准样本)
+        let longest = self.windows.last().copied().unwrap_or_default();
+        while self.samples.len() > 2 && now.saturating_duration_since(self.samples[1].0) >= longest {
+            self.samples.pop_front();
+        }
+    }
+
+    pub fn snapshot(&self) -> ThroughputSnapshot {
+        let (Some(first), Some(last)) = (self.first, self.samples.back().copied()) else {
+            return ThroughputSnapshot {
+                peaks: self.windows.iter().map(|w| (*w, Rate::default())).collect(),
+                ..Default::default()
+            };
+        };
+
+        let shortest = self.windows.first().copied().unwrap_or_default();
+        let current_anchor = self
+            .samples
+            .iter()
+            .rev()
+            .find(|(t, _, _)| last.0.saturating_duration_since(*t) >= shortest)
+            .or(self.samples.front())
+            .copied()
+            .unwrap_or(first);
+
+        ThroughputSnapshot {
+            messages: last.1,
+            bytes: last.2,
+            average: Rate::between(first, last),
+            current: Rate::between(current_anchor, last),
+            peaks: self.windows.iter().copied().zip(self.peaks.iter().copied()).collect(),
+        }
+    }
+}
+
+struct EndpointMeter {
+    name: String,
+    health: Arc<SourceHealth>,
+    meter: ThroughputMeter,
+}
+
+/// Samples every endpoint's `SourceHealth` into a `ThroughputMeter`. Cheap to clone.
+#[derive(Clone)]
+pub struct ThroughputMonitor {
+    meters: Arc<Mutex<Vec<EndpointMeter>>>,
+}
+
+impl ThroughputMonitor {
+    pub fn new(health: &[(String, Arc<SourceHealth>)], windows: &[Duration]) -> Self {
+        let meters = health
+            .iter()
+            .map(|(name, health)| EndpointMeter {
+                name: name.clone(),
+                health: health.clone(),
+                meter: ThroughputMeter::new(windows.to_vec()),
+            })
+            .collect();
+        Self {
+            meters: Arc::new(Mutex::new(meters)),
+        }
+    }
+
+    pub fn sample(&self) {
+        let now = Instant::now();
+        let mut meters = self.meters.lock().unwrap();
+        for endpoint in meters.iter_mut() {
+            let snapshot = endpoint.health.snapshot();
+            endpoint.meter.sample(now, snapshot.messages, snapshot.bytes);
+        }
+    }
+
+    /// Per-endpoint throughput in configuration order.
+    pub fn snapshot(&self) -> Vec<(String, ThroughputSnapshot)> {
+        let meters = self.meters.lock().unwrap();
+        meters.iter().map(|e| (e.name.clone(), e.meter.snapshot())).collect()
+    }
+
+    /// Current connection state and counters of every endpoint, in configuration order.
+    pub fn health(&self) -> Vec<(String, HealthSnapshot)> {
+        let meters = self.meters.lock().unwrap();
+        meters.iter().map(|e| (e.name.clone(), e.health.snapshot())).collect()
+    }
+}
+
+/// Human-readable byte rate, e.g. `1.25MB/s`.
+pub fn format_byte_rate(bytes_per_sec: f64) -> String {
+    if bytes_per_sec >= 1_048_576.0 {
+        format!("{:.2}MB/s", bytes_per_sec / 1_048_576.0)
+    } else if bytes_per_sec >= 1024.0 {
+        format!("{:.1}KB/s", bytes_per_sec / 1024.0)
+    } else {
+        format!("{:.0}B/s", bytes_per_sec)
+    }
+}
+
+/// Window length for display, e.g. `1s` or `500ms`.
+pub fn format_window(window: Duration) -> String {
+    if window.subsec_millis() == 0 {
+        format!("{}s", window.as_secs())
+    } else {
+        format!("{}ms", window.as_millis())
+    }
+}
+
+/// One endpoint's rate for display; sources that only see parsed events have no byte count.
+pub fn format_throughput(throughput: &ThroughputSnapshot, rate: Rate) -> String {
+    if throughput.bytes > 0 {
+        format!("{:8.1} 条/秒, {:>10}", rate.messages_per_sec, format_byte_rate(rate.bytes_per_sec))
+    } else {
+        format!("{:8.1} 条/秒, {:>10}", rate.messages_per_sec, "-")
+    }
+}
+
+/// Prints each endpoint's totals, average rate and peak rate per window.
+pub fn print_throughput(throughput: &[(String, ThroughputSnapshot)], width: usize) {
+    println!("{}", "📶 吞吐统计".yellow().bold());
+    println!("{}", "-".repeat(28).yellow());
+
+    for (name, snapshot) in throughput {
+        println!(
+            "{:width$} : 共 {} 条, {:.2}MB, 平均 {}",
+            name,
+            snapshot.messages,
+            snapshot.bytes as f64 / 1_048_576.0,
+            format_throughput(snapshot, snapshot.average),
+            width = width
+        );
+        for (window, peak) in &snapshot.peaks {
+            println!(
+                "{:width$}   峰值 ({:>5}): {}",
+                "",
+                format_window(*window),
+                format_throughput(snapshot, *peak),
+                width = width
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rates_and_peak_over_full_window() {
+        let start = Instant::now();
+        let mut meter = ThroughputMeter::new(vec![Duration::from_secs(1), Duration::from_secs(2)]);
+        let at = |ms: u64| start + Duration::from_millis(ms);
+
+        // 前 1 秒每秒 100 条，之后 1 秒突发 400 条，最后 1 秒空闲
+        meter.sample(at(0), 0, 0);
+        meter.sample(at(500), 50, 5_000);
+        meter.sample(at(1000), 100, 10_000);
+        meter.sample(at(1500), 300, 30_000);
+        meter.sample(at(2000), 500, 50_000);
+        meter.sample(at(3000), 500, 50_000);
+
+        let snapshot = meter.snapshot();
+        assert_eq!(snapshot.messages, 500);
+        assert!((snapshot.average.messages_per_sec - 500.0 / 3.0).abs() < 1e-9);
+        assert_eq!(snapshot.current.messages_per_sec, 0.0);
+
+        let (window, peak) = snapshot.peaks[0];
+        assert_eq!(window, Duration::from_secs(1));
+        assert_eq!(peak.messages_per_sec, 400.0);
+        assert_eq!(peak.bytes_per_sec, 40_000.0);
+        assert_eq!(snapshot.peaks[1].1.messages_per_sec, 250.0);
+    }
+}
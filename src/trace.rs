@@ -0,0 +1,410 @@
+use crate::aggregator::{Arrival, ArrivalKey};
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+const MAGIC: &[u8; 8] = b"GBTRACE\0";
+const VERSION: u8 = 1;
+
+const TAG_ARRIVAL: u8 = 1;
+const TAG_ENDPOINT_FAILED: u8 = 2;
+
+const KEY_SLOT: u8 = 0;
+const KEY_SIGNATURE: u8 = 1;
+const KEY_EVENT: u8 = 2;
+
+/// One record of a trace file.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TraceRecord {
+    Arrival(TracedArrival),
+    EndpointFailed { endpoint: usize, mono_ns: u64, reason: String },
+}
+
+impl TraceRecord {
+    pub fn mono_ns(&self) -> u64 {
+        match self {
+            TraceRecord::Arrival(arrival) => arrival.mono_ns,
+            TraceRecord::EndpointFailed { mono_ns, .. } => *mono_ns,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TracedArrival {
+    /// Index into the trace's endpoint table.
+    pub endpoint: usize,
+    pub key: ArrivalKey,
+    /// Monotonic time since the start of the run.
+    pub mono_ns: u64,
+    pub decode_ns: Option<u64>,
+    /// Encoded size of the message the key came in; 0 when unknown or already counted
+    /// for an earlier key of the same message.
+    pub size: u32,
+}
+
+/// Writes a compact binary arrival trace.
+///
+/// Layout: magic, version, wall clock of the run start (unix ns), the endpoint table,
+/// then tagged records. Integers are LEB128 varints and times are zigzag deltas from the
+/// previous record, so a slot arrival takes around 15 bytes. Wall time is not stored per
+/// record; it is the start wall clock plus the monotonic offset.
+pub struct TraceWriter<W: Write> {
+    out: W,
+    origin: Instant,
+    last_ns: u64,
+    records: u64,
+}
+
+impl TraceWriter<BufWriter<File>> {
+    pub fn create(path: impl AsRef<Path>, origin: Instant, endpoints: &[String]) -> io::Result<Self> {
+        Self::new(BufWriter::new(File::create(path)?), origin, endpoints)
+    }
+}
+
+impl<W: Write> TraceWriter<W> {
+    pub fn new(mut out: W, origin: Instant, endpoints: &[String]) -> io::Result<Self> {
+        // 起点的墙上时间由当前时间减去起点至今的单调时间得到
+        let wall = SystemTime::now().checked_sub(origin.elapsed()).unwrap_or(SystemTime::now());
+        let wall_ns = wall.duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos() as u64;
+
+        out.write_all(MAGIC)?;
+        out.write_all(&[VERSION])?;
+        out.write_all(&wall_ns.to_le_bytes())?;
+        write_varint(&mut out, endpoints.len() as u64)?;
+        for endpoint in endpoints {
+            write_bytes(&mut out, endpoint.as_bytes())?;
+        }
+        Ok(Self { out, origin, last_ns: 0, records: 0 })
+    }
+
+    fn write_time(&mut self, at: Instant) -> io::Result<()> {
+        let ns = at.saturating_duration_since(self.origin).as_nanos() as u64;
+        write_varint(&mut self.out, zigzag(ns as i64 - self.last_ns as i64))?;
+        self.last_ns = ns;
+        Ok(())
+    }
+
+    pub fn write_arrival(&mut self, endpoint: usize, arrival: &Arrival, size: u32) -> io::Result<()> {
+        self.out.write_all(&[TAG_ARRIVAL])?;
+        write_varint(&mut self.out, endpoint as u64)?;
+        write_key(&mut self.out, &arrival.key)?;
+        self.write_time(arrival.timestamp)?;
+        // 0 表示没有解码耗时，否则为纳秒数 + 1
+        let decode = arrival.decode_time.map_or(0, |d| d.as_nanos() as u64 + 1);
+        write_varint(&mut self.out, decode)?;
+        write_varint(&mut self.out, size as u64)?;
+        self.records += 1;
+        Ok(())
+    }
+
+    pub fn write_endpoint_failed(&mut self, endpoint: usize, at: Instant, reason: &str) -> io::Result<()> {
+        self.out.write_all(&[TAG_ENDPOINT_FAILED])?;
+        write_varint(&mut self.out, endpoint as u64)?;
+        self.write_time(at)?;
+        write_bytes(&mut self.out, reason.as_bytes())?;
+        self.records += 1;
+        Ok(())
+    }
+
+    /// Flushes and returns the number of records written.
+    pub fn finish(mut self) -> io::Result<u64> {
+        self.out.flush()?;
+        Ok(self.records)
+    }
+}
+
+impl<W: Write + Send + 'static> TraceWriter<W> {
+    /// Moves the writer to a blocking thread fed by the returned recorder. The task ends,
+    /// returning the record count, once every recorder clone has been dropped.
+    pub fn spawn(mut self) -> (TraceRecorder, JoinHandle<io::Result<u64>>) {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let task = tokio::task::spawn_blocking(move || {
+            while let Some(entry) = rx.blocking_recv() {
+                match entry {
+                    TraceEntry::Arrival { endpoint, arrival, size } => self.write_arrival(endpoint, &arrival, size)?,
+                    TraceEntry::EndpointFailed { endpoint, at, reason } => {
+                        self.write_endpoint_failed(endpoint, at, &reason)?
+                    }
+                }
+            }
+            self.finish()
+        });
+        (TraceRecorder { tx }, task)
+    }
+}
+
+enum TraceEntry {
+    Arrival { endpoint: usize, arrival: Arrival, size: u32 },
+    EndpointFailed { endpoint: usize, at: Instant, reason: String },
+}
+
+/// Sending side of a spawned `TraceWriter`. Never blocks; cloning is cheap.
+#[derive(Clone)]
+pub struct TraceRecorder {
+    tx: mpsc::UnboundedSender<TraceEntry>,
+}
+
+impl TraceRecorder {
+    pub fn arrival(&self, endpoint: usize, arrival: &Arrival, size: u32) {
+        let _ = self.tx.send(TraceEntry::Arrival { endpoint, arrival: arrival.clone(), size });
+    }
+
+    pub fn endpoint_failed(&self, endpoint: usize, reason: &str) {
+        let _ = self.tx.send(TraceEntry::EndpointFailed {
+            endpoint,
+            at: Instant::now(),
+            reason: reason.to_string(),
+        });
+    }
+}
+
+/// Reads a trace written by `TraceWriter`.
+pub struct TraceReader<R: Read> {
+    input: R,
+    endpoints: Vec<String>,
+    origin_wall_ns: u64,
+    last_ns: u64,
+}
+
+impl TraceReader<BufReader<File>> {
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::new(BufReader::new(File::open(path)?))
+    }
+}
+
+impl<R: Read> TraceReader<R> {
+    pub fn new(mut input: R) -> io::Result<Self> {
+        let mut magic = [0u8; 8];
+        input.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(invalid("not an arrival trace"));
+        }
+        let mut version = [0u8; 1];
+        input.read_exact(&mut version)?;
+        if version[0] != VERSION {
+            return Err(invalid(format!("unsupported trace version {}", version[0])));
+        }
+        let mut wall = [0u8; 8];
+        input.read_exact(&mut wall)?;
+
+        let count = read_varint(&mut input)?;
+        let endpoints = (0..count).map(|_| read_string(&mut input)).collect::<io::Result<_>>()?;
+        Ok(Self { input, endpoints, origin_wall_ns: u64::from_le_bytes(wall), last_ns: 0 })
+    }
+
+    pub fn endpoints(&self) -> &[String] {
+        &self.endpoints
+    }
+
+    /// Unix time of the run start in ns.
+    pub fn origin_wall_ns(&self) -> u64 {
+        self.origin_wall_ns
+    }
+
+    /// Unix time in ns of a record at `mono_ns`.
+    pub fn wall_ns(&self, mono_ns: u64) -> u64 {
+        self.origin_wall_ns + mono_ns
+    }
+
+    fn read_time(&mut self) -> io::Result<u64> {
+        let delta = unzigzag(read_varint(&mut self.input)?);
+        self.last_ns = (self.last_ns as i64 + delta).max(0) as u64;
+        Ok(self.last_ns)
+    }
+
+    fn read_endpoint(&mut self) -> io::Result<usize> {
+        let endpoint = read_varint(&mut self.input)? as usize;
+        if endpoint >= self.endpoints.len() {
+            return Err(invalid(format!("endpoint id {} out of range", endpoint)));
+        }
+        Ok(endpoint)
+    }
+
+    /// `Ok(None)` at the end of the file. A record cut off by a crash is an `UnexpectedEof`.
+    pub fn next_record(&mut self) -> io::Result<Option<TraceRecord>> {
+        let mut tag = [0u8; 1];
+        if self.input.read(&mut tag)? == 0 {
+            return Ok(None);
+        }
+        match tag[0] {
+            TAG_ARRIVAL => {
+                let endpoint = self.read_endpoint()?;
+                let key = read_key(&mut self.input)?;
+                let mono_ns = self.read_time()?;
+                let decode_ns = read_varint(&mut self.input)?.checked_sub(1);
+                let size = read_varint(&mut self.input)? as u32;
+                Ok(Some(TraceRecord::Arrival(TracedArrival { endpoint, key, mono_ns, decode_ns, size })))
+            }
+            TAG_ENDPOINT_FAILED => {
+                let endpoint = self.read_endpoint()?;
+                let mono_ns = self.read_time()?;
+                let reason = read_string(&mut self.input)?;
+                Ok(Some(TraceRecord::EndpointFailed { endpoint, mono_ns, reason }))
+            }
+            tag => Err(invalid(format!("unknown record tag {}", tag))),
+        }
+    }
+}
+
+impl<R: Read> Iterator for TraceReader<R> {
+    type Item = io::Result<TraceRecord>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_record().transpose()
+    }
+}
+
+impl TracedArrival {
+    /// The arrival with its monotonic offset applied to `origin`.
+    pub fn to_arrival(&self, endpoints: &[String], origin: Instant) -> Arrival {
+        Arrival {
+            endpoint: endpoints[self.endpoint].clone(),
+            key: self.key.clone(),
+            timestamp: origin + Duration::from_nanos(self.mono_ns),
+            decode_time: self.decode_ns.map(Duration::from_nanos),
+        }
+    }
+}
+
+fn invalid(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
+
+fn zigzag(value: i64) -> u64 {
+    ((value << 1) ^ (value >> 63)) as u64
+}
+
+fn unzigzag(value: u64) -> i64 {
+    ((value >> 1) as i64) ^ -((value & 1) as i64)
+}
+
+fn write_varint(out: &mut impl Write, mut value: u64) -> io::Result<()> {
+    let mut buf = [0u8; 10];
+    let mut len = 0;
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            buf[len] = byte;
+            len += 1;
+            break;
+        }
+        buf[len] = byte | 0x80;
+        len += 1;
+    }
+    out.write_all(&buf[..len])
+}
+
+fn read_varint(input: &mut impl Read) -> io::Result<u64> {
+    let mut value = 0u64;
+    for shift in (0..64).step_by(7) {
+        let mut byte = [0u8; 1];
+        input.read_exact(&mut byte)?;
+        value |= ((byte[0] & 0x7f) as u64) << shift;
+        if byte[0] & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err(invalid("varint too long"))
+}
+
+fn write_bytes(out: &mut impl Write, bytes: &[u8]) -> io::Result<()> {
+    write_varint(out, bytes.len() as u64)?;
+    out.write_all(bytes)
+}
+
+fn read_string(input: &mut impl Read) -> io::Result<String> {
+    let len = read_varint(input)? as usize;
+    let mut bytes = vec![0u8; len];
+    input.read_exact(&mut bytes)?;
+    String::from_utf8(bytes).map_err(|e| invalid(e.to_string()))
+}
+
+fn write_key(out: &mut impl Write, key: &ArrivalKey) -> io::Result<()> {
+    match key {
+        ArrivalKey::Slot(slot) => {
+            out.write_all(&[KEY_SLOT])?;
+            write_varint(out, *slot)
+        }
+        ArrivalKey::Signature(signature) => {
+            out.write_all(&[KEY_SIGNATURE])?;
+            write_bytes(out, signature.as_bytes())
+        }
+        ArrivalKey::Event { signature, kind } => {
+            out.write_all(&[KEY_EVENT])?;
+            write_bytes(out, signature.as_bytes())?;
+            write_bytes(out, kind.as_bytes())
+        }
+    }
+}
+
+fn read_key(input: &mut impl Read) -> io::Result<ArrivalKey> {
+    let mut kind = [0u8; 1];
+    input.read_exact(&mut kind)?;
+    match kind[0] {
+        KEY_SLOT => Ok(ArrivalKey::Slot(read_varint(input)?)),
+        KEY_SIGNATURE => Ok(ArrivalKey::Signature(read_string(input)?)),
+        KEY_EVENT => {
+            let signature = read_string(input)?;
+            let kind = read_string(input)?;
+            Ok(ArrivalKey::Event { signature, kind })
+        }
+        other => Err(invalid(format!("unknown key type {}", other))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip_with_out_of_order_times() {
+        let origin = Instant::now();
+        let endpoints = vec!["A".to_string(), "B".to_string()];
+        let arrival = |endpoint: &str, key: ArrivalKey, ms: u64, decode: Option<u64>| Arrival {
+            endpoint: endpoint.to_string(),
+            key,
+            timestamp: origin + Duration::from_millis(ms),
+            decode_time: decode.map(Duration::from_micros),
+        };
+
+        let mut bytes = Vec::new();
+        let mut writer = TraceWriter::new(&mut bytes, origin, &endpoints).unwrap();
+        writer.write_arrival(0, &arrival("A", ArrivalKey::Slot(100), 5, None), 120).unwrap();
+        // B 的时间戳早于上一条记录，差值为负
+        writer.write_arrival(1, &arrival("B", ArrivalKey::Slot(100), 3, Some(0)), 0).unwrap();
+        let event = ArrivalKey::Event { signature: "sig".to_string(), kind: "PumpFunBuy".to_string() };
+        writer.write_arrival(1, &arrival("B", event.clone(), 9, Some(40)), 512).unwrap();
+        writer.write_endpoint_failed(0, origin + Duration::from_millis(10), "stream ended").unwrap();
+        assert_eq!(writer.finish().unwrap(), 4);
+
+        let mut reader = TraceReader::new(bytes.as_slice()).unwrap();
+        assert_eq!(reader.endpoints(), endpoints.as_slice());
+        let records: Vec<_> = reader.by_ref().collect::<io::Result<_>>().unwrap();
+        assert_eq!(records.len(), 4);
+        assert_eq!(
+            records[1],
+            TraceRecord::Arrival(TracedArrival {
+                endpoint: 1,
+                key: ArrivalKey::Slot(100),
+                mono_ns: 3_000_000,
+                decode_ns: Some(0),
+                size: 0
+            })
+        );
+        let TraceRecord::Arrival(traced) = &records[2] else { panic!("expected arrival") };
+        assert_eq!((traced.key.clone(), traced.decode_ns, traced.size), (event, Some(40_000), 512));
+        assert_eq!(
+            records[3],
+            TraceRecord::EndpointFailed { endpoint: 0, mono_ns: 10_000_000, reason: "stream ended".to_string() }
+        );
+
+        // 截断的记录报告为 UnexpectedEof
+        let mut truncated = TraceReader::new(&bytes[..bytes.len() - 3]).unwrap();
+        let error = truncated.by_ref().find_map(|r| r.err()).unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+    }
+}
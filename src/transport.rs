@@ -0,0 +1,231 @@
+use bytes::Bytes;
+use http_body::{Body, Frame, SizeHint};
+use std::collections::VecDeque;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::Instant;
+use tower::{Layer, Service};
+
+/// gRPC length-prefixed message header: 1 byte compression flag + 4 bytes big-endian length.
+const GRPC_HEADER_LEN: usize = 5;
+
+/// Arrival time of one gRPC message, taken when the DATA frame completing it was polled.
+#[derive(Debug, Clone, Copy)]
+pub struct MessageStamp {
+    pub received_at: Instant,
+    /// Encoded size on the wire, including the 5 byte gRPC header.
+    pub size: usize,
+}
+
+/// Queue of message stamps shared between the transport layer and the stream consumer.
+///
+/// Messages are decoded in the order their bytes arrive, so the consumer pops exactly
+/// one stamp for every message yielded by the decoded stream.
+#[derive(Debug, Clone, Default)]
+pub struct FrameClock {
+    stamps: Arc<Mutex<VecDeque<MessageStamp>>>,
+}
+
+impl FrameClock {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn next_message(&self) -> Option<MessageStamp> {
+        self.stamps.lock().unwrap().pop_front()
+    }
+
+    fn push(&self, stamp: MessageStamp) {
+        self.stamps.lock().unwrap().push_back(stamp);
+    }
+}
+
+/// Splits a byte stream into gRPC messages without decoding them.
+#[derive(Debug, Default)]
+struct MessageSplitter {
+    header: [u8; GRPC_HEADER_LEN],
+    header_len: usize,
+    message_len: usize,
+    remaining: usize,
+}
+
+impl MessageSplitter {
+    fn feed(&mut self, mut data: &[u8], received_at: Instant, clock: &FrameClock) {
+        while !data.is_empty() {
+            if self.header_len < GRPC_HEADER_LEN {
+                let n = (GRPC_HEADER_LEN - self.header_len).min(data.len());
+                self.header[self.header_len..self.header_len + n].copy_from_slice(&data[..n]);
+                self.header_len += n;
+                data = &data[n..];
+
+                if self.header_len == GRPC_HEADER_LEN {
+                    let len = u32::from_be_bytes([self.header[1], self.header[2], self.header[3], self.header[4]]);
+                    self.message_len = len as usize;
+                    self.remaining = self.message_len;
+                    if self.remaining == 0 {
+                        self.finish(received_at, clock);
+                    }
+                }
+                continue;
+            }
+
+            let n = self.remaining.min(data.len());
+            self.remaining -= n;
+            data = &data[n..];
+            if self.remaining == 0 {
+                self.finish(received_at, clock);
+            }
+        }
+    }
+
+    fn finish(&mut self, received_at: Instant, clock: &FrameClock) {
+        clock.push(MessageStamp {
+            received_at,
+            size: self.message_len + GRPC_HEADER_LEN,
+        });
+        self.header_len = 0;
+        self.message_len = 0;
+    }
+}
+
+/// Response body that stamps every DATA frame at poll time, before the gRPC decoder sees it.
+///
+/// Poll time is when the decoder's task pulls the frame out of h2, so it includes any wait on
+/// that task; it excludes decompression and protobuf decoding.
+pub struct FrameStampBody<B> {
+    inner: B,
+    splitter: Option<MessageSplitter>,
+    clock: FrameClock,
+}
+
+impl<B> Body for FrameStampBody<B>
+where
+    B: Body<Data = Bytes> + Unpin,
+{
+    type Data = Bytes;
+    type Error = B::Error;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let this = &mut *self;
+        let poll = Pin::new(&mut this.inner).poll_frame(cx);
+
+        if let (Poll::Ready(Some(Ok(frame))), Some(splitter)) = (&poll, this.splitter.as_mut()) {
+            if let Some(data) = frame.data_ref() {
+                splitter.feed(data, Instant::now(), &this.clock);
+            }
+        }
+
+        poll
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+/// Tower layer that records frame poll times of `Subscribe` responses into a `FrameClock`.
+#[derive(Debug, Clone)]
+pub struct FrameStampLayer {
+    clock: FrameClock,
+}
+
+impl FrameStampLayer {
+    pub fn new(clock: FrameClock) -> Self {
+        Self { clock }
+    }
+}
+
+impl<S> Layer<S> for FrameStampLayer {
+    type Service = FrameStampService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        FrameStampService {
+            inner,
+            clock: self.clock.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct FrameStampService<S> {
+    inner: S,
+    clock: FrameClock,
+}
+
+impl<S, ReqBody, ResBody> Service<http::Request<ReqBody>> for FrameStampService<S>
+where
+    S: Service<http::Request<ReqBody>, Response = http::Response<ResBody>>,
+    S::Future: Send + 'static,
+    S::Error: Send + 'static,
+    ResBody: Send + 'static,
+{
+    type Response = http::Response<FrameStampBody<ResBody>>;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: http::Request<ReqBody>) -> Self::Future {
+        // 只对订阅流打时间戳，健康检查等一元调用不计入
+        let stamp = request.uri().path().ends_with("/Subscribe");
+        let clock = self.clock.clone();
+        let response = self.inner.call(request);
+
+        Box::pin(async move {
+            let response = response.await?;
+            Ok(response.map(|inner| FrameStampBody {
+                inner,
+                splitter: stamp.then(MessageSplitter::default),
+                clock,
+            }))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(len: usize) -> Vec<u8> {
+        let mut data = vec![0u8];
+        data.extend_from_slice(&(len as u32).to_be_bytes());
+        data.extend(std::iter::repeat_n(7u8, len));
+        data
+    }
+
+    #[test]
+    fn test_splitter_handles_split_and_coalesced_frames() {
+        let clock = FrameClock::new();
+        let mut splitter = MessageSplitter::default();
+
+        let mut data = message(10);
+        data.extend(message(0));
+        data.extend(message(3));
+
+        let t0 = Instant::now();
+        let t1 = t0 + std::time::Duration::from_millis(1);
+        // 第一帧包含完整的第一条消息、空消息以及第三条消息的前两个字节
+        splitter.feed(&data[..22], t0, &clock);
+        splitter.feed(&data[22..], t1, &clock);
+
+        let stamps: Vec<_> = std::iter::from_fn(|| clock.next_message()).collect();
+        assert_eq!(stamps.len(), 3);
+        assert_eq!(stamps[0].size, 15);
+        assert_eq!(stamps[1].size, 5);
+        assert_eq!(stamps[2].size, 8);
+        assert_eq!(stamps[0].received_at, t0);
+        assert_eq!(stamps[1].received_at, t0);
+        assert_eq!(stamps[2].received_at, t1);
+    }
+}
@@ -0,0 +1,341 @@
+use crate::aggregator::AggregatorEvent;
+use crate::fanout::FanOut;
+use crate::history::EndpointSummary;
+use crate::source::HealthSnapshot;
+use crate::stats::{calculate_stats, EndpointStatsMap};
+use chrono::{DateTime, Local, SecondsFormat};
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+use std::fmt::Write as _;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tracing::warn;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct WindowEndpoint {
+    #[serde(flatten)]
+    pub summary: EndpointSummary,
+    pub p90_ms: f64,
+    /// Counted by the source during the window, scored or not.
+    pub messages: u64,
+    pub bytes: u64,
+    pub errors: u64,
+    /// Connection state when the window closed.
+    pub connected: bool,
+    /// Most slots behind the tip during the window; `None` unless racing slots.
+    pub max_slot_lag: Option<u64>,
+}
+
+/// Results of one closed window.
+#[derive(Debug, Clone, Serialize)]
+pub struct WindowReport {
+    pub index: u64,
+    /// RFC 3339, local time.
+    pub start: String,
+    pub end: String,
+    pub duration_sec: f64,
+    /// Keys resolved during the window.
+    pub keys: usize,
+    /// In configuration order.
+    pub endpoints: Vec<WindowEndpoint>,
+}
+
+/// Name suffix, help text and value of each per-endpoint gauge on `/metrics`.
+type Gauge = (&'static str, &'static str, fn(&WindowEndpoint) -> f64);
+
+const GAUGES: &[Gauge] = &[
+    ("first_share_percent", "Share of keys this endpoint delivered first.", |e| e.summary.first_percent()),
+    ("tie_share_percent", "Share of keys this endpoint delivered within the tie tolerance of the earliest.", |e| e.summary.tie_percent()),
+    ("scored", "Keys scored for this endpoint.", |e| e.summary.scored as f64),
+    ("miss_rate_percent", "Share of keys that timed out before this endpoint delivered them.", |e| e.summary.miss_percent()),
+    ("avg_behind_ms", "Mean latency behind the fastest endpoint, when behind.", |e| e.summary.avg_behind_ms),
+    ("overall_avg_ms", "Mean latency behind the fastest endpoint, first and tied arrivals as 0.", |e| e.summary.overall_avg_ms),
+    ("p50_ms", "Median latency when behind.", |e| e.summary.p50_ms),
+    ("p90_ms", "90th percentile latency when behind.", |e| e.p90_ms),
+    ("p99_ms", "99th percentile latency when behind.", |e| e.summary.p99_ms),
+    ("messages", "Messages counted by the source.", |e| e.messages as f64),
+    ("bytes", "Payload bytes counted by the source.", |e| e.bytes as f64),
+    ("errors", "Stream errors.", |e| e.errors as f64),
+    ("max_slot_lag", "Most slots behind the newest slot of any endpoint.", |e| e.max_slot_lag.unwrap_or(0) as f64),
+];
+
+/// Scores resolutions into consecutive fixed-length windows for runs without an end.
+///
+/// Window boundaries fall on wall-clock multiples of the length (e.g. :00, :05, :10 for five
+/// minutes), so the first window is usually shorter and windows line up across days and hosts.
+#[derive(Debug)]
+pub struct RollingWindows {
+    length: Duration,
+    retention: usize,
+    endpoints: Vec<String>,
+    next_index: u64,
+    start: DateTime<Local>,
+    boundary: Instant,
+    stats: EndpointStatsMap,
+    keys: usize,
+    slot_lags: HashMap<String, u64>,
+    last_health: HashMap<String, HealthSnapshot>,
+    closed: VecDeque<WindowReport>,
+    fanout: FanOut,
+}
+
+impl RollingWindows {
+    /// Keeps the last `retention` closed windows.
+    pub fn new(endpoints: Vec<String>, length: Duration, retention: usize, now: Instant, wall: DateTime<Local>) -> Self {
+        let length_ms = length.as_millis().max(1) as i64;
+        let until_boundary = length_ms - wall.timestamp_millis().rem_euclid(length_ms);
+        Self {
+            length,
+            retention: retention.max(1),
+            endpoints,
+            next_index: 1,
+            start: wall,
+            boundary: now + Duration::from_millis(until_boundary as u64),
+            stats: EndpointStatsMap::new(),
+            keys: 0,
+            slot_lags: HashMap::new(),
+            last_health: HashMap::new(),
+            closed: VecDeque::new(),
+            fanout: FanOut::default(),
+        }
+    }
+
+    /// Also scores each fan-out group as one best-of-N endpoint, listed after the others.
+    pub fn with_fanout(mut self, fanout: FanOut) -> Self {
+        self.endpoints.extend(fanout.names().map(str::to_string));
+        self.fanout = fanout;
+        self
+    }
+
+    pub fn observe(&mut self, event: &AggregatorEvent) {
+        match event {
+            AggregatorEvent::Resolved(resolution) => {
+                resolution.score(&mut self.stats);
+                self.fanout.score_groups(resolution, &mut self.stats);
+                self.keys += 1;
+            }
+            AggregatorEvent::SlotLag { behind, .. } => {
+                for (endpoint, lag) in behind {
+                    let max = self.slot_lags.entry(endpoint.clone()).or_default();
+                    *max = (*max).max(*lag);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// When the current window should be closed.
+    pub fn boundary(&self) -> Instant {
+        self.boundary
+    }
+
+    /// Closes the current window and starts the next one. `health` gives the source counters
+    /// now; the window reports their change since the previous close.
+    pub fn close(&mut self, now: Instant, wall: DateTime<Local>, health: &[(String, HealthSnapshot)]) -> WindowReport {
+        let stats = std::mem::take(&mut self.stats);
+        let slot_lags = std::mem::take(&mut self.slot_lags);
+        let group_health = self.fanout.group_health(health);
+        let endpoints = self
+            .endpoints
+            .iter()
+            .map(|name| {
+                let stat = stats.get(name).cloned().unwrap_or_default();
+                let current = health
+                    .iter()
+                    .chain(&group_health)
+                    .find(|(n, _)| n == name)
+                    .map(|(_, h)| *h)
+                    .unwrap_or_default();
+                let previous = self.last_health.insert(name.clone(), current).unwrap_or_default();
+                WindowEndpoint {
+                    summary: EndpointSummary::from_stats(name, &stat),
+                    p90_ms: calculate_stats(&stat.latencies).p90,
+                    messages: current.messages.saturating_sub(previous.messages),
+                    bytes: current.bytes.saturating_sub(previous.bytes),
+                    errors: current.errors.saturating_sub(previous.errors),
+                    connected: current.connected,
+                    max_slot_lag: slot_lags.get(name).copied(),
+                }
+            })
+            .collect();
+
+        let report = WindowReport {
+            index: self.next_index,
+            start: self.start.to_rfc3339_opts(SecondsFormat::Secs, false),
+            end: wall.to_rfc3339_opts(SecondsFormat::Secs, false),
+            duration_sec: (wall - self.start).num_milliseconds() as f64 / 1000.0,
+            keys: std::mem::take(&mut self.keys),
+            endpoints,
+        };
+
+        self.next_index += 1;
+        self.start = wall;
+        // 从上一个边界推进而不是从当前时间，避免窗口逐渐漂移; 长时间挂起后跳过错过的边界
+        while self.boundary <= now {
+            self.boundary += self.length;
+        }
+        if self.closed.len() == self.retention {
+            self.closed.pop_front();
+        }
+        self.closed.push_back(report.clone());
+        report
+    }
+
+    pub fn latest(&self) -> Option<&WindowReport> {
+        self.closed.back()
+    }
+
+    /// Retained windows, oldest first.
+    pub fn retained(&self) -> impl Iterator<Item = &WindowReport> {
+        self.closed.iter()
+    }
+
+    /// The latest closed window in the Prometheus text exposition format.
+    pub fn to_prometheus(&self) -> String {
+        let mut out = String::new();
+        let Some(window) = self.latest() else {
+            return out;
+        };
+
+        for (name, help, value) in GAUGES {
+            let _ = writeln!(out, "# HELP grpc_benchmark_window_{} {} Last closed window.", name, help);
+            let _ = writeln!(out, "# TYPE grpc_benchmark_window_{} gauge", name);
+            for endpoint in &window.endpoints {
+                let label = endpoint.summary.endpoint.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n");
+                let _ = writeln!(out, "grpc_benchmark_window_{}{{endpoint=\"{}\"}} {}", name, label, value(endpoint));
+            }
+        }
+        let _ = writeln!(out, "# HELP grpc_benchmark_window_index Sequence number of the last closed window.");
+        let _ = writeln!(out, "# TYPE grpc_benchmark_window_index gauge");
+        let _ = writeln!(out, "grpc_benchmark_window_index {}", window.index);
+        let _ = writeln!(out, "# HELP grpc_benchmark_window_keys Keys resolved in the last closed window.");
+        let _ = writeln!(out, "# TYPE grpc_benchmark_window_keys gauge");
+        let _ = writeln!(out, "grpc_benchmark_window_keys {}", window.keys);
+        out
+    }
+}
+
+/// Serves `/metrics` (latest window, Prometheus format) and `/windows` (retained windows as
+/// JSON) until the task is dropped.
+pub async fn serve(listener: TcpListener, windows: Arc<Mutex<RollingWindows>>) {
+    loop {
+        let (stream, peer) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(e) => {
+                warn!("metrics listener accept failed: {}", e);
+                continue;
+            }
+        };
+        let windows = windows.clone();
+        tokio::spawn(async move {
+            if let Err(e) = respond(stream, &windows).await {
+                warn!("metrics request from {} failed: {}", peer, e);
+            }
+        });
+    }
+}
+
+async fn respond(mut stream: TcpStream, windows: &Mutex<RollingWindows>) -> std::io::Result<()> {
+    let mut request = Vec::new();
+    let mut buf = [0u8; 1024];
+    while !request.windows(4).any(|w| w == b"\r\n\r\n") && request.len() < 8192 {
+        let read = stream.read(&mut buf).await?;
+        if read == 0 {
+            break;
+        }
+        request.extend_from_slice(&buf[..read]);
+    }
+    let request = String::from_utf8_lossy(&request);
+    let path = request.split_whitespace().nth(1).unwrap_or("/");
+
+    let (status, content_type, body) = {
+        let windows = windows.lock().unwrap();
+        match path.split('?').next().unwrap_or(path) {
+            "/metrics" => ("200 OK", "text/plain; version=0.0.4", windows.to_prometheus()),
+            "/windows" => {
+                let retained: Vec<&WindowReport> = windows.retained().collect();
+                ("200 OK", "application/json", serde_json::to_string(&retained).unwrap_or_default())
+            }
+            _ => ("404 Not Found", "text/plain", "not found\n".to_string()),
+        }
+    };
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    );
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::aggregator::{Arrival, ArrivalKey, Resolution, DEFAULT_TIE_TOLERANCE};
+    use chrono::TimeZone;
+
+    fn resolved(slot: u64, order: &[(&str, u64)], t0: Instant) -> AggregatorEvent {
+        AggregatorEvent::Resolved(Resolution {
+            key: ArrivalKey::Slot(slot),
+            arrivals: order
+                .iter()
+                .map(|(endpoint, ms)| Arrival {
+                    endpoint: endpoint.to_string(),
+                    key: ArrivalKey::Slot(slot),
+                    timestamp: t0 + Duration::from_millis(*ms),
+                    decode_time: None,
+                })
+                .collect(),
+            timed_out: false,
+            missed: Vec::new(),
+            tie_tolerance: DEFAULT_TIE_TOLERANCE,
+        })
+    }
+
+    fn health(messages: u64, errors: u64) -> HealthSnapshot {
+        HealthSnapshot { connected: true, messages, errors, bytes: 0 }
+    }
+
+    #[test]
+    fn test_windows_align_roll_and_retain() {
+        let t0 = Instant::now();
+        // 12:03:30 开始，5 分钟窗口的第一个边界是 12:05:00
+        let wall = Local.with_ymd_and_hms(2024, 5, 1, 12, 3, 30).unwrap();
+        let names = vec!["A".to_string(), "B".to_string()];
+        let mut windows = RollingWindows::new(names, Duration::from_secs(300), 2, t0, wall);
+        assert_eq!(windows.boundary(), t0 + Duration::from_secs(90));
+
+        windows.observe(&resolved(1, &[("A", 0), ("B", 3)], t0));
+        windows.observe(&resolved(2, &[("B", 0), ("A", 1)], t0));
+        let first = windows.close(
+            t0 + Duration::from_secs(90),
+            wall + chrono::Duration::seconds(90),
+            &[("A".to_string(), health(10, 1)), ("B".to_string(), health(12, 0))],
+        );
+        assert_eq!((first.index, first.keys, first.duration_sec), (1, 2, 90.0));
+        assert_eq!(first.endpoints[0].summary.first, 1);
+        assert_eq!(first.endpoints[0].messages, 10);
+        assert_eq!(windows.boundary(), t0 + Duration::from_secs(390));
+
+        windows.observe(&resolved(3, &[("A", 0), ("B", 2)], t0));
+        let second = windows.close(
+            t0 + Duration::from_secs(390),
+            wall + chrono::Duration::seconds(390),
+            &[("A".to_string(), health(25, 1)), ("B".to_string(), health(20, 0))],
+        );
+        assert_eq!(second.endpoints[0].summary.scored, 1);
+        assert_eq!((second.endpoints[0].messages, second.endpoints[0].errors), (15, 0));
+
+        windows.close(t0 + Duration::from_secs(690), wall + chrono::Duration::seconds(690), &[]);
+        let retained: Vec<u64> = windows.retained().map(|w| w.index).collect();
+        assert_eq!(retained, vec![2, 3]);
+
+        let json = serde_json::to_value(windows.latest().unwrap()).unwrap();
+        assert_eq!(json["endpoints"][0]["endpoint"], "A");
+        assert!(windows.to_prometheus().contains("grpc_benchmark_window_index 3\n"));
+    }
+}